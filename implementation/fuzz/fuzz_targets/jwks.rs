@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wasi_cryptography::*;
+
+// JSON Web Key Set parsing
+fuzz_target!(|data: &[u8]| {
+    if let Ok(json) = std::str::from_utf8(data) {
+        let _ = signature_import_jwks(json);
+    }
+});
@@ -0,0 +1,36 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wasi_cryptography::*;
+
+const ALGS: [&str; 8] = [
+    "ECDSA_P256_SHA256",
+    "ECDSA_P384_SHA384",
+    "Ed25519",
+    "Ed25519_BLAKE2b512",
+    "RSA_PKCS1_2048_8192_SHA256",
+    "RSA_PKCS1_2048_8192_SHA384",
+    "RSA_PKCS1_2048_8192_SHA512",
+    "RSA_PKCS1_3072_8192_SHA384",
+];
+
+// PKCS#8 key pair import, including the v2 to v1 conversion of ECDSA keys. The first byte
+// selects the algorithm.
+fuzz_target!(|data: &[u8]| {
+    let (alg_str, pkcs8) = match data.split_first() {
+        Some((x, pkcs8)) => (ALGS[*x as usize % ALGS.len()], pkcs8),
+        None => return,
+    };
+    let op_handle = signature_op_open(alg_str).unwrap();
+    let kp_builder_handle = signature_keypair_builder_open(op_handle).unwrap();
+    if let Ok(kp_handle) =
+        signature_keypair_import(kp_builder_handle, pkcs8, KeyPairEncoding::PKCS8)
+    {
+        // An imported key pair must be usable
+        let pk_handle = signature_keypair_publickey(kp_handle).unwrap();
+        signature_publickey_close(pk_handle).unwrap();
+        signature_keypair_close(kp_handle).unwrap();
+    }
+    signature_keypair_builder_close(kp_builder_handle).unwrap();
+    signature_op_close(op_handle).unwrap();
+});
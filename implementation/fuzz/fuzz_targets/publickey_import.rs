@@ -0,0 +1,41 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wasi_cryptography::*;
+
+const ALGS: [&str; 5] = [
+    "ECDSA_P256_SHA256",
+    "ECDSA_P384_SHA384",
+    "Ed25519",
+    "Ed25519_BLAKE2b512",
+    "RSA_PKCS1_2048_8192_SHA256",
+];
+
+const ENCODINGS: [PublicKeyEncoding; 10] = [
+    PublicKeyEncoding::Raw,
+    PublicKeyEncoding::Hex,
+    PublicKeyEncoding::Base64Original,
+    PublicKeyEncoding::Base64OriginalNoPadding,
+    PublicKeyEncoding::Base64URLSafe,
+    PublicKeyEncoding::Base64URLSafeNoPadding,
+    PublicKeyEncoding::CompressedSEC1,
+    PublicKeyEncoding::SPKI,
+    PublicKeyEncoding::PEM,
+    PublicKeyEncoding::JWK,
+];
+
+// Public key import in every encoding: SEC1, SPKI, PEM, JWK, and the text encodings. The
+// first two bytes select the algorithm and the encoding.
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let alg_str = ALGS[data[0] as usize % ALGS.len()];
+    let encoding = ENCODINGS[data[1] as usize % ENCODINGS.len()];
+    let op_handle = signature_op_open(alg_str).unwrap();
+    if let Ok(pk_handle) = signature_publickey_import(op_handle, &data[2..], encoding) {
+        signature_publickey_export(pk_handle, PublicKeyEncoding::Raw).unwrap();
+        signature_publickey_close(pk_handle).unwrap();
+    }
+    signature_op_close(op_handle).unwrap();
+});
@@ -0,0 +1,44 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::sync::OnceLock;
+use wasi_cryptography::*;
+
+static PUBLIC_KEYS: OnceLock<[ECDSASignaturePublicKey; 2]> = OnceLock::new();
+
+fn public_key(alg: SignatureAlgorithm) -> ECDSASignaturePublicKey {
+    let pks = PUBLIC_KEYS.get_or_init(|| {
+        [
+            SignatureAlgorithm::ECDSA_P256_SHA256,
+            SignatureAlgorithm::ECDSA_P384_SHA384,
+        ]
+        .map(|alg| {
+            let kp = ECDSASignatureKeyPairBuilder::new(alg)
+                .generate_owned()
+                .unwrap();
+            ECDSASignaturePublicKey::from_raw(alg, kp.raw_public_key()).unwrap()
+        })
+    });
+    pks.iter().find(|pk| pk.alg == alg).unwrap().clone()
+}
+
+// DER `Ecdsa-Sig-Value` parsing, through the component export and strict verification. The
+// first byte selects the curve.
+fuzz_target!(|data: &[u8]| {
+    let (alg, encoded) = match data.split_first() {
+        Some((x, encoded)) if x & 1 == 0 => (SignatureAlgorithm::ECDSA_P256_SHA256, encoded),
+        Some((_, encoded)) => (SignatureAlgorithm::ECDSA_P384_SHA384, encoded),
+        None => return,
+    };
+    let signature = ECDSASignature::new(SignatureEncoding::DER, encoded.to_vec());
+    if let Ok((r, s)) = signature.components(alg) {
+        ECDSASignature::from_components(alg, &r, &s).unwrap();
+    }
+
+    let state = ECDSASignatureVerificationState::new(public_key(alg)).unwrap();
+    state.update(b"message").unwrap();
+    // Nobody knows the private key, so no input can be a valid signature
+    assert!(state
+        .verify_with_encoding(encoded, SignatureEncoding::DER)
+        .is_err());
+});
@@ -0,0 +1,47 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::sync::OnceLock;
+use wasi_cryptography::*;
+
+const ALGS: [&str; 4] = [
+    "ECDSA_P256_SHA256",
+    "ECDSA_P384_SHA384",
+    "Ed25519",
+    "Ed25519_BLAKE2b512",
+];
+
+// One public key per algorithm, whose private keys are discarded
+static PUBLIC_KEYS: OnceLock<Vec<(Handle, Handle)>> = OnceLock::new();
+
+// Verification of arbitrary signature bytes. The first byte selects the algorithm, the
+// second the length of the signature; the rest is the message.
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let (op_handle, pk_handle) = PUBLIC_KEYS.get_or_init(|| {
+        ALGS.iter()
+            .map(|alg_str| {
+                let op_handle = signature_op_open(alg_str).unwrap();
+                let kp_builder_handle = signature_keypair_builder_open(op_handle).unwrap();
+                let kp_handle = signature_keypair_generate(kp_builder_handle).unwrap();
+                let pk_handle = signature_keypair_publickey(kp_handle).unwrap();
+                signature_keypair_close(kp_handle).unwrap();
+                (op_handle, pk_handle)
+            })
+            .collect()
+    })[data[0] as usize % ALGS.len()];
+    let rest = &data[2..];
+    let (encoded, message) = rest.split_at((data[1] as usize).min(rest.len()));
+
+    let signature_handle = match signature_import(op_handle, SignatureEncoding::Raw, encoded) {
+        Ok(signature_handle) => signature_handle,
+        Err(_) => return,
+    };
+    let state_handle = signature_verification_state_open(pk_handle).unwrap();
+    signature_verification_state_update(state_handle, message).unwrap();
+    assert!(signature_verification_state_verify(state_handle, signature_handle).is_err());
+    signature_verification_state_close(state_handle).unwrap();
+    signature_close(signature_handle).unwrap();
+});
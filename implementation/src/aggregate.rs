@@ -0,0 +1,253 @@
+//! Experimental n-of-n aggregation of Ed25519 signatures.
+//!
+//! **WARNING: this is not MuSig or MuSig2, and has not been reviewed.** Public keys are simply
+//! added together, which is only safe if every participant proved possession of their key:
+//! without proofs, a participant can choose their key as a function of the others' and sign
+//! alone for the aggregate key. Signing takes two rounds; a nonce must never be used twice,
+//! and all participants must publish their commitments before any partial signature is
+//! computed.
+//!
+//! The combined signature is a regular Ed25519 signature, verified against the aggregate
+//! public key. Only available with the `experimental` feature.
+
+use curve25519_dalek::constants::ED25519_BASEPOINT_TABLE;
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::Scalar;
+use ring::rand::SecureRandom as _;
+use zeroize::{Zeroize, Zeroizing};
+
+use super::der;
+use super::eddsa::*;
+use super::error::*;
+use super::handles::*;
+use super::possession::*;
+use super::signature::*;
+use super::signature_keypair::*;
+use super::signature_publickey::*;
+use super::WASI_CRYPTO_CTX;
+
+const POSSESSION_AUDIENCE: &[u8] = b"wasi-crypto aggregate";
+
+/// A secret nonce for a single aggregate signature. It is consumed when computing a partial
+/// signature, and zeroized when dropped.
+pub struct AggregateNonce {
+    secret: Scalar,
+    commitment: [u8; 32],
+}
+
+impl AggregateNonce {
+    /// The commitment to publish to the other participants.
+    pub fn commitment(&self) -> [u8; 32] {
+        self.commitment
+    }
+}
+
+impl Drop for AggregateNonce {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+    }
+}
+
+fn decompress(encoded: &[u8]) -> Result<EdwardsPoint, Error> {
+    let compressed =
+        CompressedEdwardsY::from_slice(encoded).map_err(|_| CryptoError::InvalidKey)?;
+    let point = compressed.decompress().ok_or(CryptoError::InvalidKey)?;
+    ensure!(
+        !point.is_small_order() && point.is_torsion_free(),
+        CryptoError::InvalidKey
+    );
+    Ok(point)
+}
+
+fn ed25519_publickey(pk_handle: Handle) -> Result<EdDSASignaturePublicKey, Error> {
+    match WASI_CRYPTO_CTX.signature_publickey_manager.get(pk_handle)? {
+        SignaturePublicKey::EdDSA(pk) if pk.alg == SignatureAlgorithm::Ed25519 => Ok(pk),
+        _ => bail!(CryptoError::InvalidKey),
+    }
+}
+
+fn ed25519_keypair(kp_handle: Handle) -> Result<EdDSASignatureKeyPair, Error> {
+    match WASI_CRYPTO_CTX.signature_keypair_manager.get(kp_handle)? {
+        SignatureKeyPair::EdDSA(kp) if kp.alg == SignatureAlgorithm::Ed25519 => Ok(kp),
+        _ => bail!(CryptoError::InvalidKey),
+    }
+}
+
+// The secret scalar of an Ed25519 key pair, derived from its seed as specified in RFC 8032
+fn secret_scalar(kp: &EdDSASignatureKeyPair) -> Result<Scalar, Error> {
+    let pkcs8 = der::parse_pkcs8(&kp.pkcs8).map_err(|_| CryptoError::InvalidKey)?;
+    let seed = der::Decoder::new(pkcs8.private_key)
+        .read(der::TAG_OCTET_STRING)
+        .map_err(|_| CryptoError::InvalidKey)?;
+    let digest = ring::digest::digest(&ring::digest::SHA512, seed);
+    let mut clamped = Zeroizing::new([0u8; 32]);
+    clamped.copy_from_slice(&digest.as_ref()[..32]);
+    clamped[0] &= 248;
+    clamped[31] &= 127;
+    clamped[31] |= 64;
+    Ok(Scalar::from_bytes_mod_order(*clamped))
+}
+
+fn aggregate_commitments(commitments: &[[u8; 32]]) -> Result<EdwardsPoint, Error> {
+    ensure!(!commitments.is_empty(), CryptoError::InvalidSignature);
+    commitments
+        .iter()
+        .try_fold(EdwardsPoint::default(), |sum, commitment| {
+            Ok(sum + decompress(commitment).map_err(|_| CryptoError::InvalidSignature)?)
+        })
+}
+
+/// Prove possession of a key pair, so that its public key can be aggregated safely.
+pub fn signature_aggregate_prove_possession(kp_handle: Handle) -> Result<Handle, Error> {
+    let kp = ed25519_keypair(kp_handle)?;
+    signature_prove_possession(kp_handle, kp.raw_public_key(), POSSESSION_AUDIENCE)
+}
+
+/// Add Ed25519 public keys together, and return a handle to the aggregate key.
+///
+/// If `proofs` is set, it must contain a proof of possession for each key, in the same order.
+/// Without proofs, the aggregate key is vulnerable to rogue-key attacks.
+pub fn signature_aggregate_publickeys(
+    pk_handles: &[Handle],
+    proofs: Option<&[Handle]>,
+) -> Result<Handle, Error> {
+    ensure!(!pk_handles.is_empty(), CryptoError::InvalidKey);
+    if let Some(proofs) = proofs {
+        ensure!(proofs.len() == pk_handles.len(), CryptoError::InvalidKey);
+    }
+    let mut aggregate = EdwardsPoint::default();
+    for (i, &pk_handle) in pk_handles.iter().enumerate() {
+        let pk = ed25519_publickey(pk_handle)?;
+        if let Some(proofs) = proofs {
+            signature_verify_possession(pk_handle, pk.as_raw()?, POSSESSION_AUDIENCE, proofs[i])?;
+        }
+        aggregate += decompress(pk.as_raw()?)?;
+    }
+    ensure!(!aggregate.is_small_order(), CryptoError::InvalidKey);
+    let raw = aggregate.compress().to_bytes();
+    let pk = EdDSASignaturePublicKey::from_raw(SignatureAlgorithm::Ed25519, &raw)?;
+    WASI_CRYPTO_CTX
+        .signature_publickey_manager
+        .register(SignaturePublicKey::EdDSA(pk))
+}
+
+/// First round: create a fresh secret nonce, whose commitment must be sent to every
+/// participant.
+pub fn signature_aggregate_nonce() -> Result<AggregateNonce, Error> {
+    let mut random = Zeroizing::new([0u8; 64]);
+    ring::rand::SystemRandom::new()
+        .fill(&mut random[..])
+        .map_err(|_| CryptoError::RNGError)?;
+    let secret = Scalar::from_bytes_mod_order_wide(&random);
+    let commitment = (&secret * ED25519_BASEPOINT_TABLE).compress().to_bytes();
+    Ok(AggregateNonce { secret, commitment })
+}
+
+/// Second round: compute the partial signature of a participant, given the commitments of
+/// all participants including their own.
+pub fn signature_aggregate_partial_sign(
+    kp_handle: Handle,
+    nonce: AggregateNonce,
+    commitments: &[[u8; 32]],
+    aggregate_pk_handle: Handle,
+    message: &[u8],
+) -> Result<[u8; 32], Error> {
+    ensure!(
+        commitments.contains(&nonce.commitment),
+        CryptoError::InvalidSignature
+    );
+    let kp = ed25519_keypair(kp_handle)?;
+    let aggregate_pk = ed25519_publickey(aggregate_pk_handle)?;
+    let aggregate_commitment = aggregate_commitments(commitments)?.compress();
+    let mut hasher = ring::digest::Context::new(&ring::digest::SHA512);
+    hasher.update(aggregate_commitment.as_bytes());
+    hasher.update(aggregate_pk.as_raw()?);
+    hasher.update(message);
+    let mut challenge = [0u8; 64];
+    challenge.copy_from_slice(hasher.finish().as_ref());
+    let challenge = Scalar::from_bytes_mod_order_wide(&challenge);
+    let mut secret = secret_scalar(&kp)?;
+    let partial_signature = nonce.secret + challenge * secret;
+    secret.zeroize();
+    Ok(partial_signature.to_bytes())
+}
+
+/// Combine the partial signatures of all participants into an Ed25519 signature for the
+/// aggregate public key.
+pub fn signature_aggregate_combine(
+    commitments: &[[u8; 32]],
+    partial_signatures: &[[u8; 32]],
+) -> Result<Handle, Error> {
+    ensure!(
+        commitments.len() == partial_signatures.len(),
+        CryptoError::InvalidSignature
+    );
+    let aggregate_commitment = aggregate_commitments(commitments)?.compress();
+    let mut s = Scalar::ZERO;
+    for partial_signature in partial_signatures {
+        let partial_signature =
+            Option::<Scalar>::from(Scalar::from_canonical_bytes(*partial_signature))
+                .ok_or(CryptoError::InvalidSignature)?;
+        s += partial_signature;
+    }
+    let encoded = [&aggregate_commitment.to_bytes()[..], s.as_bytes()].concat();
+    let signature = Signature::EdDSA(EdDSASignature::new(encoded));
+    WASI_CRYPTO_CTX.signature_manager.register(signature)
+}
+
+#[test]
+fn test_aggregate_signature() {
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handles: Vec<_> = (0..3)
+        .map(|_| crate::signature_keypair_generate(kp_builder_handle).unwrap())
+        .collect();
+    let pk_handles: Vec<_> = kp_handles
+        .iter()
+        .map(|&kp_handle| crate::signature_keypair_publickey(kp_handle).unwrap())
+        .collect();
+    let proofs: Vec<_> = kp_handles
+        .iter()
+        .map(|&kp_handle| signature_aggregate_prove_possession(kp_handle).unwrap())
+        .collect();
+    let aggregate_pk_handle = signature_aggregate_publickeys(&pk_handles, Some(&proofs)).unwrap();
+
+    let nonces: Vec<_> = (0..3)
+        .map(|_| signature_aggregate_nonce().unwrap())
+        .collect();
+    let commitments: Vec<_> = nonces.iter().map(AggregateNonce::commitment).collect();
+    let partial_signatures: Vec<_> = kp_handles
+        .iter()
+        .zip(nonces)
+        .map(|(&kp_handle, nonce)| {
+            signature_aggregate_partial_sign(
+                kp_handle,
+                nonce,
+                &commitments,
+                aggregate_pk_handle,
+                b"test",
+            )
+            .unwrap()
+        })
+        .collect();
+    let signature_handle = signature_aggregate_combine(&commitments, &partial_signatures).unwrap();
+
+    let verify = |pk_handle, message: &[u8]| {
+        let state_handle = crate::signature_verification_state_open(pk_handle).unwrap();
+        crate::signature_verification_state_update(state_handle, message).unwrap();
+        crate::signature_verification_state_verify(state_handle, signature_handle)
+    };
+    verify(aggregate_pk_handle, b"test").unwrap();
+    assert!(verify(aggregate_pk_handle, b"other").is_err());
+    assert!(verify(pk_handles[0], b"test").is_err());
+
+    let missing = signature_aggregate_combine(&commitments[..2], &partial_signatures[..2]).unwrap();
+    let state_handle = crate::signature_verification_state_open(aggregate_pk_handle).unwrap();
+    crate::signature_verification_state_update(state_handle, b"test").unwrap();
+    assert!(crate::signature_verification_state_verify(state_handle, missing).is_err());
+
+    let mut swapped_proofs = proofs.clone();
+    swapped_proofs.swap(0, 1);
+    assert!(signature_aggregate_publickeys(&pk_handles, Some(&swapped_proofs)).is_err());
+    signature_aggregate_publickeys(&pk_handles, None).unwrap();
+}
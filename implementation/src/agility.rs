@@ -0,0 +1,176 @@
+use super::ecdsa::*;
+use super::eddsa::*;
+use super::error::*;
+use super::handles::*;
+use super::rsa::*;
+use super::signature::*;
+use super::signature_policy::*;
+use super::signature_publickey::*;
+use super::WASI_CRYPTO_CTX;
+
+/// Reinterpret a public key for another algorithm using the same key type, such as RSA with
+/// a different hash function. Returns `None` if the key cannot be used with that algorithm.
+fn reparameterize(pk: &SignaturePublicKey, alg: SignatureAlgorithm) -> Option<SignaturePublicKey> {
+    let pk = match (pk, alg.category()) {
+        (SignaturePublicKey::ECDSA(pk), AlgorithmCategory::ECDSA) => SignaturePublicKey::ECDSA(
+            ECDSASignaturePublicKey::from_raw(alg, pk.as_raw().ok()?).ok()?,
+        ),
+        (SignaturePublicKey::EdDSA(pk), AlgorithmCategory::EdDSA) => {
+            let mut reparameterized =
+                EdDSASignaturePublicKey::from_raw(alg, pk.as_raw().ok()?).ok()?;
+            reparameterized.strict_verification = pk.strict_verification;
+            SignaturePublicKey::EdDSA(reparameterized)
+        }
+        (SignaturePublicKey::RSA(pk), AlgorithmCategory::RSA) => {
+            SignaturePublicKey::RSA(RSASignaturePublicKey::from_raw(alg, pk.as_raw().ok()?).ok()?)
+        }
+        _ => return None,
+    };
+    Some(pk)
+}
+
+/// Verify a raw signature using each candidate algorithm in turn, and return the first one
+/// that accepts it.
+///
+/// Candidates that don't use the same key type as the public key, or that the algorithm
+/// policy doesn't allow, are skipped. This allows peers to negotiate parameters such as the
+/// hash function without importing the same key once per algorithm.
+pub fn signature_verify_agile(
+    pk_handle: Handle,
+    message: &[u8],
+    encoded_signature: &[u8],
+    candidate_algs: &[SignatureAlgorithm],
+) -> Result<SignatureAlgorithm, Error> {
+    let pk = WASI_CRYPTO_CTX.signature_publickey_manager.get(pk_handle)?;
+    for &alg in candidate_algs {
+        if check_algorithm_policy(alg).is_err() {
+            continue;
+        }
+        let candidate_pk = match reparameterize(&pk, alg) {
+            Some(candidate_pk) => candidate_pk,
+            None => continue,
+        };
+        let signature = match Signature::from_raw(alg, encoded_signature) {
+            Ok(signature) => signature,
+            Err(_) => continue,
+        };
+        let mut state = match ExclusiveSignatureVerificationState::from_publickey(candidate_pk) {
+            Ok(state) => state,
+            Err(_) => continue,
+        };
+        state.update(message)?;
+        if state.verify_signature(&signature).is_ok() {
+            return Ok(alg);
+        }
+    }
+    bail!(CryptoError::InvalidSignature)
+}
+
+#[test]
+fn test_agile_verification() {
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    let state_handle = crate::signature_state_open(kp_handle).unwrap();
+    crate::signature_state_update(state_handle, b"test").unwrap();
+    let signature_handle = crate::signature_state_sign(state_handle).unwrap();
+    let signature = crate::signature_export(signature_handle, SignatureEncoding::Raw).unwrap();
+
+    let candidate_algs = [
+        SignatureAlgorithm::Ed25519_BLAKE2b512,
+        SignatureAlgorithm::ECDSA_P256_SHA256,
+        SignatureAlgorithm::Ed25519,
+    ];
+    assert_eq!(
+        signature_verify_agile(pk_handle, b"test", &signature, &candidate_algs).unwrap(),
+        SignatureAlgorithm::Ed25519
+    );
+    assert!(signature_verify_agile(pk_handle, b"other", &signature, &candidate_algs).is_err());
+    assert!(signature_verify_agile(
+        pk_handle,
+        b"test",
+        &signature,
+        &[SignatureAlgorithm::Ed25519_BLAKE2b512]
+    )
+    .is_err());
+}
+
+#[test]
+fn test_agile_verification_ecdsa() {
+    let op_handle = crate::signature_op_open("ECDSA_P256_SHA256").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    let state_handle = crate::signature_state_open(kp_handle).unwrap();
+    crate::signature_state_update(state_handle, b"test").unwrap();
+    let signature_handle = crate::signature_state_sign(state_handle).unwrap();
+    let signature = crate::signature_export(signature_handle, SignatureEncoding::Raw).unwrap();
+
+    let candidate_algs = [
+        SignatureAlgorithm::Ed25519,
+        SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256,
+        SignatureAlgorithm::ECDSA_P384_SHA384,
+        SignatureAlgorithm::ECDSA_P256_SHA256,
+    ];
+    assert_eq!(
+        signature_verify_agile(pk_handle, b"test", &signature, &candidate_algs).unwrap(),
+        SignatureAlgorithm::ECDSA_P256_SHA256
+    );
+    assert!(signature_verify_agile(pk_handle, b"other", &signature, &candidate_algs).is_err());
+    assert!(signature_verify_agile(pk_handle, b"test", &signature, &candidate_algs[..3]).is_err());
+}
+
+#[test]
+fn test_agile_verification_rsa() {
+    let op_handle = crate::signature_op_open("RSA_PKCS1_2048_8192_SHA256").unwrap();
+    let raw_pk = base64::decode(crate::self_test::RSA_PUBLIC_KEY).unwrap();
+    let pk_handle =
+        crate::signature_publickey_import(op_handle, &raw_pk, PublicKeyEncoding::Raw).unwrap();
+
+    let candidate_algs = [
+        SignatureAlgorithm::ECDSA_P256_SHA256,
+        SignatureAlgorithm::Ed25519,
+        SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512,
+        SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA384,
+        SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256,
+    ];
+    for (encoded_signature, alg) in &[
+        (
+            crate::self_test::RSA_SHA256_SIGNATURE,
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256,
+        ),
+        (
+            crate::self_test::RSA_SHA384_SIGNATURE,
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA384,
+        ),
+        (
+            crate::self_test::RSA_SHA512_SIGNATURE,
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512,
+        ),
+    ] {
+        let signature = base64::decode(encoded_signature).unwrap();
+        assert_eq!(
+            signature_verify_agile(
+                pk_handle,
+                crate::self_test::MESSAGE,
+                &signature,
+                &candidate_algs
+            )
+            .unwrap(),
+            *alg
+        );
+        assert!(signature_verify_agile(pk_handle, b"other", &signature, &candidate_algs).is_err());
+    }
+    let signature = base64::decode(crate::self_test::RSA_SHA384_SIGNATURE).unwrap();
+    assert!(signature_verify_agile(
+        pk_handle,
+        crate::self_test::MESSAGE,
+        &signature,
+        &[
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256,
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512,
+        ]
+    )
+    .is_err());
+}
@@ -0,0 +1,166 @@
+//! Ed25519 batch verification with a random linear combination, as described in the
+//! Ed25519 paper: for random 128-bit scalars `z_i`, check that
+//! `sum(z_i * R_i) + sum(z_i * k_i) * A - sum(z_i * s_i) * B` is the identity, with a single
+//! multi-scalar multiplication instead of one double-scalar multiplication per signature.
+//!
+//! The equation is cofactorless, like ring's single verification. A public key or an `R`
+//! component with a small-order component, which an honest signer never produces, could
+//! cancel out in the combination, and pass a batch while single verification rejects it.
+//! Such points are rejected before batching, so that a batch never accepts more than single
+//! verification does. Only available with the `batch` feature.
+
+use alloc::vec::Vec;
+use curve25519_dalek::constants::ED25519_BASEPOINT_POINT;
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::{IsIdentity as _, VartimeMultiscalarMul as _};
+use ring::rand::SecureRandom as _;
+
+use super::error::*;
+
+// Decompress a point, rejecting non-canonical encodings as ring does, and points that are
+// not in the prime-order subgroup
+fn decompress(encoded: &[u8]) -> Option<EdwardsPoint> {
+    let compressed = CompressedEdwardsY::from_slice(encoded).ok()?;
+    let point = compressed.decompress()?;
+    if point.compress() != compressed || !point.is_torsion_free() {
+        return None;
+    }
+    Some(point)
+}
+
+/// Return `true` if all the `(signed input, signature)` pairs are valid for the raw public
+/// key. `false` means that at least one of them is invalid, but not which one.
+pub fn ed25519_verify_batch(raw_pk: &[u8], items: &[(&[u8], &[u8])]) -> Result<bool, Error> {
+    let pk = match decompress(raw_pk) {
+        Some(pk) => pk,
+        None => return Ok(false),
+    };
+    let mut random = vec![0u8; items.len() * 16];
+    ring::rand::SystemRandom::new()
+        .fill(&mut random)
+        .map_err(|_| CryptoError::RNGError)?;
+
+    let mut scalars = Vec::with_capacity(items.len() + 2);
+    let mut points = Vec::with_capacity(items.len() + 2);
+    let mut s_sum = Scalar::ZERO;
+    let mut k_sum = Scalar::ZERO;
+    for ((input, signature), z) in items.iter().zip(random.chunks(16)) {
+        if signature.len() != 64 {
+            return Ok(false);
+        }
+        let (encoded_r, encoded_s) = signature.split_at(32);
+        let r = match decompress(encoded_r) {
+            Some(r) => r,
+            None => return Ok(false),
+        };
+        let mut s = [0u8; 32];
+        s.copy_from_slice(encoded_s);
+        let s = match Option::<Scalar>::from(Scalar::from_canonical_bytes(s)) {
+            Some(s) => s,
+            None => return Ok(false),
+        };
+        let mut hasher = ring::digest::Context::new(&ring::digest::SHA512);
+        hasher.update(encoded_r);
+        hasher.update(raw_pk);
+        hasher.update(input);
+        let mut k = [0u8; 64];
+        k.copy_from_slice(hasher.finish().as_ref());
+        let k = Scalar::from_bytes_mod_order_wide(&k);
+
+        let mut wide_z = [0u8; 32];
+        wide_z[..16].copy_from_slice(z);
+        let z = Scalar::from_bytes_mod_order(wide_z);
+        s_sum += z * s;
+        k_sum += z * k;
+        scalars.push(z);
+        points.push(r);
+    }
+    scalars.push(-s_sum);
+    points.push(ED25519_BASEPOINT_POINT);
+    scalars.push(k_sum);
+    points.push(pk);
+    Ok(EdwardsPoint::vartime_multiscalar_mul(&scalars, &points).is_identity())
+}
+
+#[test]
+fn test_batch_with_one_bad_signature() {
+    use super::eddsa::*;
+    use super::signature::*;
+
+    let kp = EdDSASignatureKeyPairBuilder::new(SignatureAlgorithm::Ed25519)
+        .generate_owned()
+        .unwrap();
+    let messages: Vec<Vec<u8>> = (0..16u8).map(|i| vec![i; i as usize]).collect();
+    let signatures: Vec<_> = messages
+        .iter()
+        .map(|message| kp.sign_oneshot(message).unwrap())
+        .collect();
+    let mut items: Vec<(&[u8], &[u8])> = messages
+        .iter()
+        .zip(&signatures)
+        .map(|(message, signature)| (message.as_slice(), signature.as_ref()))
+        .collect();
+    assert!(ed25519_verify_batch(kp.raw_public_key(), &items).unwrap());
+    assert!(ed25519_verify_batch(kp.raw_public_key(), &[]).unwrap());
+
+    let mut bad_signature = signatures[5].as_ref().to_vec();
+    bad_signature[40] ^= 0x01;
+    items[5].1 = &bad_signature;
+    assert!(!ed25519_verify_batch(kp.raw_public_key(), &items).unwrap());
+
+    let pk = EdDSASignaturePublicKey::from_raw(kp.alg, kp.raw_public_key()).unwrap();
+    let bad_signature = EdDSASignature::new(bad_signature.clone());
+    let items: Vec<(&[u8], &EdDSASignature)> = messages
+        .iter()
+        .zip(&signatures)
+        .enumerate()
+        .map(|(i, (message, signature))| {
+            (
+                message.as_slice(),
+                if i == 5 { &bad_signature } else { signature },
+            )
+        })
+        .collect();
+    let valid = pk.verify_batch(&items).unwrap();
+    assert_eq!(valid.iter().position(|&valid| !valid), Some(5));
+    assert_eq!(valid.iter().filter(|&&valid| valid).count(), 15);
+}
+
+#[test]
+fn test_batch_rejects_small_order_components() {
+    use super::eddsa::*;
+    use super::signature::*;
+
+    let kp = EdDSASignatureKeyPairBuilder::new(SignatureAlgorithm::Ed25519)
+        .generate_owned()
+        .unwrap();
+    let pk = EdDSASignaturePublicKey::from_raw(kp.alg, kp.raw_public_key()).unwrap();
+    // The point of order 2, (0, -1)
+    let mut encoded_t = [0xffu8; 32];
+    encoded_t[0] = 0xec;
+    encoded_t[31] = 0x7f;
+    let t = decompress_unchecked(&encoded_t);
+
+    // Adding it to `R` cancels out whenever the random scalar is even
+    let signature = kp.sign_oneshot(b"test").unwrap();
+    let r = decompress_unchecked(&signature.as_ref()[..32]);
+    let mut tampered = signature.as_ref().to_vec();
+    tampered[..32].copy_from_slice((r + t).compress().as_bytes());
+    assert!(pk.verify(b"test", &tampered).is_err());
+    for _ in 0..16 {
+        assert!(!ed25519_verify_batch(kp.raw_public_key(), &[(b"test", &tampered)]).unwrap());
+    }
+
+    let pk_with_t = (decompress_unchecked(kp.raw_public_key()) + t).compress();
+    assert!(!ed25519_verify_batch(pk_with_t.as_bytes(), &[]).unwrap());
+    assert!(!ed25519_verify_batch(&encoded_t, &[]).unwrap());
+}
+
+#[cfg(test)]
+fn decompress_unchecked(encoded: &[u8]) -> EdwardsPoint {
+    CompressedEdwardsY::from_slice(encoded)
+        .unwrap()
+        .decompress()
+        .unwrap()
+}
@@ -0,0 +1,68 @@
+use alloc::vec::Vec;
+use ring::signature::KeyPair as _;
+
+use super::signature::*;
+
+// Check that ring can generate a key pair for the algorithm, sign with it, and verify the
+// signature. RSA can only be used for verification, and its parameters are constants that
+// are always compiled in.
+fn probe(alg: SignatureAlgorithm) -> bool {
+    let rng = ring::rand::SystemRandom::new();
+    let (signing_alg, verification_alg) = match alg {
+        SignatureAlgorithm::ECDSA_P256_SHA256 => (
+            &ring::signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+            &ring::signature::ECDSA_P256_SHA256_FIXED,
+        ),
+        SignatureAlgorithm::ECDSA_P384_SHA384 => (
+            &ring::signature::ECDSA_P384_SHA384_FIXED_SIGNING,
+            &ring::signature::ECDSA_P384_SHA384_FIXED,
+        ),
+        SignatureAlgorithm::Ed25519 | SignatureAlgorithm::Ed25519_BLAKE2b512 => {
+            let probe = || -> Result<(), ring::error::Unspecified> {
+                let pkcs8 = ring::signature::Ed25519KeyPair::generate_pkcs8(&rng)?;
+                let kp = ring::signature::Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+                    .map_err(|_| ring::error::Unspecified)?;
+                let signature = kp.sign(b"probe");
+                ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, kp.public_key())
+                    .verify(b"probe", signature.as_ref())
+            };
+            return probe().is_ok();
+        }
+        SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256
+        | SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA384
+        | SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512
+        | SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384 => return true,
+    };
+    let probe = || -> Result<(), ring::error::Unspecified> {
+        let pkcs8 = ring::signature::EcdsaKeyPair::generate_pkcs8(signing_alg, &rng)?;
+        let kp = ring::signature::EcdsaKeyPair::from_pkcs8(signing_alg, pkcs8.as_ref())
+            .map_err(|_| ring::error::Unspecified)?;
+        let signature = kp.sign(&rng, b"probe")?;
+        ring::signature::UnparsedPublicKey::new(verification_alg, kp.public_key())
+            .verify(b"probe", signature.as_ref())
+    };
+    probe().is_ok()
+}
+
+lazy_static! {
+    static ref SUPPORTED_ALGORITHMS: Vec<SignatureAlgorithm> = SignatureAlgorithm::ALL
+        .iter()
+        .copied()
+        .filter(|&alg| probe(alg))
+        .collect();
+}
+
+/// Whether the algorithm works with the ring build linked in. Algorithms are probed once,
+/// the first time this is called.
+pub fn is_supported(alg: SignatureAlgorithm) -> bool {
+    SUPPORTED_ALGORITHMS.contains(&alg)
+}
+
+#[test]
+fn test_is_supported() {
+    assert!(SignatureAlgorithm::Ed25519.is_supported());
+    assert!(SignatureAlgorithm::ECDSA_P256_SHA256.is_supported());
+    for &alg in &SignatureAlgorithm::ALL {
+        assert_eq!(alg.is_supported(), probe(alg));
+    }
+}
@@ -0,0 +1,227 @@
+//! Signature verification whose running time doesn't depend on why a signature is rejected.
+//! A malformed signature, that would otherwise be rejected before any expensive computation,
+//! is replaced with a well-formed one that never verifies, and is then verified as usual.
+//! This trades throughput for resistance to timing oracles: every rejected signature costs as
+//! much as a full verification.
+
+use alloc::vec::Vec;
+
+use super::der;
+use super::ecdsa::*;
+use super::error::*;
+use super::handles::*;
+use super::signature::*;
+use super::signature_publickey::*;
+use super::WASI_CRYPTO_CTX;
+
+// The order of the Ed25519 group, big-endian
+const ED25519_ORDER: [u8; 32] = [
+    0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x14, 0xde, 0xf9, 0xde, 0xa2, 0xf7, 0x9c, 0xd6, 0x58, 0x12, 0x63, 0x1a, 0x5c, 0xf5, 0xd3, 0xed,
+];
+
+// The encoded Ed25519 base point, a valid `R` that passes strict verification checks
+const ED25519_BASE_POINT: [u8; 32] = [
+    0x58, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+    0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+];
+
+// 1 if the big-endian `x` is in `[1, bound - 1]`, 0 otherwise, in constant time
+fn ct_in_range(x: &[u8], bound: &[u8]) -> u8 {
+    let (_, below_bound) = ct_sub(x, bound);
+    let non_zero = x.iter().fold(0u8, |acc, &b| acc | b);
+    let non_zero = (u16::from(non_zero).wrapping_neg() >> 8) as u8 & 1;
+    below_bound & non_zero
+}
+
+// A signature of the right size that the structural checks accept, but that is never valid,
+// along with whether the given signature passes these checks
+fn dummy_signature(pk: &SignaturePublicKey, signature: &[u8]) -> Result<(Vec<u8>, u8), Error> {
+    let checked = match pk {
+        SignaturePublicKey::ECDSA(pk) => {
+            let coordinate_len = ECDSASignatureOp::coordinate_len(pk.alg)?;
+            let mut one = vec![0u8; coordinate_len];
+            one[coordinate_len - 1] = 1;
+            let well_formed = if signature.len() == coordinate_len * 2 {
+                let order = ECDSASignature::curve_order(pk.alg)?;
+                let (r, s) = signature.split_at(coordinate_len);
+                ct_in_range(r, order) & ct_in_range(s, order)
+            } else {
+                0
+            };
+            ([&one[..], &one[..]].concat(), well_formed)
+        }
+        SignaturePublicKey::EdDSA(_) => {
+            let well_formed = if signature.len() == 64 {
+                // `s` is little-endian, and can be zero
+                let s: Vec<u8> = signature[32..].iter().rev().copied().collect();
+                let (_, below_order) = ct_sub(&s, &ED25519_ORDER);
+                below_order
+            } else {
+                0
+            };
+            ([&ED25519_BASE_POINT[..], &[0u8; 32]].concat(), well_formed)
+        }
+        SignaturePublicKey::RSA(pk) => {
+            let mut decoder = der::Decoder::new(pk.as_raw()?);
+            let mut rsa_pk = der::Decoder::new(decoder.read(der::TAG_SEQUENCE)?);
+            let modulus = rsa_pk.read_unsigned_integer()?;
+            let well_formed = if signature.len() == modulus.len() {
+                ct_in_range(signature, modulus)
+            } else {
+                0
+            };
+            let mut one = vec![0u8; modulus.len()];
+            one[modulus.len() - 1] = 1;
+            (one, well_formed)
+        }
+    };
+    Ok(checked)
+}
+
+/// Verify a raw signature of `message`, taking as long to reject a malformed signature,
+/// such as one with the wrong size or with out-of-range components, as to reject a
+/// well-formed signature that doesn't verify. All rejections return `VerificationFailed`.
+///
+/// This is slower than regular verification for malformed signatures, and is meant for
+/// verifiers exposed to adversarial input. Only the checks performed before verification are
+/// covered: how long the verification itself takes is up to the underlying implementation.
+pub fn signature_verify_constant_time(
+    pk_handle: Handle,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), Error> {
+    let pk = WASI_CRYPTO_CTX.signature_publickey_manager.get(pk_handle)?;
+    let (dummy, well_formed) = dummy_signature(&pk, signature)?;
+    let candidate = if well_formed == 1 { signature } else { &dummy };
+    let mut state = ExclusiveSignatureVerificationState::from_publickey(pk)?;
+    state.update(message)?;
+    let verified = state
+        .verify_with_encoding(candidate, SignatureEncoding::Raw)
+        .is_ok();
+    ensure!(
+        verified & (well_formed == 1),
+        CryptoError::VerificationFailed
+    );
+    Ok(())
+}
+
+#[test]
+fn test_verify_constant_time() {
+    let is_verification_failure = |res: Result<(), Error>| {
+        matches!(
+            res.unwrap_err().downcast_ref::<CryptoError>(),
+            Some(CryptoError::VerificationFailed)
+        )
+    };
+    for alg_str in ["Ed25519", "ECDSA_P256_SHA256", "ECDSA_P384_SHA384"] {
+        let op_handle = crate::signature_op_open(alg_str).unwrap();
+        let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+        let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+        let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+        let state_handle = signature_state_open(kp_handle).unwrap();
+        signature_state_update(state_handle, b"test").unwrap();
+        let signature_handle = signature_state_sign(state_handle).unwrap();
+        let signature = signature_export(signature_handle, SignatureEncoding::Raw).unwrap();
+
+        signature_verify_constant_time(pk_handle, b"test", &signature).unwrap();
+        assert!(is_verification_failure(signature_verify_constant_time(
+            pk_handle, b"tesu", &signature
+        )));
+        let mut tampered = signature.clone();
+        tampered[10] ^= 1;
+        assert!(is_verification_failure(signature_verify_constant_time(
+            pk_handle, b"test", &tampered
+        )));
+        assert!(is_verification_failure(signature_verify_constant_time(
+            pk_handle,
+            b"test",
+            &signature[1..]
+        )));
+        // Components equal to the group order, which are out of range
+        let mut out_of_range = signature.clone();
+        if alg_str == "Ed25519" {
+            let order: Vec<u8> = ED25519_ORDER.iter().rev().copied().collect();
+            out_of_range[32..].copy_from_slice(&order);
+        } else {
+            let alg = match alg_str {
+                "ECDSA_P256_SHA256" => SignatureAlgorithm::ECDSA_P256_SHA256,
+                _ => SignatureAlgorithm::ECDSA_P384_SHA384,
+            };
+            let order = ECDSASignature::curve_order(alg).unwrap();
+            out_of_range[..order.len()].copy_from_slice(order);
+        }
+        assert!(is_verification_failure(signature_verify_constant_time(
+            pk_handle,
+            b"test",
+            &out_of_range
+        )));
+        let (dummy, _) = dummy_signature(
+            &WASI_CRYPTO_CTX
+                .signature_publickey_manager
+                .get(pk_handle)
+                .unwrap(),
+            &[],
+        )
+        .unwrap();
+        assert!(is_verification_failure(signature_verify_constant_time(
+            pk_handle, b"test", &dummy
+        )));
+    }
+
+    let op_handle = crate::signature_op_open("RSA_PKCS1_2048_8192_SHA256").unwrap();
+    let raw_pk = base64::decode(crate::self_test::RSA_PUBLIC_KEY).unwrap();
+    let pk_handle = signature_publickey_import(op_handle, &raw_pk, PublicKeyEncoding::Raw).unwrap();
+    let signature = base64::decode(crate::self_test::RSA_SHA256_SIGNATURE).unwrap();
+    signature_verify_constant_time(pk_handle, b"abc", &signature).unwrap();
+    assert!(is_verification_failure(signature_verify_constant_time(
+        pk_handle, b"abd", &signature
+    )));
+    assert!(is_verification_failure(signature_verify_constant_time(
+        pk_handle,
+        b"abc",
+        &[0xff; 384]
+    )));
+    assert!(is_verification_failure(signature_verify_constant_time(
+        pk_handle,
+        b"abc",
+        &signature[1..]
+    )));
+}
+
+#[test]
+#[ignore = "timing-dependent, flaky on loaded machines; run with `cargo test -- --ignored`"]
+fn test_verify_constant_time_timing() {
+    use std::time::{Duration, Instant};
+
+    // A loose sanity check rather than a side-channel test: rejecting a malformed signature
+    // must cost about as much as rejecting a well-formed one, instead of being nearly free
+    let op_handle = crate::signature_op_open("ECDSA_P256_SHA256").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    let state_handle = signature_state_open(kp_handle).unwrap();
+    signature_state_update(state_handle, b"test").unwrap();
+    let signature_handle = signature_state_sign(state_handle).unwrap();
+    let mut well_formed = signature_export(signature_handle, SignatureEncoding::Raw).unwrap();
+    well_formed[63] ^= 1;
+    let wrong_length = &well_formed[1..];
+    let out_of_range = [0xffu8; 64];
+
+    let median = |signature: &[u8]| {
+        let mut samples: Vec<Duration> = (0..21)
+            .map(|_| {
+                let start = Instant::now();
+                let _ = signature_verify_constant_time(pk_handle, b"test", signature);
+                start.elapsed()
+            })
+            .collect();
+        samples.sort();
+        samples[samples.len() / 2]
+    };
+    let reference = median(&well_formed);
+    for signature in [wrong_length, &out_of_range[..]] {
+        let elapsed = median(signature);
+        assert!(elapsed * 4 > reference && elapsed < reference * 4);
+    }
+}
@@ -0,0 +1,118 @@
+use alloc::vec::Vec;
+
+use super::der;
+use super::ecdsa::*;
+use super::eddsa::*;
+use super::error::*;
+use super::handles::*;
+use super::signature::*;
+use super::signature_keypair::*;
+use super::WASI_CRYPTO_CTX;
+
+/// Encode a distinguished name such as `CN=example.com,O=Example,C=US`.
+/// Values cannot contain commas; escaping is not supported.
+fn encode_name(subject_dn: &str) -> Result<Vec<u8>, Error> {
+    let mut rdns = vec![];
+    for component in subject_dn.split(',') {
+        let mut parts = component.splitn(2, '=');
+        let key = parts.next().unwrap_or_default().trim();
+        let value = parts.next().unwrap_or_default().trim();
+        ensure!(!value.is_empty(), "Invalid distinguished name");
+        let (attr_oid, value_tag) = match key {
+            "CN" => (der::OID_COMMON_NAME, der::TAG_UTF8_STRING),
+            "C" => (der::OID_COUNTRY_NAME, der::TAG_PRINTABLE_STRING),
+            "L" => (der::OID_LOCALITY_NAME, der::TAG_UTF8_STRING),
+            "ST" => (der::OID_STATE_OR_PROVINCE_NAME, der::TAG_UTF8_STRING),
+            "O" => (der::OID_ORGANIZATION_NAME, der::TAG_UTF8_STRING),
+            "OU" => (der::OID_ORGANIZATIONAL_UNIT_NAME, der::TAG_UTF8_STRING),
+            _ => bail!("Unsupported distinguished name attribute"),
+        };
+        let attr = der::sequence(&[
+            &der::oid(attr_oid),
+            &der::encode(value_tag, value.as_bytes()),
+        ]);
+        rdns.push(der::set(&[&attr]));
+    }
+    let rdns: Vec<&[u8]> = rdns.iter().map(|rdn| rdn.as_slice()).collect();
+    Ok(der::sequence(&rdns))
+}
+
+/// Build a DER-encoded PKCS#10 `CertificationRequest` for the key pair, signed with it.
+pub fn signature_keypair_generate_csr(
+    kp_handle: Handle,
+    subject_dn: &str,
+) -> Result<Vec<u8>, Error> {
+    let kp = WASI_CRYPTO_CTX.signature_keypair_manager.get(kp_handle)?;
+    let (spki, signature_alg_id) = match &kp {
+        SignatureKeyPair::ECDSA(kp) => {
+            let pk = ECDSASignaturePublicKey::from_raw(kp.alg, kp.raw_public_key())?;
+            let signature_alg_oid = match kp.alg {
+                SignatureAlgorithm::ECDSA_P256_SHA256 => der::OID_ECDSA_WITH_SHA256,
+                SignatureAlgorithm::ECDSA_P384_SHA384 => der::OID_ECDSA_WITH_SHA384,
+                _ => bail!(CryptoError::NotAvailable),
+            };
+            (
+                pk.as_spki()?,
+                der::sequence(&[&der::oid(signature_alg_oid)]),
+            )
+        }
+        SignatureKeyPair::EdDSA(kp) => {
+            ensure!(
+                kp.alg == SignatureAlgorithm::Ed25519,
+                CryptoError::NotAvailable
+            );
+            let pk = EdDSASignaturePublicKey::from_raw(kp.alg, kp.raw_public_key())?;
+            (pk.as_spki()?, der::sequence(&[&der::oid(der::OID_ED25519)]))
+        }
+        SignatureKeyPair::RSA(_) | SignatureKeyPair::Remote(_) => bail!(CryptoError::NotAvailable),
+    };
+    let version = der::unsigned_integer(&[0]);
+    let attributes = der::encode(der::TAG_CONTEXT_0, &[]);
+    let info = der::sequence(&[&version, &encode_name(subject_dn)?, &spki, &attributes]);
+    let signature = match kp {
+        SignatureKeyPair::ECDSA(kp) => {
+            let state = ECDSASignatureState::new(kp);
+            state.update(&info)?;
+            der::ecdsa_signature_from_fixed(state.sign()?.as_ref())?
+        }
+        SignatureKeyPair::EdDSA(kp) => {
+            let state = EdDSASignatureState::new(kp);
+            state.update(&info)?;
+            state.sign()?.as_ref().to_vec()
+        }
+        SignatureKeyPair::RSA(_) | SignatureKeyPair::Remote(_) => bail!(CryptoError::NotAvailable),
+    };
+    Ok(der::sequence(&[
+        &info,
+        &signature_alg_id,
+        &der::bit_string(&signature),
+    ]))
+}
+
+#[test]
+#[ignore = "requires openssl; run with `cargo test -- --ignored`"]
+fn test_csr_verifies_with_openssl() {
+    use std::process::Command;
+
+    for alg_str in &["Ed25519", "ECDSA_P256_SHA256", "ECDSA_P384_SHA384"] {
+        let op_handle = crate::signature_op_open(alg_str).unwrap();
+        let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+        let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+        let csr =
+            signature_keypair_generate_csr(kp_handle, "CN=example.com,O=Example,C=US").unwrap();
+
+        let path = std::env::temp_dir().join(format!(
+            "wasi-crypto-csr-{}-{}.der",
+            std::process::id(),
+            alg_str
+        ));
+        std::fs::write(&path, &csr).unwrap();
+        let output = Command::new("openssl")
+            .args(["req", "-inform", "DER", "-noout", "-verify", "-in"])
+            .arg(&path)
+            .output();
+        std::fs::remove_file(&path).unwrap();
+        let output = output.expect("openssl not found");
+        assert!(output.status.success(), "{:?}", output);
+    }
+}
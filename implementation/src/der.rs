@@ -0,0 +1,302 @@
+use alloc::vec::Vec;
+use zeroize::Zeroizing;
+
+use super::error::*;
+
+pub const TAG_BOOLEAN: u8 = 0x01;
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_BIT_STRING: u8 = 0x03;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_NULL: u8 = 0x05;
+pub const TAG_OID: u8 = 0x06;
+pub const TAG_UTF8_STRING: u8 = 0x0c;
+pub const TAG_PRINTABLE_STRING: u8 = 0x13;
+pub const TAG_GENERALIZED_TIME: u8 = 0x18;
+pub const TAG_SEQUENCE: u8 = 0x30;
+pub const TAG_SET: u8 = 0x31;
+pub const TAG_CONTEXT_0: u8 = 0xa0;
+pub const TAG_CONTEXT_1: u8 = 0xa1;
+pub const TAG_CONTEXT_PRIMITIVE_1: u8 = 0x81;
+
+// Encoded object identifiers, without the tag and length
+pub const OID_ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+pub const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+pub const OID_PRIME256V1: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+pub const OID_SECP384R1: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+pub const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+pub const OID_ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+pub const OID_ECDSA_WITH_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+pub const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+pub const OID_COUNTRY_NAME: &[u8] = &[0x55, 0x04, 0x06];
+pub const OID_LOCALITY_NAME: &[u8] = &[0x55, 0x04, 0x07];
+pub const OID_STATE_OR_PROVINCE_NAME: &[u8] = &[0x55, 0x04, 0x08];
+pub const OID_ORGANIZATION_NAME: &[u8] = &[0x55, 0x04, 0x0a];
+pub const OID_ORGANIZATIONAL_UNIT_NAME: &[u8] = &[0x55, 0x04, 0x0b];
+pub const OID_SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+pub const OID_SHA384: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02];
+pub const OID_SHA512: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03];
+pub const OID_SIGNED_DATA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x02];
+pub const OID_CONTENT_TYPE: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x03];
+pub const OID_MESSAGE_DIGEST: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x04];
+pub const OID_TST_INFO: &[u8] = &[
+    0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x10, 0x01, 0x04,
+];
+pub const OID_PBES2: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0d];
+pub const OID_PBKDF2: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0c];
+pub const OID_HMAC_WITH_SHA1: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x07];
+pub const OID_HMAC_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x09];
+pub const OID_HMAC_WITH_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x0a];
+pub const OID_HMAC_WITH_SHA512: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x0b];
+pub const OID_AES256_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x2a];
+
+// The length is reserved upfront, so that secret content is never left behind by a
+// reallocation.
+pub fn encode(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(2 + core::mem::size_of::<usize>() + content.len());
+    encoded.push(tag);
+    let len = content.len();
+    if len < 0x80 {
+        encoded.push(len as u8);
+    } else {
+        let len_bytes = len.to_be_bytes();
+        let skip = len_bytes.iter().take_while(|&&x| x == 0).count();
+        encoded.push(0x80 | (len_bytes.len() - skip) as u8);
+        encoded.extend_from_slice(&len_bytes[skip..]);
+    }
+    encoded.extend_from_slice(content);
+    encoded
+}
+
+pub fn sequence(items: &[&[u8]]) -> Vec<u8> {
+    encode(TAG_SEQUENCE, &items.concat())
+}
+
+/// Like `sequence()`, but zeroizes the temporary concatenation of items holding secrets.
+pub fn secret_sequence(items: &[&[u8]]) -> Vec<u8> {
+    let content = Zeroizing::new(items.concat());
+    encode(TAG_SEQUENCE, &content)
+}
+
+pub fn set(items: &[&[u8]]) -> Vec<u8> {
+    encode(TAG_SET, &items.concat())
+}
+
+pub fn oid(encoded_oid: &[u8]) -> Vec<u8> {
+    encode(TAG_OID, encoded_oid)
+}
+
+pub fn bit_string(bits: &[u8]) -> Vec<u8> {
+    let mut content = vec![0x00];
+    content.extend_from_slice(bits);
+    encode(TAG_BIT_STRING, &content)
+}
+
+/// Encode a big-endian unsigned integer, as found in ECDSA signature components.
+pub fn unsigned_integer(be: &[u8]) -> Vec<u8> {
+    let skip = be.iter().take_while(|&&x| x == 0).count();
+    let mut content = vec![];
+    match be.get(skip) {
+        None => content.push(0x00),
+        Some(&x) if x & 0x80 != 0 => content.push(0x00),
+        _ => {}
+    }
+    content.extend_from_slice(&be[skip..]);
+    encode(TAG_INTEGER, &content)
+}
+
+/// Convert a fixed-size `r || s` ECDSA signature into a DER `Ecdsa-Sig-Value`.
+pub fn ecdsa_signature_from_fixed(fixed: &[u8]) -> Result<Vec<u8>, Error> {
+    let half = fixed.len() / 2;
+    ensure!(
+        half > 0 && half * 2 == fixed.len(),
+        CryptoError::InvalidSignature
+    );
+    let (r, s) = fixed.split_at(half);
+    Ok(sequence(&[&unsigned_integer(r), &unsigned_integer(s)]))
+}
+
+pub struct Decoder<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        Decoder { input }
+    }
+
+    pub fn read_any(&mut self) -> Result<(u8, &'a [u8]), Error> {
+        ensure!(self.input.len() >= 2, "Truncated DER encoding");
+        let tag = self.input[0];
+        ensure!(tag & 0x1f != 0x1f, "Unsupported DER tag");
+        let (len, header_len) = match self.input[1] {
+            len if len < 0x80 => (len as usize, 2),
+            len_len @ 0x81..=0x84 => {
+                let len_len = (len_len & 0x7f) as usize;
+                ensure!(self.input.len() >= 2 + len_len, "Truncated DER encoding");
+                let len_bytes = &self.input[2..2 + len_len];
+                ensure!(len_bytes[0] != 0, "Non-canonical DER length");
+                let len = len_bytes
+                    .iter()
+                    .fold(0usize, |len, &x| (len << 8) | x as usize);
+                ensure!(len >= 0x80, "Non-canonical DER length");
+                (len, 2 + len_len)
+            }
+            _ => bail!("Unsupported DER length"),
+        };
+        ensure!(
+            self.input.len() - header_len >= len,
+            "Truncated DER encoding"
+        );
+        let content = &self.input[header_len..header_len + len];
+        self.input = &self.input[header_len + len..];
+        Ok((tag, content))
+    }
+
+    pub fn read(&mut self, expected_tag: u8) -> Result<&'a [u8], Error> {
+        let (tag, content) = self.read_any()?;
+        ensure!(tag == expected_tag, "Unexpected DER tag");
+        Ok(content)
+    }
+
+    /// Read a non-negative integer, and return its big-endian value without leading zeros.
+    pub fn read_unsigned_integer(&mut self) -> Result<&'a [u8], Error> {
+        let content = self.read(TAG_INTEGER)?;
+        ensure!(!content.is_empty(), "Empty DER integer");
+        ensure!(content[0] & 0x80 == 0, "Negative DER integer");
+        if content[0] == 0 && content.len() > 1 {
+            ensure!(content[1] & 0x80 != 0, "Non-canonical DER integer");
+            return Ok(&content[1..]);
+        }
+        Ok(content)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    pub fn finish(&self) -> Result<(), Error> {
+        ensure!(self.input.is_empty(), "Trailing data after DER encoding");
+        Ok(())
+    }
+}
+
+/// Convert a DER `Ecdsa-Sig-Value` into a fixed-size `r || s` signature.
+pub fn ecdsa_signature_to_fixed(encoded: &[u8], coordinate_len: usize) -> Result<Vec<u8>, Error> {
+    let parse = || -> Result<_, Error> {
+        let mut decoder = Decoder::new(encoded);
+        let mut sig_value = Decoder::new(decoder.read(TAG_SEQUENCE)?);
+        decoder.finish()?;
+        let r = sig_value.read_unsigned_integer()?;
+        let s = sig_value.read_unsigned_integer()?;
+        sig_value.finish()?;
+        Ok((r, s))
+    };
+    let (r, s) = parse().map_err(|_| CryptoError::InvalidSignature)?;
+    ensure!(
+        r.len() <= coordinate_len && s.len() <= coordinate_len,
+        CryptoError::InvalidSignature
+    );
+    let mut fixed = vec![0u8; coordinate_len * 2];
+    fixed[coordinate_len - r.len()..coordinate_len].copy_from_slice(r);
+    fixed[coordinate_len * 2 - s.len()..].copy_from_slice(s);
+    Ok(fixed)
+}
+
+/// The fields of a PKCS#8 v1 (RFC 5208) or v2 (RFC 5958) private key, with the
+/// attributes left out.
+pub struct Pkcs8<'a> {
+    pub version: u8,
+    pub alg_id: &'a [u8],
+    pub private_key: &'a [u8],
+    pub public_key: Option<&'a [u8]>,
+}
+
+/// Return the content of a `BIT STRING` with no unused bits.
+fn bit_string_content(content: &[u8]) -> Result<&[u8], Error> {
+    ensure!(content.first() == Some(&0x00), "Unsupported DER bit string");
+    Ok(&content[1..])
+}
+
+pub fn parse_pkcs8(encoded: &[u8]) -> Result<Pkcs8<'_>, Error> {
+    let mut decoder = Decoder::new(encoded);
+    let mut key_info = Decoder::new(decoder.read(TAG_SEQUENCE)?);
+    decoder.finish()?;
+    let version = match key_info.read_unsigned_integer()? {
+        [0] => 0,
+        [1] => 1,
+        _ => bail!("Unsupported PKCS#8 version"),
+    };
+    let alg_id = key_info.read(TAG_SEQUENCE)?;
+    let private_key = key_info.read(TAG_OCTET_STRING)?;
+    let mut public_key = None;
+    while !key_info.is_empty() {
+        match key_info.read_any()? {
+            (TAG_CONTEXT_0, _) if public_key.is_none() => {}
+            // ring wraps the public key in an explicit tag, RFC 5958 uses an implicit one
+            (TAG_CONTEXT_1, content) if version == 1 => {
+                let mut wrapped = Decoder::new(content);
+                public_key = Some(bit_string_content(wrapped.read(TAG_BIT_STRING)?)?);
+                wrapped.finish()?;
+            }
+            (TAG_CONTEXT_PRIMITIVE_1, content) if version == 1 => {
+                public_key = Some(bit_string_content(content)?);
+            }
+            _ => bail!("Unexpected PKCS#8 field"),
+        }
+        ensure!(
+            public_key.is_none() || key_info.is_empty(),
+            "Unexpected PKCS#8 field"
+        );
+    }
+    Ok(Pkcs8 {
+        version,
+        alg_id,
+        private_key,
+        public_key,
+    })
+}
+
+/// Encode a PKCS#8 v1 private key.
+pub fn pkcs8_v1(alg_id: &[u8], private_key: &[u8]) -> Vec<u8> {
+    let private_key = Zeroizing::new(encode(TAG_OCTET_STRING, private_key));
+    secret_sequence(&[
+        &unsigned_integer(&[0]),
+        &encode(TAG_SEQUENCE, alg_id),
+        &private_key,
+    ])
+}
+
+/// Encode a PKCS#8 v2 private key, using the same layout as ring.
+pub fn pkcs8_v2(alg_id: &[u8], private_key: &[u8], public_key: &[u8]) -> Vec<u8> {
+    let private_key = Zeroizing::new(encode(TAG_OCTET_STRING, private_key));
+    secret_sequence(&[
+        &unsigned_integer(&[1]),
+        &encode(TAG_SEQUENCE, alg_id),
+        &private_key,
+        &encode(TAG_CONTEXT_1, &bit_string(public_key)),
+    ])
+}
+
+#[test]
+fn test_pkcs8_encoding_leaves_no_secret_behind() {
+    let secret = [0x42u8; 32];
+    let alg_id = oid(OID_ED25519);
+    let private_key = Zeroizing::new(encode(TAG_OCTET_STRING, &secret));
+    assert!(crate::test_support::frees_secret(secret, || drop(
+        secret.to_vec()
+    )));
+
+    let mut v1 = None;
+    assert!(!crate::test_support::frees_secret(secret, || {
+        v1 = Some(Zeroizing::new(pkcs8_v1(&alg_id, &private_key)));
+    }));
+    let mut v2 = None;
+    assert!(!crate::test_support::frees_secret(secret, || {
+        v2 = Some(Zeroizing::new(pkcs8_v2(&alg_id, &private_key, &[0x01; 32])));
+    }));
+    for (encoded, version) in [(v1.unwrap(), 0), (v2.unwrap(), 1)] {
+        let parsed = parse_pkcs8(&encoded).unwrap();
+        assert_eq!(parsed.version, version);
+        assert_eq!(parsed.private_key, &private_key[..]);
+    }
+}
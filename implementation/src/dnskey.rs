@@ -0,0 +1,162 @@
+//! Public keys distributed through the DNS. A DNSKEY record (RFC 4034) carries the key
+//! itself, in the wire format of its DNSSEC algorithm: `x || y` for ECDSA (RFC 6605), and the
+//! raw key for Ed25519 (RFC 8080). An SSHFP record (RFC 4255) only carries the fingerprint of
+//! an SSH key, so it can be checked against a key, but no key can be imported from it.
+
+use ring::digest;
+
+use super::ecdsa::*;
+use super::eddsa::*;
+use super::error::*;
+use super::signature::*;
+use super::signature_policy::*;
+use super::signature_publickey::*;
+use super::ssh;
+
+const DNSKEY_PROTOCOL: u8 = 3;
+const DNSSEC_ECDSAP256SHA256: u8 = 13;
+const DNSSEC_ECDSAP384SHA384: u8 = 14;
+const DNSSEC_ED25519: u8 = 15;
+const SSHFP_ED25519: u8 = 4;
+const SSHFP_SHA1: u8 = 1;
+const SSHFP_SHA256: u8 = 2;
+
+fn dnssec_algorithm(number: u8) -> Result<SignatureAlgorithm, Error> {
+    match number {
+        DNSSEC_ECDSAP256SHA256 => Ok(SignatureAlgorithm::ECDSA_P256_SHA256),
+        DNSSEC_ECDSAP384SHA384 => Ok(SignatureAlgorithm::ECDSA_P384_SHA384),
+        DNSSEC_ED25519 => Ok(SignatureAlgorithm::Ed25519),
+        _ => bail!(CryptoError::UnsupportedAlgorithm),
+    }
+}
+
+/// Import the public key of a DNSKEY record, given its RDATA: the flags, the protocol, the
+/// algorithm number and the key. Only the ECDSAP256SHA256 (13), ECDSAP384SHA384 (14) and
+/// ED25519 (15) algorithms are supported; other numbers return `UnsupportedAlgorithm`.
+pub fn signature_publickey_from_dnskey(rdata: &[u8]) -> Result<SignaturePublicKey, Error> {
+    ensure!(rdata.len() > 4, CryptoError::InvalidKey);
+    let (protocol, alg_number, raw_pk) = (rdata[2], rdata[3], &rdata[4..]);
+    ensure!(protocol == DNSKEY_PROTOCOL, CryptoError::InvalidKey);
+    let alg = dnssec_algorithm(alg_number)?;
+    check_algorithm_policy(alg)?;
+    let pk = match alg.category() {
+        AlgorithmCategory::ECDSA => {
+            let coordinate_len = ECDSASignatureOp::coordinate_len(alg)?;
+            ensure!(raw_pk.len() == coordinate_len * 2, CryptoError::InvalidKey);
+            let (x, y) = raw_pk.split_at(coordinate_len);
+            SignaturePublicKey::ECDSA(ECDSASignaturePublicKey::from_xy(alg, x, y)?)
+        }
+        _ => SignaturePublicKey::EdDSA(EdDSASignaturePublicKey::from_raw(alg, raw_pk)?),
+    };
+    Ok(pk)
+}
+
+/// Compute the key tag of a DNSKEY record (RFC 4034, appendix B), which identifies the key
+/// that made an RRSIG signature.
+pub fn signature_dnskey_key_tag(rdata: &[u8]) -> u16 {
+    let mut acc = rdata.iter().enumerate().fold(0u32, |acc, (i, &x)| {
+        acc + if i % 2 == 0 {
+            u32::from(x) << 8
+        } else {
+            u32::from(x)
+        }
+    });
+    acc += acc >> 16;
+    acc as u16
+}
+
+/// Check whether an SSHFP record, given its RDATA, is the fingerprint of a public key. Only
+/// Ed25519 fingerprints (algorithm 4) are supported, with SHA-1 or SHA-256.
+pub fn signature_publickey_matches_sshfp(
+    pk: &SignaturePublicKey,
+    rdata: &[u8],
+) -> Result<bool, Error> {
+    ensure!(rdata.len() > 2, CryptoError::InvalidKey);
+    let (alg_number, fingerprint_type, fingerprint) = (rdata[0], rdata[1], &rdata[2..]);
+    ensure!(
+        alg_number == SSHFP_ED25519,
+        CryptoError::UnsupportedAlgorithm
+    );
+    let digest_alg = match fingerprint_type {
+        SSHFP_SHA1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        SSHFP_SHA256 => &digest::SHA256,
+        _ => bail!(CryptoError::UnsupportedAlgorithm),
+    };
+    let raw_pk = match pk {
+        SignaturePublicKey::EdDSA(pk) if pk.alg == SignatureAlgorithm::Ed25519 => &pk.raw,
+        _ => return Ok(false),
+    };
+    let blob = ssh::ed25519_public_key_blob(raw_pk);
+    Ok(digest::digest(digest_alg, &blob).as_ref() == fingerprint)
+}
+
+#[test]
+fn test_dnskey() {
+    // RFC 8080, section 6.1: the DNSKEY of example.com, and its signature of the MX RRset
+    let rdata = [
+        &[0x01, 0x01, 0x03, 0x0f][..],
+        &base64::decode("l02Woi0iS8Aa25FQkUd9RMzZHJpBoRQwAQEX1SxZJA4=").unwrap(),
+    ]
+    .concat();
+    assert_eq!(signature_dnskey_key_tag(&rdata), 3613);
+    let pk = signature_publickey_from_dnskey(&rdata).unwrap();
+    assert_eq!(pk.alg(), SignatureAlgorithm::Ed25519);
+    // The RRSIG RDATA without the signature, followed by the RRset in canonical form
+    let signed_data = crate::signature::parse_hex(
+        "000f0f0200000e1055d4fc6055b94ce00e1d076578616d706c6503636f6d00\
+         076578616d706c6503636f6d00000f000100000e100014000a046d61696c07\
+         6578616d706c6503636f6d00",
+    )
+    .unwrap();
+    let signature = base64::decode(
+        "oL9krJun7xfBOIWcGHi7mag5/hdZrKWw15jPGrHpjQeRAvTdszaPD+QLs3fx8A4M3e23mRZ9VrbpMngwcrqNAg==",
+    )
+    .unwrap();
+    let mut state = ExclusiveSignatureVerificationState::from_publickey(pk.clone()).unwrap();
+    state.update(&signed_data).unwrap();
+    state
+        .verify_with_encoding(&signature, SignatureEncoding::Raw)
+        .unwrap();
+
+    // SSHFP fingerprints of the same key, computed with Python's `hashlib`
+    let sshfp = |fingerprint_type: u8, fingerprint: &str| {
+        [
+            &[SSHFP_ED25519, fingerprint_type][..],
+            &crate::signature::parse_hex(fingerprint).unwrap(),
+        ]
+        .concat()
+    };
+    let sha256_sshfp = sshfp(
+        SSHFP_SHA256,
+        "57dfb3d3b492a92960960ca75109893f0c9c889602f69ca214279c8bf339f1e4",
+    );
+    assert!(signature_publickey_matches_sshfp(&pk, &sha256_sshfp).unwrap());
+    let sha1_sshfp = sshfp(SSHFP_SHA1, "0434a48db7eac048f6299ea5c3dc4ea4916634a4");
+    assert!(signature_publickey_matches_sshfp(&pk, &sha1_sshfp).unwrap());
+    let mut other_sshfp = sha256_sshfp.clone();
+    other_sshfp[2] ^= 1;
+    assert!(!signature_publickey_matches_sshfp(&pk, &other_sshfp).unwrap());
+
+    // RFC 6605, section 6.1: a P-256 DNSKEY
+    let rdata = [
+        &[0x01, 0x01, 0x03, 0x0d][..],
+        &base64::decode(
+            "GojIhhXUN/u4v54ZQqGSnyhWJwaubCvTmeexv7bR6edbkrSqQpF64cYbcB7wNcP+e+MAnLr+Wi9xMWyQLc8NAA==",
+        )
+        .unwrap(),
+    ]
+    .concat();
+    let pk = signature_publickey_from_dnskey(&rdata).unwrap();
+    assert_eq!(pk.alg(), SignatureAlgorithm::ECDSA_P256_SHA256);
+    assert!(!signature_publickey_matches_sshfp(&pk, &sha256_sshfp).unwrap());
+    assert!(signature_publickey_from_dnskey(&rdata[..rdata.len() - 1]).is_err());
+
+    // RSA/SHA-256 (8) isn't supported
+    let mut rsa_rdata = rdata.clone();
+    rsa_rdata[3] = 8;
+    let err = signature_publickey_from_dnskey(&rsa_rdata).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::UnsupportedAlgorithm)
+    ));
+}
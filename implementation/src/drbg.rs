@@ -0,0 +1,284 @@
+//! HMAC_DRBG with HMAC-SHA-256, as specified in NIST SP 800-90A, section 10.1.2. It is
+//! seeded from the system RNG, and reseeded from it after a configurable number of requests.
+//! Once installed in a context, it provides the key material of the key pairs generated in
+//! that context, and the ECDSA nonces of its key pairs.
+
+use alloc::vec::Vec;
+use ring::hmac;
+use ring::rand::SecureRandom as _;
+use zeroize::Zeroizing;
+
+use super::error::*;
+use super::sync::Mutex;
+
+const OUTLEN: usize = 32;
+const ENTROPY_LEN: usize = 32;
+const NONCE_LEN: usize = 16;
+// SP 800-90A, table 2
+const MAX_RESEED_INTERVAL: u64 = 1 << 48;
+const MAX_REQUEST_LEN: usize = 1 << 16;
+
+struct DrbgState {
+    key: Zeroizing<[u8; OUTLEN]>,
+    v: Zeroizing<[u8; OUTLEN]>,
+    reseed_counter: u64,
+}
+
+impl DrbgState {
+    fn hmac(key: &[u8], parts: &[&[u8]]) -> [u8; OUTLEN] {
+        let mut ctx = hmac::Context::with_key(&hmac::Key::new(hmac::HMAC_SHA256, key));
+        for part in parts {
+            ctx.update(part);
+        }
+        let mut out = [0u8; OUTLEN];
+        out.copy_from_slice(ctx.sign().as_ref());
+        out
+    }
+
+    fn update(&mut self, provided_data: &[u8]) {
+        *self.key = Self::hmac(&*self.key, &[&*self.v, &[0x00], provided_data]);
+        *self.v = Self::hmac(&*self.key, &[&*self.v]);
+        if !provided_data.is_empty() {
+            *self.key = Self::hmac(&*self.key, &[&*self.v, &[0x01], provided_data]);
+            *self.v = Self::hmac(&*self.key, &[&*self.v]);
+        }
+    }
+
+    fn instantiate(seed_material: &[u8]) -> Self {
+        let mut state = DrbgState {
+            key: Zeroizing::new([0x00; OUTLEN]),
+            v: Zeroizing::new([0x01; OUTLEN]),
+            reseed_counter: 1,
+        };
+        state.update(seed_material);
+        state
+    }
+
+    fn reseed(&mut self, entropy_input: &[u8]) {
+        self.update(entropy_input);
+        self.reseed_counter = 1;
+    }
+
+    fn generate(&mut self, out: &mut [u8]) {
+        for chunk in out.chunks_mut(OUTLEN) {
+            *self.v = Self::hmac(&*self.key, &[&*self.v]);
+            chunk.copy_from_slice(&self.v[..chunk.len()]);
+        }
+        self.update(&[]);
+        self.reseed_counter += 1;
+    }
+}
+
+fn system_entropy(len: usize) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let mut entropy = Zeroizing::new(vec![0u8; len]);
+    ring::rand::SystemRandom::new()
+        .fill(&mut entropy)
+        .map_err(|_| CryptoError::RNGError)?;
+    Ok(entropy)
+}
+
+/// A thread-safe HMAC_DRBG instance, shared by the key pairs of a context.
+pub struct Drbg {
+    state: Mutex<DrbgState>,
+    reseed_interval: u64,
+}
+
+impl core::fmt::Debug for Drbg {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Drbg")
+            .field("reseed_interval", &self.reseed_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drbg {
+    /// Instantiate a DRBG seeded from the system RNG, that reseeds itself after
+    /// `reseed_interval` requests. The interval must be between 1 and 2^48.
+    pub fn new(reseed_interval: u64) -> Result<Self, Error> {
+        let seed_material = system_entropy(ENTROPY_LEN + NONCE_LEN)?;
+        Self::from_seed_material(&seed_material, reseed_interval)
+    }
+
+    fn from_seed_material(seed_material: &[u8], reseed_interval: u64) -> Result<Self, Error> {
+        ensure!(
+            reseed_interval > 0 && reseed_interval <= MAX_RESEED_INTERVAL,
+            CryptoError::Overflow
+        );
+        Ok(Drbg {
+            state: Mutex::new(DrbgState::instantiate(seed_material)),
+            reseed_interval,
+        })
+    }
+
+    /// Fill `out` with random bytes, in as many requests as needed to stay below the maximum
+    /// request size.
+    pub fn fill(&self, out: &mut [u8]) -> Result<(), Error> {
+        let mut state = self.state.lock();
+        for chunk in out.chunks_mut(MAX_REQUEST_LEN) {
+            if state.reseed_counter > self.reseed_interval {
+                state.reseed(&system_entropy(ENTROPY_LEN)?);
+            }
+            state.generate(chunk);
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn reseed_counter(&self) -> u64 {
+        self.state.lock().reseed_counter
+    }
+}
+
+/// Adapter for the RustCrypto APIs, which expect an infallible `RngCore`. It panics if the
+/// DRBG can't be reseeded, which only happens if the system RNG fails.
+#[cfg(feature = "drbg")]
+pub(crate) struct DrbgRng<'a>(pub &'a Drbg);
+
+#[cfg(feature = "drbg")]
+impl p256::elliptic_curve::rand_core::RngCore for DrbgRng<'_> {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill(dest).expect("DRBG reseeding failed");
+    }
+
+    fn try_fill_bytes(
+        &mut self,
+        dest: &mut [u8],
+    ) -> Result<(), p256::elliptic_curve::rand_core::Error> {
+        self.0.fill(dest).map_err(|_| {
+            core::num::NonZeroU32::new(p256::elliptic_curve::rand_core::Error::CUSTOM_START)
+                .unwrap()
+                .into()
+        })
+    }
+}
+
+#[cfg(feature = "drbg")]
+impl p256::elliptic_curve::rand_core::CryptoRng for DrbgRng<'_> {}
+
+#[cfg(test)]
+pub(crate) fn test_drbg(reseed_interval: u64) -> Drbg {
+    Drbg::from_seed_material(&[0x42; ENTROPY_LEN + NONCE_LEN], reseed_interval).unwrap()
+}
+
+#[test]
+fn test_hmac_drbg_vector() {
+    // NIST CAVP HMAC_DRBG.rsp, SHA-256, no prediction resistance, COUNT = 0
+    let seed_material = crate::signature::parse_hex(
+        "ca851911349384bffe89de1cbdc46e6831e44d34a4fb935ee285dd14b71a7488\
+         659ba96c601dc69fc902940805ec0ca8",
+    )
+    .unwrap();
+    let drbg = Drbg::from_seed_material(&seed_material, MAX_RESEED_INTERVAL).unwrap();
+    let mut returned_bits = [0u8; 128];
+    drbg.fill(&mut returned_bits).unwrap();
+    drbg.fill(&mut returned_bits).unwrap();
+    assert_eq!(
+        returned_bits.to_vec(),
+        crate::signature::parse_hex(
+            "e528e9abf2dece54d47c7e75e5fe302149f817ea9fb4bee6f4199697d04d5b89\
+             d54fbb978a15b5c443c9ec21036d2460b6f73ebad0dc2aba6e624abf07745bc1\
+             07694bb7547bb0995f70de25d6b29e2d3011bb19d27676c07162c8b5ccde0668\
+             961df86803482cb37ed6d5c0bb8d50cf1f50d476aa0458bdaba806f48be9dcb8"
+        )
+        .unwrap()
+    );
+
+    let drbg = test_drbg(2);
+    let mut block = [0u8; 32];
+    for _ in 0..3 {
+        drbg.fill(&mut block).unwrap();
+    }
+    assert_eq!(drbg.reseed_counter(), 2);
+    assert!(Drbg::new(0).is_err());
+    assert!(Drbg::new(MAX_RESEED_INTERVAL + 1).is_err());
+}
+
+#[test]
+fn test_drbg_key_generation() {
+    use alloc::sync::Arc;
+
+    let generate = |ctx: &crate::CryptoContext, alg_str| {
+        let op_handle = ctx.signature_op_open(alg_str).unwrap();
+        let kp_builder_handle = ctx.signature_keypair_builder_open(op_handle).unwrap();
+        ctx.signature_keypair_generate(kp_builder_handle)
+    };
+    let export = |ctx: &crate::CryptoContext, kp_handle| {
+        ctx.signature_keypair_export(kp_handle, crate::KeyPairEncoding::PKCS8)
+            .unwrap()
+    };
+    let (ctx_a, ctx_b) = (
+        crate::CryptoContext::new().unwrap(),
+        crate::CryptoContext::new().unwrap(),
+    );
+    let (drbg_a, drbg_b) = (Arc::new(test_drbg(1000)), Arc::new(test_drbg(1000)));
+    ctx_a
+        .signature_keypair_manager
+        .set_drbg(Some(drbg_a.clone()));
+    ctx_b.signature_keypair_manager.set_drbg(Some(drbg_b));
+
+    // Contexts whose DRBGs have the same seed generate the same key pairs
+    let kp_a = generate(&ctx_a, "Ed25519").unwrap();
+    assert_eq!(drbg_a.reseed_counter(), 2);
+    let kp_b = generate(&ctx_b, "Ed25519").unwrap();
+    assert_eq!(export(&ctx_a, kp_a), export(&ctx_b, kp_b));
+    let ctx = crate::CryptoContext::new().unwrap();
+    let kp = generate(&ctx, "Ed25519").unwrap();
+    assert_ne!(export(&ctx, kp), export(&ctx_a, kp_a));
+
+    let kp_a = generate(&ctx_a, "ECDSA_P256_SHA256");
+    if !cfg!(feature = "drbg") {
+        assert!(matches!(
+            kp_a.unwrap_err().downcast_ref::<CryptoError>(),
+            Some(CryptoError::NotAvailable)
+        ));
+        return;
+    }
+    let kp_a = kp_a.unwrap();
+    let kp_b = generate(&ctx_b, "ECDSA_P256_SHA256").unwrap();
+    assert_eq!(export(&ctx_a, kp_a), export(&ctx_b, kp_b));
+
+    // Nonces are drawn from the DRBG as well
+    let kp = ctx_a.signature_keypair_manager.get(kp_a).unwrap();
+    let requests = drbg_a.reseed_counter();
+    let signature = kp.sign_oneshot(b"test").unwrap();
+    assert!(drbg_a.reseed_counter() > requests);
+    let raw_pk = kp.raw_public_key().to_vec();
+    crate::ECDSASignaturePublicKey::from_raw(crate::SignatureAlgorithm::ECDSA_P256_SHA256, &raw_pk)
+        .unwrap()
+        .verify(b"test", signature.as_ref())
+        .unwrap();
+}
+
+#[test]
+fn test_install_drbg() {
+    let ctx = crate::CryptoContext::new().unwrap();
+    let res = ctx.signature_install_drbg(1000);
+    if cfg!(feature = "drbg") {
+        res.unwrap();
+    } else {
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<CryptoError>(),
+            Some(CryptoError::NotAvailable)
+        ));
+    }
+
+    // ECDSA key pairs can be generated and used either way
+    let op_handle = ctx.signature_op_open("ECDSA_P256_SHA256").unwrap();
+    let kp_builder_handle = ctx.signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = ctx.signature_keypair_generate(kp_builder_handle).unwrap();
+    let state_handle = ctx.signature_state_open(kp_handle).unwrap();
+    ctx.signature_state_update(state_handle, b"test").unwrap();
+    ctx.signature_state_sign(state_handle).unwrap();
+}
@@ -1,36 +1,77 @@
-use parking_lot::Mutex;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
 use ring::signature::KeyPair as _;
-use std::sync::Arc;
-use zeroize::Zeroize;
+use zeroize::Zeroizing;
 
+use super::der;
+use super::drbg::*;
 use super::error::*;
+use super::framing;
 use super::handles::*;
 use super::signature::*;
 use super::signature_keypair::*;
-use super::WASI_CRYPTO_CTX;
+use super::signature_op::*;
+use super::sync::Mutex;
+use super::CryptoContext;
 
 #[derive(Clone, Copy, Debug)]
 pub struct ECDSASignatureOp {
     pub alg: SignatureAlgorithm,
+    pub rng_fallback: bool,
+    pub prehashed_only: bool,
+    pub non_exportable: bool,
+    pub max_signatures: Option<u64>,
 }
 
 impl ECDSASignatureOp {
-    pub fn new(alg: SignatureAlgorithm) -> Self {
-        ECDSASignatureOp { alg }
+    pub fn with_options(
+        alg: SignatureAlgorithm,
+        options: &SignatureOptions,
+    ) -> Result<Self, Error> {
+        ensure!(
+            !options.rng_fallback || cfg!(feature = "deterministic"),
+            CryptoError::NotAvailable
+        );
+        ensure!(
+            !options.prehashed_only || cfg!(feature = "prehash"),
+            CryptoError::NotAvailable
+        );
+        let op = ECDSASignatureOp {
+            alg,
+            rng_fallback: options.rng_fallback,
+            prehashed_only: options.prehashed_only,
+            non_exportable: options.non_exportable,
+            max_signatures: options.max_signatures,
+        };
+        Ok(op)
+    }
+
+    pub fn coordinate_len(alg: SignatureAlgorithm) -> Result<usize, Error> {
+        let coordinate_len = match alg {
+            SignatureAlgorithm::ECDSA_P256_SHA256 => 32,
+            SignatureAlgorithm::ECDSA_P384_SHA384 => 48,
+            _ => bail!(CryptoError::NotAvailable),
+        };
+        Ok(coordinate_len)
     }
 }
 
+/// Clones share the PKCS#8 document and the parsed ring key. Both are released together when
+/// the last clone is dropped, and the document is zeroized at that point. Clones also share
+/// the signature counter, and its store if it is persisted.
 #[derive(Debug, Clone)]
 pub struct ECDSASignatureKeyPair {
     pub alg: SignatureAlgorithm,
-    pub pkcs8: Vec<u8>,
+    pub pkcs8: Arc<Zeroizing<Vec<u8>>>,
     pub ring_kp: Arc<ring::signature::EcdsaKeyPair>,
-}
-
-impl Drop for ECDSASignatureKeyPair {
-    fn drop(&mut self) {
-        self.pkcs8.zeroize();
-    }
+    pub rng_fallback: bool,
+    pub prehashed_only: bool,
+    pub non_exportable: bool,
+    pub max_signatures: Option<u64>,
+    pub sign_count: Arc<AtomicU64>,
+    pub counter_store: Option<Arc<Mutex<dyn CounterStore>>>,
+    pub drbg: Option<Arc<Drbg>>,
 }
 
 impl ECDSASignatureKeyPair {
@@ -49,19 +90,83 @@ impl ECDSASignatureKeyPair {
         Ok(ring_alg)
     }
 
+    /// Convert a PKCS#8 v2 document into the v1 form expected by ring, moving the public key
+    /// into the `ECPrivateKey` structure if it is not there already.
+    fn pkcs8_to_v1(pkcs8: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let parsed = der::parse_pkcs8(pkcs8)?;
+        let public_key = match (parsed.version, parsed.public_key) {
+            (0, _) => return Ok(Zeroizing::new(pkcs8.to_vec())),
+            (_, Some(public_key)) => public_key,
+            _ => bail!("Missing public key"),
+        };
+        let mut decoder = der::Decoder::new(parsed.private_key);
+        let mut ec_private_key = der::Decoder::new(decoder.read(der::TAG_SEQUENCE)?);
+        decoder.finish()?;
+        let mut fields = vec![
+            Zeroizing::new(der::encode(
+                der::TAG_INTEGER,
+                ec_private_key.read(der::TAG_INTEGER)?,
+            )),
+            Zeroizing::new(der::encode(
+                der::TAG_OCTET_STRING,
+                ec_private_key.read(der::TAG_OCTET_STRING)?,
+            )),
+        ];
+        let mut has_public_key = false;
+        while !ec_private_key.is_empty() {
+            let (tag, content) = ec_private_key.read_any()?;
+            has_public_key |= tag == der::TAG_CONTEXT_1;
+            fields.push(Zeroizing::new(der::encode(tag, content)));
+        }
+        if !has_public_key {
+            fields.push(Zeroizing::new(der::encode(
+                der::TAG_CONTEXT_1,
+                &der::bit_string(public_key),
+            )));
+        }
+        let fields: Vec<&[u8]> = fields.iter().map(|field| field.as_slice()).collect();
+        let ec_private_key = Zeroizing::new(der::secret_sequence(&fields));
+        Ok(Zeroizing::new(der::pkcs8_v1(
+            parsed.alg_id,
+            &ec_private_key,
+        )))
+    }
+
+    /// ring only accepts PKCS#8 v1 keys embedding the public key in their `ECPrivateKey`
+    /// structure, which is also what it generates. v2 keys are converted to that form, so
+    /// exported key pairs are always PKCS#8 v1.
     pub fn from_pkcs8(alg: SignatureAlgorithm, pkcs8: &[u8]) -> Result<Self, Error> {
         let ring_alg = Self::ring_alg_from_alg(alg)?;
-        let ring_kp = ring::signature::EcdsaKeyPair::from_pkcs8(ring_alg, pkcs8)
+        let v2_public_key = der::parse_pkcs8(pkcs8)
+            .map_err(|_| CryptoError::InvalidKey)?
+            .public_key
+            .map(|public_key| public_key.to_vec());
+        let pkcs8 = Self::pkcs8_to_v1(pkcs8).map_err(|_| CryptoError::InvalidKey)?;
+        let ring_kp = ring::signature::EcdsaKeyPair::from_pkcs8(ring_alg, &pkcs8)
             .map_err(|_| CryptoError::InvalidKey)?;
+        if let Some(v2_public_key) = v2_public_key {
+            ensure!(
+                v2_public_key == ring_kp.public_key().as_ref(),
+                CryptoError::InvalidKey
+            );
+        }
         let kp = ECDSASignatureKeyPair {
             alg,
-            pkcs8: pkcs8.to_vec(),
+            pkcs8: Arc::new(pkcs8),
             ring_kp: Arc::new(ring_kp),
+            rng_fallback: false,
+            prehashed_only: false,
+            non_exportable: false,
+            max_signatures: None,
+            sign_count: Arc::new(AtomicU64::new(0)),
+            counter_store: None,
+            drbg: None,
         };
         Ok(kp)
     }
 
     pub fn as_pkcs8(&self) -> Result<&[u8], Error> {
+        ensure!(!self.non_exportable, CryptoError::NotAvailable);
         Ok(&self.pkcs8)
     }
 
@@ -76,33 +181,332 @@ impl ECDSASignatureKeyPair {
     pub fn raw_public_key(&self) -> &[u8] {
         self.ring_kp.public_key().as_ref()
     }
+
+    /// The number of signatures this key pair has produced since it was created or imported.
+    pub fn sign_count(&self) -> u64 {
+        self.sign_count.load(Ordering::Relaxed)
+    }
+
+    /// Persist the signature counter in `counter_store`, starting from the count it holds.
+    pub fn with_counter_store(
+        mut self,
+        counter_store: Arc<Mutex<dyn CounterStore>>,
+    ) -> Result<Self, Error> {
+        let count = counter_store.lock().load()?;
+        self.sign_count = Arc::new(AtomicU64::new(count));
+        self.counter_store = Some(counter_store);
+        Ok(self)
+    }
+
+    // Reserve a signature, and fail with `NotAvailable` once `max_signatures` were produced,
+    // so that long-lived keys get rotated. A persisted count is reloaded from the store, in
+    // case another import of the key pair shares it, and stored before the signature is
+    // computed, so that a crash can skip a count but never reuse one.
+    fn count_signature(&self) -> Result<(), Error> {
+        let max_signatures = self.max_signatures.unwrap_or(u64::MAX);
+        if let Some(counter_store) = &self.counter_store {
+            let mut counter_store = counter_store.lock();
+            let count = counter_store.load()?;
+            ensure!(count < max_signatures, CryptoError::NotAvailable);
+            counter_store.store(count + 1)?;
+            self.sign_count.store(count + 1, Ordering::Relaxed);
+            return Ok(());
+        }
+        self.sign_count
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                if count < max_signatures {
+                    Some(count + 1)
+                } else {
+                    None
+                }
+            })
+            .map_err(|_| CryptoError::NotAvailable)?;
+        Ok(())
+    }
+
+    // ring only fails to compute an ECDSA signature if the RNG fails
+    fn ring_sign(
+        &self,
+        input: &[u8],
+    ) -> Result<ring::signature::Signature, ring::error::Unspecified> {
+        #[cfg(test)]
+        {
+            if SIMULATED_RNG_FAILURE.with(|failure| failure.get()) {
+                return Err(ring::error::Unspecified);
+            }
+        }
+        let rng = ring::rand::SystemRandom::new();
+        self.ring_kp.sign(&rng, input)
+    }
+
+    // With ring, the nonce and the private scalar never leave ring. The secret buffers owned
+    // by this crate, the PKCS#8 document and the fields it is rebuilt from, are all wrapped in
+    // `Zeroizing`. The RustCrypto paths rely on the `SigningKey`, the `SecretKey` and the
+    // nonce being zeroized on drop by the `ecdsa` and `elliptic-curve` crates. This is
+    // checked by `test_signing_leaves_no_secret_behind()`.
+    #[cfg(feature = "deterministic")]
+    fn sign_deterministic(&self, input: &[u8]) -> Result<Vec<u8>, Error> {
+        use p256::ecdsa::signature::Signer as _;
+        use p256::pkcs8::DecodePrivateKey as _;
+
+        let encoded_signature = match self.alg {
+            SignatureAlgorithm::ECDSA_P256_SHA256 => {
+                let sk = p256::ecdsa::SigningKey::from_pkcs8_der(&self.pkcs8)
+                    .map_err(|_| CryptoError::InvalidKey)?;
+                let signature: p256::ecdsa::Signature = sk
+                    .try_sign(input)
+                    .map_err(|_| CryptoError::AlgorithmFailure)?;
+                signature.to_bytes().to_vec()
+            }
+            SignatureAlgorithm::ECDSA_P384_SHA384 => {
+                let sk = p384::ecdsa::SigningKey::from_pkcs8_der(&self.pkcs8)
+                    .map_err(|_| CryptoError::InvalidKey)?;
+                let signature: p384::ecdsa::Signature = sk
+                    .try_sign(input)
+                    .map_err(|_| CryptoError::AlgorithmFailure)?;
+                signature.to_bytes().to_vec()
+            }
+            _ => bail!(CryptoError::NotAvailable),
+        };
+        Ok(encoded_signature)
+    }
+
+    #[cfg(not(feature = "deterministic"))]
+    fn sign_deterministic(&self, _input: &[u8]) -> Result<Vec<u8>, Error> {
+        bail!(CryptoError::NotAvailable)
+    }
+
+    /// Sign a digest of the message computed by the caller, with the hash function of the
+    /// algorithm. Nonces are derived as specified in RFC 6979.
+    #[cfg(feature = "prehash")]
+    pub fn sign_digest(&self, digest: &[u8]) -> Result<ECDSASignature, Error> {
+        use p256::ecdsa::signature::hazmat::PrehashSigner as _;
+        use p256::pkcs8::DecodePrivateKey as _;
+
+        ensure!(
+            digest.len() == self.alg.digest_alg().output_len,
+            CryptoError::InvalidLength
+        );
+        self.count_signature()?;
+        let encoded_signature = match self.alg {
+            SignatureAlgorithm::ECDSA_P256_SHA256 => {
+                let sk = p256::ecdsa::SigningKey::from_pkcs8_der(&self.pkcs8)
+                    .map_err(|_| CryptoError::InvalidKey)?;
+                let signature: p256::ecdsa::Signature = sk
+                    .sign_prehash(digest)
+                    .map_err(|_| CryptoError::AlgorithmFailure)?;
+                signature.to_bytes().to_vec()
+            }
+            SignatureAlgorithm::ECDSA_P384_SHA384 => {
+                let sk = p384::ecdsa::SigningKey::from_pkcs8_der(&self.pkcs8)
+                    .map_err(|_| CryptoError::InvalidKey)?;
+                let signature: p384::ecdsa::Signature = sk
+                    .sign_prehash(digest)
+                    .map_err(|_| CryptoError::AlgorithmFailure)?;
+                signature.to_bytes().to_vec()
+            }
+            _ => bail!(CryptoError::NotAvailable),
+        };
+        Ok(ECDSASignature::new(
+            SignatureEncoding::Raw,
+            encoded_signature,
+        ))
+    }
+
+    #[cfg(not(feature = "prehash"))]
+    pub fn sign_digest(&self, _digest: &[u8]) -> Result<ECDSASignature, Error> {
+        bail!(CryptoError::NotAvailable)
+    }
+
+    /// Sign a digest computed with a hash function that differs from the one of the
+    /// algorithm, such as a SHA-512 digest with P-256. As specified in FIPS 186-4, section
+    /// 6.4, only its left-most bits are used, as many as the size of the group order, which
+    /// is the size of the field for P-256 and P-384. The digest cannot be shorter than that.
+    /// Verifiers must truncate the digest the same way. Requires the `prehash` feature.
+    pub fn sign_truncated_digest(&self, digest: &[u8]) -> Result<ECDSASignature, Error> {
+        let coordinate_len = ECDSASignatureOp::coordinate_len(self.alg)?;
+        ensure!(digest.len() >= coordinate_len, CryptoError::InvalidLength);
+        self.sign_digest(&digest[..coordinate_len])
+    }
+
+    /// Sign with the RustCrypto implementation, drawing the nonce from `rng`.
+    #[cfg(any(test, feature = "drbg"))]
+    fn sign_with_rng(
+        &self,
+        rng: &mut impl p256::elliptic_curve::rand_core::CryptoRngCore,
+        input: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        use p256::ecdsa::signature::RandomizedSigner as _;
+        use p256::pkcs8::DecodePrivateKey as _;
+
+        let encoded_signature = match self.alg {
+            SignatureAlgorithm::ECDSA_P256_SHA256 => {
+                let sk = p256::ecdsa::SigningKey::from_pkcs8_der(&self.pkcs8)
+                    .map_err(|_| CryptoError::InvalidKey)?;
+                let signature: p256::ecdsa::Signature = sk
+                    .try_sign_with_rng(rng, input)
+                    .map_err(|_| CryptoError::AlgorithmFailure)?;
+                signature.to_bytes().to_vec()
+            }
+            SignatureAlgorithm::ECDSA_P384_SHA384 => {
+                let sk = p384::ecdsa::SigningKey::from_pkcs8_der(&self.pkcs8)
+                    .map_err(|_| CryptoError::InvalidKey)?;
+                let signature: p384::ecdsa::Signature = sk
+                    .try_sign_with_rng(rng, input)
+                    .map_err(|_| CryptoError::AlgorithmFailure)?;
+                signature.to_bytes().to_vec()
+            }
+            _ => bail!(CryptoError::NotAvailable),
+        };
+        Ok(encoded_signature)
+    }
+
+    /// Sign with the RustCrypto implementation, seeding the nonce from the test RNG, if one
+    /// was installed with `deterministic_test_rng()`.
+    #[cfg(test)]
+    fn sign_with_test_rng(&self, input: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        DETERMINISTIC_TEST_RNG.with(|rng| match rng.borrow_mut().as_mut() {
+            None => Ok(None),
+            Some(rng) => self.sign_with_rng(rng, input).map(Some),
+        })
+    }
+
+    #[cfg(feature = "drbg")]
+    fn sign_with_drbg(&self, drbg: &Drbg, input: &[u8]) -> Result<Vec<u8>, Error> {
+        self.sign_with_rng(&mut DrbgRng(drbg), input)
+    }
+
+    #[cfg(not(feature = "drbg"))]
+    fn sign_with_drbg(&self, _drbg: &Drbg, _input: &[u8]) -> Result<Vec<u8>, Error> {
+        bail!(CryptoError::NotAvailable)
+    }
+
+    // Draw the nonce from the DRBG of the context if one is installed, and from the system
+    // RNG otherwise
+    fn sign_randomized(&self, input: &[u8]) -> Result<Vec<u8>, Error> {
+        if let Some(drbg) = &self.drbg {
+            return self.sign_with_drbg(drbg, input);
+        }
+        match self.ring_sign(input) {
+            Ok(signature) => Ok(signature.as_ref().to_vec()),
+            Err(_) if self.rng_fallback => self.sign_deterministic(input),
+            Err(_) => bail!(CryptoError::RNGError),
+        }
+    }
+
+    /// Sign a complete message, without the buffering and locking of a signature state.
+    /// Prehashed-only key pairs can only sign digests, and return `NotAvailable`.
+    pub fn sign_oneshot(&self, input: &[u8]) -> Result<ECDSASignature, Error> {
+        ensure!(!self.prehashed_only, CryptoError::NotAvailable);
+        self.count_signature()?;
+        #[cfg(test)]
+        {
+            if let Some(encoded_signature) = self.sign_with_test_rng(input)? {
+                return Ok(ECDSASignature::new(
+                    SignatureEncoding::Raw,
+                    encoded_signature,
+                ));
+            }
+        }
+        let encoded_signature = self.sign_randomized(input)?;
+        let signature = ECDSASignature::new(SignatureEncoding::Raw, encoded_signature);
+        Ok(signature)
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
 pub struct ECDSASignatureKeyPairBuilder {
     pub alg: SignatureAlgorithm,
+    pub rng_fallback: bool,
+    pub prehashed_only: bool,
+    pub non_exportable: bool,
+    pub max_signatures: Option<u64>,
 }
 
 impl ECDSASignatureKeyPairBuilder {
     pub fn new(alg: SignatureAlgorithm) -> Self {
-        ECDSASignatureKeyPairBuilder { alg }
+        ECDSASignatureKeyPairBuilder {
+            alg,
+            rng_fallback: false,
+            prehashed_only: false,
+            non_exportable: false,
+            max_signatures: None,
+        }
     }
 
-    pub fn generate(&self) -> Result<Handle, Error> {
-        let kp = ECDSASignatureKeyPair::generate(self.alg)?;
-        let handle = WASI_CRYPTO_CTX
-            .signature_keypair_manager
-            .register(SignatureKeyPair::ECDSA(kp))?;
+    pub fn from_op(op: ECDSASignatureOp) -> Self {
+        ECDSASignatureKeyPairBuilder {
+            alg: op.alg,
+            rng_fallback: op.rng_fallback,
+            prehashed_only: op.prehashed_only,
+            non_exportable: op.non_exportable,
+            max_signatures: op.max_signatures,
+        }
+    }
+
+    /// Generate a key pair owned by the caller, instead of registering it in the context.
+    pub fn generate_owned(&self) -> Result<ECDSASignatureKeyPair, Error> {
+        let mut kp = ECDSASignatureKeyPair::generate(self.alg)?;
+        kp.rng_fallback = self.rng_fallback;
+        kp.prehashed_only = self.prehashed_only;
+        kp.non_exportable = self.non_exportable;
+        kp.max_signatures = self.max_signatures;
+        Ok(kp)
+    }
+
+    /// Generate a key pair whose private scalar is drawn from `drbg`. Requires the `drbg`
+    /// feature.
+    #[cfg(feature = "drbg")]
+    pub fn generate_with_drbg(&self, drbg: &Drbg) -> Result<ECDSASignatureKeyPair, Error> {
+        use p256::pkcs8::EncodePrivateKey as _;
+
+        let pkcs8 = match self.alg {
+            SignatureAlgorithm::ECDSA_P256_SHA256 => {
+                p256::SecretKey::random(&mut DrbgRng(drbg)).to_pkcs8_der()
+            }
+            SignatureAlgorithm::ECDSA_P384_SHA384 => {
+                p384::SecretKey::random(&mut DrbgRng(drbg)).to_pkcs8_der()
+            }
+            _ => bail!(CryptoError::NotAvailable),
+        }
+        .map_err(|_| CryptoError::AlgorithmFailure)?;
+        self.import_owned(pkcs8.as_bytes())
+    }
+
+    #[cfg(not(feature = "drbg"))]
+    pub fn generate_with_drbg(&self, _drbg: &Drbg) -> Result<ECDSASignatureKeyPair, Error> {
+        bail!(CryptoError::NotAvailable)
+    }
+
+    pub fn generate(&self, ctx: &CryptoContext) -> Result<Handle, Error> {
+        let manager = &ctx.signature_keypair_manager;
+        let kp = manager.generate(&SignatureKeyPairBuilder::ECDSA(*self))?;
+        let handle = manager.register(kp)?;
         Ok(handle)
     }
 
-    pub fn import(&self, encoded: &[u8], encoding: KeyPairEncoding) -> Result<Handle, Error> {
+    /// Import a PKCS#8 key pair owned by the caller, instead of registering it in the context.
+    pub fn import_owned(&self, pkcs8: &[u8]) -> Result<ECDSASignatureKeyPair, Error> {
+        let mut kp = ECDSASignatureKeyPair::from_pkcs8(self.alg, pkcs8)?;
+        kp.rng_fallback = self.rng_fallback;
+        kp.prehashed_only = self.prehashed_only;
+        kp.non_exportable = self.non_exportable;
+        kp.max_signatures = self.max_signatures;
+        Ok(kp)
+    }
+
+    pub fn import(
+        &self,
+        ctx: &CryptoContext,
+        encoded: &[u8],
+        encoding: KeyPairEncoding,
+    ) -> Result<Handle, Error> {
         match encoding {
             KeyPairEncoding::PKCS8 => {}
             _ => bail!(CryptoError::NotAvailable),
         };
-        let kp = ECDSASignatureKeyPair::from_pkcs8(self.alg, encoded)?;
-        let handle = WASI_CRYPTO_CTX
+        let kp = self.import_owned(encoded)?;
+        let handle = ctx
             .signature_keypair_manager
             .register(SignatureKeyPair::ECDSA(kp))?;
         Ok(handle)
@@ -127,10 +531,200 @@ impl AsRef<[u8]> for ECDSASignature {
     }
 }
 
+/// Lowercase hex of the encoded signature, whatever its encoding.
+impl core::fmt::Display for ECDSASignature {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        fmt_hex(&self.encoded, f)
+    }
+}
+
+/// Parse a hex-encoded signature. Since the algorithm is not known, it is `DER` if
+/// `detect_encoding()` detects `DER` for one of the curves, and `Raw` for none of them; it is
+/// `Raw` otherwise. The length is checked when the signature is used with an algorithm.
+impl core::str::FromStr for ECDSASignature {
+    type Err = Error;
+
+    fn from_str(hex: &str) -> Result<Self, Error> {
+        let encoded = parse_hex(hex)?;
+        let detected: Vec<_> = [
+            SignatureAlgorithm::ECDSA_P256_SHA256,
+            SignatureAlgorithm::ECDSA_P384_SHA384,
+        ]
+        .iter()
+        .filter_map(|&alg| ECDSASignature::detect_encoding(alg, &encoded).ok())
+        .collect();
+        let encoding = if detected.contains(&SignatureEncoding::DER)
+            && !detected.contains(&SignatureEncoding::Raw)
+        {
+            SignatureEncoding::DER
+        } else {
+            SignatureEncoding::Raw
+        };
+        Ok(ECDSASignature::new(encoding, encoded))
+    }
+}
+
+// Subtract big-endian integers of the same size, and return `a - b` with the final borrow,
+// which is 1 if `a < b`. The running time only depends on the size of the integers.
+pub(crate) fn ct_sub(a: &[u8], b: &[u8]) -> (Vec<u8>, u8) {
+    debug_assert_eq!(a.len(), b.len());
+    let mut difference = vec![0u8; a.len()];
+    let mut borrow = 0u16;
+    for ((out, &x), &y) in difference.iter_mut().zip(a).zip(b).rev() {
+        let digit = u16::from(x).wrapping_sub(u16::from(y)).wrapping_sub(borrow);
+        *out = digit as u8;
+        borrow = (digit >> 8) & 1;
+    }
+    (difference, borrow as u8)
+}
+
+// Replace `a` with `b` if `choice` is 1, and leave it as it is if `choice` is 0, without
+// branching on `choice`
+fn ct_select(a: &mut [u8], b: &[u8], choice: u8) {
+    let mask = 0u8.wrapping_sub(choice);
+    for (x, &y) in a.iter_mut().zip(b) {
+        *x ^= mask & (*x ^ y);
+    }
+}
+
 impl ECDSASignature {
     pub fn new(encoding: SignatureEncoding, encoded: Vec<u8>) -> Self {
         ECDSASignature { encoding, encoded }
     }
+
+    pub(crate) fn curve_order(alg: SignatureAlgorithm) -> Result<&'static [u8], Error> {
+        let order: &[u8] = match alg {
+            SignatureAlgorithm::ECDSA_P256_SHA256 => &[
+                0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                0xff, 0xff, 0xbc, 0xe6, 0xfa, 0xad, 0xa7, 0x17, 0x9e, 0x84, 0xf3, 0xb9, 0xca, 0xc2,
+                0xfc, 0x63, 0x25, 0x51,
+            ],
+            SignatureAlgorithm::ECDSA_P384_SHA384 => &[
+                0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xc7, 0x63, 0x4d, 0x81,
+                0xf4, 0x37, 0x2d, 0xdf, 0x58, 0x1a, 0x0d, 0xb2, 0x48, 0xb0, 0xa7, 0x7a, 0xec, 0xec,
+                0x19, 0x6a, 0xcc, 0xc5, 0x29, 0x73,
+            ],
+            _ => bail!(CryptoError::NotAvailable),
+        };
+        Ok(order)
+    }
+
+    /// Return the `r` and `s` components as big-endian integers, left-padded with zeros
+    /// to the size of the curve's field.
+    pub fn components(&self, alg: SignatureAlgorithm) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let coordinate_len = ECDSASignatureOp::coordinate_len(alg)?;
+        let fixed = match self.encoding {
+            SignatureEncoding::Raw => {
+                ensure!(
+                    self.encoded.len() == coordinate_len * 2,
+                    CryptoError::InvalidSignature
+                );
+                self.encoded.clone()
+            }
+            SignatureEncoding::DER => der::ecdsa_signature_to_fixed(&self.encoded, coordinate_len)?,
+            _ => bail!(CryptoError::NotAvailable),
+        };
+        let (r, s) = fixed.split_at(coordinate_len);
+        Ok((r.to_vec(), s.to_vec()))
+    }
+
+    /// Encode the signature as a JWS `ES256` or `ES384` signature, before base64url encoding:
+    /// `r || s`, each left-padded with zeros to the size of the curve's field.
+    pub fn to_jws_bytes(&self, alg: SignatureAlgorithm) -> Result<Vec<u8>, Error> {
+        let (r, s) = self.components(alg)?;
+        Ok([r, s].concat())
+    }
+
+    /// Decode a base64url-decoded JWS `ES256` or `ES384` signature.
+    pub fn from_jws_bytes(alg: SignatureAlgorithm, bytes: &[u8]) -> Result<Self, Error> {
+        let coordinate_len = ECDSASignatureOp::coordinate_len(alg)?;
+        ensure!(
+            bytes.len() == coordinate_len * 2,
+            CryptoError::InvalidSignature
+        );
+        Ok(ECDSASignature::new(SignatureEncoding::Raw, bytes.to_vec()))
+    }
+
+    /// Build a signature from big-endian `r` and `s` components, that must be in `[1, n-1]`.
+    pub fn from_components(alg: SignatureAlgorithm, r: &[u8], s: &[u8]) -> Result<Self, Error> {
+        let coordinate_len = ECDSASignatureOp::coordinate_len(alg)?;
+        let order = Self::curve_order(alg)?;
+        let mut fixed = vec![0u8; coordinate_len * 2];
+        for (component, out) in [r, s].iter().zip(fixed.chunks_mut(coordinate_len)) {
+            let skip = component.iter().take_while(|&&x| x == 0).count();
+            let component = &component[skip..];
+            ensure!(
+                !component.is_empty() && component.len() <= coordinate_len,
+                CryptoError::InvalidSignature
+            );
+            out[coordinate_len - component.len()..].copy_from_slice(component);
+            ensure!(&out[..] < order, CryptoError::InvalidSignature);
+        }
+        Ok(ECDSASignature::new(SignatureEncoding::Raw, fixed))
+    }
+
+    /// Return a raw signature whose `s` is at most `(n-1)/2`, replacing it with `n - s` if it
+    /// is not, for verifiers that reject high-S signatures to prevent malleability. Both
+    /// signatures are valid. `s` must be in `[1, n-1]`, and `InvalidSignature` is returned
+    /// otherwise. The checks, the comparison and the subtraction run in constant time, so that
+    /// whether the signature had to be normalized doesn't leak.
+    pub fn normalize_s(&self, alg: SignatureAlgorithm) -> Result<Self, Error> {
+        let (r, mut s) = self.components(alg)?;
+        let order = Self::curve_order(alg)?;
+        let (_, below_order) = ct_sub(&s, order);
+        let s_bits = s.iter().fold(0u8, |acc, &x| acc | x);
+        let is_zero = ((u16::from(s_bits).wrapping_sub(1) >> 8) & 1) as u8;
+        ensure!(below_order & !is_zero == 1, CryptoError::InvalidSignature);
+        // `n` is odd, so `(n-1)/2` is `n >> 1`
+        let mut half_order = vec![0u8; order.len()];
+        let mut carry = 0u8;
+        for (out, &x) in half_order.iter_mut().zip(order) {
+            *out = carry | (x >> 1);
+            carry = x << 7;
+        }
+        let (_, is_high) = ct_sub(&half_order, &s);
+        let (negated_s, _) = ct_sub(order, &s);
+        ct_select(&mut s, &negated_s, is_high);
+        Ok(ECDSASignature::new(SignatureEncoding::Raw, [r, s].concat()))
+    }
+
+    /// Detect the encoding of a signature that may be either `DER` or `Raw`. DER signatures
+    /// start with a `SEQUENCE` tag, and raw ones are exactly twice the size of the field. A
+    /// signature that could be both is only read as DER if its raw components would be out
+    /// of `[1, n-1]`, which they never are for a valid raw signature.
+    pub fn detect_encoding(
+        alg: SignatureAlgorithm,
+        encoded: &[u8],
+    ) -> Result<SignatureEncoding, Error> {
+        let coordinate_len = ECDSASignatureOp::coordinate_len(alg)?;
+        let is_der = encoded.first() == Some(&der::TAG_SEQUENCE)
+            && der::ecdsa_signature_to_fixed(encoded, coordinate_len).is_ok();
+        if encoded.len() != coordinate_len * 2 {
+            ensure!(is_der, CryptoError::InvalidSignature);
+            return Ok(SignatureEncoding::DER);
+        }
+        let order = Self::curve_order(alg)?;
+        let raw_in_range = encoded
+            .chunks(coordinate_len)
+            .all(|component| component.iter().any(|&x| x != 0) && component < order);
+        if is_der && !raw_in_range {
+            Ok(SignatureEncoding::DER)
+        } else {
+            Ok(SignatureEncoding::Raw)
+        }
+    }
+
+    /// Parse a signature that may be either `DER` or `Raw`, and normalize it to `Raw`.
+    pub fn from_any_encoding(alg: SignatureAlgorithm, encoded: &[u8]) -> Result<Self, Error> {
+        let fixed = match Self::detect_encoding(alg, encoded)? {
+            SignatureEncoding::DER => {
+                der::ecdsa_signature_to_fixed(encoded, ECDSASignatureOp::coordinate_len(alg)?)?
+            }
+            _ => encoded.to_vec(),
+        };
+        Ok(ECDSASignature::new(SignatureEncoding::Raw, fixed))
+    }
 }
 
 impl ECDSASignatureState {
@@ -141,45 +735,220 @@ impl ECDSASignatureState {
         }
     }
 
+    /// Create a state whose input buffer can hold `capacity` bytes without reallocating.
+    pub fn with_capacity(kp: ECDSASignatureKeyPair, capacity: usize) -> Self {
+        ECDSASignatureState {
+            kp,
+            input: Mutex::new(Vec::with_capacity(capacity)),
+        }
+    }
+
     pub fn update(&self, input: &[u8]) -> Result<(), Error> {
-        self.input.lock().extend_from_slice(input);
+        self.update_from_slices(&[input])
+    }
+
+    pub fn update_framed(&self, field: &[u8]) -> Result<(), Error> {
+        self.update_framed_from_slices(&[field])
+    }
+
+    pub fn update_from_slices(&self, slices: &[&[u8]]) -> Result<(), Error> {
+        let mut input = self.input.lock();
+        slices
+            .iter()
+            .for_each(|slice| input.extend_from_slice(slice));
+        Ok(())
+    }
+
+    pub fn update_framed_from_slices(&self, fields: &[&[u8]]) -> Result<(), Error> {
+        let mut input = self.input.lock();
+        fields
+            .iter()
+            .for_each(|field| framing::append_framed(&mut input, field));
         Ok(())
     }
 
     pub fn sign(&self) -> Result<ECDSASignature, Error> {
-        let rng = ring::rand::SystemRandom::new();
+        self.kp.sign_oneshot(&self.input.lock())
+    }
+
+    /// Write a raw signature into `out`, and return its length. Like `sign_oneshot()`, this
+    /// returns `NotAvailable` for prehashed-only key pairs.
+    pub fn sign_into(&self, out: &mut [u8]) -> Result<usize, Error> {
+        ensure!(!self.kp.prehashed_only, CryptoError::NotAvailable);
+        let len = ECDSASignatureOp::coordinate_len(self.kp.alg)? * 2;
+        ensure!(out.len() >= len, CryptoError::InvalidLength);
+        self.kp.count_signature()?;
         let input = self.input.lock();
-        let encoded_signature = self
-            .kp
-            .ring_kp
-            .sign(&rng, &input)
-            .map_err(|_| CryptoError::AlgorithmFailure)?
-            .as_ref()
-            .to_vec();
-        let signature = ECDSASignature::new(SignatureEncoding::Raw, encoded_signature);
-        Ok(signature)
+        #[cfg(test)]
+        {
+            if let Some(encoded_signature) = self.kp.sign_with_test_rng(&input)? {
+                out[..len].copy_from_slice(&encoded_signature);
+                return Ok(len);
+            }
+        }
+        out[..len].copy_from_slice(&self.kp.sign_randomized(&input)?);
+        Ok(len)
     }
 }
 
+#[cfg(test)]
+thread_local! {
+    static SIMULATED_RNG_FAILURE: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+    static DETERMINISTIC_TEST_RNG: std::cell::RefCell<Option<TestRng>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// A reproducible RNG producing `HMAC-SHA256(seed, counter)` blocks. Only for tests.
+#[cfg(test)]
+struct TestRng {
+    key: ring::hmac::Key,
+    counter: u64,
+}
+
+#[cfg(test)]
+impl p256::elliptic_curve::rand_core::RngCore for TestRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(32) {
+            let block = ring::hmac::sign(&self.key, &self.counter.to_be_bytes());
+            chunk.copy_from_slice(&block.as_ref()[..chunk.len()]);
+            self.counter += 1;
+        }
+    }
+
+    fn try_fill_bytes(
+        &mut self,
+        dest: &mut [u8],
+    ) -> Result<(), p256::elliptic_curve::rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl p256::elliptic_curve::rand_core::CryptoRng for TestRng {}
+
+/// Make ECDSA signatures computed on the current thread reproducible, by deriving the nonces
+/// from `seed`, the key and the message. `None` restores the system RNG.
+///
+/// This is only compiled for tests, so that the test suite can assert concrete signatures.
+#[cfg(test)]
+pub fn deterministic_test_rng(seed: Option<u64>) {
+    let rng = seed.map(|seed| TestRng {
+        key: ring::hmac::Key::new(ring::hmac::HMAC_SHA256, &seed.to_be_bytes()),
+        counter: 0,
+    });
+    DETERMINISTIC_TEST_RNG.with(|test_rng| *test_rng.borrow_mut() = rng);
+}
+
 #[derive(Debug)]
 pub struct ECDSASignatureVerificationState {
     pub pk: ECDSASignaturePublicKey,
+    #[cfg(not(feature = "prehash"))]
     pub input: Mutex<Vec<u8>>,
+    #[cfg(feature = "prehash")]
+    pub input: Mutex<ECDSAInputDigest>,
+}
+
+/// Running digest of the input of a verification state. With the `prehash` feature, the
+/// input is hashed as it is received, and the state only keeps this digest.
+#[cfg(feature = "prehash")]
+pub struct ECDSAInputDigest(ring::digest::Context);
+
+#[cfg(feature = "prehash")]
+impl core::fmt::Debug for ECDSAInputDigest {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("ECDSAInputDigest")
+            .field(&self.0.algorithm())
+            .finish()
+    }
 }
 
 impl ECDSASignatureVerificationState {
-    pub fn new(pk: ECDSASignaturePublicKey) -> Self {
-        ECDSASignatureVerificationState {
-            pk,
-            input: Mutex::new(vec![]),
+    pub fn new(pk: ECDSASignaturePublicKey) -> Result<Self, Error> {
+        if !pk.validated {
+            pk.validate()?;
         }
+        #[cfg(not(feature = "prehash"))]
+        let input = vec![];
+        #[cfg(feature = "prehash")]
+        let input = ECDSAInputDigest(ring::digest::Context::new(pk.alg.digest_alg()));
+        let state = ECDSASignatureVerificationState {
+            pk,
+            input: Mutex::new(input),
+        };
+        Ok(state)
     }
 
     pub fn update(&self, input: &[u8]) -> Result<(), Error> {
-        self.input.lock().extend_from_slice(input);
+        self.update_from_slices(&[input])
+    }
+
+    pub fn update_framed(&self, field: &[u8]) -> Result<(), Error> {
+        self.update_framed_from_slices(&[field])
+    }
+
+    #[cfg(not(feature = "prehash"))]
+    pub fn update_from_slices(&self, slices: &[&[u8]]) -> Result<(), Error> {
+        let mut input = self.input.lock();
+        slices
+            .iter()
+            .for_each(|slice| input.extend_from_slice(slice));
+        Ok(())
+    }
+
+    #[cfg(not(feature = "prehash"))]
+    pub fn update_framed_from_slices(&self, fields: &[&[u8]]) -> Result<(), Error> {
+        let mut input = self.input.lock();
+        fields
+            .iter()
+            .for_each(|field| framing::append_framed(&mut input, field));
+        Ok(())
+    }
+
+    #[cfg(feature = "prehash")]
+    pub fn update_from_slices(&self, slices: &[&[u8]]) -> Result<(), Error> {
+        let mut input = self.input.lock();
+        slices.iter().for_each(|slice| input.0.update(slice));
+        Ok(())
+    }
+
+    #[cfg(feature = "prehash")]
+    pub fn update_framed_from_slices(&self, fields: &[&[u8]]) -> Result<(), Error> {
+        let mut input = self.input.lock();
+        for field in fields {
+            input.0.update(&framing::length_prefix(field));
+            input.0.update(field);
+        }
         Ok(())
     }
 
+    #[cfg(not(feature = "prehash"))]
+    pub fn input_digest(&self) -> Vec<u8> {
+        ring::digest::digest(self.pk.alg.digest_alg(), &self.input.lock())
+            .as_ref()
+            .to_vec()
+    }
+
+    #[cfg(feature = "prehash")]
+    pub fn input_digest(&self) -> Vec<u8> {
+        self.input.lock().0.clone().finish().as_ref().to_vec()
+    }
+
+    /// DER signatures are verified with ring's ASN.1 algorithms, and are never converted to
+    /// their fixed-size form.
+    #[cfg(not(feature = "prehash"))]
     pub fn verify(&self, signature: &ECDSASignature) -> Result<(), Error> {
         let ring_alg = match (self.pk.alg, signature.encoding) {
             (SignatureAlgorithm::ECDSA_P256_SHA256, SignatureEncoding::Raw) => {
@@ -202,24 +971,880 @@ impl ECDSASignatureVerificationState {
             .map_err(|_| CryptoError::VerificationFailed)?;
         Ok(())
     }
+
+    /// DER signatures are parsed directly by the RustCrypto types, and are never converted to
+    /// their fixed-size form.
+    #[cfg(feature = "prehash")]
+    pub fn verify(&self, signature: &ECDSASignature) -> Result<(), Error> {
+        self.verify_and_return_digest(signature)?;
+        Ok(())
+    }
+
+    /// Verify a signature, and return the digest of the input it was verified against, for
+    /// protocols that keep using the transcript hash without hashing the input again.
+    /// Requires the `prehash` feature.
+    #[cfg(feature = "prehash")]
+    pub fn verify_and_return_digest(&self, signature: &ECDSASignature) -> Result<Vec<u8>, Error> {
+        let digest = self.input_digest();
+        self.pk.verify_digest(&digest, signature)?;
+        Ok(digest)
+    }
+
+    #[cfg(not(feature = "prehash"))]
+    pub fn verify_and_return_digest(&self, _signature: &ECDSASignature) -> Result<Vec<u8>, Error> {
+        bail!(CryptoError::NotAvailable)
+    }
+
+    /// Verify a signature that must be strictly encoded as `encoding`, either `Raw` (`r || s`)
+    /// or `DER`, instead of relying on the encoding recorded in an `ECDSASignature`.
+    pub fn verify_with_encoding(
+        &self,
+        encoded: &[u8],
+        encoding: SignatureEncoding,
+    ) -> Result<(), Error> {
+        let coordinate_len = ECDSASignatureOp::coordinate_len(self.pk.alg)?;
+        match encoding {
+            SignatureEncoding::Raw => ensure!(
+                encoded.len() == coordinate_len * 2,
+                CryptoError::InvalidSignature
+            ),
+            SignatureEncoding::DER => {
+                der::ecdsa_signature_to_fixed(encoded, coordinate_len)?;
+            }
+            _ => bail!(CryptoError::NotAvailable),
+        }
+        self.verify(&ECDSASignature::new(encoding, encoded.to_vec()))
+    }
+
+    /// Verify a signature that may be either `DER` or `Raw`, as detected by
+    /// `ECDSASignature::detect_encoding()`.
+    pub fn verify_any_encoding(&self, encoded: &[u8]) -> Result<(), Error> {
+        self.verify(&ECDSASignature::from_any_encoding(self.pk.alg, encoded)?)
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct ECDSASignaturePublicKey {
     pub alg: SignatureAlgorithm,
     pub raw: Vec<u8>,
+    // Set once the point has been checked to be on the curve, so that verification states
+    // don't check it again
+    pub(crate) validated: bool,
 }
 
 impl ECDSASignaturePublicKey {
+    /// The point is checked to be on the curve, and `InvalidKey` is returned if it is not.
     pub fn from_raw(alg: SignatureAlgorithm, raw: &[u8]) -> Result<Self, Error> {
-        let pk = ECDSASignaturePublicKey {
+        let mut pk = ECDSASignaturePublicKey {
             alg,
             raw: raw.to_vec(),
+            validated: false,
         };
+        pk.validate()?;
+        pk.validated = true;
         Ok(pk)
     }
 
+    /// Build a public key from its big-endian affine coordinates, such as the `x` and `y`
+    /// fields of a JWK. Both must be exactly the size of the curve's field, and the point
+    /// must be on the curve.
+    pub fn from_xy(alg: SignatureAlgorithm, x: &[u8], y: &[u8]) -> Result<Self, Error> {
+        let coordinate_len = ECDSASignatureOp::coordinate_len(alg)?;
+        ensure!(
+            x.len() == coordinate_len && y.len() == coordinate_len,
+            CryptoError::InvalidKey
+        );
+        Self::from_raw(alg, &[&[0x04], x, y].concat())
+    }
+
     pub fn as_raw(&self) -> Result<&[u8], Error> {
         Ok(&self.raw)
     }
+
+    /// Verify a raw `r || s` signature of a complete message, using the key's algorithm.
+    pub fn verify(&self, message: &[u8], raw_signature: &[u8]) -> Result<(), Error> {
+        let coordinate_len = ECDSASignatureOp::coordinate_len(self.alg)?;
+        ensure!(
+            raw_signature.len() == coordinate_len * 2,
+            CryptoError::InvalidSignature
+        );
+        let state = ECDSASignatureVerificationState::new(self.clone())?;
+        state.update(message)?;
+        state.verify(&ECDSASignature::new(
+            SignatureEncoding::Raw,
+            raw_signature.to_vec(),
+        ))
+    }
+
+    /// Verify a signature of a digest computed by the caller, with the hash function of the
+    /// algorithm. Requires the `prehash` feature.
+    #[cfg(feature = "prehash")]
+    pub fn verify_digest(&self, digest: &[u8], signature: &ECDSASignature) -> Result<(), Error> {
+        use p256::ecdsa::signature::hazmat::PrehashVerifier as _;
+
+        ensure!(
+            digest.len() == self.alg.digest_alg().output_len,
+            CryptoError::InvalidLength
+        );
+        let encoded = signature.as_ref();
+        let verified = match (self.alg, signature.encoding) {
+            (SignatureAlgorithm::ECDSA_P256_SHA256, encoding) => {
+                let vk = p256::ecdsa::VerifyingKey::from_sec1_bytes(&self.raw)
+                    .map_err(|_| CryptoError::InvalidKey)?;
+                match encoding {
+                    SignatureEncoding::Raw => p256::ecdsa::Signature::from_slice(encoded),
+                    SignatureEncoding::DER => p256::ecdsa::Signature::from_der(encoded),
+                    _ => bail!(CryptoError::NotAvailable),
+                }
+                .and_then(|signature| vk.verify_prehash(digest, &signature))
+            }
+            (SignatureAlgorithm::ECDSA_P384_SHA384, encoding) => {
+                let vk = p384::ecdsa::VerifyingKey::from_sec1_bytes(&self.raw)
+                    .map_err(|_| CryptoError::InvalidKey)?;
+                match encoding {
+                    SignatureEncoding::Raw => p384::ecdsa::Signature::from_slice(encoded),
+                    SignatureEncoding::DER => p384::ecdsa::Signature::from_der(encoded),
+                    _ => bail!(CryptoError::NotAvailable),
+                }
+                .and_then(|signature| vk.verify_prehash(digest, &signature))
+            }
+            _ => bail!(CryptoError::NotAvailable),
+        };
+        verified.map_err(|_| CryptoError::VerificationFailed)?;
+        Ok(())
+    }
+
+    #[cfg(not(feature = "prehash"))]
+    pub fn verify_digest(&self, _digest: &[u8], _signature: &ECDSASignature) -> Result<(), Error> {
+        bail!(CryptoError::NotAvailable)
+    }
+
+    pub fn as_spki(&self) -> Result<Vec<u8>, Error> {
+        let curve_oid = match self.alg {
+            SignatureAlgorithm::ECDSA_P256_SHA256 => der::OID_PRIME256V1,
+            SignatureAlgorithm::ECDSA_P384_SHA384 => der::OID_SECP384R1,
+            _ => bail!(CryptoError::NotAvailable),
+        };
+        let alg_id = der::sequence(&[&der::oid(der::OID_EC_PUBLIC_KEY), &der::oid(curve_oid)]);
+        Ok(der::sequence(&[&alg_id, &der::bit_string(&self.raw)]))
+    }
+
+    // ring only checks the point when verifying a signature, so we perform an ephemeral
+    // key agreement with it to have the point decoded and checked to be on the curve.
+    pub fn validate(&self) -> Result<(), Error> {
+        let ring_agreement_alg = match self.alg {
+            SignatureAlgorithm::ECDSA_P256_SHA256 => &ring::agreement::ECDH_P256,
+            SignatureAlgorithm::ECDSA_P384_SHA384 => &ring::agreement::ECDH_P384,
+            _ => bail!(CryptoError::NotAvailable),
+        };
+        let coordinate_len = ECDSASignatureOp::coordinate_len(self.alg)?;
+        ensure!(
+            self.raw.len() == 1 + coordinate_len * 2 && self.raw[0] == 0x04,
+            CryptoError::InvalidKey
+        );
+        let rng = ring::rand::SystemRandom::new();
+        let ephemeral_sk = ring::agreement::EphemeralPrivateKey::generate(ring_agreement_alg, &rng)
+            .map_err(|_| CryptoError::RNGError)?;
+        let ring_pk = ring::agreement::UnparsedPublicKey::new(ring_agreement_alg, &self.raw);
+        ring::agreement::agree_ephemeral(ephemeral_sk, &ring_pk, CryptoError::InvalidKey, |_| {
+            Ok(())
+        })?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_verification_state_rejects_malformed_key() {
+    let kp = ECDSASignatureKeyPair::generate(SignatureAlgorithm::ECDSA_P256_SHA256).unwrap();
+    let pk = ECDSASignaturePublicKey::from_raw(kp.alg, kp.raw_public_key()).unwrap();
+    assert!(pk.validated);
+    assert!(ECDSASignatureVerificationState::new(pk).is_ok());
+
+    let mut off_curve = vec![0x04];
+    off_curve.extend_from_slice(&[0x01; 64]);
+    for raw in [&kp.raw_public_key()[..64], &off_curve[..]] {
+        let err = ECDSASignaturePublicKey::from_raw(kp.alg, raw).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::InvalidKey)
+        ));
+        // Keys that were not built by `from_raw()` are still checked by verification states
+        let mut pk = ECDSASignaturePublicKey {
+            alg: kp.alg,
+            raw: raw.to_vec(),
+            validated: false,
+        };
+        assert!(ECDSASignatureVerificationState::new(pk.clone()).is_err());
+
+        // Validated keys are not checked again, but ring still rejects the point
+        pk.validated = true;
+        let state = ECDSASignatureVerificationState::new(pk).unwrap();
+        state.update(b"test").unwrap();
+        assert!(state
+            .verify(&ECDSASignature::new(SignatureEncoding::Raw, vec![0x01; 64]))
+            .is_err());
+    }
+}
+
+#[test]
+fn test_signing_leaves_no_secret_behind() {
+    let alg = SignatureAlgorithm::ECDSA_P256_SHA256;
+    let kp = ECDSASignatureKeyPair::generate(alg).unwrap();
+    let v1 = Zeroizing::new(kp.as_pkcs8().unwrap().to_vec());
+    let parsed = der::parse_pkcs8(&v1).unwrap();
+    let mut decoder = der::Decoder::new(parsed.private_key);
+    let mut ec_private_key = der::Decoder::new(decoder.read(der::TAG_SEQUENCE).unwrap());
+    let version = ec_private_key.read(der::TAG_INTEGER).unwrap();
+    let mut secret = Zeroizing::new([0u8; 32]);
+    secret.copy_from_slice(ec_private_key.read(der::TAG_OCTET_STRING).unwrap());
+    let ec_private_key = Zeroizing::new(der::secret_sequence(&[
+        &der::encode(der::TAG_INTEGER, version),
+        &Zeroizing::new(der::encode(der::TAG_OCTET_STRING, &secret[..])),
+    ]));
+    let v2 = Zeroizing::new(der::pkcs8_v2(
+        parsed.alg_id,
+        &ec_private_key,
+        kp.raw_public_key(),
+    ));
+
+    // The sanity check: a plain copy of the secret is caught
+    assert!(crate::test_support::frees_secret(*secret, || drop(
+        secret.to_vec()
+    )));
+
+    let leaked = crate::test_support::frees_secret(*secret, || {
+        // A v2 document is rebuilt as v1 from its fields
+        let kp = ECDSASignatureKeyPair::from_pkcs8(alg, &v2).unwrap();
+        kp.sign_oneshot(b"test").unwrap();
+        // The RustCrypto paths parse the document into a `SigningKey`
+        deterministic_test_rng(Some(42));
+        kp.sign_oneshot(b"test").unwrap();
+        deterministic_test_rng(None);
+        if cfg!(feature = "deterministic") {
+            kp.sign_deterministic(b"test").unwrap();
+        }
+        if cfg!(feature = "prehash") {
+            kp.sign_digest(&[0x42; 32]).unwrap();
+        }
+        #[cfg(feature = "drbg")]
+        {
+            let drbg = crate::drbg::test_drbg(1000);
+            ECDSASignatureKeyPairBuilder::new(alg)
+                .generate_with_drbg(&drbg)
+                .unwrap();
+            kp.sign_with_drbg(&drbg, b"test").unwrap();
+        }
+    });
+    assert!(!leaked);
+}
+
+#[test]
+fn test_verify_with_encoding() {
+    let kp = ECDSASignatureKeyPair::generate(SignatureAlgorithm::ECDSA_P384_SHA384).unwrap();
+    let state = ECDSASignatureState::new(kp.clone());
+    state.update(b"test").unwrap();
+    let fixed = state.sign().unwrap().encoded;
+    let der = der::ecdsa_signature_from_fixed(&fixed).unwrap();
+
+    let pk = ECDSASignaturePublicKey::from_raw(kp.alg, kp.raw_public_key()).unwrap();
+    let verification_state = ECDSASignatureVerificationState::new(pk).unwrap();
+    verification_state.update(b"test").unwrap();
+    verification_state
+        .verify_with_encoding(&fixed, SignatureEncoding::Raw)
+        .unwrap();
+    verification_state
+        .verify_with_encoding(&der, SignatureEncoding::DER)
+        .unwrap();
+
+    for (encoded, encoding) in &[
+        (&fixed, SignatureEncoding::DER),
+        (&der, SignatureEncoding::Raw),
+    ] {
+        let err = verification_state
+            .verify_with_encoding(encoded, *encoding)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::InvalidSignature)
+        ));
+    }
+}
+
+#[test]
+fn test_signature_components() {
+    let alg = SignatureAlgorithm::ECDSA_P256_SHA256;
+    let kp = ECDSASignatureKeyPair::generate(alg).unwrap();
+    let state = ECDSASignatureState::new(kp);
+    state.update(b"test").unwrap();
+    let signature = state.sign().unwrap();
+
+    let (r, s) = signature.components(alg).unwrap();
+    assert_eq!((r.len(), s.len()), (32, 32));
+    assert_eq!(
+        ECDSASignature::from_components(alg, &r, &s).unwrap(),
+        signature
+    );
+    let der_signature = ECDSASignature::new(
+        SignatureEncoding::DER,
+        der::ecdsa_signature_from_fixed(&signature.encoded).unwrap(),
+    );
+    assert_eq!(
+        der_signature.components(alg).unwrap(),
+        (r.clone(), s.clone())
+    );
+
+    let order = ECDSASignature::curve_order(alg).unwrap();
+    assert!(ECDSASignature::from_components(alg, order, &s).is_err());
+    assert!(ECDSASignature::from_components(alg, &r, &[0u8; 32]).is_err());
+    assert!(ECDSASignature::from_components(alg, &[0x01; 33], &s).is_err());
+    let mut padded_r = vec![0u8; 8];
+    padded_r.extend_from_slice(&r);
+    assert_eq!(
+        ECDSASignature::from_components(alg, &padded_r, &s).unwrap(),
+        signature
+    );
+}
+
+#[test]
+fn test_rng_fallback() {
+    let alg = SignatureAlgorithm::ECDSA_P256_SHA256;
+    let mut kp = ECDSASignatureKeyPair::generate(alg).unwrap();
+    let pk = ECDSASignaturePublicKey::from_raw(alg, kp.raw_public_key()).unwrap();
+
+    SIMULATED_RNG_FAILURE.with(|failure| failure.set(true));
+    let state = ECDSASignatureState::new(kp.clone());
+    state.update(b"test").unwrap();
+    let err = state.sign().unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::RNGError)
+    ));
+
+    kp.rng_fallback = true;
+    let state = ECDSASignatureState::new(kp);
+    state.update(b"test").unwrap();
+    let signature = state.sign();
+    SIMULATED_RNG_FAILURE.with(|failure| failure.set(false));
+    if cfg!(feature = "deterministic") {
+        let verification_state = ECDSASignatureVerificationState::new(pk).unwrap();
+        verification_state.update(b"test").unwrap();
+        verification_state.verify(&signature.unwrap()).unwrap();
+    } else {
+        assert!(signature.is_err());
+    }
+}
+
+#[test]
+fn test_streamed_verification() {
+    let alg = SignatureAlgorithm::ECDSA_P256_SHA256;
+    let kp = ECDSASignatureKeyPair::generate(alg).unwrap();
+    let pk = ECDSASignaturePublicKey::from_raw(alg, kp.raw_public_key()).unwrap();
+    let message: Vec<u8> = (0..1 << 20).map(|i| i as u8).collect();
+    let signature = kp.sign_oneshot(&message).unwrap();
+
+    let streamed = |message: &[u8]| {
+        let state = ECDSASignatureVerificationState::new(pk.clone()).unwrap();
+        for chunk in message.chunks(4096) {
+            state.update(chunk).unwrap();
+        }
+        state.verify(&signature).is_ok()
+    };
+    let oneshot = |message: &[u8]| {
+        ring::signature::UnparsedPublicKey::new(
+            &ring::signature::ECDSA_P256_SHA256_FIXED,
+            kp.raw_public_key(),
+        )
+        .verify(message, signature.as_ref())
+        .is_ok()
+    };
+    assert!(streamed(&message));
+    assert_eq!(streamed(&message), oneshot(&message));
+    assert!(!streamed(&message[1..]));
+    assert_eq!(streamed(&message[1..]), oneshot(&message[1..]));
+}
+
+#[test]
+fn test_verify_and_return_digest() {
+    let alg = SignatureAlgorithm::ECDSA_P384_SHA384;
+    let kp = ECDSASignatureKeyPair::generate(alg).unwrap();
+    let pk = ECDSASignaturePublicKey::from_raw(alg, kp.raw_public_key()).unwrap();
+    let signature = kp.sign_oneshot(b"client hello, server hello").unwrap();
+    let verify = |transcript: &[&[u8]]| {
+        let state = ECDSASignatureVerificationState::new(pk.clone()).unwrap();
+        for message in transcript {
+            state.update(message).unwrap();
+        }
+        state.verify_and_return_digest(&signature)
+    };
+
+    let verified = verify(&[b"client hello, ", b"server hello"]);
+    if cfg!(feature = "prehash") {
+        let digest = ring::digest::digest(&ring::digest::SHA384, b"client hello, server hello");
+        assert_eq!(verified.unwrap(), digest.as_ref());
+        let err = verify(&[b"client hello"]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::VerificationFailed)
+        ));
+    } else {
+        let err = verified.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::NotAvailable)
+        ));
+    }
+}
+
+#[cfg(feature = "prehash")]
+#[test]
+fn test_sign_truncated_digest() {
+    use p256::ecdsa::signature::hazmat::PrehashVerifier as _;
+
+    let digest = ring::digest::digest(&ring::digest::SHA512, b"test");
+    let digest = digest.as_ref();
+    for &alg in &[
+        SignatureAlgorithm::ECDSA_P256_SHA256,
+        SignatureAlgorithm::ECDSA_P384_SHA384,
+    ] {
+        let kp = ECDSASignatureKeyPair::generate(alg).unwrap();
+        let coordinate_len = ECDSASignatureOp::coordinate_len(alg).unwrap();
+        let signature = kp.sign_truncated_digest(digest).unwrap();
+        // Nonces are deterministic, so the truncated digest gives the same signature
+        assert_eq!(
+            signature,
+            kp.sign_digest(&digest[..coordinate_len]).unwrap()
+        );
+
+        let verified = |digest: &[u8]| match alg {
+            SignatureAlgorithm::ECDSA_P256_SHA256 => {
+                p256::ecdsa::VerifyingKey::from_sec1_bytes(kp.raw_public_key())
+                    .unwrap()
+                    .verify_prehash(
+                        digest,
+                        &p256::ecdsa::Signature::from_slice(signature.as_ref()).unwrap(),
+                    )
+                    .is_ok()
+            }
+            _ => p384::ecdsa::VerifyingKey::from_sec1_bytes(kp.raw_public_key())
+                .unwrap()
+                .verify_prehash(
+                    digest,
+                    &p384::ecdsa::Signature::from_slice(signature.as_ref()).unwrap(),
+                )
+                .is_ok(),
+        };
+        assert!(verified(&digest[..coordinate_len]));
+        assert!(verified(digest));
+        assert!(!verified(&digest[digest.len() - coordinate_len..]));
+
+        let err = kp
+            .sign_truncated_digest(&digest[..coordinate_len - 1])
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::InvalidLength)
+        ));
+    }
+}
+
+#[test]
+fn test_signature_hex() {
+    let alg = SignatureAlgorithm::ECDSA_P256_SHA256;
+    let signature = ECDSASignatureKeyPair::generate(alg)
+        .unwrap()
+        .sign_oneshot(b"test")
+        .unwrap();
+    let hex = signature.to_string();
+    assert_eq!(hex.len(), 128);
+    assert!(!hex.bytes().any(|x| x.is_ascii_uppercase()));
+    assert_eq!(hex.parse::<ECDSASignature>().unwrap(), signature);
+    assert_eq!(
+        hex.to_uppercase().parse::<ECDSASignature>().unwrap(),
+        signature
+    );
+
+    // DER signatures keep their encoding
+    for alg in &[alg, SignatureAlgorithm::ECDSA_P384_SHA384] {
+        let signature = ECDSASignatureKeyPair::generate(*alg)
+            .unwrap()
+            .sign_oneshot(b"test")
+            .unwrap();
+        let der = ECDSASignature::new(
+            SignatureEncoding::DER,
+            der::ecdsa_signature_from_fixed(&signature.encoded).unwrap(),
+        );
+        assert_eq!(der.to_string().parse::<ECDSASignature>().unwrap(), der);
+        assert_eq!(
+            signature.to_string().parse::<ECDSASignature>().unwrap(),
+            signature
+        );
+    }
+    assert_eq!(
+        "0102".parse::<ECDSASignature>().unwrap().encoding,
+        SignatureEncoding::Raw
+    );
+
+    for invalid in &[&hex[1..], "0g", "+f", "é"] {
+        let err = invalid.parse::<ECDSASignature>().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::InvalidSignature)
+        ));
+    }
+}
+
+#[test]
+fn test_jws_signatures() {
+    let decode = |s: &str| base64::decode_config(s, base64::URL_SAFE_NO_PAD).unwrap();
+    let vectors = [
+        // RFC 7515, appendix A.3, with the public key as `0x04 || x || y`
+        (
+            SignatureAlgorithm::ECDSA_P256_SHA256,
+            "eyJhbGciOiJFUzI1NiJ9.eyJpc3MiOiJqb2UiLA0KICJleHAiOjEzMDA4MTkzODAsDQogImh0dHA6Ly9leGFtcGxlLmNvbS9pc19yb290Ijp0cnVlfQ",
+            "DtEhU3ljbEg8L38VWAfUAqOyKAM6-Xx-F4GawxaepmXFCgfTjDxw5djxLa8ISlSApmWQxfKTUJqPP3-Kg6NU1Q",
+            "BH_Nzidw9sRdQYPL7m_bS3tYBzM1e-nvE7rPbjx70VRFx_FEzRu9m36HLN_tue659LNpXW6pCyStikYjKIWI5a0",
+        ),
+        // Generated with OpenSSL; `r` has a leading zero byte
+        (
+            SignatureAlgorithm::ECDSA_P384_SHA384,
+            "eyJhbGciOiJFUzM4NCJ9.eyJzdWIiOiJ3YXNpLWNyeXB0byJ9",
+            "AMlp50HMekNdT8J4N4eyKHQ17pH7dn9VpOkOT1S5El7S1RNIMutFFtPLpUbYuLB-_CeOobBglPf60y-ID6dhVxPwWbG7shTV7MpfvHCCqI6LYprGO5wRA6ZXkN36DIqD",
+            "BNu9DEhztRjwEHJjhFeSHf_ReTRgPDl53eUgXMIRmJk9esAIS8Edo6aKH6l1l6Cz0ystz9_g2qJnEEzf9CUjwUb_lNOX4TmIaBWypeBeg5z6jUZE16CVyZQH-TYQSkTkBA",
+        ),
+    ];
+    for (alg, signing_input, encoded_signature, encoded_pk) in vectors.iter() {
+        let pk = ECDSASignaturePublicKey::from_raw(*alg, &decode(encoded_pk)).unwrap();
+        let jws_bytes = decode(encoded_signature);
+        let signature = ECDSASignature::from_jws_bytes(*alg, &jws_bytes).unwrap();
+        let state = ECDSASignatureVerificationState::new(pk).unwrap();
+        state.update(signing_input.as_bytes()).unwrap();
+        state.verify(&signature).unwrap();
+        assert_eq!(signature.to_jws_bytes(*alg).unwrap(), jws_bytes);
+
+        let der_signature = ECDSASignature::new(
+            SignatureEncoding::DER,
+            der::ecdsa_signature_from_fixed(&jws_bytes).unwrap(),
+        );
+        assert_eq!(der_signature.to_jws_bytes(*alg).unwrap(), jws_bytes);
+
+        assert!(ECDSASignature::from_jws_bytes(*alg, &jws_bytes[1..]).is_err());
+        assert!(ECDSASignature::from_jws_bytes(*alg, &[&jws_bytes[..], &[0]].concat()).is_err());
+    }
+}
+
+#[test]
+fn test_sign_into_prehashed_only() {
+    let mut kp = ECDSASignatureKeyPair::generate(SignatureAlgorithm::ECDSA_P256_SHA256).unwrap();
+    kp.prehashed_only = true;
+    let state = ECDSASignatureState::new(kp);
+    state.update(b"test").unwrap();
+    let err = state.sign_into(&mut [0u8; 64]).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::NotAvailable)
+    ));
+}
+
+#[test]
+fn test_pkcs8_versions() {
+    let alg = SignatureAlgorithm::ECDSA_P256_SHA256;
+    let kp = ECDSASignatureKeyPair::generate(alg).unwrap();
+    let v1 = kp.as_pkcs8().unwrap().to_vec();
+    let public_key = kp.raw_public_key().to_vec();
+
+    // Rebuild the key as PKCS#8 v2, with the public key only outside the `ECPrivateKey`
+    let parsed = der::parse_pkcs8(&v1).unwrap();
+    let mut decoder = der::Decoder::new(parsed.private_key);
+    let mut ec_private_key = der::Decoder::new(decoder.read(der::TAG_SEQUENCE).unwrap());
+    let version = ec_private_key.read(der::TAG_INTEGER).unwrap();
+    let secret = ec_private_key.read(der::TAG_OCTET_STRING).unwrap();
+    let ec_private_key = der::sequence(&[
+        &der::encode(der::TAG_INTEGER, version),
+        &der::encode(der::TAG_OCTET_STRING, secret),
+    ]);
+    let v2 = der::pkcs8_v2(parsed.alg_id, &ec_private_key, &public_key);
+    assert!(ring::signature::EcdsaKeyPair::from_pkcs8(
+        &ring::signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+        &v2
+    )
+    .is_err());
+
+    for encoded in &[&v1, &v2] {
+        let kp = ECDSASignatureKeyPair::from_pkcs8(alg, encoded).unwrap();
+        assert_eq!(kp.raw_public_key(), &public_key[..]);
+        let kp = ECDSASignatureKeyPair::from_pkcs8(alg, kp.as_pkcs8().unwrap()).unwrap();
+        assert_eq!(kp.raw_public_key(), &public_key[..]);
+        assert_eq!(der::parse_pkcs8(kp.as_pkcs8().unwrap()).unwrap().version, 0);
+    }
+
+    let other = ECDSASignatureKeyPair::generate(alg).unwrap();
+    let mismatched = der::pkcs8_v2(parsed.alg_id, &ec_private_key, other.raw_public_key());
+    assert!(ECDSASignatureKeyPair::from_pkcs8(alg, &mismatched).is_err());
+}
+
+#[test]
+fn test_der_verification() {
+    for alg in &[
+        SignatureAlgorithm::ECDSA_P256_SHA256,
+        SignatureAlgorithm::ECDSA_P384_SHA384,
+    ] {
+        let kp = ECDSASignatureKeyPair::generate(*alg).unwrap();
+        let state = ECDSASignatureState::new(kp.clone());
+        state.update(b"test").unwrap();
+        let fixed = state.sign().unwrap().encoded;
+        let mut der = der::ecdsa_signature_from_fixed(&fixed).unwrap();
+        let pk = ECDSASignaturePublicKey::from_raw(kp.alg, kp.raw_public_key()).unwrap();
+        let verify = |signature: &ECDSASignature| {
+            let verification_state = ECDSASignatureVerificationState::new(pk.clone()).unwrap();
+            verification_state.update(b"test").unwrap();
+            verification_state.verify(signature).is_ok()
+        };
+        let coordinate_len = ECDSASignatureOp::coordinate_len(*alg).unwrap();
+        let converted = |der: &[u8]| {
+            let fixed = der::ecdsa_signature_to_fixed(der, coordinate_len).unwrap();
+            ECDSASignature::new(SignatureEncoding::Raw, fixed)
+        };
+
+        assert!(verify(&ECDSASignature::new(
+            SignatureEncoding::DER,
+            der.clone()
+        )));
+        assert!(verify(&converted(&der)));
+
+        *der.last_mut().unwrap() ^= 0x01;
+        assert!(!verify(&ECDSASignature::new(
+            SignatureEncoding::DER,
+            der.clone()
+        )));
+        assert!(!verify(&converted(&der)));
+    }
+}
+
+#[test]
+fn test_deterministic_test_rng() {
+    let alg = SignatureAlgorithm::ECDSA_P256_SHA256;
+    let kp = ECDSASignatureKeyPair::generate(alg).unwrap();
+    let pk = ECDSASignaturePublicKey::from_raw(alg, kp.raw_public_key()).unwrap();
+    let sign_with_seed = |seed| {
+        deterministic_test_rng(seed);
+        kp.sign_oneshot(b"test").unwrap()
+    };
+
+    let signature = sign_with_seed(Some(42));
+    assert_eq!(sign_with_seed(Some(42)), signature);
+    pk.verify(b"test", signature.as_ref()).unwrap();
+
+    let state = ECDSASignatureState::new(kp.clone());
+    state.update(b"test").unwrap();
+    let mut out = [0u8; 64];
+    deterministic_test_rng(Some(42));
+    assert_eq!(state.sign_into(&mut out).unwrap(), 64);
+    assert_eq!(&out[..], signature.as_ref());
+
+    assert_ne!(sign_with_seed(Some(43)), signature);
+    assert_ne!(sign_with_seed(None), sign_with_seed(None));
+}
+
+#[test]
+fn test_sign_count() {
+    let options = SignatureOptions {
+        max_signatures: Some(3),
+        ..Default::default()
+    };
+    let op_handle = crate::signature_op_open_with_options("ECDSA_P256_SHA256", &options).unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    assert_eq!(crate::signature_keypair_sign_count(kp_handle).unwrap(), 0);
+
+    for expected_count in 1..=3 {
+        let state_handle = crate::signature_state_open(kp_handle).unwrap();
+        crate::signature_state_update(state_handle, b"test").unwrap();
+        crate::signature_state_sign(state_handle).unwrap();
+        assert_eq!(
+            crate::signature_keypair_sign_count(kp_handle).unwrap(),
+            expected_count
+        );
+    }
+    let state_handle = crate::signature_state_open(kp_handle).unwrap();
+    crate::signature_state_update(state_handle, b"test").unwrap();
+    let err = crate::signature_state_sign(state_handle).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::NotAvailable)
+    ));
+    assert_eq!(crate::signature_keypair_sign_count(kp_handle).unwrap(), 3);
+}
+
+#[test]
+fn test_state_with_capacity() {
+    let kp = ECDSASignatureKeyPair::generate(SignatureAlgorithm::ECDSA_P256_SHA256).unwrap();
+    let state = ECDSASignatureState::with_capacity(kp.clone(), 1 << 20);
+    assert!(state.input.lock().capacity() >= 1 << 20);
+    state.update(b"test").unwrap();
+    deterministic_test_rng(Some(42));
+    let signature = state.sign().unwrap();
+    deterministic_test_rng(Some(42));
+    let expected = kp.sign_oneshot(b"test").unwrap();
+    deterministic_test_rng(None);
+    assert_eq!(signature, expected);
+}
+
+#[test]
+fn test_publickey_from_xy() {
+    // Key and signature of "sample" with SHA-256 from RFC 6979, appendix A.2.5
+    let alg = SignatureAlgorithm::ECDSA_P256_SHA256;
+    let x = [
+        0x60, 0xfe, 0xd4, 0xba, 0x25, 0x5a, 0x9d, 0x31, 0xc9, 0x61, 0xeb, 0x74, 0xc6, 0x35, 0x6d,
+        0x68, 0xc0, 0x49, 0xb8, 0x92, 0x3b, 0x61, 0xfa, 0x6c, 0xe6, 0x69, 0x62, 0x2e, 0x60, 0xf2,
+        0x9f, 0xb6,
+    ];
+    let y = [
+        0x79, 0x03, 0xfe, 0x10, 0x08, 0xb8, 0xbc, 0x99, 0xa4, 0x1a, 0xe9, 0xe9, 0x56, 0x28, 0xbc,
+        0x64, 0xf2, 0xf1, 0xb2, 0x0c, 0x2d, 0x7e, 0x9f, 0x51, 0x77, 0xa3, 0xc2, 0x94, 0xd4, 0x46,
+        0x22, 0x99,
+    ];
+    let signature = [
+        0xef, 0xd4, 0x8b, 0x2a, 0xac, 0xb6, 0xa8, 0xfd, 0x11, 0x40, 0xdd, 0x9c, 0xd4, 0x5e, 0x81,
+        0xd6, 0x9d, 0x2c, 0x87, 0x7b, 0x56, 0xaa, 0xf9, 0x91, 0xc3, 0x4d, 0x0e, 0xa8, 0x4e, 0xaf,
+        0x37, 0x16, 0xf7, 0xcb, 0x1c, 0x94, 0x2d, 0x65, 0x7c, 0x41, 0xd4, 0x36, 0xc7, 0xa1, 0xb6,
+        0xe2, 0x9f, 0x65, 0xf3, 0xe9, 0x00, 0xdb, 0xb9, 0xaf, 0xf4, 0x06, 0x4d, 0xc4, 0xab, 0x2f,
+        0x84, 0x3a, 0xcd, 0xa8,
+    ];
+    let pk = ECDSASignaturePublicKey::from_xy(alg, &x, &y).unwrap();
+    pk.verify(b"sample", &signature).unwrap();
+    assert!(pk.verify(b"test", &signature).is_err());
+
+    let mut off_curve_y = y;
+    off_curve_y[31] ^= 0x01;
+    assert!(ECDSASignaturePublicKey::from_xy(alg, &x, &off_curve_y).is_err());
+    assert!(ECDSASignaturePublicKey::from_xy(alg, &x[1..], &y).is_err());
+    assert!(
+        ECDSASignaturePublicKey::from_xy(SignatureAlgorithm::ECDSA_P384_SHA384, &x, &y).is_err()
+    );
+}
+
+#[test]
+fn test_verify_any_encoding() {
+    for alg in &[
+        SignatureAlgorithm::ECDSA_P256_SHA256,
+        SignatureAlgorithm::ECDSA_P384_SHA384,
+    ] {
+        let kp = ECDSASignatureKeyPair::generate(*alg).unwrap();
+        let fixed = kp.sign_oneshot(b"test").unwrap().encoded;
+        let der = der::ecdsa_signature_from_fixed(&fixed).unwrap();
+        let pk = ECDSASignaturePublicKey::from_raw(kp.alg, kp.raw_public_key()).unwrap();
+        let verify = |encoded: &[u8]| {
+            let verification_state = ECDSASignatureVerificationState::new(pk.clone()).unwrap();
+            verification_state.update(b"test").unwrap();
+            verification_state.verify_any_encoding(encoded)
+        };
+        verify(&fixed).unwrap();
+        verify(&der).unwrap();
+        let mut tampered = der.clone();
+        *tampered.last_mut().unwrap() ^= 0x01;
+        assert!(verify(&tampered).is_err());
+        let err = verify(&fixed[1..]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::InvalidSignature)
+        ));
+    }
+
+    // DER signatures of the P-256 raw size, with 33-byte and 25-byte components: the raw `s`
+    // would start with the trailing bytes of the DER `r`
+    let alg = SignatureAlgorithm::ECDSA_P256_SHA256;
+    let mut fixed = [0x11u8; 64];
+    fixed[0] = 0x80;
+    fixed[27..32].copy_from_slice(&[0xff; 5]);
+    fixed[32..39].copy_from_slice(&[0x00; 7]);
+    fixed[39] = 0x01;
+    let der = der::ecdsa_signature_from_fixed(&fixed).unwrap();
+    assert_eq!(der.len(), 64);
+    assert_eq!(
+        ECDSASignature::detect_encoding(alg, &der).unwrap(),
+        SignatureEncoding::DER
+    );
+    assert_eq!(
+        ECDSASignature::from_any_encoding(alg, &der)
+            .unwrap()
+            .encoded,
+        fixed.to_vec()
+    );
+    // With 29-byte components, both forms are in range, and raw is preferred
+    let mut fixed = [0x11u8; 64];
+    fixed[..3].copy_from_slice(&[0x00; 3]);
+    fixed[32..35].copy_from_slice(&[0x00; 3]);
+    fixed[31] = 0x01;
+    let der = der::ecdsa_signature_from_fixed(&fixed).unwrap();
+    assert_eq!(der.len(), 64);
+    assert_eq!(
+        ECDSASignature::detect_encoding(alg, &der).unwrap(),
+        SignatureEncoding::Raw
+    );
+}
+
+#[test]
+fn test_normalize_s() {
+    for alg in &[
+        SignatureAlgorithm::ECDSA_P256_SHA256,
+        SignatureAlgorithm::ECDSA_P384_SHA384,
+    ] {
+        let coordinate_len = ECDSASignatureOp::coordinate_len(*alg).unwrap();
+        let order = ECDSASignature::curve_order(*alg).unwrap();
+        let r = vec![0x01; coordinate_len];
+        let normalized_s = |s: &[u8]| {
+            let signature = ECDSASignature::new(SignatureEncoding::Raw, [&r, s].concat());
+            let normalized = signature.normalize_s(*alg).unwrap().encoded;
+            assert_eq!(&normalized[..coordinate_len], &r[..]);
+            normalized[coordinate_len..].to_vec()
+        };
+        let int = |x: u8| {
+            let mut bytes = vec![0u8; coordinate_len];
+            bytes[coordinate_len - 1] = x;
+            bytes
+        };
+        let add = |a: &[u8], b: &[u8]| {
+            let mut sum = vec![0u8; coordinate_len];
+            let mut carry = 0u16;
+            for ((out, &x), &y) in sum.iter_mut().zip(a).zip(b).rev() {
+                let digit = u16::from(x) + u16::from(y) + carry;
+                *out = digit as u8;
+                carry = digit >> 8;
+            }
+            sum
+        };
+        let sub = |a: &[u8], b: &[u8]| ct_sub(a, b).0;
+
+        let half_order: Vec<u8> = (0..coordinate_len)
+            .map(|i| (order[i] >> 1) | if i > 0 { order[i - 1] << 7 } else { 0 })
+            .collect();
+        assert_eq!(add(&add(&half_order, &half_order), &int(1)), order);
+        let below_half = sub(&half_order, &int(1));
+        let above_half = add(&half_order, &int(1));
+        assert_eq!(normalized_s(&half_order), half_order);
+        assert_eq!(normalized_s(&below_half), below_half);
+        assert_eq!(normalized_s(&above_half), half_order);
+        assert_eq!(normalized_s(&add(&half_order, &int(2))), below_half);
+        assert_eq!(normalized_s(&sub(order, &int(1))), int(1));
+        assert_eq!(normalized_s(&int(1)), int(1));
+        for invalid_s in [int(0), order.to_vec(), add(order, &int(1))] {
+            let err = ECDSASignature::new(SignatureEncoding::Raw, [r.clone(), invalid_s].concat())
+                .normalize_s(*alg)
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<CryptoError>(),
+                Some(CryptoError::InvalidSignature)
+            ));
+        }
+
+        // A normalized signature still verifies, whether its `s` was high or not
+        let kp = ECDSASignatureKeyPair::generate(*alg).unwrap();
+        let pk = ECDSASignaturePublicKey::from_raw(kp.alg, kp.raw_public_key()).unwrap();
+        for _ in 0..8 {
+            let signature = kp.sign_oneshot(b"test").unwrap();
+            let normalized = signature.normalize_s(*alg).unwrap();
+            assert!(normalized.encoded[coordinate_len..] <= half_order[..]);
+            pk.verify(b"test", &normalized.encoded).unwrap();
+        }
+    }
 }
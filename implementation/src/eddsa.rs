@@ -1,45 +1,129 @@
-use parking_lot::Mutex;
+use alloc::borrow::Cow;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use blake2::Digest as _;
 use ring::signature::KeyPair as _;
-use std::sync::Arc;
-use zeroize::Zeroize;
+use zeroize::Zeroizing;
 
+#[cfg(feature = "batch")]
+use super::batch;
+use super::der;
+use super::drbg::*;
 use super::error::*;
+use super::framing;
 use super::handles::*;
 use super::signature::*;
 use super::signature_keypair::*;
-use super::WASI_CRYPTO_CTX;
+use super::signature_op::*;
+use super::sync::Mutex;
+use super::CryptoContext;
+
+// Return the input signed with Ed25519: the message itself, or its BLAKE2b-512 digest for
+// `Ed25519_BLAKE2b512`, as with libsodium's `crypto_generichash()` prehashing.
+pub(crate) fn signed_input(alg: SignatureAlgorithm, input: &[u8]) -> Result<Cow<'_, [u8]>, Error> {
+    match alg {
+        SignatureAlgorithm::Ed25519 => Ok(Cow::Borrowed(input)),
+        SignatureAlgorithm::Ed25519_BLAKE2b512 => {
+            Ok(Cow::Owned(blake2::Blake2b512::digest(input).to_vec()))
+        }
+        _ => bail!(CryptoError::NotAvailable),
+    }
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct EdDSASignatureOp {
     pub alg: SignatureAlgorithm,
+    pub strict_verification: bool,
+    pub prehashed_only: bool,
+    pub non_exportable: bool,
 }
 
 impl EdDSASignatureOp {
     pub fn new(alg: SignatureAlgorithm) -> Self {
-        EdDSASignatureOp { alg }
+        EdDSASignatureOp {
+            alg,
+            strict_verification: false,
+            prehashed_only: false,
+            non_exportable: false,
+        }
+    }
+
+    pub fn with_options(
+        alg: SignatureAlgorithm,
+        options: &SignatureOptions,
+    ) -> Result<Self, Error> {
+        ensure!(
+            !options.strict_verification || cfg!(feature = "strict"),
+            CryptoError::NotAvailable
+        );
+        ensure!(
+            !options.prehashed_only || alg == SignatureAlgorithm::Ed25519_BLAKE2b512,
+            CryptoError::NotAvailable
+        );
+        let op = EdDSASignatureOp {
+            alg,
+            strict_verification: options.strict_verification,
+            prehashed_only: options.prehashed_only,
+            non_exportable: options.non_exportable,
+        };
+        Ok(op)
     }
 }
 
+/// Clones share the PKCS#8 document and the parsed ring key. Both are released together when
+/// the last clone is dropped, and the document is zeroized at that point.
 #[derive(Clone, Debug)]
 pub struct EdDSASignatureKeyPair {
     pub alg: SignatureAlgorithm,
-    pub pkcs8: Vec<u8>,
+    pub pkcs8: Arc<Zeroizing<Vec<u8>>>,
     pub ring_kp: Arc<ring::signature::Ed25519KeyPair>,
+    pub strict_verification: bool,
+    pub prehashed_only: bool,
+    pub non_exportable: bool,
 }
 
 impl EdDSASignatureKeyPair {
+    /// Both PKCS#8 v1 (seed only) and v2 (seed and public key) encodings are accepted.
+    /// v1 keys are converted, so that exported key pairs are always PKCS#8 v2, like the
+    /// ones generated by ring.
     pub fn from_pkcs8(alg: SignatureAlgorithm, pkcs8: &[u8]) -> Result<Self, Error> {
-        let ring_kp = ring::signature::Ed25519KeyPair::from_pkcs8(pkcs8)
+        // The public key is still checked against the seed if it is present
+        let ring_kp = ring::signature::Ed25519KeyPair::from_pkcs8_maybe_unchecked(pkcs8)
             .map_err(|_| CryptoError::InvalidKey)?;
+        let parsed = der::parse_pkcs8(pkcs8).map_err(|_| CryptoError::InvalidKey)?;
+        let pkcs8 = match parsed.public_key {
+            Some(_) => pkcs8.to_vec(),
+            None => der::pkcs8_v2(
+                parsed.alg_id,
+                parsed.private_key,
+                ring_kp.public_key().as_ref(),
+            ),
+        };
         let kp = EdDSASignatureKeyPair {
             alg,
-            pkcs8: pkcs8.to_vec(),
+            pkcs8: Arc::new(Zeroizing::new(pkcs8)),
             ring_kp: Arc::new(ring_kp),
+            strict_verification: false,
+            prehashed_only: false,
+            non_exportable: false,
         };
         Ok(kp)
     }
 
+    /// Build a key pair from a 32-byte RFC 8032 seed, the secret key that Ed25519
+    /// implementations store and export. The seed is hashed with SHA-512: the first half of
+    /// the digest is clamped into the secret scalar, and the second half is used to derive
+    /// the nonces. This is what callers want, unless they only hold a secret scalar without
+    /// its seed; see `EdDSASignaturePublicKey::from_raw_scalar()`.
+    pub fn from_seed(alg: SignatureAlgorithm, seed: &[u8]) -> Result<Self, Error> {
+        ensure!(seed.len() == 32, CryptoError::InvalidKey);
+        let private_key = Zeroizing::new(der::encode(der::TAG_OCTET_STRING, seed));
+        let pkcs8 = Zeroizing::new(der::pkcs8_v1(&der::oid(der::OID_ED25519), &private_key));
+        Self::from_pkcs8(alg, &pkcs8)
+    }
+
     pub fn as_pkcs8(&self) -> Result<&[u8], Error> {
+        ensure!(!self.non_exportable, CryptoError::NotAvailable);
         Ok(&self.pkcs8)
     }
 
@@ -53,39 +137,101 @@ impl EdDSASignatureKeyPair {
     pub fn raw_public_key(&self) -> &[u8] {
         self.ring_kp.public_key().as_ref()
     }
-}
 
-impl Drop for EdDSASignatureKeyPair {
-    fn drop(&mut self) {
-        self.pkcs8.zeroize();
+    /// Sign the BLAKE2b-512 digest of a message, computed by the caller.
+    pub fn sign_digest(&self, digest: &[u8]) -> Result<EdDSASignature, Error> {
+        ensure!(
+            self.alg == SignatureAlgorithm::Ed25519_BLAKE2b512,
+            CryptoError::NotAvailable
+        );
+        ensure!(digest.len() == 64, CryptoError::InvalidLength);
+        Ok(EdDSASignature(self.ring_kp.sign(digest).as_ref().to_vec()))
+    }
+
+    /// Sign a complete message, without the buffering and locking of a signature state.
+    /// Prehashed-only key pairs can only sign digests, and return `NotAvailable`.
+    pub fn sign_oneshot(&self, input: &[u8]) -> Result<EdDSASignature, Error> {
+        ensure!(!self.prehashed_only, CryptoError::NotAvailable);
+        let input = signed_input(self.alg, input)?;
+        let signature_u8 = self.ring_kp.sign(&input).as_ref().to_vec();
+        let signature = EdDSASignature(signature_u8);
+        Ok(signature)
     }
 }
 
 #[derive(Clone, Copy, Debug)]
 pub struct EdDSASignatureKeyPairBuilder {
     pub alg: SignatureAlgorithm,
+    pub strict_verification: bool,
+    pub prehashed_only: bool,
+    pub non_exportable: bool,
 }
 
 impl EdDSASignatureKeyPairBuilder {
     pub fn new(alg: SignatureAlgorithm) -> Self {
-        EdDSASignatureKeyPairBuilder { alg }
+        EdDSASignatureKeyPairBuilder {
+            alg,
+            strict_verification: false,
+            prehashed_only: false,
+            non_exportable: false,
+        }
     }
 
-    pub fn generate(&self) -> Result<Handle, Error> {
-        let kp = EdDSASignatureKeyPair::generate(self.alg)?;
-        let handle = WASI_CRYPTO_CTX
-            .signature_keypair_manager
-            .register(SignatureKeyPair::EdDSA(kp))?;
+    pub fn from_op(op: EdDSASignatureOp) -> Self {
+        EdDSASignatureKeyPairBuilder {
+            alg: op.alg,
+            strict_verification: op.strict_verification,
+            prehashed_only: op.prehashed_only,
+            non_exportable: op.non_exportable,
+        }
+    }
+
+    /// Generate a key pair owned by the caller, instead of registering it in the context.
+    pub fn generate_owned(&self) -> Result<EdDSASignatureKeyPair, Error> {
+        let mut kp = EdDSASignatureKeyPair::generate(self.alg)?;
+        kp.strict_verification = self.strict_verification;
+        kp.prehashed_only = self.prehashed_only;
+        kp.non_exportable = self.non_exportable;
+        Ok(kp)
+    }
+
+    /// Generate a key pair whose seed is drawn from `drbg`.
+    pub fn generate_with_drbg(&self, drbg: &Drbg) -> Result<EdDSASignatureKeyPair, Error> {
+        let mut seed = Zeroizing::new([0u8; 32]);
+        drbg.fill(&mut *seed)?;
+        let private_key = Zeroizing::new(der::encode(der::TAG_OCTET_STRING, &*seed));
+        let pkcs8 = Zeroizing::new(der::pkcs8_v1(&der::oid(der::OID_ED25519), &private_key));
+        self.import_owned(&pkcs8)
+    }
+
+    pub fn generate(&self, ctx: &CryptoContext) -> Result<Handle, Error> {
+        let manager = &ctx.signature_keypair_manager;
+        let kp = manager.generate(&SignatureKeyPairBuilder::EdDSA(*self))?;
+        let handle = manager.register(kp)?;
         Ok(handle)
     }
 
-    pub fn import(&self, encoded: &[u8], encoding: KeyPairEncoding) -> Result<Handle, Error> {
+    /// Import a PKCS#8 key pair owned by the caller, instead of registering it in the context.
+    pub fn import_owned(&self, pkcs8: &[u8]) -> Result<EdDSASignatureKeyPair, Error> {
+        let mut kp = EdDSASignatureKeyPair::from_pkcs8(self.alg, pkcs8)?;
+        kp.strict_verification = self.strict_verification;
+        kp.prehashed_only = self.prehashed_only;
+        kp.non_exportable = self.non_exportable;
+        Ok(kp)
+    }
+
+    pub fn import(
+        &self,
+        ctx: &CryptoContext,
+        encoded: &[u8],
+        encoding: KeyPairEncoding,
+    ) -> Result<Handle, Error> {
         match encoding {
             KeyPairEncoding::PKCS8 => {}
             _ => bail!(CryptoError::NotAvailable),
         };
-        let kp = EdDSASignatureKeyPair::from_pkcs8(self.alg, encoded)?;
-        let handle = WASI_CRYPTO_CTX
+        let kp = self.import_owned(encoded)?;
+        let handle = ctx
             .signature_keypair_manager
             .register(SignatureKeyPair::EdDSA(kp))?;
         Ok(handle)
@@ -101,6 +247,22 @@ impl AsRef<[u8]> for EdDSASignature {
     }
 }
 
+/// Lowercase hex of the signature.
+impl core::fmt::Display for EdDSASignature {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        fmt_hex(&self.0, f)
+    }
+}
+
+/// Parse a hex-encoded signature. The length is checked when the signature is verified.
+impl core::str::FromStr for EdDSASignature {
+    type Err = Error;
+
+    fn from_str(hex: &str) -> Result<Self, Error> {
+        Ok(EdDSASignature::new(parse_hex(hex)?))
+    }
+}
+
 impl EdDSASignature {
     pub fn new(encoded: Vec<u8>) -> Self {
         EdDSASignature(encoded)
@@ -121,16 +283,51 @@ impl EdDSASignatureState {
         }
     }
 
+    /// Create a state whose input buffer can hold `capacity` bytes without reallocating.
+    pub fn with_capacity(kp: EdDSASignatureKeyPair, capacity: usize) -> Self {
+        EdDSASignatureState {
+            kp,
+            input: Mutex::new(Vec::with_capacity(capacity)),
+        }
+    }
+
     pub fn update(&self, input: &[u8]) -> Result<(), Error> {
-        self.input.lock().extend_from_slice(input);
+        self.update_from_slices(&[input])
+    }
+
+    pub fn update_framed(&self, field: &[u8]) -> Result<(), Error> {
+        self.update_framed_from_slices(&[field])
+    }
+
+    pub fn update_from_slices(&self, slices: &[&[u8]]) -> Result<(), Error> {
+        let mut input = self.input.lock();
+        slices
+            .iter()
+            .for_each(|slice| input.extend_from_slice(slice));
+        Ok(())
+    }
+
+    pub fn update_framed_from_slices(&self, fields: &[&[u8]]) -> Result<(), Error> {
+        let mut input = self.input.lock();
+        fields
+            .iter()
+            .for_each(|field| framing::append_framed(&mut input, field));
         Ok(())
     }
 
     pub fn sign(&self) -> Result<EdDSASignature, Error> {
+        self.kp.sign_oneshot(&self.input.lock())
+    }
+
+    /// Write the signature into `out`, and return its length. Like `sign_oneshot()`, this
+    /// returns `NotAvailable` for prehashed-only key pairs.
+    pub fn sign_into(&self, out: &mut [u8]) -> Result<usize, Error> {
+        ensure!(!self.kp.prehashed_only, CryptoError::NotAvailable);
+        ensure!(out.len() >= 64, CryptoError::InvalidLength);
         let input = self.input.lock();
-        let signature_u8 = self.kp.ring_kp.sign(&input).as_ref().to_vec();
-        let signature = EdDSASignature(signature_u8);
-        Ok(signature)
+        let signature = self.kp.ring_kp.sign(&signed_input(self.kp.alg, &input)?);
+        out[..64].copy_from_slice(signature.as_ref());
+        Ok(64)
     }
 }
 
@@ -141,46 +338,616 @@ pub struct EdDSASignatureVerificationState {
 }
 
 impl EdDSASignatureVerificationState {
-    pub fn new(pk: EdDSASignaturePublicKey) -> Self {
-        EdDSASignatureVerificationState {
+    pub fn new(pk: EdDSASignaturePublicKey) -> Result<Self, Error> {
+        pk.validate()?;
+        let state = EdDSASignatureVerificationState {
             pk,
             input: Mutex::new(vec![]),
-        }
+        };
+        Ok(state)
     }
 
     pub fn update(&self, input: &[u8]) -> Result<(), Error> {
-        self.input.lock().extend_from_slice(input);
+        self.update_from_slices(&[input])
+    }
+
+    pub fn update_framed(&self, field: &[u8]) -> Result<(), Error> {
+        self.update_framed_from_slices(&[field])
+    }
+
+    pub fn update_from_slices(&self, slices: &[&[u8]]) -> Result<(), Error> {
+        let mut input = self.input.lock();
+        slices
+            .iter()
+            .for_each(|slice| input.extend_from_slice(slice));
         Ok(())
     }
 
+    pub fn update_framed_from_slices(&self, fields: &[&[u8]]) -> Result<(), Error> {
+        let mut input = self.input.lock();
+        fields
+            .iter()
+            .for_each(|field| framing::append_framed(&mut input, field));
+        Ok(())
+    }
+
+    pub fn input_digest(&self) -> Vec<u8> {
+        let input = self.input.lock();
+        match self.pk.alg {
+            SignatureAlgorithm::Ed25519_BLAKE2b512 => blake2::Blake2b512::digest(&*input).to_vec(),
+            _ => ring::digest::digest(self.pk.alg.digest_alg(), &input)
+                .as_ref()
+                .to_vec(),
+        }
+    }
+
     pub fn verify(&self, signature: &EdDSASignature) -> Result<(), Error> {
-        let ring_alg = match self.pk.alg {
-            SignatureAlgorithm::Ed25519 => &ring::signature::ED25519,
-            _ => bail!(CryptoError::NotAvailable),
-        };
-        let ring_pk = ring::signature::UnparsedPublicKey::new(ring_alg, self.pk.as_raw()?);
+        let input = self.input.lock();
+        let input = signed_input(self.pk.alg, &input)?;
+        if self.pk.strict_verification {
+            return self.verify_strict(&input, signature);
+        }
+        let ring_pk =
+            ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, self.pk.as_raw()?);
         ring_pk
-            .verify(self.input.lock().as_ref(), signature.as_ref())
+            .verify(&input, signature.as_ref())
+            .map_err(|_| CryptoError::VerificationFailed)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "strict")]
+    fn verify_strict(&self, input: &[u8], signature: &EdDSASignature) -> Result<(), Error> {
+        let mut raw_pk = [0u8; 32];
+        raw_pk.copy_from_slice(self.pk.as_raw()?);
+        let dalek_pk = ed25519_dalek::VerifyingKey::from_bytes(&raw_pk)
+            .map_err(|_| CryptoError::InvalidKey)?;
+        let dalek_signature = ed25519_dalek::Signature::from_slice(signature.as_ref())
+            .map_err(|_| CryptoError::InvalidSignature)?;
+        dalek_pk
+            .verify_strict(input, &dalek_signature)
             .map_err(|_| CryptoError::VerificationFailed)?;
         Ok(())
     }
+
+    #[cfg(not(feature = "strict"))]
+    fn verify_strict(&self, _input: &[u8], _signature: &EdDSASignature) -> Result<(), Error> {
+        bail!(CryptoError::NotAvailable)
+    }
 }
+
 #[derive(Clone, Debug)]
 pub struct EdDSASignaturePublicKey {
     pub alg: SignatureAlgorithm,
     pub raw: Vec<u8>,
+    pub strict_verification: bool,
 }
 
+// Encoded points of order 1, 2, 4 and 8. With such a public key, a signature is valid for
+// many messages, so a signer could claim to have signed content that it didn't sign.
+const ED25519_SMALL_ORDER_POINTS: [[u8; 32]; 8] = [
+    [
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00,
+    ],
+    [
+        0xec, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0x7f,
+    ],
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00,
+    ],
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x80,
+    ],
+    [
+        0xc7, 0x17, 0x6a, 0x70, 0x3d, 0x4d, 0xd8, 0x4f, 0xba, 0x3c, 0x0b, 0x76, 0x0d, 0x10, 0x67,
+        0x0f, 0x2a, 0x20, 0x53, 0xfa, 0x2c, 0x39, 0xcc, 0xc6, 0x4e, 0xc7, 0xfd, 0x77, 0x92, 0xac,
+        0x03, 0x7a,
+    ],
+    [
+        0xc7, 0x17, 0x6a, 0x70, 0x3d, 0x4d, 0xd8, 0x4f, 0xba, 0x3c, 0x0b, 0x76, 0x0d, 0x10, 0x67,
+        0x0f, 0x2a, 0x20, 0x53, 0xfa, 0x2c, 0x39, 0xcc, 0xc6, 0x4e, 0xc7, 0xfd, 0x77, 0x92, 0xac,
+        0x03, 0xfa,
+    ],
+    [
+        0x26, 0xe8, 0x95, 0x8f, 0xc2, 0xb2, 0x27, 0xb0, 0x45, 0xc3, 0xf4, 0x89, 0xf2, 0xef, 0x98,
+        0xf0, 0xd5, 0xdf, 0xac, 0x05, 0xd3, 0xc6, 0x33, 0x39, 0xb1, 0x38, 0x02, 0x88, 0x6d, 0x53,
+        0xfc, 0x05,
+    ],
+    [
+        0x26, 0xe8, 0x95, 0x8f, 0xc2, 0xb2, 0x27, 0xb0, 0x45, 0xc3, 0xf4, 0x89, 0xf2, 0xef, 0x98,
+        0xf0, 0xd5, 0xdf, 0xac, 0x05, 0xd3, 0xc6, 0x33, 0x39, 0xb1, 0x38, 0x02, 0x88, 0x6d, 0x53,
+        0xfc, 0x85,
+    ],
+];
+
 impl EdDSASignaturePublicKey {
     pub fn from_raw(alg: SignatureAlgorithm, raw: &[u8]) -> Result<Self, Error> {
         let pk = EdDSASignaturePublicKey {
             alg,
             raw: raw.to_vec(),
+            strict_verification: false,
         };
+        pk.validate()?;
         Ok(pk)
     }
 
     pub fn as_raw(&self) -> Result<&[u8], Error> {
         Ok(&self.raw)
     }
+
+    /// Verify a signature of a complete message, using the key's algorithm.
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), Error> {
+        let expected_len = match self.alg {
+            SignatureAlgorithm::Ed25519 | SignatureAlgorithm::Ed25519_BLAKE2b512 => 64,
+            _ => bail!(CryptoError::NotAvailable),
+        };
+        ensure!(
+            signature.len() == expected_len,
+            CryptoError::InvalidSignature
+        );
+        let state = EdDSASignatureVerificationState::new(self.clone())?;
+        state.update(message)?;
+        state.verify(&EdDSASignature::new(signature.to_vec()))
+    }
+
+    /// Verify a signature of the BLAKE2b-512 digest of a message, computed by the caller.
+    pub fn verify_digest(&self, digest: &[u8], signature: &EdDSASignature) -> Result<(), Error> {
+        ensure!(
+            self.alg == SignatureAlgorithm::Ed25519_BLAKE2b512,
+            CryptoError::NotAvailable
+        );
+        ensure!(digest.len() == 64, CryptoError::InvalidLength);
+        let state = EdDSASignatureVerificationState::new(self.clone())?;
+        if self.strict_verification {
+            return state.verify_strict(digest, signature);
+        }
+        let ring_pk =
+            ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, self.as_raw()?);
+        ring_pk
+            .verify(digest, signature.as_ref())
+            .map_err(|_| CryptoError::VerificationFailed)?;
+        Ok(())
+    }
+
+    /// Verify signatures of many complete messages, parsing the key only once. Returns one
+    /// entry per item, set to `true` if its signature is valid.
+    ///
+    /// With the `batch` feature, all the signatures are first checked at once with a random
+    /// linear combination, and only verified one by one if the batch fails, to find the
+    /// invalid ones. Strict verification always checks signatures one by one.
+    pub fn verify_batch(&self, items: &[(&[u8], &EdDSASignature)]) -> Result<Vec<bool>, Error> {
+        self.validate()?;
+        if self.strict_verification {
+            return self.verify_batch_strict(items);
+        }
+        let inputs = items
+            .iter()
+            .map(|(message, signature)| Ok((signed_input(self.alg, message)?, signature.as_ref())))
+            .collect::<Result<Vec<_>, Error>>()?;
+        #[cfg(feature = "batch")]
+        {
+            let batch: Vec<(&[u8], &[u8])> = inputs
+                .iter()
+                .map(|(input, signature)| (input.as_ref(), *signature))
+                .collect();
+            if batch::ed25519_verify_batch(self.as_raw()?, &batch)? {
+                return Ok(vec![true; items.len()]);
+            }
+        }
+        let ring_pk =
+            ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, self.as_raw()?);
+        let valid = inputs
+            .iter()
+            .map(|(input, signature)| ring_pk.verify(input, signature).is_ok())
+            .collect();
+        Ok(valid)
+    }
+
+    #[cfg(feature = "strict")]
+    fn verify_batch_strict(&self, items: &[(&[u8], &EdDSASignature)]) -> Result<Vec<bool>, Error> {
+        let mut raw_pk = [0u8; 32];
+        raw_pk.copy_from_slice(self.as_raw()?);
+        let dalek_pk = ed25519_dalek::VerifyingKey::from_bytes(&raw_pk)
+            .map_err(|_| CryptoError::InvalidKey)?;
+        items
+            .iter()
+            .map(|(message, signature)| {
+                let input = signed_input(self.alg, message)?;
+                let valid = ed25519_dalek::Signature::from_slice(signature.as_ref())
+                    .map(|dalek_signature| dalek_pk.verify_strict(&input, &dalek_signature).is_ok())
+                    .unwrap_or(false);
+                Ok(valid)
+            })
+            .collect()
+    }
+
+    #[cfg(not(feature = "strict"))]
+    fn verify_batch_strict(&self, _items: &[(&[u8], &EdDSASignature)]) -> Result<Vec<bool>, Error> {
+        bail!(CryptoError::NotAvailable)
+    }
+
+    pub fn as_spki(&self) -> Result<Vec<u8>, Error> {
+        match self.alg {
+            SignatureAlgorithm::Ed25519 | SignatureAlgorithm::Ed25519_BLAKE2b512 => {}
+            _ => bail!(CryptoError::NotAvailable),
+        };
+        let alg_id = der::sequence(&[&der::oid(der::OID_ED25519)]);
+        Ok(der::sequence(&[&alg_id, &der::bit_string(&self.raw)]))
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        let expected_len = match self.alg {
+            SignatureAlgorithm::Ed25519 | SignatureAlgorithm::Ed25519_BLAKE2b512 => {
+                ring::signature::ED25519_PUBLIC_KEY_LEN
+            }
+            _ => bail!(CryptoError::NotAvailable),
+        };
+        ensure!(self.raw.len() == expected_len, CryptoError::InvalidKey);
+        ensure!(
+            !ED25519_SMALL_ORDER_POINTS
+                .iter()
+                .any(|point| point[..] == self.raw[..]),
+            CryptoError::InvalidKey
+        );
+        Ok(())
+    }
+}
+
+#[test]
+fn test_verification_state_rejects_malformed_key() {
+    let kp = EdDSASignatureKeyPair::generate(SignatureAlgorithm::Ed25519).unwrap();
+    let new_state = |raw_pk: &[u8]| {
+        let pk = EdDSASignaturePublicKey {
+            alg: kp.alg,
+            raw: raw_pk.to_vec(),
+            strict_verification: false,
+        };
+        EdDSASignatureVerificationState::new(pk)
+    };
+    new_state(kp.raw_public_key()).unwrap();
+    assert!(new_state(&kp.raw_public_key()[..31]).is_err());
+    assert!(new_state(&[kp.raw_public_key(), &[0x00]].concat()).is_err());
+    for point in &ED25519_SMALL_ORDER_POINTS {
+        assert!(new_state(point).is_err());
+    }
+}
+
+#[test]
+fn test_generate_owned() {
+    let kp_builder = EdDSASignatureKeyPairBuilder::new(SignatureAlgorithm::Ed25519);
+    let kp = kp_builder.generate_owned().unwrap();
+    let pk = EdDSASignaturePublicKey::from_raw(kp.alg, kp.raw_public_key()).unwrap();
+
+    let state = EdDSASignatureState::new(kp);
+    state.update(b"test").unwrap();
+    let signature = state.sign().unwrap();
+    drop(state);
+
+    let verification_state = EdDSASignatureVerificationState::new(pk).unwrap();
+    verification_state.update(b"test").unwrap();
+    verification_state.verify(&signature).unwrap();
+}
+
+#[test]
+fn test_small_order_public_keys() {
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    for point in &ED25519_SMALL_ORDER_POINTS {
+        let err =
+            crate::signature_publickey_import(op_handle, point, crate::PublicKeyEncoding::Raw)
+                .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::InvalidKey)
+        ));
+    }
+}
+
+#[test]
+fn test_sign_oneshot() {
+    let kp = EdDSASignatureKeyPair::generate(SignatureAlgorithm::Ed25519).unwrap();
+    let state = EdDSASignatureState::new(kp.clone());
+    state.update(&[0x42; 32]).unwrap();
+    assert_eq!(state.sign().unwrap(), kp.sign_oneshot(&[0x42; 32]).unwrap());
+}
+
+#[test]
+fn test_signature_hex() {
+    // Test 1 of RFC 8032, section 7.1, signing the empty message
+    const PK: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+    const SIGNATURE: &str = concat!(
+        "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39",
+        "701cf9b46bd25bf5f0595bbe24655141438e7a100b"
+    );
+    let signature: EdDSASignature = SIGNATURE.parse().unwrap();
+    assert_eq!(signature.to_string(), SIGNATURE);
+    let pk =
+        EdDSASignaturePublicKey::from_raw(SignatureAlgorithm::Ed25519, &parse_hex(PK).unwrap())
+            .unwrap();
+    pk.verify(b"", signature.as_ref()).unwrap();
+    assert_eq!(
+        SIGNATURE.to_uppercase().parse::<EdDSASignature>().unwrap(),
+        signature
+    );
+
+    for invalid in &[&SIGNATURE[1..], "zz", "-1"] {
+        let err = invalid.parse::<EdDSASignature>().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::InvalidSignature)
+        ));
+    }
+}
+
+#[test]
+fn test_blake2b_prehashed_signature() {
+    // Signature of the BLAKE2b-512 digest of `MESSAGE` with the key derived from `SEED`,
+    // computed with OpenSSL. Ed25519 signatures are deterministic, so this is also what
+    // libsodium's `crypto_sign_detached()` returns for `crypto_generichash()` with 64 bytes.
+    const MESSAGE: &[u8] = b"libsodium prehashed message";
+    const SEED: [u8; 32] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d,
+        0x1e, 0x1f,
+    ];
+    const SIGNATURE: [u8; 64] = [
+        0xef, 0x59, 0x7b, 0x1e, 0x87, 0xd8, 0xa9, 0x9a, 0xc9, 0xda, 0x3a, 0x01, 0x16, 0xf1, 0xff,
+        0xb2, 0x34, 0x29, 0x62, 0x61, 0x65, 0x9e, 0x16, 0xb2, 0xba, 0x0a, 0x86, 0x49, 0xf0, 0xcf,
+        0x30, 0x0b, 0x5c, 0x96, 0x68, 0xa3, 0x53, 0xb3, 0x78, 0xc8, 0xf7, 0x6e, 0x55, 0xf0, 0x66,
+        0x27, 0xd7, 0x56, 0xb3, 0x61, 0x7d, 0x36, 0xbd, 0xc8, 0xb7, 0x20, 0x2a, 0xae, 0xd7, 0x1c,
+        0xfd, 0x79, 0xd6, 0x0e,
+    ];
+    const PUBLIC_KEY: [u8; 32] = [
+        0x03, 0xa1, 0x07, 0xbf, 0xf3, 0xce, 0x10, 0xbe, 0x1d, 0x70, 0xdd, 0x18, 0xe7, 0x4b, 0xc0,
+        0x99, 0x67, 0xe4, 0xd6, 0x30, 0x9b, 0xa5, 0x0d, 0x5f, 0x1d, 0xdc, 0x86, 0x64, 0x12, 0x55,
+        0x31, 0xb8,
+    ];
+
+    let alg = SignatureAlgorithm::Ed25519_BLAKE2b512;
+    let pkcs8 = [
+        &[
+            0x30, 0x53, 0x02, 0x01, 0x01, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22,
+            0x04, 0x20,
+        ][..],
+        &SEED,
+        &[0xa1, 0x23, 0x03, 0x21, 0x00],
+        &PUBLIC_KEY,
+    ]
+    .concat();
+    let kp = EdDSASignatureKeyPair::from_pkcs8(alg, &pkcs8).unwrap();
+    assert_eq!(kp.sign_oneshot(MESSAGE).unwrap().as_ref(), &SIGNATURE[..]);
+
+    let pk = EdDSASignaturePublicKey::from_raw(alg, &PUBLIC_KEY).unwrap();
+    pk.verify(MESSAGE, &SIGNATURE).unwrap();
+    assert!(pk.verify(b"other", &SIGNATURE).is_err());
+    let pk = EdDSASignaturePublicKey::from_raw(SignatureAlgorithm::Ed25519, &PUBLIC_KEY).unwrap();
+    assert!(pk.verify(MESSAGE, &SIGNATURE).is_err());
+}
+
+#[test]
+fn test_strict_verification() {
+    use super::signature_publickey::SignaturePublicKey;
+
+    // A mixed-order public key and a signature with a small-order `R` component, satisfying
+    // the cofactorless verification equation. `ring` accepts it; the strict rules don't.
+    const MESSAGE: &[u8] = b"small order R 5";
+    const PUBLIC_KEY: [u8; 32] = [
+        0xe6, 0xb9, 0x37, 0x38, 0x6b, 0x28, 0x61, 0x6b, 0x48, 0x90, 0x2a, 0x14, 0xdf, 0x24, 0xfe,
+        0x94, 0x05, 0x0e, 0x78, 0xae, 0xac, 0x3e, 0x9e, 0x20, 0xae, 0x8d, 0xbf, 0x17, 0x37, 0x02,
+        0x6a, 0x13,
+    ];
+    const SIGNATURE: [u8; 64] = [
+        0xc7, 0x17, 0x6a, 0x70, 0x3d, 0x4d, 0xd8, 0x4f, 0xba, 0x3c, 0x0b, 0x76, 0x0d, 0x10, 0x67,
+        0x0f, 0x2a, 0x20, 0x53, 0xfa, 0x2c, 0x39, 0xcc, 0xc6, 0x4e, 0xc7, 0xfd, 0x77, 0x92, 0xac,
+        0x03, 0x7a, 0x46, 0xc7, 0xcc, 0x71, 0xf8, 0x1c, 0xb9, 0x59, 0xb3, 0x7a, 0xfb, 0xa4, 0x57,
+        0xc8, 0x36, 0x4b, 0x6f, 0xa4, 0x8c, 0xb1, 0x9e, 0x13, 0xaa, 0x3f, 0x0f, 0x27, 0xff, 0x27,
+        0x80, 0x11, 0x1a, 0x09,
+    ];
+
+    let alg = SignatureAlgorithm::Ed25519;
+    let mut pk = EdDSASignaturePublicKey::from_raw(alg, &PUBLIC_KEY).unwrap();
+    pk.verify(MESSAGE, &SIGNATURE).unwrap();
+
+    let options = SignatureOptions {
+        strict_verification: true,
+        ..Default::default()
+    };
+    if !cfg!(feature = "strict") {
+        assert!(crate::signature_op_open_with_options("Ed25519", &options).is_err());
+        return;
+    }
+    pk.strict_verification = true;
+    assert!(pk.verify(MESSAGE, &SIGNATURE).is_err());
+
+    // Strict keys still verify regular signatures, and are derived from strict ops
+    let op_handle = crate::signature_op_open_with_options("Ed25519", &options).unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    let pk = match crate::WASI_CRYPTO_CTX
+        .signature_publickey_manager
+        .get(pk_handle)
+        .unwrap()
+    {
+        SignaturePublicKey::EdDSA(pk) => pk,
+        _ => unreachable!(),
+    };
+    assert!(pk.strict_verification);
+    let kp = match crate::WASI_CRYPTO_CTX
+        .signature_keypair_manager
+        .get(kp_handle)
+        .unwrap()
+    {
+        SignatureKeyPair::EdDSA(kp) => kp,
+        _ => unreachable!(),
+    };
+    let signature = kp.sign_oneshot(MESSAGE).unwrap();
+    pk.verify(MESSAGE, signature.as_ref()).unwrap();
+
+    // A non-canonical `S` component, `S + L`, is rejected either way
+    let mut malleated = signature.as_ref().to_vec();
+    let l = [
+        0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde,
+        0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x10,
+    ];
+    let mut carry = 0u16;
+    for (x, y) in malleated[32..].iter_mut().zip(l.iter()) {
+        let sum = *x as u16 + *y as u16 + carry;
+        *x = sum as u8;
+        carry = sum >> 8;
+    }
+    assert!(pk.verify(MESSAGE, &malleated).is_err());
+    let mut pk = pk;
+    pk.strict_verification = false;
+    assert!(pk.verify(MESSAGE, &malleated).is_err());
+}
+
+#[test]
+fn test_sign_into_prehashed_only() {
+    let mut kp = EdDSASignatureKeyPair::generate(SignatureAlgorithm::Ed25519_BLAKE2b512).unwrap();
+    kp.prehashed_only = true;
+    let state = EdDSASignatureState::new(kp);
+    state.update(b"test").unwrap();
+    let err = state.sign_into(&mut [0u8; 64]).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::NotAvailable)
+    ));
+}
+
+#[test]
+fn test_pkcs8_versions() {
+    const SEED: [u8; 32] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d,
+        0x1e, 0x1f,
+    ];
+    const PUBLIC_KEY: [u8; 32] = [
+        0x03, 0xa1, 0x07, 0xbf, 0xf3, 0xce, 0x10, 0xbe, 0x1d, 0x70, 0xdd, 0x18, 0xe7, 0x4b, 0xc0,
+        0x99, 0x67, 0xe4, 0xd6, 0x30, 0x9b, 0xa5, 0x0d, 0x5f, 0x1d, 0xdc, 0x86, 0x64, 0x12, 0x55,
+        0x31, 0xb8,
+    ];
+    const HEADER: [u8; 9] = [0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22];
+
+    let v1 = [
+        &[0x30, 0x2e, 0x02, 0x01, 0x00][..],
+        &HEADER,
+        &[0x04, 0x20],
+        &SEED,
+    ]
+    .concat();
+    let v2 = [
+        &[0x30, 0x53, 0x02, 0x01, 0x01][..],
+        &HEADER,
+        &[0x04, 0x20],
+        &SEED,
+        &[0xa1, 0x23, 0x03, 0x21, 0x00],
+        &PUBLIC_KEY,
+    ]
+    .concat();
+
+    let alg = SignatureAlgorithm::Ed25519;
+    for encoded in &[&v1, &v2] {
+        let kp = EdDSASignatureKeyPair::from_pkcs8(alg, encoded).unwrap();
+        assert_eq!(kp.raw_public_key(), &PUBLIC_KEY[..]);
+        assert_eq!(kp.as_pkcs8().unwrap(), &v2[..]);
+    }
+
+    let mut mismatched = v2.clone();
+    *mismatched.last_mut().unwrap() ^= 0x01;
+    assert!(EdDSASignatureKeyPair::from_pkcs8(alg, &mismatched).is_err());
+}
+
+#[test]
+fn test_pkcs8_conversion_leaves_no_secret_behind() {
+    let seed = [0x42u8; 32];
+    let private_key = Zeroizing::new(der::encode(der::TAG_OCTET_STRING, &seed));
+    let v1 = Zeroizing::new(der::pkcs8_v1(&der::oid(der::OID_ED25519), &private_key));
+
+    // The v1 document is converted to v2, and the key pair is released afterwards
+    let leaked = crate::test_support::frees_secret(seed, || {
+        let kp = EdDSASignatureKeyPair::from_pkcs8(SignatureAlgorithm::Ed25519, &v1).unwrap();
+        assert_eq!(der::parse_pkcs8(kp.as_pkcs8().unwrap()).unwrap().version, 1);
+        kp.sign_oneshot(b"test").unwrap();
+    });
+    assert!(!leaked);
+}
+
+#[test]
+fn test_shared_secret_lifetime() {
+    let kp = EdDSASignatureKeyPair::generate(SignatureAlgorithm::Ed25519).unwrap();
+    let pkcs8 = kp.as_pkcs8().unwrap().to_vec();
+    let weak_pkcs8 = Arc::downgrade(&kp.pkcs8);
+    let weak_ring_kp = Arc::downgrade(&kp.ring_kp);
+
+    let clone = kp.clone();
+    assert_eq!(Arc::strong_count(&kp.pkcs8), 2);
+    assert_eq!(Arc::strong_count(&kp.ring_kp), 2);
+    drop(kp);
+    assert_eq!(Arc::strong_count(&clone.pkcs8), 1);
+    assert_eq!(Arc::strong_count(&clone.ring_kp), 1);
+    assert_eq!(clone.as_pkcs8().unwrap(), &pkcs8[..]);
+    clone.sign_oneshot(b"test").unwrap();
+
+    drop(clone);
+    assert!(weak_pkcs8.upgrade().is_none());
+    assert!(weak_ring_kp.upgrade().is_none());
+}
+
+#[test]
+fn test_state_with_capacity() {
+    let kp = EdDSASignatureKeyPair::generate(SignatureAlgorithm::Ed25519).unwrap();
+    let state = EdDSASignatureState::with_capacity(kp.clone(), 1 << 20);
+    assert!(state.input.lock().capacity() >= 1 << 20);
+    state.update(b"test").unwrap();
+    assert_eq!(
+        state.sign().unwrap().as_ref(),
+        kp.sign_oneshot(b"test").unwrap().as_ref()
+    );
+}
+
+#[test]
+fn test_verify_batch() {
+    for &alg in &[
+        SignatureAlgorithm::Ed25519,
+        SignatureAlgorithm::Ed25519_BLAKE2b512,
+    ] {
+        let kp = EdDSASignatureKeyPairBuilder::new(alg)
+            .generate_owned()
+            .unwrap();
+        let pk = EdDSASignaturePublicKey::from_raw(alg, kp.raw_public_key()).unwrap();
+        let first = kp.sign_oneshot(b"first").unwrap();
+        let second = kp.sign_oneshot(b"second").unwrap();
+        let truncated = EdDSASignature::new(first.as_ref()[..32].to_vec());
+
+        let items: [(&[u8], &EdDSASignature); 4] = [
+            (b"first", &first),
+            (b"second", &first),
+            (b"second", &second),
+            (b"first", &truncated),
+        ];
+        assert_eq!(
+            pk.verify_batch(&items).unwrap(),
+            vec![true, false, true, false]
+        );
+        assert!(pk.verify_batch(&[]).unwrap().is_empty());
+
+        let mut strict_pk = pk.clone();
+        strict_pk.strict_verification = true;
+        if cfg!(feature = "strict") {
+            assert_eq!(
+                strict_pk.verify_batch(&items).unwrap(),
+                vec![true, false, true, false]
+            );
+        } else {
+            assert!(strict_pk.verify_batch(&items).is_err());
+        }
+    }
 }
@@ -1,6 +1,6 @@
-pub use anyhow::{bail, ensure, Error};
+pub use anyhow::{bail, ensure, Context, Error};
 
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Clone, Copy, Debug, Eq, PartialEq)]
 pub enum CryptoError {
     #[error("Operation not available")]
     NotAvailable,
@@ -20,6 +20,20 @@ pub enum CryptoError {
     InvalidHandle,
     #[error("Overflow")]
     Overflow,
+    #[error("Digest mismatch")]
+    DigestMismatch,
+    #[error("Expired")]
+    Expired,
+    #[error("Nonce reused")]
+    NonceReused,
+    #[error("Invalid length")]
+    InvalidLength,
+    #[error("Key mismatch")]
+    KeyMismatch,
+    #[error("Policy violation")]
+    PolicyViolation,
+    #[error("Unsupported algorithm")]
+    UnsupportedAlgorithm,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -35,9 +49,92 @@ pub enum WasiCryptoError {
     Closed = 7,
     InvalidHandle = 8,
     Overflow = 9,
+    DigestMismatch = 10,
+    Expired = 11,
+    NonceReused = 12,
+    InvalidLength = 13,
+    KeyMismatch = 14,
+    PolicyViolation = 15,
+    UnsupportedAlgorithm = 16,
+}
+
+impl WasiCryptoError {
+    /// The `errno` to return across the ABI for an error returned by this crate. Errors that
+    /// don't carry a `CryptoError`, either directly or as their context, are reported as
+    /// algorithm failures.
+    pub fn from_error(err: &Error) -> Self {
+        err.downcast_ref::<CryptoError>()
+            .map_or(WasiCryptoError::AlgorithmFailure, CryptoError::as_raw_errno)
+    }
+}
+
+/// ring doesn't tell why an operation failed, so its errors are reported as algorithm failures.
+/// Functions returning `CryptoError` can use a plain `?`. Functions returning `Error` need
+/// `.map_err(CryptoError::from)?`: without `std`, ring's error doesn't implement
+/// `core::error::Error`, so a plain `?` doesn't compile, and with `std` the resulting error
+/// wouldn't downcast to `CryptoError`.
+///
+/// Call sites that know better map ring's errors explicitly: signature verification to
+/// `VerificationFailed`, and the EdDSA key pair parsing and generation to `InvalidKey` and
+/// `RNGError`, since ring only fails there on malformed keys or when the system RNG fails.
+impl From<ring::error::Unspecified> for CryptoError {
+    fn from(_: ring::error::Unspecified) -> Self {
+        CryptoError::AlgorithmFailure
+    }
+}
+
+impl From<WasiCryptoError> for u16 {
+    fn from(errno: WasiCryptoError) -> Self {
+        errno as u16
+    }
+}
+
+impl From<CryptoError> for u16 {
+    fn from(err: CryptoError) -> Self {
+        err.as_raw_errno().into()
+    }
+}
+
+/// Coarse categories of errors, for hosts mapping them to their own error codes.
+/// Unlike `WasiCryptoError`, a category only changes if the meaning of an error changes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CryptoErrorKind {
+    /// The operation, algorithm or encoding is not supported.
+    Unsupported,
+    /// A key, a signature or another input is malformed.
+    InvalidInput,
+    /// The input is well-formed, but a signature or a digest doesn't match.
+    VerificationFailed,
+    /// A handle doesn't refer to a live object.
+    InvalidHandle,
+    /// A valid input was rejected by a policy, such as a freshness or an algorithm policy.
+    Rejected,
+    /// The system or the underlying implementation failed.
+    Internal,
 }
 
 impl CryptoError {
+    // No wildcard arm, so that a new variant has to be given a category
+    pub fn kind(&self) -> CryptoErrorKind {
+        match self {
+            CryptoError::NotAvailable | CryptoError::UnsupportedAlgorithm => {
+                CryptoErrorKind::Unsupported
+            }
+            CryptoError::InvalidKey
+            | CryptoError::InvalidSignature
+            | CryptoError::Overflow
+            | CryptoError::InvalidLength => CryptoErrorKind::InvalidInput,
+            CryptoError::VerificationFailed
+            | CryptoError::DigestMismatch
+            | CryptoError::KeyMismatch => CryptoErrorKind::VerificationFailed,
+            CryptoError::Closed | CryptoError::InvalidHandle => CryptoErrorKind::InvalidHandle,
+            CryptoError::Expired | CryptoError::NonceReused | CryptoError::PolicyViolation => {
+                CryptoErrorKind::Rejected
+            }
+            CryptoError::RNGError | CryptoError::AlgorithmFailure => CryptoErrorKind::Internal,
+        }
+    }
+
     pub fn as_raw_errno(&self) -> WasiCryptoError {
         match self {
             CryptoError::NotAvailable => WasiCryptoError::NotAvailable,
@@ -49,6 +146,101 @@ impl CryptoError {
             CryptoError::Closed => WasiCryptoError::Closed,
             CryptoError::InvalidHandle => WasiCryptoError::InvalidHandle,
             CryptoError::Overflow => WasiCryptoError::Overflow,
+            CryptoError::DigestMismatch => WasiCryptoError::DigestMismatch,
+            CryptoError::Expired => WasiCryptoError::Expired,
+            CryptoError::NonceReused => WasiCryptoError::NonceReused,
+            CryptoError::InvalidLength => WasiCryptoError::InvalidLength,
+            CryptoError::KeyMismatch => WasiCryptoError::KeyMismatch,
+            CryptoError::PolicyViolation => WasiCryptoError::PolicyViolation,
+            CryptoError::UnsupportedAlgorithm => WasiCryptoError::UnsupportedAlgorithm,
         }
     }
 }
+
+#[test]
+fn test_error_kinds() {
+    let kinds = [
+        (CryptoError::NotAvailable, CryptoErrorKind::Unsupported),
+        (CryptoError::InvalidKey, CryptoErrorKind::InvalidInput),
+        (
+            CryptoError::VerificationFailed,
+            CryptoErrorKind::VerificationFailed,
+        ),
+        (CryptoError::RNGError, CryptoErrorKind::Internal),
+        (CryptoError::AlgorithmFailure, CryptoErrorKind::Internal),
+        (CryptoError::InvalidSignature, CryptoErrorKind::InvalidInput),
+        (CryptoError::Closed, CryptoErrorKind::InvalidHandle),
+        (CryptoError::InvalidHandle, CryptoErrorKind::InvalidHandle),
+        (CryptoError::Overflow, CryptoErrorKind::InvalidInput),
+        (
+            CryptoError::DigestMismatch,
+            CryptoErrorKind::VerificationFailed,
+        ),
+        (CryptoError::Expired, CryptoErrorKind::Rejected),
+        (CryptoError::NonceReused, CryptoErrorKind::Rejected),
+        (CryptoError::InvalidLength, CryptoErrorKind::InvalidInput),
+        (
+            CryptoError::KeyMismatch,
+            CryptoErrorKind::VerificationFailed,
+        ),
+        (CryptoError::PolicyViolation, CryptoErrorKind::Rejected),
+        (
+            CryptoError::UnsupportedAlgorithm,
+            CryptoErrorKind::Unsupported,
+        ),
+    ];
+    for (error, kind) in kinds.iter() {
+        assert_eq!(error.kind(), *kind, "{:?}", error);
+    }
+}
+
+#[test]
+fn test_ring_error() {
+    fn unbound_key(key: &[u8]) -> Result<ring::aead::UnboundKey, CryptoError> {
+        Ok(ring::aead::UnboundKey::new(&ring::aead::AES_256_GCM, key)?)
+    }
+    assert!(unbound_key(&[0u8; 32]).is_ok());
+    assert_eq!(
+        unbound_key(&[0u8; 31]).unwrap_err(),
+        CryptoError::AlgorithmFailure
+    );
+    let err: Error = unbound_key(&[0u8; 31]).unwrap_err().into();
+    assert_eq!(
+        err.downcast_ref::<CryptoError>(),
+        Some(&CryptoError::AlgorithmFailure)
+    );
+}
+
+#[test]
+fn test_errno() {
+    // The codes of the `errno` enumeration in `wasi_ephemeral_crypto.witx`
+    let errnos = [
+        (CryptoError::NotAvailable, 1),
+        (CryptoError::InvalidKey, 2),
+        (CryptoError::VerificationFailed, 3),
+        (CryptoError::RNGError, 4),
+        (CryptoError::AlgorithmFailure, 5),
+        (CryptoError::InvalidSignature, 6),
+        (CryptoError::Closed, 7),
+        (CryptoError::InvalidHandle, 8),
+        (CryptoError::Overflow, 9),
+        (CryptoError::DigestMismatch, 10),
+        (CryptoError::Expired, 11),
+        (CryptoError::NonceReused, 12),
+        (CryptoError::InvalidLength, 13),
+        (CryptoError::KeyMismatch, 14),
+        (CryptoError::PolicyViolation, 15),
+        (CryptoError::UnsupportedAlgorithm, 16),
+    ];
+    for (error, errno) in errnos {
+        let err = Error::new(CryptoError::Closed).context(error);
+        assert_eq!(u16::from(WasiCryptoError::from_error(&err)), errno);
+        let error = err.downcast::<CryptoError>().unwrap();
+        assert_eq!(u16::from(error), errno);
+    }
+    assert_eq!(u16::from(WasiCryptoError::Success), 0);
+    assert_eq!(
+        WasiCryptoError::from_error(&anyhow::anyhow!("Invalid derivation path")),
+        WasiCryptoError::AlgorithmFailure
+    );
+}
@@ -0,0 +1,60 @@
+use alloc::vec::Vec;
+
+/// Append a field to a multi-part input, prefixed with its length as a 64-bit big-endian
+/// integer. With this framing, the fields `["ab", "c"]` and `["a", "bc"]` are absorbed as
+/// different inputs, while a plain concatenation would be ambiguous.
+pub fn append_framed(input: &mut Vec<u8>, field: &[u8]) {
+    input.extend_from_slice(&length_prefix(field));
+    input.extend_from_slice(field);
+}
+
+/// Return the prefix written before a framed field, for inputs that are not buffered.
+pub fn length_prefix(field: &[u8]) -> [u8; 8] {
+    (field.len() as u64).to_be_bytes()
+}
+
+/// Read a framed field from the start of `input`, and advance `input` past it.
+pub fn read_framed<'t>(input: &mut &'t [u8]) -> Option<&'t [u8]> {
+    if input.len() < 8 {
+        return None;
+    }
+    let (prefix, rest) = input.split_at(8);
+    let mut len_bytes = [0u8; 8];
+    len_bytes.copy_from_slice(prefix);
+    let len = u64::from_be_bytes(len_bytes);
+    if len > rest.len() as u64 {
+        return None;
+    }
+    let (field, rest) = rest.split_at(len as usize);
+    *input = rest;
+    Some(field)
+}
+
+#[test]
+fn test_framed_fields() {
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let sign_fields = |fields: &[&[u8]]| {
+        let state_handle = crate::signature_state_open(kp_handle).unwrap();
+        for field in fields {
+            crate::signature_state_update_framed(state_handle, field).unwrap();
+        }
+        let signature_handle = crate::signature_state_sign(state_handle).unwrap();
+        crate::signature_export(signature_handle, crate::SignatureEncoding::Raw).unwrap()
+    };
+    let signature = sign_fields(&[b"ab", b"c"]);
+    assert_eq!(signature, sign_fields(&[b"ab", b"c"]));
+    assert_ne!(signature, sign_fields(&[b"a", b"bc"]));
+
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    let verification_state_handle = crate::signature_verification_state_open(pk_handle).unwrap();
+    crate::signature_verification_state_update_framed(verification_state_handle, b"ab").unwrap();
+    crate::signature_verification_state_update_framed(verification_state_handle, b"c").unwrap();
+    crate::signature_verification_state_verify_with_encoding(
+        verification_state_handle,
+        &signature,
+        crate::SignatureEncoding::Raw,
+    )
+    .unwrap();
+}
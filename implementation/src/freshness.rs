@@ -0,0 +1,170 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::error::*;
+use super::framing;
+use super::handles::*;
+use super::signature::*;
+
+const FRESHNESS_LABEL: &[u8] = b"wasi-crypto fresh";
+
+fn fresh_input(message: &[u8], nonce: &[u8], timestamp: u64) -> Vec<u8> {
+    let mut input = vec![];
+    framing::append_framed(&mut input, FRESHNESS_LABEL);
+    framing::append_framed(&mut input, nonce);
+    framing::append_framed(&mut input, &timestamp.to_be_bytes());
+    framing::append_framed(&mut input, message);
+    input
+}
+
+/// Sign a message along with a nonce and a UNIX timestamp (in seconds), that are both part of
+/// the signed input.
+pub fn signature_sign_fresh(
+    kp_handle: Handle,
+    message: &[u8],
+    nonce: &[u8],
+    timestamp: u64,
+) -> Result<Handle, Error> {
+    ensure!(!nonce.is_empty(), "Empty nonce");
+    sign_input(kp_handle, &fresh_input(message, nonce, timestamp))
+}
+
+/// Verify a signature created with `signature_sign_fresh()`.
+/// The signature is rejected with `Expired` if the timestamp is more than `max_age` away from
+/// the current time, and with `NonceReused` if `nonce_store` returns `false`, meaning that
+/// the nonce has already been seen. `nonce_store` is only called for valid, fresh signatures.
+pub fn signature_verify_fresh(
+    pk_handle: Handle,
+    message: &[u8],
+    nonce: &[u8],
+    timestamp: u64,
+    signature_handle: Handle,
+    max_age: Duration,
+    nonce_store: &mut dyn FnMut(&[u8]) -> bool,
+) -> Result<(), Error> {
+    verify_input(
+        pk_handle,
+        &fresh_input(message, nonce, timestamp),
+        signature_handle,
+    )?;
+    check_freshness(timestamp, max_age)?;
+    ensure!(nonce_store(nonce), CryptoError::NonceReused);
+    Ok(())
+}
+
+fn check_freshness(timestamp: u64, max_age: Duration) -> Result<(), Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| CryptoError::AlgorithmFailure)?
+        .as_secs();
+    ensure!(
+        now.abs_diff(timestamp) <= max_age.as_secs(),
+        CryptoError::Expired
+    );
+    Ok(())
+}
+
+/// Verify a signature of a message that starts with a UNIX timestamp (in seconds), encoded
+/// as a 64-bit big-endian integer. After the signature has been verified, it is rejected
+/// with `Expired` if the timestamp is more than `max_age` away from the current time.
+pub fn signature_verify_timestamped(
+    pk_handle: Handle,
+    message: &[u8],
+    signature_handle: Handle,
+    max_age: Duration,
+) -> Result<(), Error> {
+    ensure!(message.len() >= 8, CryptoError::InvalidLength);
+    verify_input(pk_handle, message, signature_handle)?;
+    let mut timestamp_bytes = [0u8; 8];
+    timestamp_bytes.copy_from_slice(&message[..8]);
+    check_freshness(u64::from_be_bytes(timestamp_bytes), max_age)
+}
+
+#[test]
+fn test_fresh_signatures() {
+    use std::collections::HashSet;
+
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let max_age = Duration::from_secs(60);
+    let mut seen_nonces = HashSet::new();
+    let mut nonce_store = |nonce: &[u8]| seen_nonces.insert(nonce.to_vec());
+
+    let signature_handle = signature_sign_fresh(kp_handle, b"test", b"nonce1", now).unwrap();
+    let verify = |timestamp, nonce_store: &mut dyn FnMut(&[u8]) -> bool| {
+        signature_verify_fresh(
+            pk_handle,
+            b"test",
+            b"nonce1",
+            timestamp,
+            signature_handle,
+            max_age,
+            nonce_store,
+        )
+    };
+    assert!(verify(now + 1, &mut nonce_store).is_err());
+    verify(now, &mut nonce_store).unwrap();
+    let err = verify(now, &mut nonce_store).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::NonceReused)
+    ));
+
+    let stale_timestamp = now - 3600;
+    let signature_handle =
+        signature_sign_fresh(kp_handle, b"test", b"nonce2", stale_timestamp).unwrap();
+    let err = signature_verify_fresh(
+        pk_handle,
+        b"test",
+        b"nonce2",
+        stale_timestamp,
+        signature_handle,
+        max_age,
+        &mut nonce_store,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::Expired)
+    ));
+}
+
+#[test]
+fn test_timestamped_signatures() {
+    let op_handle = crate::signature_op_open("ECDSA_P256_SHA256").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let max_age = Duration::from_secs(60);
+    let timestamped = |timestamp: u64| [&timestamp.to_be_bytes()[..], b"test"].concat();
+
+    let message = timestamped(now);
+    let signature_handle = sign_input(kp_handle, &message).unwrap();
+    signature_verify_timestamped(pk_handle, &message, signature_handle, max_age).unwrap();
+    assert!(signature_verify_timestamped(
+        pk_handle,
+        &timestamped(now + 1),
+        signature_handle,
+        max_age
+    )
+    .is_err());
+
+    let message = timestamped(now - 3600);
+    let signature_handle = sign_input(kp_handle, &message).unwrap();
+    let err =
+        signature_verify_timestamped(pk_handle, &message, signature_handle, max_age).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::Expired)
+    ));
+    assert!(signature_verify_timestamped(pk_handle, b"short", signature_handle, max_age).is_err());
+}
@@ -1,14 +1,34 @@
-use parking_lot::Mutex;
-use std::collections::HashMap;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
 
 use super::error::*;
+use super::sync::Mutex;
 
 pub type Handle = u32;
 
+const COUNTER_MASK: Handle = 0xffff;
+
+/// Serialize a handle independently of the host byte order.
+pub fn handle_to_le_bytes(handle: Handle) -> [u8; 4] {
+    handle.to_le_bytes()
+}
+
+pub fn handle_to_be_bytes(handle: Handle) -> [u8; 4] {
+    handle.to_be_bytes()
+}
+
+fn handle_bytes(bytes: &[u8]) -> Result<[u8; 4], Error> {
+    ensure!(bytes.len() == 4, CryptoError::InvalidLength);
+    let mut handle_bytes = [0u8; 4];
+    handle_bytes.copy_from_slice(bytes);
+    Ok(handle_bytes)
+}
+
 struct HandlesManagerInner<HandleType: Clone + Sync> {
     last_handle: Handle,
-    map: HashMap<Handle, HandleType>,
+    map: BTreeMap<Handle, HandleType>,
     type_id: u8,
+    context_id: u8,
 }
 
 pub struct HandlesManager<HandleType: Clone + Sync> {
@@ -17,8 +37,14 @@ pub struct HandlesManager<HandleType: Clone + Sync> {
 
 impl<HandleType: Clone + Sync> HandlesManager<HandleType> {
     pub fn new(handle_type: u8) -> Self {
+        Self::with_context(handle_type, 0)
+    }
+
+    /// A manager whose handles are also tagged with `context_id`, so that a handle created
+    /// in one context is not valid in another one.
+    pub fn with_context(handle_type: u8, context_id: u8) -> Self {
         HandlesManager {
-            inner: Mutex::new(HandlesManagerInner::new(handle_type)),
+            inner: Mutex::new(HandlesManagerInner::with_context(handle_type, context_id)),
         }
     }
 
@@ -31,26 +57,67 @@ impl<HandleType: Clone + Sync> HandlesManager<HandleType> {
     }
 
     pub fn get(&self, handle: Handle) -> Result<HandleType, Error> {
-        self.inner.lock().get(handle).map(|x| x.clone())
+        self.inner.lock().get(handle).cloned()
+    }
+
+    pub fn count(&self) -> usize {
+        self.inner.lock().map.len()
+    }
+
+    /// Return a copy of the registered handles and objects, sorted by handle. Objects
+    /// registered or closed afterwards don't affect it.
+    pub fn snapshot(&self) -> Vec<(Handle, HandleType)> {
+        self.inner
+            .lock()
+            .map
+            .iter()
+            .map(|(&handle, object)| (handle, object.clone()))
+            .collect()
+    }
+
+    /// Deserialize a handle, and check that it is within the range this manager allocates
+    /// from. The handle doesn't have to be currently registered.
+    pub fn handle_from_le_bytes(&self, bytes: &[u8]) -> Result<Handle, Error> {
+        self.check_range(Handle::from_le_bytes(handle_bytes(bytes)?))
+    }
+
+    pub fn handle_from_be_bytes(&self, bytes: &[u8]) -> Result<Handle, Error> {
+        self.check_range(Handle::from_be_bytes(handle_bytes(bytes)?))
+    }
+
+    fn check_range(&self, handle: Handle) -> Result<Handle, Error> {
+        ensure!(
+            handle & !COUNTER_MASK == self.inner.lock().prefix(),
+            CryptoError::InvalidHandle
+        );
+        Ok(handle)
     }
 }
 
 impl<HandleType: Clone + Sync> HandlesManagerInner<HandleType> {
-    pub fn new(type_id: u8) -> Self {
-        HandlesManagerInner {
-            last_handle: (type_id as Handle).rotate_right(8),
-            map: HashMap::new(),
+    fn with_context(type_id: u8, context_id: u8) -> Self {
+        let mut inner = HandlesManagerInner {
+            last_handle: 0,
+            map: BTreeMap::new(),
             type_id,
-        }
+            context_id,
+        };
+        inner.last_handle = inner.prefix();
+        inner
+    }
+
+    // The type in the top byte, then the context, then the counter
+    fn prefix(&self) -> Handle {
+        (self.type_id as Handle) << 24 | (self.context_id as Handle) << 16
     }
 
     pub fn close(&mut self, handle: Handle) -> Result<(), Error> {
-        self.map.remove(&handle).ok_or(CryptoError::Closed)?;
+        self.map.remove(&handle).ok_or(CryptoError::InvalidHandle)?;
         Ok(())
     }
 
     fn next_handle(&self, handle: Handle) -> Handle {
-        ((handle.wrapping_add(1) << 8) | (self.type_id as Handle)).rotate_right(8)
+        self.prefix() | (handle.wrapping_add(1) & COUNTER_MASK)
     }
 
     pub fn register(&mut self, op: HandleType) -> Result<Handle, Error> {
@@ -59,8 +126,8 @@ impl<HandleType: Clone + Sync> HandlesManagerInner<HandleType> {
             if !self.map.contains_key(&handle) {
                 break;
             }
-            ensure!(handle != self.last_handle, "No more handles");
-            handle = self.next_handle(self.last_handle);
+            ensure!(handle != self.last_handle, CryptoError::Overflow);
+            handle = self.next_handle(handle);
         }
         self.last_handle = handle;
         ensure!(self.map.insert(handle, op).is_none(), "Collision");
@@ -72,3 +139,94 @@ impl<HandleType: Clone + Sync> HandlesManagerInner<HandleType> {
         Ok(op)
     }
 }
+
+#[test]
+fn test_handles_manager() {
+    let strings: HandlesManager<String> = HandlesManager::new(0x10);
+    let numbers: HandlesManager<u64> = HandlesManager::new(0x11);
+
+    let string_handle = strings.register("test".to_string()).unwrap();
+    let number_handle = numbers.register(42).unwrap();
+    let number_handle2 = numbers.register(43).unwrap();
+    assert_ne!(number_handle, number_handle2);
+    assert_eq!((string_handle >> 24, number_handle >> 24), (0x10, 0x11));
+    assert_eq!((strings.count(), numbers.count()), (1, 2));
+    assert_eq!(strings.get(string_handle).unwrap(), "test");
+    assert_eq!(numbers.get(number_handle2).unwrap(), 43);
+    assert!(strings.get(number_handle).is_err());
+
+    numbers.close(number_handle).unwrap();
+    assert!(numbers.get(number_handle).is_err());
+    let err = numbers.close(number_handle).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::InvalidHandle)
+    ));
+    assert_eq!(numbers.count(), 1);
+}
+
+#[test]
+fn test_handles_manager_skips_used_handles() {
+    let mut inner: HandlesManagerInner<u8> = HandlesManagerInner::with_context(0x10, 0);
+    let handle = inner.register(1).unwrap();
+    let next_handle = inner.next_handle(handle);
+    inner.map.insert(next_handle, 2);
+    let handle2 = inner.register(3).unwrap();
+    assert_ne!(handle2, next_handle);
+    assert_eq!(inner.get(handle2).unwrap(), &3);
+}
+
+#[test]
+fn test_handle_serialization() {
+    let strings: HandlesManager<String> = HandlesManager::new(0x10);
+    let numbers: HandlesManager<u64> = HandlesManager::new(0x11);
+    let handle = strings.register("test".to_string()).unwrap();
+
+    let le = handle_to_le_bytes(handle);
+    let be = handle_to_be_bytes(handle);
+    assert_eq!(le[3], 0x10);
+    assert_eq!(be[0], 0x10);
+    assert_eq!(strings.handle_from_le_bytes(&le).unwrap(), handle);
+    assert_eq!(strings.handle_from_be_bytes(&be).unwrap(), handle);
+    assert_eq!(
+        strings
+            .get(strings.handle_from_be_bytes(&be).unwrap())
+            .unwrap(),
+        "test"
+    );
+
+    assert!(numbers.handle_from_le_bytes(&le).is_err());
+    assert!(strings.handle_from_le_bytes(&be).is_err());
+    assert!(strings.handle_from_be_bytes(&be[..3]).is_err());
+    assert!(strings.handle_from_be_bytes(&[0x10, 0, 0, 0, 1]).is_err());
+}
+
+#[test]
+fn test_handles_manager_contexts() {
+    let strings_a: HandlesManager<String> = HandlesManager::with_context(0x10, 1);
+    let strings_b: HandlesManager<String> = HandlesManager::with_context(0x10, 2);
+    let handle_a = strings_a.register("a".to_string()).unwrap();
+    let handle_b = strings_b.register("b".to_string()).unwrap();
+    assert_ne!(handle_a, handle_b);
+    assert!(strings_b.get(handle_a).is_err());
+    assert!(strings_a.get(handle_b).is_err());
+    assert!(strings_b
+        .handle_from_be_bytes(&handle_to_be_bytes(handle_a))
+        .is_err());
+}
+
+#[test]
+fn test_handles_manager_exhaustion() {
+    let mut inner: HandlesManagerInner<u8> = HandlesManagerInner::with_context(0x10, 1);
+    for _ in 0..=COUNTER_MASK {
+        inner.register(0).unwrap();
+    }
+    let err = inner.register(0).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::Overflow)
+    ));
+    let handle = inner.next_handle(inner.last_handle);
+    inner.close(handle).unwrap();
+    assert_eq!(inner.register(0).unwrap(), handle);
+}
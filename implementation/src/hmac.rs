@@ -0,0 +1,277 @@
+//! HMAC with a choice of hash function. The SHA-2 variants are computed by `ring`; HMAC-SHA3-256,
+//! for protocols that require it, is only available with the `sha3` feature.
+
+#[cfg(feature = "sha3")]
+use ::hmac::Mac as _;
+#[cfg(feature = "sha3")]
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use ring::hmac;
+
+use super::error::*;
+
+#[cfg(feature = "sha3")]
+type HmacSha3_256 = ::hmac::Hmac<sha3::Sha3_256>;
+
+/// The hash function of an HMAC key.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HashAlgorithm {
+    SHA256,
+    SHA384,
+    SHA512,
+    SHA3_256,
+}
+
+impl HashAlgorithm {
+    /// Whether HMAC with this hash function is available in this build.
+    pub fn is_supported(self) -> bool {
+        self != HashAlgorithm::SHA3_256 || cfg!(feature = "sha3")
+    }
+
+    /// The size of the tags, in bytes.
+    pub fn output_len(self) -> usize {
+        match self {
+            HashAlgorithm::SHA256 | HashAlgorithm::SHA3_256 => 32,
+            HashAlgorithm::SHA384 => 48,
+            HashAlgorithm::SHA512 => 64,
+        }
+    }
+}
+
+enum HmacKeyInner {
+    Ring(hmac::Key),
+    #[cfg(feature = "sha3")]
+    Sha3(Box<HmacSha3_256>),
+}
+
+/// A key for computing and verifying HMAC tags.
+pub struct HmacKey {
+    alg: HashAlgorithm,
+    inner: HmacKeyInner,
+}
+
+impl core::fmt::Debug for HmacKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("HmacKey")
+            .field("alg", &self.alg)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HmacKey {
+    /// Create a key for `alg`, or return `NotAvailable` if that hash function isn't
+    /// supported in this build.
+    pub fn new(alg: HashAlgorithm, key: &[u8]) -> Result<Self, Error> {
+        let ring_key = |ring_alg| HmacKeyInner::Ring(hmac::Key::new(ring_alg, key));
+        let inner = match alg {
+            HashAlgorithm::SHA256 => ring_key(hmac::HMAC_SHA256),
+            HashAlgorithm::SHA384 => ring_key(hmac::HMAC_SHA384),
+            HashAlgorithm::SHA512 => ring_key(hmac::HMAC_SHA512),
+            #[cfg(feature = "sha3")]
+            HashAlgorithm::SHA3_256 => HmacKeyInner::Sha3(Box::new(
+                HmacSha3_256::new_from_slice(key).map_err(|_| CryptoError::InvalidKey)?,
+            )),
+            #[cfg(not(feature = "sha3"))]
+            HashAlgorithm::SHA3_256 => bail!(CryptoError::NotAvailable),
+        };
+        Ok(HmacKey { alg, inner })
+    }
+
+    pub fn alg(&self) -> HashAlgorithm {
+        self.alg
+    }
+}
+
+// States are short-lived, so they aren't worth boxing
+#[allow(clippy::large_enum_variant)]
+enum HmacStateInner {
+    Ring(hmac::Context),
+    #[cfg(feature = "sha3")]
+    Sha3(HmacSha3_256),
+}
+
+/// An incremental HMAC computation.
+pub struct HmacState {
+    alg: HashAlgorithm,
+    inner: HmacStateInner,
+}
+
+impl HmacState {
+    pub fn new(key: &HmacKey) -> Self {
+        let inner = match &key.inner {
+            HmacKeyInner::Ring(ring_key) => HmacStateInner::Ring(hmac::Context::with_key(ring_key)),
+            #[cfg(feature = "sha3")]
+            HmacKeyInner::Sha3(mac) => HmacStateInner::Sha3(*mac.clone()),
+        };
+        HmacState {
+            alg: key.alg,
+            inner,
+        }
+    }
+
+    pub fn alg(&self) -> HashAlgorithm {
+        self.alg
+    }
+
+    pub fn update(&mut self, input: &[u8]) {
+        match &mut self.inner {
+            HmacStateInner::Ring(ctx) => ctx.update(input),
+            #[cfg(feature = "sha3")]
+            HmacStateInner::Sha3(mac) => mac.update(input),
+        }
+    }
+
+    /// Return the tag of the data absorbed so far.
+    pub fn sign(self) -> Vec<u8> {
+        match self.inner {
+            HmacStateInner::Ring(ctx) => ctx.sign().as_ref().to_vec(),
+            #[cfg(feature = "sha3")]
+            HmacStateInner::Sha3(mac) => mac.finalize().into_bytes().to_vec(),
+        }
+    }
+
+    /// Check `tag` against the tag of the data absorbed so far, in constant time. Return
+    /// `VerificationFailed` if they don't match.
+    pub fn verify(self, tag: &[u8]) -> Result<(), Error> {
+        let computed = self.sign();
+        ring::constant_time::verify_slices_are_equal(&computed, tag)
+            .map_err(|_| CryptoError::VerificationFailed)?;
+        Ok(())
+    }
+}
+
+/// Compute the HMAC tag of a single message.
+pub fn hmac(alg: HashAlgorithm, key: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut state = HmacState::new(&HmacKey::new(alg, key)?);
+    state.update(data);
+    Ok(state.sign())
+}
+
+/// Verify the HMAC tag of a single message, in constant time. Return `VerificationFailed` if
+/// it doesn't match.
+pub fn hmac_verify(alg: HashAlgorithm, key: &[u8], data: &[u8], tag: &[u8]) -> Result<(), Error> {
+    let mut state = HmacState::new(&HmacKey::new(alg, key)?);
+    state.update(data);
+    state.verify(tag)
+}
+
+#[test]
+fn test_hmac() {
+    let tag = |alg, key: &[u8], message: &[u8]| {
+        let key = HmacKey::new(alg, key)?;
+        let mut state = HmacState::new(&key);
+        state.update(message);
+        Ok::<_, Error>(state.sign())
+    };
+
+    // RFC 4231, test cases 2 and 6
+    let large_key = [0xaau8; 131];
+    let large_key_message: &[u8] = b"Test Using Larger Than Block-Size Key - Hash Key First";
+    assert_eq!(
+        tag(
+            HashAlgorithm::SHA384,
+            b"Jefe",
+            b"what do ya want for nothing?"
+        )
+        .unwrap(),
+        crate::signature::parse_hex(
+            "af45d2e376484031617f78d2b58a6b1b9c7ef464f5a01b47e42ec3736322445e\
+             8e2240ca5e69e2c78b3239ecfab21649"
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        tag(HashAlgorithm::SHA384, &large_key, large_key_message).unwrap(),
+        crate::signature::parse_hex(
+            "4ece084485813e9088d2c63a041bc5b44f9ef1012a2b588f3cd11f05033ac4c6\
+             0c2ef6ab4030fe8296248df163f44952"
+        )
+        .unwrap()
+    );
+
+    let key = HmacKey::new(HashAlgorithm::SHA384, b"Jefe").unwrap();
+    let mut state = HmacState::new(&key);
+    state.update(b"what do ya want ");
+    state.update(b"for nothing?");
+    let computed = state.sign();
+    assert_eq!(computed.len(), HashAlgorithm::SHA384.output_len());
+    let mut state = HmacState::new(&key);
+    state.update(b"what do ya want for nothing?");
+    state.verify(&computed).unwrap();
+    let mut state = HmacState::new(&key);
+    state.update(b"what do ya want for nothing!");
+    let err = state.verify(&computed).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::VerificationFailed)
+    ));
+
+    // Computed with Python's `hmac` and `hashlib.sha3_256`
+    let sha3_tag = tag(
+        HashAlgorithm::SHA3_256,
+        b"Jefe",
+        b"what do ya want for nothing?",
+    );
+    if !cfg!(feature = "sha3") {
+        assert!(!HashAlgorithm::SHA3_256.is_supported());
+        assert!(matches!(
+            sha3_tag.unwrap_err().downcast_ref::<CryptoError>(),
+            Some(CryptoError::NotAvailable)
+        ));
+        return;
+    }
+    assert_eq!(
+        sha3_tag.unwrap(),
+        crate::signature::parse_hex(
+            "c7d4072e788877ae3596bbb0da73b887c9171f93095b294ae857fbe2645e1ba5"
+        )
+        .unwrap()
+    );
+    let large_key: Vec<u8> = (0..200).collect();
+    assert_eq!(
+        tag(HashAlgorithm::SHA3_256, &large_key, large_key_message).unwrap(),
+        crate::signature::parse_hex(
+            "8eec50302366153768c3e7e6a5fdb64e67a92f814c9990f371b141c813e79612"
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn test_hmac_oneshot() {
+    // RFC 4231, test cases 1, 2, 3 and 6
+    let vectors: [(&[u8], &[u8], &str); 4] = [
+        (
+            &[0x0b; 20],
+            b"Hi There",
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+        ),
+        (
+            b"Jefe",
+            b"what do ya want for nothing?",
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+        ),
+        (
+            &[0xaa; 20],
+            &[0xdd; 50],
+            "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe",
+        ),
+        (
+            &[0xaa; 131],
+            b"Test Using Larger Than Block-Size Key - Hash Key First",
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+        ),
+    ];
+    for (key, data, expected) in vectors.iter() {
+        let expected = crate::signature::parse_hex(expected).unwrap();
+        assert_eq!(hmac(HashAlgorithm::SHA256, key, data).unwrap(), expected);
+        hmac_verify(HashAlgorithm::SHA256, key, data, &expected).unwrap();
+        for tag in [&expected[..31], &[0u8; 32][..]] {
+            let err = hmac_verify(HashAlgorithm::SHA256, key, data, tag).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<CryptoError>(),
+                Some(CryptoError::VerificationFailed)
+            ));
+        }
+    }
+}
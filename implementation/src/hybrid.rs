@@ -0,0 +1,126 @@
+use alloc::vec::Vec;
+
+use super::error::*;
+use super::framing;
+use super::handles::*;
+use super::signature::*;
+use super::WASI_CRYPTO_CTX;
+
+fn hybrid_input(algs: [SignatureAlgorithm; 2], message: &[u8]) -> Vec<u8> {
+    let mut input = vec![];
+    framing::append_framed(&mut input, b"wasi-crypto hybrid");
+    for alg in &algs {
+        framing::append_framed(&mut input, alg.name().as_bytes());
+    }
+    framing::append_framed(&mut input, message);
+    input
+}
+
+/// Sign a message with two key pairs, typically using different algorithms.
+///
+/// Both signatures cover the message and the pair of algorithms. The envelope is the
+/// algorithm name and the raw signature for each key pair, in order, each of them framed
+/// with a 64-bit big-endian length.
+pub fn signature_sign_hybrid(kp_handles: [Handle; 2], message: &[u8]) -> Result<Vec<u8>, Error> {
+    let kps = [
+        WASI_CRYPTO_CTX
+            .signature_keypair_manager
+            .get(kp_handles[0])?,
+        WASI_CRYPTO_CTX
+            .signature_keypair_manager
+            .get(kp_handles[1])?,
+    ];
+    let input = hybrid_input([kps[0].alg(), kps[1].alg()], message);
+    let mut envelope = vec![];
+    for kp in &kps {
+        let signature = kp.sign_oneshot(&input)?;
+        framing::append_framed(&mut envelope, kp.alg().name().as_bytes());
+        framing::append_framed(&mut envelope, signature.as_ref());
+    }
+    Ok(envelope)
+}
+
+/// Verify an envelope created with `signature_sign_hybrid()`. Both signatures must be valid.
+pub fn signature_verify_hybrid(
+    pk_handles: [Handle; 2],
+    message: &[u8],
+    envelope: &[u8],
+) -> Result<(), Error> {
+    let pks = [
+        WASI_CRYPTO_CTX
+            .signature_publickey_manager
+            .get(pk_handles[0])?,
+        WASI_CRYPTO_CTX
+            .signature_publickey_manager
+            .get(pk_handles[1])?,
+    ];
+    let input = hybrid_input([pks[0].alg(), pks[1].alg()], message);
+    let mut envelope = envelope;
+    let mut signatures = vec![];
+    for pk in &pks {
+        let alg_name = framing::read_framed(&mut envelope).ok_or(CryptoError::InvalidSignature)?;
+        let encoded = framing::read_framed(&mut envelope).ok_or(CryptoError::InvalidSignature)?;
+        ensure!(
+            alg_name == pk.alg().name().as_bytes(),
+            CryptoError::VerificationFailed
+        );
+        signatures.push(Signature::from_raw(pk.alg(), encoded)?);
+    }
+    ensure!(envelope.is_empty(), CryptoError::InvalidSignature);
+    for (pk, signature) in pks.iter().zip(&signatures) {
+        let mut state = ExclusiveSignatureVerificationState::from_publickey(pk.clone())?;
+        state.update(&input)?;
+        state.verify_signature(signature)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_hybrid_signatures() {
+    let mut kp_handles = [0; 2];
+    let mut pk_handles = [0; 2];
+    for (i, alg_str) in ["Ed25519", "ECDSA_P256_SHA256"].iter().enumerate() {
+        let op_handle = crate::signature_op_open(alg_str).unwrap();
+        let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+        kp_handles[i] = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+        pk_handles[i] = crate::signature_keypair_publickey(kp_handles[i]).unwrap();
+    }
+
+    let envelope = signature_sign_hybrid(kp_handles, b"test").unwrap();
+    signature_verify_hybrid(pk_handles, b"test", &envelope).unwrap();
+    assert!(signature_verify_hybrid(pk_handles, b"other", &envelope).is_err());
+    assert!(signature_verify_hybrid([pk_handles[1], pk_handles[0]], b"test", &envelope).is_err());
+
+    // The Ed25519 signature starts after its framed algorithm name
+    let first_signature_offset = 8 + "Ed25519".len() + 8;
+    for &offset in &[first_signature_offset, envelope.len() - 1] {
+        let mut tampered = envelope.clone();
+        tampered[offset] ^= 0x01;
+        assert!(signature_verify_hybrid(pk_handles, b"test", &tampered).is_err());
+    }
+    assert!(signature_verify_hybrid(pk_handles, b"test", &envelope[..envelope.len() - 1]).is_err());
+}
+
+#[test]
+fn test_hybrid_prehashed_only() {
+    let options = crate::SignatureOptions {
+        prehashed_only: true,
+        ..Default::default()
+    };
+    let mut kp_handles = [0; 2];
+    for (i, op_handle) in [
+        crate::signature_op_open_with_options("Ed25519_BLAKE2b512", &options).unwrap(),
+        crate::signature_op_open("ECDSA_P256_SHA256").unwrap(),
+    ]
+    .iter()
+    .enumerate()
+    {
+        let kp_builder_handle = crate::signature_keypair_builder_open(*op_handle).unwrap();
+        kp_handles[i] = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    }
+    let err = signature_sign_hybrid(kp_handles, b"test").unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::NotAvailable)
+    ));
+}
@@ -0,0 +1,110 @@
+use alloc::vec::Vec;
+
+use super::der;
+use super::ecdsa::*;
+use super::error::*;
+use super::signature::*;
+
+/// Structural analysis of an encoded signature. This doesn't say anything about the validity
+/// of the signature, which requires a public key.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SignatureInspection {
+    /// Detected encoding, or `None` if the signature doesn't match any supported encoding.
+    pub encoding: Option<SignatureEncoding>,
+    /// Length of the encoded signature, in bytes.
+    pub len: usize,
+    /// ECDSA `r` and `s` components, left-padded with zeros to the size of the curve's field.
+    pub components: Option<(Vec<u8>, Vec<u8>)>,
+    /// Whether the signature is well-formed for the algorithm.
+    pub well_formed: bool,
+}
+
+fn inspect_ecdsa(alg: SignatureAlgorithm, encoded: &[u8]) -> Result<SignatureInspection, Error> {
+    let coordinate_len = ECDSASignatureOp::coordinate_len(alg)?;
+    let encoding = if der::ecdsa_signature_to_fixed(encoded, coordinate_len).is_ok() {
+        Some(SignatureEncoding::DER)
+    } else if encoded.len() == coordinate_len * 2 {
+        Some(SignatureEncoding::Raw)
+    } else {
+        None
+    };
+    let components = encoding.and_then(|encoding| {
+        ECDSASignature::new(encoding, encoded.to_vec())
+            .components(alg)
+            .ok()
+    });
+    let well_formed = match &components {
+        Some((r, s)) => ECDSASignature::from_components(alg, r, s).is_ok(),
+        None => false,
+    };
+    Ok(SignatureInspection {
+        encoding,
+        len: encoded.len(),
+        components,
+        well_formed,
+    })
+}
+
+/// Inspect the structure of a signature, without a public key.
+pub fn signature_inspect(
+    alg: SignatureAlgorithm,
+    encoded: &[u8],
+) -> Result<SignatureInspection, Error> {
+    let well_formed = match alg {
+        SignatureAlgorithm::ECDSA_P256_SHA256 | SignatureAlgorithm::ECDSA_P384_SHA384 => {
+            return inspect_ecdsa(alg, encoded)
+        }
+        SignatureAlgorithm::Ed25519 | SignatureAlgorithm::Ed25519_BLAKE2b512 => encoded.len() == 64,
+        SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256
+        | SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA384
+        | SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512 => {
+            (2048 / 8..=8192 / 8).contains(&encoded.len())
+        }
+        SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384 => {
+            (3072 / 8..=8192 / 8).contains(&encoded.len())
+        }
+    };
+    Ok(SignatureInspection {
+        encoding: if well_formed {
+            Some(SignatureEncoding::Raw)
+        } else {
+            None
+        },
+        len: encoded.len(),
+        components: None,
+        well_formed,
+    })
+}
+
+#[test]
+fn test_signature_inspect() {
+    let alg = SignatureAlgorithm::ECDSA_P256_SHA256;
+    let (r, s) = (vec![0x01; 32], vec![0x80; 32]);
+    let fixed = [r.clone(), s.clone()].concat();
+
+    let inspection = signature_inspect(alg, &fixed).unwrap();
+    assert_eq!(inspection.encoding, Some(SignatureEncoding::Raw));
+    assert_eq!(inspection.len, 64);
+    assert_eq!(inspection.components, Some((r.clone(), s.clone())));
+    assert!(inspection.well_formed);
+
+    let encoded_der = der::ecdsa_signature_from_fixed(&fixed).unwrap();
+    let inspection = signature_inspect(alg, &encoded_der).unwrap();
+    assert_eq!(inspection.encoding, Some(SignatureEncoding::DER));
+    assert_eq!(inspection.len, encoded_der.len());
+    assert_eq!(inspection.components, Some((r, s)));
+    assert!(inspection.well_formed);
+
+    let inspection = signature_inspect(alg, &encoded_der[..encoded_der.len() - 1]).unwrap();
+    assert_eq!(inspection.encoding, None);
+    assert_eq!(inspection.components, None);
+    assert!(!inspection.well_formed);
+
+    let inspection = signature_inspect(alg, &[0xff; 64]).unwrap();
+    assert_eq!(inspection.encoding, Some(SignatureEncoding::Raw));
+    assert!(!inspection.well_formed);
+
+    let inspection = signature_inspect(SignatureAlgorithm::Ed25519, &[0; 63]).unwrap();
+    assert_eq!(inspection.encoding, None);
+    assert!(!inspection.well_formed);
+}
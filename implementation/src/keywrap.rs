@@ -0,0 +1,450 @@
+use alloc::vec::Vec;
+use cbc::cipher::block_padding::Pkcs7;
+use cbc::cipher::{BlockDecryptMut as _, BlockEncryptMut as _, KeyIvInit as _};
+use core::num::NonZeroU32;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::SecureRandom;
+use zeroize::{Zeroize, Zeroizing};
+
+use super::der;
+use super::error::*;
+use super::handles::*;
+use super::signature_keypair::*;
+
+fn wrapping_key(wrapping_key: &[u8]) -> Result<LessSafeKey, Error> {
+    let key = UnboundKey::new(&AES_256_GCM, wrapping_key).map_err(|_| CryptoError::InvalidKey)?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Export a key pair as PKCS#8, encrypted with AES-256-GCM under a 32-byte wrapping key.
+/// The result is `nonce || ciphertext || tag`, with a random nonce.
+pub fn signature_keypair_export_wrapped(
+    kp_handle: Handle,
+    wrapping_key_bytes: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let key = wrapping_key(wrapping_key_bytes)?;
+    let mut nonce = [0u8; NONCE_LEN];
+    ring::rand::SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| CryptoError::RNGError)?;
+    let mut pkcs8 = signature_keypair_export(kp_handle, KeyPairEncoding::PKCS8)?;
+    let tag = key.seal_in_place_separate_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::empty(),
+        &mut pkcs8,
+    );
+    let mut wrapped = nonce.to_vec();
+    wrapped.extend_from_slice(&pkcs8);
+    pkcs8.zeroize();
+    let tag = tag.map_err(CryptoError::from)?;
+    wrapped.extend_from_slice(tag.as_ref());
+    Ok(wrapped)
+}
+
+/// Import a key pair exported with `signature_keypair_export_wrapped()`.
+pub fn signature_keypair_import_wrapped(
+    kp_builder_handle: Handle,
+    wrapping_key_bytes: &[u8],
+    wrapped: &[u8],
+) -> Result<Handle, Error> {
+    let key = wrapping_key(wrapping_key_bytes)?;
+    ensure!(
+        wrapped.len() >= NONCE_LEN + AES_256_GCM.tag_len(),
+        CryptoError::InvalidKey
+    );
+    let (nonce, ciphertext) = wrapped.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| CryptoError::InvalidKey)?;
+    let mut buffer = ciphertext.to_vec();
+    let handle = match key.open_in_place(nonce, Aad::empty(), &mut buffer) {
+        Ok(pkcs8) => signature_keypair_import(kp_builder_handle, pkcs8, KeyPairEncoding::PKCS8),
+        Err(_) => Err(CryptoError::VerificationFailed.into()),
+    };
+    buffer.zeroize();
+    handle
+}
+
+const AES_CBC_IV_LEN: usize = 16;
+// Rejecting larger counts keeps a crafted document from tying up the importer for hours
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+
+/// Parameters of the PBKDF2-HMAC-SHA256 derivation of the key encrypting a passphrase
+/// protected PKCS#8 document. At most 10,000,000 iterations are accepted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct KdfParams {
+    pub iterations: u32,
+    pub salt_len: usize,
+}
+
+impl Default for KdfParams {
+    /// 600,000 iterations, as recommended by OWASP for PBKDF2-HMAC-SHA256, and a 16-byte salt.
+    fn default() -> Self {
+        KdfParams {
+            iterations: 600_000,
+            salt_len: 16,
+        }
+    }
+}
+
+fn pbkdf2_key(
+    prf: ring::pbkdf2::Algorithm,
+    iterations: NonZeroU32,
+    salt: &[u8],
+    password: &[u8],
+) -> Zeroizing<[u8; 32]> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    ring::pbkdf2::derive(prf, iterations, salt, password, &mut *key);
+    key
+}
+
+/// Export a key pair as an RFC 5958 `EncryptedPrivateKeyInfo`, encrypted with PBES2 using
+/// PBKDF2-HMAC-SHA256 and AES-256-CBC, as produced by `openssl pkcs8 -topk8 -v2 aes256`.
+pub fn signature_keypair_export_encrypted_pkcs8(
+    kp_handle: Handle,
+    password: &[u8],
+    kdf_params: &KdfParams,
+) -> Result<Vec<u8>, Error> {
+    let iterations = NonZeroU32::new(kdf_params.iterations).ok_or(CryptoError::InvalidLength)?;
+    ensure!(
+        iterations.get() <= MAX_PBKDF2_ITERATIONS,
+        CryptoError::Overflow
+    );
+    ensure!(kdf_params.salt_len >= 8, CryptoError::InvalidLength);
+    let rng = ring::rand::SystemRandom::new();
+    let mut salt = vec![0u8; kdf_params.salt_len];
+    let mut iv = [0u8; AES_CBC_IV_LEN];
+    rng.fill(&mut salt).map_err(|_| CryptoError::RNGError)?;
+    rng.fill(&mut iv).map_err(|_| CryptoError::RNGError)?;
+    // OpenSSL rejects the PKCS#8 v2 encoding of ring, so the public key is left out
+    let pkcs8 = Zeroizing::new(signature_keypair_export(kp_handle, KeyPairEncoding::PKCS8)?);
+    let parsed = der::parse_pkcs8(&pkcs8).map_err(|_| CryptoError::AlgorithmFailure)?;
+    let pkcs8 = Zeroizing::new(der::pkcs8_v1(parsed.alg_id, parsed.private_key));
+    let key = pbkdf2_key(
+        ring::pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        &salt,
+        password,
+    );
+    let ciphertext = cbc::Encryptor::<aes::Aes256>::new(&(*key).into(), &iv.into())
+        .encrypt_padded_vec_mut::<Pkcs7>(&pkcs8);
+    Ok(encode_pbes2(&salt, iterations.get(), &iv, &ciphertext))
+}
+
+fn encode_pbes2(salt: &[u8], iterations: u32, iv: &[u8], ciphertext: &[u8]) -> Vec<u8> {
+    let pbkdf2_params = der::sequence(&[
+        &der::encode(der::TAG_OCTET_STRING, salt),
+        &der::unsigned_integer(&iterations.to_be_bytes()),
+        &der::sequence(&[
+            &der::oid(der::OID_HMAC_WITH_SHA256),
+            &der::encode(der::TAG_NULL, &[]),
+        ]),
+    ]);
+    let pbes2_params = der::sequence(&[
+        &der::sequence(&[&der::oid(der::OID_PBKDF2), &pbkdf2_params]),
+        &der::sequence(&[
+            &der::oid(der::OID_AES256_CBC),
+            &der::encode(der::TAG_OCTET_STRING, iv),
+        ]),
+    ]);
+    der::sequence(&[
+        &der::sequence(&[&der::oid(der::OID_PBES2), &pbes2_params]),
+        &der::encode(der::TAG_OCTET_STRING, ciphertext),
+    ])
+}
+
+struct Pbes2Params<'a> {
+    prf: ring::pbkdf2::Algorithm,
+    salt: &'a [u8],
+    iterations: NonZeroU32,
+    iv: &'a [u8],
+    ciphertext: &'a [u8],
+}
+
+// Only AES-256-CBC is supported as the encryption scheme, with any SHA-1 or SHA-2 PRF
+fn parse_pbes2(encrypted: &[u8]) -> Result<Pbes2Params<'_>, Error> {
+    let mut outer = der::Decoder::new(encrypted);
+    let mut info = der::Decoder::new(outer.read(der::TAG_SEQUENCE)?);
+    outer.finish()?;
+    let mut alg_id = der::Decoder::new(info.read(der::TAG_SEQUENCE)?);
+    let ciphertext = info.read(der::TAG_OCTET_STRING)?;
+    info.finish()?;
+    ensure!(
+        alg_id.read(der::TAG_OID)? == der::OID_PBES2,
+        CryptoError::NotAvailable
+    );
+    let mut pbes2_params = der::Decoder::new(alg_id.read(der::TAG_SEQUENCE)?);
+    alg_id.finish()?;
+
+    let mut kdf = der::Decoder::new(pbes2_params.read(der::TAG_SEQUENCE)?);
+    ensure!(
+        kdf.read(der::TAG_OID)? == der::OID_PBKDF2,
+        CryptoError::NotAvailable
+    );
+    let mut pbkdf2_params = der::Decoder::new(kdf.read(der::TAG_SEQUENCE)?);
+    kdf.finish()?;
+    let salt = pbkdf2_params.read(der::TAG_OCTET_STRING)?;
+    let iterations = pbkdf2_params.read_unsigned_integer()?;
+    ensure!(iterations.len() <= 4, CryptoError::Overflow);
+    let iterations = iterations
+        .iter()
+        .fold(0u32, |acc, &x| (acc << 8) | x as u32);
+    ensure!(iterations <= MAX_PBKDF2_ITERATIONS, CryptoError::Overflow);
+    let iterations = NonZeroU32::new(iterations).ok_or(CryptoError::InvalidKey)?;
+    let (mut tag, mut content) = match pbkdf2_params.is_empty() {
+        true => (0, &[][..]),
+        false => pbkdf2_params.read_any()?,
+    };
+    // The optional key length must match AES-256
+    if tag == der::TAG_INTEGER {
+        ensure!(content == [32], CryptoError::InvalidKey);
+        (tag, content) = match pbkdf2_params.is_empty() {
+            true => (0, &[][..]),
+            false => pbkdf2_params.read_any()?,
+        };
+    }
+    let prf = match tag {
+        0 => ring::pbkdf2::PBKDF2_HMAC_SHA1,
+        der::TAG_SEQUENCE => {
+            let mut prf = der::Decoder::new(content);
+            let prf_oid = prf.read(der::TAG_OID)?;
+            if !prf.is_empty() {
+                prf.read(der::TAG_NULL)?;
+            }
+            prf.finish()?;
+            match prf_oid {
+                der::OID_HMAC_WITH_SHA1 => ring::pbkdf2::PBKDF2_HMAC_SHA1,
+                der::OID_HMAC_WITH_SHA256 => ring::pbkdf2::PBKDF2_HMAC_SHA256,
+                der::OID_HMAC_WITH_SHA384 => ring::pbkdf2::PBKDF2_HMAC_SHA384,
+                der::OID_HMAC_WITH_SHA512 => ring::pbkdf2::PBKDF2_HMAC_SHA512,
+                _ => bail!(CryptoError::NotAvailable),
+            }
+        }
+        _ => bail!(CryptoError::InvalidKey),
+    };
+    pbkdf2_params.finish()?;
+
+    let mut scheme = der::Decoder::new(pbes2_params.read(der::TAG_SEQUENCE)?);
+    pbes2_params.finish()?;
+    ensure!(
+        scheme.read(der::TAG_OID)? == der::OID_AES256_CBC,
+        CryptoError::NotAvailable
+    );
+    let iv = scheme.read(der::TAG_OCTET_STRING)?;
+    scheme.finish()?;
+    ensure!(iv.len() == AES_CBC_IV_LEN, CryptoError::InvalidKey);
+    Ok(Pbes2Params {
+        prf,
+        salt,
+        iterations,
+        iv,
+        ciphertext,
+    })
+}
+
+/// Import a key pair from a passphrase protected PKCS#8 document, such as one exported with
+/// `signature_keypair_export_encrypted_pkcs8()` or by OpenSSL. A wrong passphrase returns
+/// `VerificationFailed` in most cases, but can also return `InvalidKey`, since CBC padding
+/// is not authenticated.
+pub fn signature_keypair_import_encrypted_pkcs8(
+    kp_builder_handle: Handle,
+    encrypted: &[u8],
+    password: &[u8],
+) -> Result<Handle, Error> {
+    let params = match parse_pbes2(encrypted) {
+        Err(err) if err.downcast_ref::<CryptoError>().is_none() => bail!(CryptoError::InvalidKey),
+        params => params?,
+    };
+    let key = pbkdf2_key(params.prf, params.iterations, params.salt, password);
+    let mut iv = [0u8; AES_CBC_IV_LEN];
+    iv.copy_from_slice(params.iv);
+    let pkcs8 = cbc::Decryptor::<aes::Aes256>::new(&(*key).into(), &iv.into())
+        .decrypt_padded_vec_mut::<Pkcs7>(params.ciphertext)
+        .map(Zeroizing::new)
+        .map_err(|_| CryptoError::VerificationFailed)?;
+    signature_keypair_import(kp_builder_handle, &pkcs8, KeyPairEncoding::PKCS8)
+}
+
+#[test]
+fn test_keypair_wrapping() {
+    let op_handle = crate::signature_op_open("ECDSA_P256_SHA256").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let pkcs8 = signature_keypair_export(kp_handle, KeyPairEncoding::PKCS8).unwrap();
+
+    let wrapping_key = [0x42; 32];
+    let wrapped = signature_keypair_export_wrapped(kp_handle, &wrapping_key).unwrap();
+    assert!(!wrapped
+        .windows(pkcs8.len())
+        .any(|window| window == pkcs8.as_slice()));
+    let kp2_handle =
+        signature_keypair_import_wrapped(kp_builder_handle, &wrapping_key, &wrapped).unwrap();
+    assert_eq!(
+        signature_keypair_export(kp2_handle, KeyPairEncoding::PKCS8).unwrap(),
+        pkcs8
+    );
+
+    let err =
+        signature_keypair_import_wrapped(kp_builder_handle, &[0x43; 32], &wrapped).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::VerificationFailed)
+    ));
+
+    crate::signature_keypair_close(kp_handle).unwrap();
+    crate::signature_keypair_close(kp2_handle).unwrap();
+    crate::signature_keypair_builder_close(kp_builder_handle).unwrap();
+    crate::signature_op_close(op_handle).unwrap();
+}
+
+#[test]
+fn test_encrypted_pkcs8() {
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let pkcs8 = signature_keypair_export(kp_handle, KeyPairEncoding::PKCS8).unwrap();
+
+    let kdf_params = KdfParams {
+        iterations: 1000,
+        ..KdfParams::default()
+    };
+    let encrypted =
+        signature_keypair_export_encrypted_pkcs8(kp_handle, b"passphrase", &kdf_params).unwrap();
+    let kp2_handle =
+        signature_keypair_import_encrypted_pkcs8(kp_builder_handle, &encrypted, b"passphrase")
+            .unwrap();
+    assert_eq!(
+        signature_keypair_export(kp2_handle, KeyPairEncoding::PKCS8).unwrap(),
+        pkcs8
+    );
+    assert!(
+        signature_keypair_import_encrypted_pkcs8(kp_builder_handle, &encrypted, b"wrong").is_err()
+    );
+    let err = signature_keypair_import_encrypted_pkcs8(kp_builder_handle, &pkcs8, b"passphrase")
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::InvalidKey)
+    ));
+    assert!(signature_keypair_export_encrypted_pkcs8(
+        kp_handle,
+        b"passphrase",
+        &KdfParams {
+            iterations: 0,
+            ..kdf_params
+        }
+    )
+    .is_err());
+
+    // openssl pkcs8 -topk8 -v2 aes-256-cbc -v2prf hmacWithSHA256 -iter 2048 -outform DER
+    let openssl_encrypted = crate::signature::parse_hex(
+        "30819b305706092a864886f70d01050d304a302906092a864886f70d01050c301c04089416d475a23ebd1f\
+         02020800300c06082a864886f70d02090500301d060960864801650304012a0410ee14c506e2febbacca19\
+         8d74087f73dd044098943851565130c753bfa955842e1eb24dc9bea873712a65c041729282c6170125b319\
+         dd098c9ff89a0206ac1bbe15d6b1dc28dd4672be0557a7183cd03a7681",
+    )
+    .unwrap();
+    let kp3_handle = signature_keypair_import_encrypted_pkcs8(
+        kp_builder_handle,
+        &openssl_encrypted,
+        b"correct-horse",
+    )
+    .unwrap();
+    let kp3 = crate::WASI_CRYPTO_CTX
+        .signature_keypair_manager
+        .get(kp3_handle)
+        .unwrap();
+    assert_eq!(
+        kp3.raw_public_key(),
+        crate::signature::parse_hex(
+            "c212f0a67e905706cbc122e82fbf3b374e7b06b1c9b1986308233e55e256da21"
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn test_encrypted_pkcs8_iteration_limit() {
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+
+    let kdf_params = KdfParams {
+        iterations: MAX_PBKDF2_ITERATIONS + 1,
+        ..KdfParams::default()
+    };
+    let err = signature_keypair_export_encrypted_pkcs8(kp_handle, b"passphrase", &kdf_params)
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::Overflow)
+    ));
+
+    // Rejected before any key derivation, so this returns immediately
+    let encrypted = encode_pbes2(&[0x42; 16], u32::MAX, &[0x42; AES_CBC_IV_LEN], &[0x42; 64]);
+    let err =
+        signature_keypair_import_encrypted_pkcs8(kp_builder_handle, &encrypted, b"passphrase")
+            .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::Overflow)
+    ));
+
+    crate::signature_keypair_close(kp_handle).unwrap();
+    crate::signature_keypair_builder_close(kp_builder_handle).unwrap();
+    crate::signature_op_close(op_handle).unwrap();
+}
+
+#[test]
+#[ignore = "requires openssl; run with `cargo test -- --ignored`"]
+fn test_encrypted_pkcs8_with_openssl() {
+    use std::process::Command;
+
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let kdf_params = KdfParams {
+        iterations: 2048,
+        ..KdfParams::default()
+    };
+    let encrypted =
+        signature_keypair_export_encrypted_pkcs8(kp_handle, b"passphrase", &kdf_params).unwrap();
+
+    let path = std::env::temp_dir().join(format!("wasi-crypto-{}.p8", std::process::id()));
+    std::fs::write(&path, &encrypted).unwrap();
+    let output = Command::new("openssl")
+        .args([
+            "pkcs8",
+            "-inform",
+            "DER",
+            "-outform",
+            "DER",
+            "-passin",
+            "pass:passphrase",
+            "-in",
+        ])
+        .arg(&path)
+        .output();
+    std::fs::remove_file(&path).unwrap();
+    let output = output.expect("openssl not found");
+    assert!(output.status.success(), "{:?}", output);
+
+    // OpenSSL decrypts the same key pair
+    let kp2_handle =
+        signature_keypair_import(kp_builder_handle, &output.stdout, KeyPairEncoding::PKCS8)
+            .unwrap();
+    let (kp, kp2) = (
+        crate::WASI_CRYPTO_CTX
+            .signature_keypair_manager
+            .get(kp_handle)
+            .unwrap(),
+        crate::WASI_CRYPTO_CTX
+            .signature_keypair_manager
+            .get(kp2_handle)
+            .unwrap(),
+    );
+    assert_eq!(kp.raw_public_key(), kp2.raw_public_key());
+
+    crate::signature_keypair_close(kp_handle).unwrap();
+    crate::signature_keypair_close(kp2_handle).unwrap();
+    crate::signature_keypair_builder_close(kp_builder_handle).unwrap();
+    crate::signature_op_close(op_handle).unwrap();
+}
@@ -1,68 +1,509 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![allow(clippy::upper_case_acronyms)]
+
+#[macro_use]
+extern crate alloc;
+
 #[macro_use]
 extern crate lazy_static;
 
+#[cfg(feature = "experimental")]
+mod aggregate;
+mod agility;
+#[cfg(feature = "batch")]
+mod batch;
+mod capabilities;
+mod constant_time;
+mod csr;
+mod der;
+mod dnskey;
+mod drbg;
 mod ecdsa;
 mod eddsa;
 mod error;
+mod framing;
+#[cfg(feature = "std")]
+mod freshness;
 mod handles;
+mod hmac;
+mod hybrid;
+mod inspect;
+mod jwks;
+mod keywrap;
+mod message;
+mod nonce_reuse;
+mod possession;
+#[cfg(feature = "std")]
+mod reader;
+mod remote;
+#[cfg(feature = "std")]
+mod report;
 mod rsa;
+mod self_test;
 mod signature;
 mod signature_keypair;
+mod signature_keypair_manager;
 mod signature_op;
+mod signature_policy;
 mod signature_publickey;
+mod signature_publickey_cache;
+mod signature_publickey_encoding;
+mod sigstore;
+mod slip10;
+mod ssh;
+#[cfg(test)]
+mod test_support;
+mod timestamp;
+mod tls13;
+#[cfg(feature = "std")]
+mod vanity;
+mod webcrypto;
+mod x25519;
 
+use alloc::vec::Vec;
+
+use error::{ensure, Error};
 use handles::*;
 use signature::*;
 use signature_keypair::*;
+use signature_keypair_manager::*;
 use signature_op::*;
 use signature_publickey::*;
+use signature_publickey_cache::*;
 
-pub use error::{CryptoError, WasiCryptoError};
-pub use handles::Handle;
-pub use signature::SignatureEncoding;
-pub use signature_keypair::KeyPairEncoding;
-pub use signature_publickey::PublicKeyEncoding;
+// Locks spin when there is no operating system to park threads
+#[cfg(feature = "std")]
+use parking_lot as sync;
+#[cfg(not(feature = "std"))]
+use spin as sync;
 
+#[cfg(feature = "experimental")]
+pub use aggregate::{
+    signature_aggregate_combine, signature_aggregate_nonce, signature_aggregate_partial_sign,
+    signature_aggregate_prove_possession, signature_aggregate_publickeys, AggregateNonce,
+};
+pub use agility::signature_verify_agile;
+pub use constant_time::signature_verify_constant_time;
+pub use csr::signature_keypair_generate_csr;
+pub use dnskey::{
+    signature_dnskey_key_tag, signature_publickey_from_dnskey, signature_publickey_matches_sshfp,
+};
+pub use ecdsa::{
+    ECDSASignature, ECDSASignatureKeyPair, ECDSASignatureKeyPairBuilder, ECDSASignaturePublicKey,
+    ECDSASignatureState, ECDSASignatureVerificationState,
+};
+pub use eddsa::{
+    EdDSASignature, EdDSASignatureKeyPair, EdDSASignatureKeyPairBuilder, EdDSASignaturePublicKey,
+    EdDSASignatureState, EdDSASignatureVerificationState,
+};
+pub use error::{CryptoError, CryptoErrorKind, WasiCryptoError};
+#[cfg(feature = "std")]
+pub use freshness::{signature_sign_fresh, signature_verify_fresh, signature_verify_timestamped};
+pub use handles::{handle_to_be_bytes, handle_to_le_bytes, Handle};
+pub use hmac::{hmac, hmac_verify, HashAlgorithm, HmacKey, HmacState};
+pub use hybrid::{signature_sign_hybrid, signature_verify_hybrid};
+pub use inspect::{signature_inspect, SignatureInspection};
+pub use jwks::{signature_import_jwks, Jwks, JwksKey};
+pub use keywrap::{
+    signature_keypair_export_encrypted_pkcs8, signature_keypair_export_wrapped,
+    signature_keypair_import_encrypted_pkcs8, signature_keypair_import_wrapped, KdfParams,
+};
+pub use message::{
+    signature_sign_domain_separated, signature_sign_key_committing, signature_sign_message,
+    signature_verify_domain_separated, signature_verify_key_committing, signature_verify_message,
+    Message,
+};
+pub use nonce_reuse::signature_ecdsa_detect_nonce_reuse;
+#[cfg(feature = "dangerous")]
+pub use nonce_reuse::signature_ecdsa_recover_key_from_reuse;
+pub use possession::{signature_prove_possession, signature_verify_possession};
+pub use self_test::run_self_tests;
 pub use signature::{
-    signature_close, signature_export, signature_import, signature_state_close,
-    signature_state_open, signature_state_sign, signature_state_update,
+    AlgorithmCategory, SignatureAlgorithm, SignatureEncoding, SignatureStateOptions, SignedInput,
+};
+pub use signature_keypair::{CounterStore, KeyPairEncoding, KeyPairInfo};
+pub use signature_policy::{signature_set_algorithm_policy, AlgorithmPolicy};
+
+pub use signature_publickey::{PublicKeyEncoding, PublicKeyListFormat};
+pub use x25519::{signature_publickey_to_x25519, SharedSecret};
+
+pub use signature::{
+    signature_close, signature_export, signature_export_components, signature_import,
+    signature_import_components, signature_state_close, signature_state_open,
+    signature_state_open_with_options, signature_state_sign, signature_state_sign_and_return_input,
+    signature_state_sign_digest, signature_state_sign_into, signature_state_sign_with_public_key,
+    signature_state_update, signature_state_update_framed,
+    signature_state_update_framed_from_slices, signature_state_update_from_slices,
     signature_verification_state_close, signature_verification_state_open,
-    signature_verification_state_update, signature_verification_state_verify,
+    signature_verification_state_open_with_options, signature_verification_state_update,
+    signature_verification_state_update_framed,
+    signature_verification_state_update_framed_from_slices,
+    signature_verification_state_update_from_slices, signature_verification_state_verify,
+    signature_verification_state_verify_any_encoding,
+    signature_verification_state_verify_with_encoding,
+    signature_verification_state_verify_with_expected_digest, signature_verify_root,
 };
 
 pub use signature_keypair::{
-    signature_keypair_builder_close, signature_keypair_builder_open, signature_keypair_close,
-    signature_keypair_export, signature_keypair_from_id, signature_keypair_generate,
-    signature_keypair_id, signature_keypair_import, signature_keypair_publickey,
+    signature_install_drbg, signature_keypair_builder_close, signature_keypair_builder_open,
+    signature_keypair_close, signature_keypair_deserialize, signature_keypair_encrypt_in_memory,
+    signature_keypair_export, signature_keypair_export_into, signature_keypair_for_each,
+    signature_keypair_from_id, signature_keypair_generate,
+    signature_keypair_generate_with_public_key, signature_keypair_handles, signature_keypair_id,
+    signature_keypair_import, signature_keypair_import_detect, signature_keypair_import_expecting,
+    signature_keypair_import_with_counter_store, signature_keypair_matches_public_key,
+    signature_keypair_publickey, signature_keypair_serialize, signature_keypair_sign_count,
+    signature_publickey_matches_keypair,
 };
 
-pub use signature_op::{signature_op_close, signature_op_open};
+pub use signature_op::SignatureOptions;
 
+pub use signature_op::{signature_op_close, signature_op_open, signature_op_open_with_options};
+
+#[cfg(feature = "async")]
+pub use reader::signature_sign_async_reader;
+#[cfg(feature = "std")]
+pub use reader::{signature_sign_reader, signature_verify_from_reader};
+pub use remote::{signature_keypair_from_remote_signer, RemoteSigner};
+#[cfg(feature = "std")]
+pub use report::{signature_verify_report, VerifyResult};
 pub use signature_publickey::{
-    signature_publickey_close, signature_publickey_export, signature_publickey_import,
+    signature_publickey_cache_set_capacity, signature_publickey_close, signature_publickey_export,
+    signature_publickey_import, signature_publickey_import_list,
+};
+pub use sigstore::{
+    signature_sigstore_bundle_publickey, signature_verify_sigstore_bundle, SigstoreBundle,
 };
+pub use slip10::signature_keypair_derive_slip10_ed25519;
+pub use ssh::signature_publickey_ssh_cert_body;
+pub use timestamp::{
+    signature_timestamp_request, signature_verify_timestamp_response, TimestampHashAlgorithm,
+};
+pub use tls13::{
+    signature_sign_tls13_certificate_verify, signature_verify_tls13_certificate_verify,
+};
+#[cfg(feature = "std")]
+pub use vanity::signature_keypair_generate_vanity;
+pub use webcrypto::signature_verify_webcrypto_ecdsa;
 
-pub struct WasiCryptoCtx {
+/// The tables of the objects that handles refer to. The `signature_*` functions use a
+/// process-wide default context; embedders that need isolated instances, for example one per
+/// tenant, can create their own, and generate or import key pairs with its methods.
+/// Handles are only valid in the context they were created in: they are tagged with an 8-bit
+/// context identifier, so that using them in another context fails with `InvalidHandle`.
+/// This leaves room for 255 contexts besides the default one, and for 65,536 live handles of
+/// each type per context. Past these limits, creating a context or registering an object
+/// fails with `Overflow`.
+pub struct CryptoContext {
     pub signature_op_manager: HandlesManager<SignatureOp>,
     pub signature_keypair_builder_manager: HandlesManager<SignatureKeyPairBuilder>,
-    pub signature_keypair_manager: HandlesManager<SignatureKeyPair>,
+    pub signature_keypair_manager: KeyPairManager,
     pub signature_state_manager: HandlesManager<ExclusiveSignatureState>,
     pub signature_manager: HandlesManager<Signature>,
     pub signature_publickey_manager: HandlesManager<SignaturePublicKey>,
+    pub signature_publickey_cache: PublicKeyCache,
+    pub signature_algorithm_policy: sync::RwLock<AlgorithmPolicy>,
     pub signature_verification_state_manager: HandlesManager<ExclusiveSignatureVerificationState>,
 }
 
-// These maps should be stored in a WASI context
+pub type WasiCryptoCtx = CryptoContext;
+
+// The default context uses the identifier 0
+static NEXT_CONTEXT_ID: core::sync::atomic::AtomicU16 = core::sync::atomic::AtomicU16::new(1);
+
+// Identifiers are never reused, so that a handle can't alias an object of a later context
+fn next_context_id(next_id: &core::sync::atomic::AtomicU16) -> Result<u8, Error> {
+    let id = next_id
+        .fetch_update(
+            core::sync::atomic::Ordering::Relaxed,
+            core::sync::atomic::Ordering::Relaxed,
+            |id| (id <= u8::MAX as u16).then(|| id + 1),
+        )
+        .map_err(|_| CryptoError::Overflow)?;
+    Ok(id as u8)
+}
+
+impl CryptoContext {
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self::with_id(next_context_id(&NEXT_CONTEXT_ID)?))
+    }
+
+    fn with_id(id: u8) -> Self {
+        CryptoContext {
+            signature_op_manager: HandlesManager::with_context(0x00, id),
+            signature_keypair_builder_manager: HandlesManager::with_context(0x01, id),
+            signature_keypair_manager: KeyPairManager::with_context(0x02, id),
+            signature_state_manager: HandlesManager::with_context(0x03, id),
+            signature_manager: HandlesManager::with_context(0x04, id),
+            signature_publickey_manager: HandlesManager::with_context(0x05, id),
+            signature_publickey_cache: PublicKeyCache::new(0),
+            signature_algorithm_policy: sync::RwLock::new(AlgorithmPolicy::default()),
+            signature_verification_state_manager: HandlesManager::with_context(0x06, id),
+        }
+    }
+
+    pub fn signature_op_open(&self, alg_str: &str) -> Result<Handle, Error> {
+        SignatureOp::open(self, alg_str, &SignatureOptions::default())
+    }
+
+    pub fn signature_op_open_with_options(
+        &self,
+        alg_str: &str,
+        options: &SignatureOptions,
+    ) -> Result<Handle, Error> {
+        SignatureOp::open(self, alg_str, options)
+    }
+
+    pub fn signature_op_close(&self, handle: Handle) -> Result<(), Error> {
+        self.signature_op_manager.close(handle)
+    }
+
+    pub fn signature_keypair_builder_open(&self, op_handle: Handle) -> Result<Handle, Error> {
+        SignatureKeyPairBuilder::open(self, op_handle)
+    }
+
+    pub fn signature_keypair_builder_close(&self, handle: Handle) -> Result<(), Error> {
+        self.signature_keypair_builder_manager.close(handle)
+    }
+
+    pub fn signature_keypair_generate(&self, kp_builder_handle: Handle) -> Result<Handle, Error> {
+        SignatureKeyPair::generate(self, kp_builder_handle)
+    }
+
+    pub fn signature_keypair_import(
+        &self,
+        kp_builder_handle: Handle,
+        encoded: &[u8],
+        encoding: KeyPairEncoding,
+    ) -> Result<Handle, Error> {
+        SignatureKeyPair::import(self, kp_builder_handle, encoded, encoding)
+    }
+
+    /// Generate a key pair for `alg` with the default options, and return its handle along
+    /// with its public key in `encoding`, such as JWK or PEM. The key pair is only registered
+    /// if the public key can be encoded that way.
+    pub fn keypair_generate_export_public(
+        &self,
+        alg: SignatureAlgorithm,
+        encoding: PublicKeyEncoding,
+    ) -> Result<(Handle, Vec<u8>), Error> {
+        SignatureKeyPairBuilder::default_for(alg).generate_with_public_key(self, encoding)
+    }
+
+    /// Import a PKCS#8 key pair, and return its handle along with the algorithm detected from
+    /// its algorithm identifier.
+    pub fn import_detect(
+        &self,
+        encoded: &[u8],
+        encoding: KeyPairEncoding,
+    ) -> Result<(Handle, SignatureAlgorithm), Error> {
+        SignatureKeyPair::import_detect(self, encoded, encoding)
+    }
+
+    pub fn signature_keypair_export(
+        &self,
+        kp_handle: Handle,
+        encoding: KeyPairEncoding,
+    ) -> Result<Vec<u8>, Error> {
+        self.signature_keypair_manager
+            .get(kp_handle)?
+            .export(encoding)
+    }
+
+    pub fn signature_keypair_close(&self, handle: Handle) -> Result<(), Error> {
+        self.signature_keypair_manager.close(handle)
+    }
+
+    pub fn signature_keypair_publickey(&self, kp_handle: Handle) -> Result<Handle, Error> {
+        self.signature_keypair_manager
+            .get(kp_handle)?
+            .public_key(self)
+    }
+
+    pub fn signature_publickey_import(
+        &self,
+        op_handle: Handle,
+        encoded: &[u8],
+        encoding: PublicKeyEncoding,
+    ) -> Result<Handle, Error> {
+        SignaturePublicKey::import(self, op_handle, encoded, encoding)
+    }
+
+    pub fn signature_publickey_export(
+        &self,
+        pk_handle: Handle,
+        encoding: PublicKeyEncoding,
+    ) -> Result<Vec<u8>, Error> {
+        SignaturePublicKey::export(self, pk_handle, encoding)
+    }
+
+    pub fn signature_publickey_close(&self, handle: Handle) -> Result<(), Error> {
+        self.signature_publickey_manager.close(handle)
+    }
+
+    pub fn signature_state_open(&self, kp_handle: Handle) -> Result<Handle, Error> {
+        ExclusiveSignatureState::open(self, kp_handle, &SignatureStateOptions::default())
+    }
+
+    pub fn signature_state_update(&self, state_handle: Handle, input: &[u8]) -> Result<(), Error> {
+        self.signature_state_manager
+            .get(state_handle)?
+            .update(input)
+    }
+
+    pub fn signature_state_sign(&self, state_handle: Handle) -> Result<Handle, Error> {
+        let signature = self.signature_state_manager.get(state_handle)?.sign()?;
+        self.signature_manager.register(signature)
+    }
+
+    pub fn signature_state_close(&self, handle: Handle) -> Result<(), Error> {
+        self.signature_state_manager.close(handle)
+    }
+
+    pub fn signature_verification_state_open(&self, pk_handle: Handle) -> Result<Handle, Error> {
+        ExclusiveSignatureVerificationState::open(
+            self,
+            pk_handle,
+            &SignatureStateOptions::default(),
+        )
+    }
+
+    pub fn signature_verification_state_update(
+        &self,
+        verification_state_handle: Handle,
+        input: &[u8],
+    ) -> Result<(), Error> {
+        self.signature_verification_state_manager
+            .get(verification_state_handle)?
+            .update(input)
+    }
+
+    pub fn signature_verification_state_verify(
+        &self,
+        verification_state_handle: Handle,
+        signature_handle: Handle,
+    ) -> Result<(), Error> {
+        let state = self
+            .signature_verification_state_manager
+            .get(verification_state_handle)?;
+        state.verify_signature(&self.signature_manager.get(signature_handle)?)
+    }
+
+    pub fn signature_verification_state_verify_with_expected_digest(
+        &self,
+        verification_state_handle: Handle,
+        expected_digest: &[u8],
+        signature_handle: Handle,
+    ) -> Result<(), Error> {
+        self.signature_verification_state_manager
+            .get(verification_state_handle)?
+            .verify_with_expected_digest(self, expected_digest, signature_handle)
+    }
+
+    pub fn signature_verification_state_close(&self, handle: Handle) -> Result<(), Error> {
+        self.signature_verification_state_manager.close(handle)
+    }
+
+    pub fn signature_export(
+        &self,
+        signature_handle: Handle,
+        encoding: SignatureEncoding,
+    ) -> Result<Vec<u8>, Error> {
+        Signature::export(self, signature_handle, encoding)
+    }
+
+    pub fn signature_import(
+        &self,
+        op_handle: Handle,
+        encoding: SignatureEncoding,
+        encoded: &[u8],
+    ) -> Result<Handle, Error> {
+        Signature::import(self, op_handle, encoding, encoded)
+    }
+
+    pub fn signature_export_components(
+        &self,
+        op_handle: Handle,
+        signature_handle: Handle,
+    ) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        Signature::export_components(self, op_handle, signature_handle)
+    }
+
+    pub fn signature_import_components(
+        &self,
+        op_handle: Handle,
+        r: &[u8],
+        s: &[u8],
+    ) -> Result<Handle, Error> {
+        Signature::import_components(self, op_handle, r, s)
+    }
+
+    pub fn signature_close(&self, handle: Handle) -> Result<(), Error> {
+        self.signature_manager.close(handle)
+    }
+
+    pub fn sign_input(&self, kp_handle: Handle, input: &[u8]) -> Result<Handle, Error> {
+        Signature::sign_input(self, kp_handle, input)
+    }
+
+    pub fn verify_input(
+        &self,
+        pk_handle: Handle,
+        input: &[u8],
+        signature_handle: Handle,
+    ) -> Result<(), Error> {
+        Signature::verify_input(self, pk_handle, input, signature_handle)
+    }
+
+    pub fn signature_verify_root(
+        &self,
+        pk_handle: Handle,
+        root: &[u8],
+        signature_handle: Handle,
+    ) -> Result<(), Error> {
+        Signature::verify_root(self, pk_handle, root, signature_handle)
+    }
+
+    /// Install a DRBG that the key pairs of this context are generated from, and that
+    /// provides their ECDSA nonces. It reseeds itself after `reseed_interval` requests.
+    /// Returns `NotAvailable` without the `drbg` feature, since ECDSA couldn't use it.
+    pub fn signature_install_drbg(&self, reseed_interval: u64) -> Result<(), Error> {
+        ensure!(cfg!(feature = "drbg"), CryptoError::NotAvailable);
+        let drbg = drbg::Drbg::new(reseed_interval)?;
+        self.signature_keypair_manager
+            .set_drbg(Some(alloc::sync::Arc::new(drbg)));
+        Ok(())
+    }
+
+    /// Return `true` if the public key is the one of the key pair.
+    pub fn signature_publickey_matches_keypair(
+        &self,
+        pk_handle: Handle,
+        kp_handle: Handle,
+    ) -> Result<bool, Error> {
+        SignatureKeyPair::matches_publickey(self, kp_handle, pk_handle)
+    }
+
+    /// Return `true` if `raw_pk` is the raw public key of the key pair.
+    pub fn keypair_matches_public_key(
+        &self,
+        kp_handle: Handle,
+        raw_pk: &[u8],
+    ) -> Result<bool, Error> {
+        let kp = self.signature_keypair_manager.get(kp_handle)?;
+        Ok(kp.matches_raw_public_key(raw_pk))
+    }
+
+    /// Verify every `(public key, input, signature)` item, and return one result per item.
+    #[cfg(feature = "std")]
+    pub fn verify_report(&self, items: &[(Handle, &[u8], &Signature)]) -> Vec<VerifyResult> {
+        report::verify_report(self, items)
+    }
+}
+
+// The default context, used by the `signature_*` functions
 lazy_static! {
-    static ref WASI_CRYPTO_CTX: WasiCryptoCtx = WasiCryptoCtx {
-        signature_op_manager: HandlesManager::new(0x00),
-        signature_keypair_builder_manager: HandlesManager::new(0x01),
-        signature_keypair_manager: HandlesManager::new(0x02),
-        signature_state_manager: HandlesManager::new(0x03),
-        signature_manager: HandlesManager::new(0x04),
-        signature_publickey_manager: HandlesManager::new(0x05),
-        signature_verification_state_manager: HandlesManager::new(0x06),
-    };
+    static ref WASI_CRYPTO_CTX: CryptoContext = CryptoContext::with_id(0);
 }
 
 #[test]
@@ -87,3 +528,184 @@ fn test_signatures() {
     signature_verification_state_close(verification_state_handle).unwrap();
     signature_close(signature_handle).unwrap();
 }
+
+#[test]
+fn test_independent_contexts() {
+    let ctx_a = CryptoContext::new().unwrap();
+    let ctx_b = CryptoContext::new().unwrap();
+    let generate = |ctx: &CryptoContext| {
+        let op_handle = ctx.signature_op_open("Ed25519").unwrap();
+        let kp_builder_handle = ctx.signature_keypair_builder_open(op_handle).unwrap();
+        ctx.signature_keypair_generate(kp_builder_handle)
+    };
+
+    let kp_a = generate(&ctx_a).unwrap();
+    assert!(ctx_b
+        .signature_keypair_export(kp_a, KeyPairEncoding::PKCS8)
+        .is_err());
+    let kp_b = generate(&ctx_b).unwrap();
+    let pkcs8_a = ctx_a
+        .signature_keypair_export(kp_a, KeyPairEncoding::PKCS8)
+        .unwrap();
+    let pkcs8_b = ctx_b
+        .signature_keypair_export(kp_b, KeyPairEncoding::PKCS8)
+        .unwrap();
+    assert_ne!(pkcs8_a, pkcs8_b);
+
+    ctx_a.signature_keypair_close(kp_a).unwrap();
+    assert!(ctx_a
+        .signature_keypair_export(kp_a, KeyPairEncoding::PKCS8)
+        .is_err());
+    ctx_b
+        .signature_keypair_export(kp_b, KeyPairEncoding::PKCS8)
+        .unwrap();
+
+    let op_handle = ctx_b.signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = ctx_b.signature_keypair_builder_open(op_handle).unwrap();
+    let imported = ctx_b
+        .signature_keypair_import(kp_builder_handle, &pkcs8_a, KeyPairEncoding::PKCS8)
+        .unwrap();
+    assert_eq!(
+        ctx_b
+            .signature_keypair_export(imported, KeyPairEncoding::PKCS8)
+            .unwrap(),
+        pkcs8_a
+    );
+
+    *ctx_a.signature_algorithm_policy.write() =
+        AlgorithmPolicy::allow_only(&[SignatureAlgorithm::ECDSA_P256_SHA256]);
+    assert!(generate(&ctx_a).is_err());
+    generate(&ctx_b).unwrap();
+}
+
+#[test]
+fn test_context_ids_are_not_reused() {
+    let next_id = core::sync::atomic::AtomicU16::new(254);
+    assert_eq!(next_context_id(&next_id).unwrap(), 254);
+    assert_eq!(next_context_id(&next_id).unwrap(), 255);
+    for _ in 0..2 {
+        let err = next_context_id(&next_id).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::Overflow)
+        ));
+    }
+}
+
+#[test]
+fn test_handles_are_bound_to_their_context() {
+    let ctx_a = CryptoContext::new().unwrap();
+    let ctx_b = CryptoContext::new().unwrap();
+    let generate = |ctx: &CryptoContext| {
+        let op_handle = ctx.signature_op_open("Ed25519").unwrap();
+        let kp_builder_handle = ctx.signature_keypair_builder_open(op_handle).unwrap();
+        let kp_handle = ctx.signature_keypair_generate(kp_builder_handle).unwrap();
+        (op_handle, kp_handle)
+    };
+    let is_invalid_handle = |err: Error| {
+        matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::InvalidHandle)
+        )
+    };
+    // Both contexts, and the default one, have allocated handles of every type
+    let (op_a, kp_a) = generate(&ctx_a);
+    let (op_b, kp_b) = generate(&ctx_b);
+    let (_, kp_default) = generate(&WASI_CRYPTO_CTX);
+
+    let sign_verify = |ctx: &CryptoContext, kp_handle| {
+        let state_handle = ctx.signature_state_open(kp_handle)?;
+        ctx.signature_state_update(state_handle, b"test")?;
+        let signature_handle = ctx.signature_state_sign(state_handle)?;
+        let pk_handle = ctx.signature_keypair_publickey(kp_handle)?;
+        let verification_state_handle = ctx.signature_verification_state_open(pk_handle)?;
+        ctx.signature_verification_state_update(verification_state_handle, b"test")?;
+        ctx.signature_verification_state_verify(verification_state_handle, signature_handle)?;
+        Ok::<_, Error>((state_handle, pk_handle, signature_handle))
+    };
+    let (state_a, pk_a, signature_a) = sign_verify(&ctx_a, kp_a).unwrap();
+    let (state_b, pk_b, signature_b) = sign_verify(&ctx_b, kp_b).unwrap();
+    sign_verify(&WASI_CRYPTO_CTX, kp_default).unwrap();
+    assert_ne!((op_a, kp_a, state_a), (op_b, kp_b, state_b));
+    assert_ne!((pk_a, signature_a), (pk_b, signature_b));
+
+    assert!(is_invalid_handle(sign_verify(&ctx_b, kp_a).unwrap_err()));
+    assert!(is_invalid_handle(signature_state_open(kp_a).unwrap_err()));
+    assert!(is_invalid_handle(
+        ctx_b.signature_state_update(state_a, b"test").unwrap_err()
+    ));
+    assert!(is_invalid_handle(
+        ctx_b.signature_verification_state_open(pk_a).unwrap_err()
+    ));
+    assert!(is_invalid_handle(
+        ctx_b
+            .signature_export(signature_a, SignatureEncoding::Raw)
+            .unwrap_err()
+    ));
+    assert!(is_invalid_handle(
+        ctx_a.signature_keypair_builder_open(op_b).unwrap_err()
+    ));
+    assert!(is_invalid_handle(
+        signature_keypair_export(kp_b, KeyPairEncoding::PKCS8).unwrap_err()
+    ));
+
+    // One-shot verification, signature components and the per-algorithm builders use the
+    // context they are given as well
+    let op_handle = ctx_a.signature_op_open("ECDSA_P256_SHA256").unwrap();
+    let kp_builder_handle = ctx_a.signature_keypair_builder_open(op_handle).unwrap();
+    let kp_builder = match ctx_a
+        .signature_keypair_builder_manager
+        .get(kp_builder_handle)
+        .unwrap()
+    {
+        SignatureKeyPairBuilder::ECDSA(kp_builder) => kp_builder,
+        _ => unreachable!(),
+    };
+    let kp_handle = kp_builder.generate(&ctx_a).unwrap();
+    let pkcs8 = ctx_a
+        .signature_keypair_export(kp_handle, KeyPairEncoding::PKCS8)
+        .unwrap();
+    assert!(WASI_CRYPTO_CTX
+        .signature_keypair_manager
+        .get(kp_handle)
+        .is_err());
+    let imported = kp_builder
+        .import(&ctx_a, &pkcs8, KeyPairEncoding::PKCS8)
+        .unwrap();
+    let signature_handle = ctx_a.sign_input(imported, b"test").unwrap();
+    let pk_handle = ctx_a.signature_keypair_publickey(kp_handle).unwrap();
+    ctx_a
+        .verify_input(pk_handle, b"test", signature_handle)
+        .unwrap();
+    assert!(is_invalid_handle(
+        ctx_b
+            .verify_input(pk_b, b"test", signature_handle)
+            .unwrap_err()
+    ));
+    let verification_state_handle = ctx_a.signature_verification_state_open(pk_handle).unwrap();
+    ctx_a
+        .signature_verification_state_update(verification_state_handle, b"test")
+        .unwrap();
+    let state = ctx_a
+        .signature_verification_state_manager
+        .get(verification_state_handle)
+        .unwrap();
+    state.verify(&ctx_a, signature_handle).unwrap();
+    assert!(is_invalid_handle(
+        state
+            .verify(&WASI_CRYPTO_CTX, signature_handle)
+            .unwrap_err()
+    ));
+    let (r, s) = ctx_a
+        .signature_export_components(op_handle, signature_handle)
+        .unwrap();
+    let signature_handle = ctx_a
+        .signature_import_components(op_handle, &r, &s)
+        .unwrap();
+    ctx_a
+        .verify_input(pk_handle, b"test", signature_handle)
+        .unwrap();
+    assert!(is_invalid_handle(
+        signature_import_components(op_handle, &r, &s).unwrap_err()
+    ));
+}
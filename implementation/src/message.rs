@@ -0,0 +1,189 @@
+use alloc::vec::Vec;
+
+use super::error::*;
+use super::framing;
+use super::handles::*;
+use super::signature::*;
+use super::signature_publickey::*;
+use super::WASI_CRYPTO_CTX;
+
+const MESSAGE_LABEL: &[u8] = b"wasi-crypto message";
+
+/// A message bound to a domain tag. The tag describes what the message means, so that a key
+/// signing messages with different meanings cannot have a signature for one accepted as a
+/// signature for the other.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Message<'t> {
+    pub tag: &'t [u8],
+    pub data: &'t [u8],
+}
+
+impl<'t> Message<'t> {
+    pub fn new(tag: &'t [u8], data: &'t [u8]) -> Self {
+        Message { tag, data }
+    }
+
+    fn signed_input(&self) -> Vec<u8> {
+        let mut input = vec![];
+        framing::append_framed(&mut input, MESSAGE_LABEL);
+        framing::append_framed(&mut input, self.tag);
+        framing::append_framed(&mut input, self.data);
+        input
+    }
+}
+
+pub fn signature_sign_message(kp_handle: Handle, message: &Message) -> Result<Handle, Error> {
+    sign_input(kp_handle, &message.signed_input())
+}
+
+pub fn signature_verify_message(
+    pk_handle: Handle,
+    message: &Message,
+    signature_handle: Handle,
+) -> Result<(), Error> {
+    verify_input(pk_handle, &message.signed_input(), signature_handle)
+}
+
+/// Sign `message` for a domain, with any algorithm. This is the same as signing
+/// `Message::new(domain, message)`: the domain and the message are both length-prefixed, so
+/// that algorithms without a native context parameter, such as ECDSA and RSA, get the same
+/// separation as Ed25519ctx.
+pub fn signature_sign_domain_separated(
+    kp_handle: Handle,
+    domain: &[u8],
+    message: &[u8],
+) -> Result<Handle, Error> {
+    signature_sign_message(kp_handle, &Message::new(domain, message))
+}
+
+/// Verify a signature created with `signature_sign_domain_separated()` for the same domain.
+pub fn signature_verify_domain_separated(
+    pk_handle: Handle,
+    domain: &[u8],
+    message: &[u8],
+    signature_handle: Handle,
+) -> Result<(), Error> {
+    signature_verify_message(pk_handle, &Message::new(domain, message), signature_handle)
+}
+
+const KEY_COMMITTING_LABEL: &[u8] = b"wasi-crypto key-committing";
+
+// The framed label, the SHA-256 hash of the raw public key, and the message
+fn key_committing_input(raw_pk: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut input = vec![];
+    framing::append_framed(&mut input, KEY_COMMITTING_LABEL);
+    framing::append_framed(
+        &mut input,
+        ring::digest::digest(&ring::digest::SHA256, raw_pk).as_ref(),
+    );
+    framing::append_framed(&mut input, message);
+    input
+}
+
+/// Sign `message` bound to the signer's public key, so that the signature cannot be
+/// attributed to another key, even one chosen to accept the same signature bytes. This is a
+/// protection against duplicate-signature key selection with ECDSA and Ed25519.
+///
+/// The signed input is the length-prefixed label `wasi-crypto key-committing`, the SHA-256
+/// hash of the raw public key, and the message. Other implementations can only verify these
+/// signatures if they reproduce this framing.
+pub fn signature_sign_key_committing(kp_handle: Handle, message: &[u8]) -> Result<Handle, Error> {
+    let kp = WASI_CRYPTO_CTX.signature_keypair_manager.get(kp_handle)?;
+    sign_input(
+        kp_handle,
+        &key_committing_input(kp.raw_public_key(), message),
+    )
+}
+
+/// Verify a signature created with `signature_sign_key_committing()`, for the public key it
+/// is verified with.
+pub fn signature_verify_key_committing(
+    pk_handle: Handle,
+    message: &[u8],
+    signature_handle: Handle,
+) -> Result<(), Error> {
+    let raw_pk = signature_publickey_export(pk_handle, PublicKeyEncoding::Raw)?;
+    verify_input(
+        pk_handle,
+        &key_committing_input(&raw_pk, message),
+        signature_handle,
+    )
+}
+
+#[test]
+fn test_message_tags() {
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+
+    let message = Message::new(b"transfer", b"data");
+    let signature_handle = signature_sign_message(kp_handle, &message).unwrap();
+    signature_verify_message(pk_handle, &message, signature_handle).unwrap();
+
+    let other_message = Message::new(b"receipt", b"data");
+    assert!(signature_verify_message(pk_handle, &other_message, signature_handle).is_err());
+    let other_signature_handle = signature_sign_message(kp_handle, &other_message).unwrap();
+    assert_ne!(
+        crate::signature_export(signature_handle, crate::SignatureEncoding::Raw).unwrap(),
+        crate::signature_export(other_signature_handle, crate::SignatureEncoding::Raw).unwrap()
+    );
+}
+
+#[test]
+fn test_domain_separation() {
+    for alg_str in &["Ed25519", "ECDSA_P256_SHA256", "ECDSA_P384_SHA384"] {
+        let op_handle = crate::signature_op_open(alg_str).unwrap();
+        let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+        let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+        let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+
+        let signature_handle =
+            signature_sign_domain_separated(kp_handle, b"login", b"nonce").unwrap();
+        signature_verify_domain_separated(pk_handle, b"login", b"nonce", signature_handle).unwrap();
+        assert!(signature_verify_domain_separated(
+            pk_handle,
+            b"payment",
+            b"nonce",
+            signature_handle
+        )
+        .is_err());
+        assert!(
+            signature_verify_domain_separated(pk_handle, b"loginn", b"once", signature_handle)
+                .is_err()
+        );
+        assert!(verify_input(pk_handle, b"nonce", signature_handle).is_err());
+        signature_verify_message(
+            pk_handle,
+            &Message::new(b"login", b"nonce"),
+            signature_handle,
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn test_key_committing() {
+    for alg_str in &["Ed25519", "ECDSA_P256_SHA256"] {
+        let op_handle = crate::signature_op_open(alg_str).unwrap();
+        let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+        let kp_a = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+        let kp_b = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+        let pk_a = crate::signature_keypair_publickey(kp_a).unwrap();
+        let pk_b = crate::signature_keypair_publickey(kp_b).unwrap();
+
+        let signature_handle = signature_sign_key_committing(kp_a, b"message").unwrap();
+        signature_verify_key_committing(pk_a, b"message", signature_handle).unwrap();
+        assert!(signature_verify_key_committing(pk_a, b"other", signature_handle).is_err());
+        assert!(verify_input(pk_a, b"message", signature_handle).is_err());
+
+        // A signature that is valid under the other key for the input committed to the first
+        // key is still rejected, since the verifier commits to its own key
+        let raw_pk_a =
+            crate::signature_publickey_export(pk_a, crate::PublicKeyEncoding::Raw).unwrap();
+        let forged = sign_input(kp_b, &key_committing_input(&raw_pk_a, b"message")).unwrap();
+        verify_input(pk_b, &key_committing_input(&raw_pk_a, b"message"), forged).unwrap();
+        assert!(signature_verify_key_committing(pk_b, b"message", forged).is_err());
+        assert!(signature_verify_key_committing(pk_b, b"message", signature_handle).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+//! Detection of ECDSA nonce reuse, for auditing signing implementations. Two signatures with
+//! the same `r` under the same key were computed with the same nonce, and reveal the private
+//! key to anyone who knows both messages.
+
+#[cfg(feature = "dangerous")]
+use alloc::vec::Vec;
+
+use super::ecdsa::*;
+use super::error::*;
+use super::signature::*;
+
+/// Return `true` if both signatures share their `r` component, meaning that they were computed
+/// with the same nonce if they are for the same key. The signatures can use different
+/// encodings.
+pub fn signature_ecdsa_detect_nonce_reuse(
+    alg: SignatureAlgorithm,
+    signature_a: &ECDSASignature,
+    signature_b: &ECDSASignature,
+) -> Result<bool, Error> {
+    let (r_a, _) = signature_a.components(alg)?;
+    let (r_b, _) = signature_b.components(alg)?;
+    Ok(r_a == r_b)
+}
+
+#[cfg(feature = "dangerous")]
+fn recover_scalar<S, U>(
+    digests: [&[u8]; 2],
+    signatures: [&ECDSASignature; 2],
+    alg: SignatureAlgorithm,
+) -> Result<zeroize::Zeroizing<Vec<u8>>, Error>
+where
+    S: p256::elliptic_curve::PrimeField
+        + p256::elliptic_curve::ops::Reduce<U, Bytes = <S as p256::elliptic_curve::PrimeField>::Repr>,
+    U: p256::elliptic_curve::bigint::Integer,
+{
+    let to_scalar = |bytes: &[u8], reduce: bool| -> Result<S, Error> {
+        let mut repr = S::Repr::default();
+        repr.as_mut().copy_from_slice(bytes);
+        if reduce {
+            return Ok(S::reduce_bytes(&repr));
+        }
+        Option::from(S::from_repr(repr)).ok_or_else(|| CryptoError::InvalidSignature.into())
+    };
+    let (r, s_a) = signatures[0].components(alg)?;
+    let (r_b, s_b) = signatures[1].components(alg)?;
+    ensure!(r == r_b, CryptoError::InvalidSignature);
+    let r = to_scalar(&r, false)?;
+    let (s_a, s_b) = (to_scalar(&s_a, false)?, to_scalar(&s_b, false)?);
+    let (z_a, z_b) = (to_scalar(digests[0], true)?, to_scalar(digests[1], true)?);
+
+    // s = k^-1 (z + r d), so k = (z_a - z_b) / (s_a - s_b) and d = (s_a k - z_a) / r
+    let s_diff_inv: Option<S> = (s_a - s_b).invert().into();
+    let k = (z_a - z_b) * s_diff_inv.ok_or(CryptoError::InvalidSignature)?;
+    let r_inv: Option<S> = r.invert().into();
+    let d = (s_a * k - z_a) * r_inv.ok_or(CryptoError::InvalidSignature)?;
+    Ok(zeroize::Zeroizing::new(d.to_repr().as_ref().to_vec()))
+}
+
+/// Recover the private scalar of a key from two signatures of different messages computed
+/// with the same nonce, as a big-endian integer. This exists to test defenses against nonce
+/// reuse, and is only available with the `dangerous` feature.
+#[cfg(feature = "dangerous")]
+pub fn signature_ecdsa_recover_key_from_reuse(
+    alg: SignatureAlgorithm,
+    message_a: &[u8],
+    signature_a: &ECDSASignature,
+    message_b: &[u8],
+    signature_b: &ECDSASignature,
+) -> Result<zeroize::Zeroizing<Vec<u8>>, Error> {
+    let digest_a = ring::digest::digest(alg.digest_alg(), message_a);
+    let digest_b = ring::digest::digest(alg.digest_alg(), message_b);
+    let digests = [digest_a.as_ref(), digest_b.as_ref()];
+    let signatures = [signature_a, signature_b];
+    match alg {
+        SignatureAlgorithm::ECDSA_P256_SHA256 => {
+            recover_scalar::<p256::Scalar, p256::U256>(digests, signatures, alg)
+        }
+        SignatureAlgorithm::ECDSA_P384_SHA384 => {
+            recover_scalar::<p384::Scalar, p384::U384>(digests, signatures, alg)
+        }
+        _ => bail!(CryptoError::NotAvailable),
+    }
+}
+
+// Sign with a chosen nonce `k`: `r = x(kG) mod n` and `s = k^-1 (z + r d)`
+#[cfg(test)]
+fn sign_with_nonce(kp: &ECDSASignatureKeyPair, k: &p256::Scalar, message: &[u8]) -> ECDSASignature {
+    use p256::elliptic_curve::ops::Reduce;
+    use p256::elliptic_curve::point::AffineCoordinates as _;
+    use p256::pkcs8::DecodePrivateKey as _;
+
+    let d = *p256::SecretKey::from_pkcs8_der(&kp.pkcs8)
+        .unwrap()
+        .to_nonzero_scalar();
+    let mut z = p256::FieldBytes::default();
+    z.copy_from_slice(ring::digest::digest(&ring::digest::SHA256, message).as_ref());
+    let z = <p256::Scalar as Reduce<p256::U256>>::reduce_bytes(&z);
+    let r = (p256::ProjectivePoint::GENERATOR * k).to_affine().x();
+    let r = <p256::Scalar as Reduce<p256::U256>>::reduce_bytes(&r);
+    let s = k.invert().unwrap() * (z + r * d);
+    ECDSASignature::from_components(
+        SignatureAlgorithm::ECDSA_P256_SHA256,
+        &r.to_bytes(),
+        &s.to_bytes(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_detect_nonce_reuse() {
+    let alg = SignatureAlgorithm::ECDSA_P256_SHA256;
+    let kp = ECDSASignatureKeyPairBuilder::new(alg)
+        .generate_owned()
+        .unwrap();
+    let k = p256::Scalar::from(0x5ca1ab1e_u64);
+    let signature_a = sign_with_nonce(&kp, &k, b"first message");
+    let signature_b = sign_with_nonce(&kp, &k, b"second message");
+    ECDSASignaturePublicKey::from_raw(alg, kp.raw_public_key())
+        .unwrap()
+        .verify(b"first message", signature_a.as_ref())
+        .unwrap();
+    assert_ne!(signature_a, signature_b);
+    assert!(signature_ecdsa_detect_nonce_reuse(alg, &signature_a, &signature_b).unwrap());
+
+    let (r, s) = signature_b.components(alg).unwrap();
+    let signature_b_der = ECDSASignature::new(
+        SignatureEncoding::DER,
+        crate::der::ecdsa_signature_from_fixed(&[r, s].concat()).unwrap(),
+    );
+    assert!(signature_ecdsa_detect_nonce_reuse(alg, &signature_a, &signature_b_der).unwrap());
+
+    let fresh = kp.sign_oneshot(b"second message").unwrap();
+    assert!(!signature_ecdsa_detect_nonce_reuse(alg, &signature_a, &fresh).unwrap());
+
+    #[cfg(feature = "dangerous")]
+    {
+        use p256::pkcs8::DecodePrivateKey as _;
+
+        let d = signature_ecdsa_recover_key_from_reuse(
+            alg,
+            b"first message",
+            &signature_a,
+            b"second message",
+            &signature_b,
+        )
+        .unwrap();
+        let sk = p256::SecretKey::from_pkcs8_der(&kp.pkcs8).unwrap();
+        assert_eq!(&d[..], &sk.to_bytes()[..]);
+        assert!(signature_ecdsa_recover_key_from_reuse(
+            alg,
+            b"first message",
+            &signature_a,
+            b"second message",
+            &fresh,
+        )
+        .is_err());
+    }
+}
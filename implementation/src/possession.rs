@@ -0,0 +1,52 @@
+use alloc::vec::Vec;
+
+use super::error::*;
+use super::framing;
+use super::handles::*;
+use super::signature::*;
+
+fn possession_input(challenge: &[u8], audience: &[u8]) -> Result<Vec<u8>, Error> {
+    ensure!(!challenge.is_empty(), "Empty challenge");
+    let mut input = vec![];
+    framing::append_framed(&mut input, audience);
+    framing::append_framed(&mut input, challenge);
+    Ok(input)
+}
+
+/// Prove possession of a key pair by signing a challenge for a given audience.
+/// The audience is part of the signed input, so that a proof cannot be replayed to another
+/// audience.
+pub fn signature_prove_possession(
+    kp_handle: Handle,
+    challenge: &[u8],
+    audience: &[u8],
+) -> Result<Handle, Error> {
+    sign_input(kp_handle, &possession_input(challenge, audience)?)
+}
+
+pub fn signature_verify_possession(
+    pk_handle: Handle,
+    challenge: &[u8],
+    audience: &[u8],
+    signature_handle: Handle,
+) -> Result<(), Error> {
+    verify_input(
+        pk_handle,
+        &possession_input(challenge, audience)?,
+        signature_handle,
+    )
+}
+
+#[test]
+fn test_possession_is_bound_to_audience() {
+    let op_handle = crate::signature_op_open("ECDSA_P256_SHA256").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+
+    let proof_handle = signature_prove_possession(kp_handle, b"challenge", b"A").unwrap();
+    signature_verify_possession(pk_handle, b"challenge", b"A", proof_handle).unwrap();
+    assert!(signature_verify_possession(pk_handle, b"challenge", b"B", proof_handle).is_err());
+    assert!(signature_verify_possession(pk_handle, b"other", b"A", proof_handle).is_err());
+    assert!(signature_prove_possession(kp_handle, b"", b"A").is_err());
+}
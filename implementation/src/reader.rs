@@ -0,0 +1,164 @@
+use std::io::Read;
+
+use super::error::*;
+use super::handles::*;
+use super::signature::*;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+fn io_error(err: std::io::Error) -> Error {
+    Error::new(err).context(CryptoError::AlgorithmFailure)
+}
+
+// Run `f` with a new signature state for the key pair, and sign its input. The state is
+// closed even if `f` fails.
+fn with_state(
+    kp_handle: Handle,
+    f: impl FnOnce(Handle) -> Result<(), Error>,
+) -> Result<Handle, Error> {
+    let state_handle = signature_state_open(kp_handle)?;
+    let signature_handle = f(state_handle).and_then(|_| signature_state_sign(state_handle));
+    signature_state_close(state_handle)?;
+    signature_handle
+}
+
+// Call `f` with each chunk read from `reader`, until the end of the stream
+fn for_each_chunk(
+    reader: &mut dyn Read,
+    mut f: impl FnMut(&[u8]) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    loop {
+        let len = match reader.read(&mut chunk) {
+            Ok(0) => return Ok(()),
+            Ok(len) => len,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(io_error(err)),
+        };
+        f(&chunk[..len])?;
+    }
+}
+
+/// Sign everything `reader` returns until the end of the stream, read in chunks. I/O errors
+/// are returned with the `AlgorithmFailure` error as their context.
+pub fn signature_sign_reader(kp_handle: Handle, mut reader: impl Read) -> Result<Handle, Error> {
+    with_state(kp_handle, |state_handle| {
+        for_each_chunk(&mut reader, |chunk| {
+            signature_state_update(state_handle, chunk)
+        })
+    })
+}
+
+/// Verify a signature of everything `reader` returns until the end of the stream. The input
+/// is buffered for EdDSA, which needs the complete message, and for ECDSA without the
+/// `prehash` feature; it is hashed as it is read otherwise. I/O errors are returned with the
+/// `AlgorithmFailure` error as their context.
+pub fn signature_verify_from_reader(
+    pk_handle: Handle,
+    reader: &mut dyn Read,
+    signature_handle: Handle,
+) -> Result<(), Error> {
+    let state_handle = signature_verification_state_open(pk_handle)?;
+    let verified = for_each_chunk(reader, |chunk| {
+        signature_verification_state_update(state_handle, chunk)
+    })
+    .and_then(|_| signature_verification_state_verify(state_handle, signature_handle));
+    signature_verification_state_close(state_handle)?;
+    verified
+}
+
+/// Asynchronously sign everything `reader` returns until the end of the stream, without
+/// blocking the executor while waiting for input. Only available with the `async` feature.
+#[cfg(feature = "async")]
+pub async fn signature_sign_async_reader(
+    kp_handle: Handle,
+    mut reader: impl tokio::io::AsyncRead + Unpin,
+) -> Result<Handle, Error> {
+    use tokio::io::AsyncReadExt as _;
+
+    let state_handle = signature_state_open(kp_handle)?;
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    let absorbed = loop {
+        let len = match reader.read(&mut chunk).await {
+            Ok(0) => break Ok(()),
+            Ok(len) => len,
+            Err(err) => break Err(io_error(err)),
+        };
+        if let Err(err) = signature_state_update(state_handle, &chunk[..len]) {
+            break Err(err);
+        }
+    };
+    let signature_handle = absorbed.and_then(|_| signature_state_sign(state_handle));
+    signature_state_close(state_handle)?;
+    signature_handle
+}
+
+#[test]
+fn test_sign_reader() {
+    let message = vec![0x42u8; CHUNK_SIZE * 2 + 1];
+    for alg_str in &["Ed25519", "Ed25519_BLAKE2b512", "ECDSA_P256_SHA256"] {
+        let op_handle = crate::signature_op_open(alg_str).unwrap();
+        let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+        let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+        let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+        let verify = |signature_handle, message: &[u8]| {
+            let state_handle = crate::signature_verification_state_open(pk_handle).unwrap();
+            crate::signature_verification_state_update(state_handle, message).unwrap();
+            crate::signature_verification_state_verify(state_handle, signature_handle)
+        };
+
+        let signature_handle = signature_sign_reader(kp_handle, &message[..]).unwrap();
+        verify(signature_handle, &message).unwrap();
+        assert!(verify(signature_handle, &message[1..]).is_err());
+
+        let mut cursor = std::io::Cursor::new(&message);
+        signature_verify_from_reader(pk_handle, &mut cursor, signature_handle).unwrap();
+        let mut cursor = std::io::Cursor::new(&message[1..]);
+        assert!(signature_verify_from_reader(pk_handle, &mut cursor, signature_handle).is_err());
+    }
+
+    struct FailingReader;
+    impl Read for FailingReader {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("unreadable"))
+        }
+    }
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let err = signature_sign_reader(kp_handle, FailingReader).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::AlgorithmFailure)
+    ));
+    let signature_handle = signature_sign_reader(kp_handle, &b"test"[..]).unwrap();
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    let err =
+        signature_verify_from_reader(pk_handle, &mut FailingReader, signature_handle).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::AlgorithmFailure)
+    ));
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_sign_async_reader() {
+    let message = vec![0x42u8; CHUNK_SIZE * 2 + 1];
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    for alg_str in &["Ed25519", "Ed25519_BLAKE2b512", "ECDSA_P256_SHA256"] {
+        let op_handle = crate::signature_op_open(alg_str).unwrap();
+        let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+        let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+        let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+
+        let signature_handle = runtime
+            .block_on(signature_sign_async_reader(kp_handle, &message[..]))
+            .unwrap();
+        let state_handle = crate::signature_verification_state_open(pk_handle).unwrap();
+        crate::signature_verification_state_update(state_handle, &message).unwrap();
+        crate::signature_verification_state_verify(state_handle, signature_handle).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use super::eddsa;
+use super::error::*;
+use super::framing;
+use super::handles::*;
+use super::signature::*;
+use super::signature_keypair::*;
+use super::signature_op::*;
+use super::signature_publickey::*;
+use super::sync::Mutex;
+use super::WASI_CRYPTO_CTX;
+
+/// A closure computing signatures with a private key held elsewhere, such as in an HSM or by
+/// a remote signing service. It receives the digest of the message for ECDSA and RSA, and
+/// the input signed with Ed25519 for EdDSA, and returns a raw signature.
+pub type RemoteSigner = Arc<dyn Fn(&[u8]) -> Result<Vec<u8>, Error> + Send + Sync>;
+
+/// A key pair that only holds a public key, and delegates signing to a `RemoteSigner`. It
+/// cannot be exported, and is never encrypted in memory since it holds no secret.
+#[derive(Clone)]
+pub struct RemoteSignatureKeyPair {
+    pub alg: SignatureAlgorithm,
+    pub pk: SignaturePublicKey,
+    raw_pk: Vec<u8>,
+    signer: RemoteSigner,
+}
+
+impl core::fmt::Debug for RemoteSignatureKeyPair {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RemoteSignatureKeyPair")
+            .field("alg", &self.alg)
+            .field("raw_pk", &self.raw_pk)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RemoteSignatureKeyPair {
+    pub fn new(
+        signature_op: &SignatureOp,
+        raw_pk: &[u8],
+        signer: RemoteSigner,
+    ) -> Result<Self, Error> {
+        let pk = SignaturePublicKey::from_raw(signature_op, raw_pk)?;
+        Ok(RemoteSignatureKeyPair {
+            alg: signature_op.alg(),
+            pk,
+            raw_pk: raw_pk.to_vec(),
+            signer,
+        })
+    }
+
+    pub fn raw_public_key(&self) -> &[u8] {
+        &self.raw_pk
+    }
+
+    pub fn sign_oneshot(&self, input: &[u8]) -> Result<Signature, Error> {
+        let signer_input = match self.alg.category() {
+            AlgorithmCategory::EdDSA => eddsa::signed_input(self.alg, input)?.into_owned(),
+            AlgorithmCategory::ECDSA | AlgorithmCategory::RSA => {
+                ring::digest::digest(self.alg.digest_alg(), input)
+                    .as_ref()
+                    .to_vec()
+            }
+        };
+        let encoded = (self.signer)(&signer_input)?;
+        Signature::from_raw(self.alg, &encoded).map_err(|_| CryptoError::InvalidSignature.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct RemoteSignatureState {
+    pub kp: RemoteSignatureKeyPair,
+    pub input: Mutex<Vec<u8>>,
+}
+
+impl RemoteSignatureState {
+    pub fn new(kp: RemoteSignatureKeyPair) -> Self {
+        RemoteSignatureState {
+            kp,
+            input: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn update(&self, input: &[u8]) -> Result<(), Error> {
+        self.update_from_slices(&[input])
+    }
+
+    pub fn update_from_slices(&self, slices: &[&[u8]]) -> Result<(), Error> {
+        let mut input = self.input.lock();
+        slices
+            .iter()
+            .for_each(|slice| input.extend_from_slice(slice));
+        Ok(())
+    }
+
+    pub fn update_framed_from_slices(&self, fields: &[&[u8]]) -> Result<(), Error> {
+        let mut input = self.input.lock();
+        fields
+            .iter()
+            .for_each(|field| framing::append_framed(&mut input, field));
+        Ok(())
+    }
+
+    pub fn sign(&self) -> Result<Signature, Error> {
+        self.kp.sign_oneshot(&self.input.lock())
+    }
+
+    /// Write the signature into `out`, and return its length.
+    pub fn sign_into(&self, out: &mut [u8]) -> Result<usize, Error> {
+        let signature = self.sign()?;
+        let len = signature.as_ref().len();
+        ensure!(out.len() >= len, CryptoError::InvalidLength);
+        out[..len].copy_from_slice(signature.as_ref());
+        Ok(len)
+    }
+}
+
+/// Create a key pair for the algorithm of `op_handle`, whose signatures are computed by
+/// `signer`. `raw_pk` is the raw public key the signatures are verified with.
+pub fn signature_keypair_from_remote_signer(
+    op_handle: Handle,
+    raw_pk: &[u8],
+    signer: RemoteSigner,
+) -> Result<Handle, Error> {
+    let signature_op = WASI_CRYPTO_CTX.signature_op_manager.get(op_handle)?;
+    WASI_CRYPTO_CTX
+        .signature_algorithm_policy
+        .read()
+        .check(signature_op.alg())?;
+    let kp = RemoteSignatureKeyPair::new(&signature_op, raw_pk, signer)?;
+    WASI_CRYPTO_CTX
+        .signature_keypair_manager
+        .register(SignatureKeyPair::Remote(kp))
+}
+
+#[test]
+fn test_remote_signer() {
+    // The "remote" key pair lives in another context, and is only reached through the closure
+    let remote_ctx = crate::CryptoContext::new().unwrap();
+    for alg_str in &["Ed25519", "Ed25519_BLAKE2b512", "ECDSA_P256_SHA256"] {
+        let remote_op = remote_ctx.signature_op_open(alg_str).unwrap();
+        let remote_builder = remote_ctx
+            .signature_keypair_builder_open(remote_op)
+            .unwrap();
+        let remote_kp_handle = remote_ctx
+            .signature_keypair_generate(remote_builder)
+            .unwrap();
+        let remote_kp = remote_ctx
+            .signature_keypair_manager
+            .get(remote_kp_handle)
+            .unwrap();
+        let raw_pk = remote_kp.raw_public_key().to_vec();
+        let signer: RemoteSigner = Arc::new(move |signer_input: &[u8]| match &remote_kp {
+            SignatureKeyPair::ECDSA(kp) => {
+                use p256::ecdsa::signature::hazmat::PrehashSigner as _;
+                use p256::pkcs8::DecodePrivateKey as _;
+
+                let sk = p256::ecdsa::SigningKey::from_pkcs8_der(&kp.pkcs8)?;
+                let signature: p256::ecdsa::Signature = sk.sign_prehash(signer_input)?;
+                Ok(signature.to_bytes().to_vec())
+            }
+            SignatureKeyPair::EdDSA(kp) => Ok(kp.ring_kp.sign(signer_input).as_ref().to_vec()),
+            _ => bail!(CryptoError::NotAvailable),
+        });
+
+        let op_handle = crate::signature_op_open(alg_str).unwrap();
+        let kp_handle = signature_keypair_from_remote_signer(op_handle, &raw_pk, signer).unwrap();
+        let state_handle = crate::signature_state_open(kp_handle).unwrap();
+        crate::signature_state_update(state_handle, b"test").unwrap();
+        let signature_handle = crate::signature_state_sign(state_handle).unwrap();
+
+        let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+        let verify = |message: &[u8]| {
+            let state_handle = crate::signature_verification_state_open(pk_handle).unwrap();
+            crate::signature_verification_state_update(state_handle, message).unwrap();
+            crate::signature_verification_state_verify(state_handle, signature_handle)
+        };
+        verify(b"test").unwrap();
+        assert!(verify(b"other").is_err());
+        assert!(crate::signature_keypair_export(kp_handle, crate::KeyPairEncoding::PKCS8).is_err());
+    }
+
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let failing: RemoteSigner = Arc::new(|_: &[u8]| bail!(CryptoError::AlgorithmFailure));
+    assert!(signature_keypair_from_remote_signer(op_handle, &[0u8; 31], failing.clone()).is_err());
+    let kp_handle = signature_keypair_from_remote_signer(op_handle, &[0x42; 32], failing).unwrap();
+    let err = crate::signature_sign_reader(kp_handle, &b"test"[..]).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::AlgorithmFailure)
+    ));
+}
@@ -0,0 +1,139 @@
+use std::thread;
+
+use super::error::*;
+use super::handles::*;
+use super::signature::*;
+use super::{CryptoContext, WASI_CRYPTO_CTX};
+
+/// The outcome of verifying one item of a report.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct VerifyResult {
+    /// The position of the item in the input.
+    pub index: usize,
+    pub valid: bool,
+    /// Why the item failed to verify, `None` if it is valid. Errors that don't carry a
+    /// `CryptoError` are reported as `AlgorithmFailure`.
+    pub error: Option<CryptoError>,
+}
+
+fn verify_item(
+    ctx: &CryptoContext,
+    pk_handle: Handle,
+    input: &[u8],
+    signature: &Signature,
+) -> Result<(), Error> {
+    let pk = ctx.signature_publickey_manager.get(pk_handle)?;
+    let mut state = ExclusiveSignatureVerificationState::from_publickey(pk)?;
+    state.update(input)?;
+    state.verify_signature(signature)
+}
+
+/// Verify every `(public key, input, signature)` item, and return one result per item, in the
+/// same order, instead of stopping at the first failure. Items are verified on all available
+/// threads.
+pub fn verify_report(
+    ctx: &CryptoContext,
+    items: &[(Handle, &[u8], &Signature)],
+) -> Vec<VerifyResult> {
+    let threads = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(items.len())
+        .max(1);
+    let chunk_size = items.len().div_ceil(threads).max(1);
+    let verified: Vec<Vec<Result<(), Error>>> = thread::scope(|scope| {
+        let workers: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|&(pk_handle, input, signature)| {
+                            verify_item(ctx, pk_handle, input, signature)
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("verification thread panicked"))
+            .collect()
+    });
+    verified
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(index, res)| {
+            let error = res.err().map(|err| {
+                err.downcast_ref::<CryptoError>()
+                    .copied()
+                    .unwrap_or(CryptoError::AlgorithmFailure)
+            });
+            VerifyResult {
+                index,
+                valid: error.is_none(),
+                error,
+            }
+        })
+        .collect()
+}
+
+/// Verify every item with the default context, and return one result per item.
+pub fn signature_verify_report(items: &[(Handle, &[u8], &Signature)]) -> Vec<VerifyResult> {
+    verify_report(&WASI_CRYPTO_CTX, items)
+}
+
+#[test]
+fn test_verify_report() {
+    let keys: Vec<_> = ["Ed25519", "ECDSA_P256_SHA256"]
+        .iter()
+        .map(|alg_str| {
+            let op_handle = crate::signature_op_open(alg_str).unwrap();
+            let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+            let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+            let kp = WASI_CRYPTO_CTX
+                .signature_keypair_manager
+                .get(kp_handle)
+                .unwrap();
+            let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+            (pk_handle, kp.sign_oneshot(b"test").unwrap())
+        })
+        .collect();
+    let (ed_pk, ed_signature) = &keys[0];
+    let (ec_pk, ec_signature) = &keys[1];
+    let closed_pk = WASI_CRYPTO_CTX
+        .signature_publickey_manager
+        .register(
+            WASI_CRYPTO_CTX
+                .signature_publickey_manager
+                .get(*ed_pk)
+                .unwrap(),
+        )
+        .unwrap();
+    crate::signature_publickey_close(closed_pk).unwrap();
+
+    let items: Vec<(Handle, &[u8], &Signature)> = vec![
+        (*ed_pk, b"test", ed_signature),
+        (*ec_pk, b"test", ec_signature),
+        (*ed_pk, b"other", ed_signature),
+        (*ec_pk, b"test", ed_signature),
+        (closed_pk, b"test", ed_signature),
+        (*ec_pk, b"other", ec_signature),
+    ];
+    let report = signature_verify_report(&items);
+    let outcomes: Vec<_> = report.iter().map(|res| (res.index, res.error)).collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            (0, None),
+            (1, None),
+            (2, Some(CryptoError::VerificationFailed)),
+            (3, Some(CryptoError::InvalidSignature)),
+            (4, Some(CryptoError::InvalidHandle)),
+            (5, Some(CryptoError::VerificationFailed)),
+        ]
+    );
+    assert!(report[0].valid && !report[2].valid);
+    assert!(signature_verify_report(&[]).is_empty());
+}
@@ -1,36 +1,48 @@
-use parking_lot::Mutex;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use ring::signature::KeyPair as _;
-use std::sync::Arc;
-use zeroize::Zeroize;
+use zeroize::Zeroizing;
 
+use super::der;
 use super::error::*;
+use super::framing;
 use super::handles::*;
 use super::signature::*;
 use super::signature_keypair::*;
-use super::WASI_CRYPTO_CTX;
+use super::signature_op::*;
+use super::sync::Mutex;
+use super::CryptoContext;
 
 #[derive(Clone, Copy, Debug)]
 pub struct RSASignatureOp {
     pub alg: SignatureAlgorithm,
+    pub non_exportable: bool,
 }
 
 impl RSASignatureOp {
     pub fn new(alg: SignatureAlgorithm) -> Self {
-        RSASignatureOp { alg }
+        RSASignatureOp {
+            alg,
+            non_exportable: false,
+        }
+    }
+
+    pub fn with_options(alg: SignatureAlgorithm, options: &SignatureOptions) -> Self {
+        RSASignatureOp {
+            alg,
+            non_exportable: options.non_exportable,
+        }
     }
 }
 
+/// Clones share the PKCS#8 document and the parsed ring key. Both are released together when
+/// the last clone is dropped, and the document is zeroized at that point.
 #[derive(Clone, Debug)]
 pub struct RSASignatureKeyPair {
     pub alg: SignatureAlgorithm,
-    pub pkcs8: Vec<u8>,
+    pub pkcs8: Arc<Zeroizing<Vec<u8>>>,
     pub ring_kp: Arc<ring::signature::RsaKeyPair>,
-}
-
-impl Drop for RSASignatureKeyPair {
-    fn drop(&mut self) {
-        self.pkcs8.zeroize();
-    }
+    pub non_exportable: bool,
 }
 
 impl RSASignatureKeyPair {
@@ -39,13 +51,15 @@ impl RSASignatureKeyPair {
             ring::signature::RsaKeyPair::from_pkcs8(pkcs8).map_err(|_| CryptoError::InvalidKey)?;
         let kp = RSASignatureKeyPair {
             alg,
-            pkcs8: pkcs8.to_vec(),
+            pkcs8: Arc::new(Zeroizing::new(pkcs8.to_vec())),
             ring_kp: Arc::new(ring_kp),
+            non_exportable: false,
         };
         Ok(kp)
     }
 
     pub fn as_pkcs8(&self) -> Result<&[u8], Error> {
+        ensure!(!self.non_exportable, CryptoError::NotAvailable);
         Ok(&self.pkcs8)
     }
 
@@ -57,29 +71,74 @@ impl RSASignatureKeyPair {
     pub fn raw_public_key(&self) -> &[u8] {
         self.ring_kp.public_key().as_ref()
     }
+
+    fn padding_alg(&self) -> Result<&'static dyn ring::signature::RsaEncoding, Error> {
+        let padding_alg: &'static dyn ring::signature::RsaEncoding = match self.alg {
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256 => &ring::signature::RSA_PKCS1_SHA256,
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA384 => &ring::signature::RSA_PKCS1_SHA384,
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512 => &ring::signature::RSA_PKCS1_SHA512,
+            SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384 => &ring::signature::RSA_PKCS1_SHA384,
+            _ => bail!(CryptoError::NotAvailable),
+        };
+        Ok(padding_alg)
+    }
+
+    /// Sign a complete message, without the buffering and locking of a signature state.
+    pub fn sign_oneshot(&self, input: &[u8]) -> Result<RSASignature, Error> {
+        let rng = ring::rand::SystemRandom::new();
+        let mut signature_u8 = vec![0u8; self.ring_kp.public_modulus_len()];
+        self.ring_kp
+            .sign(self.padding_alg()?, &rng, input, &mut signature_u8)
+            .map_err(CryptoError::from)?;
+        let signature = RSASignature(signature_u8);
+        Ok(signature)
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
 pub struct RSASignatureKeyPairBuilder {
     pub alg: SignatureAlgorithm,
+    pub non_exportable: bool,
 }
 
 impl RSASignatureKeyPairBuilder {
     pub fn new(alg: SignatureAlgorithm) -> Self {
-        RSASignatureKeyPairBuilder { alg }
+        RSASignatureKeyPairBuilder {
+            alg,
+            non_exportable: false,
+        }
+    }
+
+    pub fn from_op(op: RSASignatureOp) -> Self {
+        RSASignatureKeyPairBuilder {
+            alg: op.alg,
+            non_exportable: op.non_exportable,
+        }
     }
 
     pub fn generate(&self) -> Result<Handle, Error> {
         bail!(CryptoError::NotAvailable)
     }
 
-    pub fn import(&self, encoded: &[u8], encoding: KeyPairEncoding) -> Result<Handle, Error> {
+    /// Import a PKCS#8 key pair owned by the caller, instead of registering it in the context.
+    pub fn import_owned(&self, pkcs8: &[u8]) -> Result<RSASignatureKeyPair, Error> {
+        let mut kp = RSASignatureKeyPair::from_pkcs8(self.alg, pkcs8)?;
+        kp.non_exportable = self.non_exportable;
+        Ok(kp)
+    }
+
+    pub fn import(
+        &self,
+        ctx: &CryptoContext,
+        encoded: &[u8],
+        encoding: KeyPairEncoding,
+    ) -> Result<Handle, Error> {
         match encoding {
             KeyPairEncoding::PKCS8 => {}
             _ => bail!(CryptoError::NotAvailable),
         };
-        let kp = RSASignatureKeyPair::from_pkcs8(self.alg, encoded)?;
-        let handle = WASI_CRYPTO_CTX
+        let kp = self.import_owned(encoded)?;
+        let handle = ctx
             .signature_keypair_manager
             .register(SignatureKeyPair::RSA(kp))?;
         Ok(handle)
@@ -115,27 +174,44 @@ impl RSASignatureState {
     }
 
     pub fn update(&self, input: &[u8]) -> Result<(), Error> {
-        self.input.lock().extend_from_slice(input);
+        self.update_from_slices(&[input])
+    }
+
+    pub fn update_from_slices(&self, slices: &[&[u8]]) -> Result<(), Error> {
+        let mut input = self.input.lock();
+        slices
+            .iter()
+            .for_each(|slice| input.extend_from_slice(slice));
+        Ok(())
+    }
+
+    pub fn update_framed_from_slices(&self, fields: &[&[u8]]) -> Result<(), Error> {
+        let mut input = self.input.lock();
+        fields
+            .iter()
+            .for_each(|field| framing::append_framed(&mut input, field));
         Ok(())
     }
 
     pub fn sign(&self) -> Result<RSASignature, Error> {
+        self.kp.sign_oneshot(&self.input.lock())
+    }
+
+    /// Write the signature into `out`, and return its length.
+    pub fn sign_into(&self, out: &mut [u8]) -> Result<usize, Error> {
+        let len = self.kp.ring_kp.public_modulus_len();
+        ensure!(out.len() >= len, CryptoError::InvalidLength);
         let rng = ring::rand::SystemRandom::new();
-        let input = self.input.lock();
-        let mut signature_u8 = vec![];
-        let padding_alg = match self.kp.alg {
-            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256 => &ring::signature::RSA_PKCS1_SHA256,
-            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA384 => &ring::signature::RSA_PKCS1_SHA384,
-            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512 => &ring::signature::RSA_PKCS1_SHA512,
-            SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384 => &ring::signature::RSA_PKCS1_SHA384,
-            _ => bail!(CryptoError::NotAvailable),
-        };
         self.kp
             .ring_kp
-            .sign(padding_alg, &rng, &input, &mut signature_u8)
-            .map_err(|_| CryptoError::AlgorithmFailure)?;
-        let signature = RSASignature(signature_u8);
-        Ok(signature)
+            .sign(
+                self.kp.padding_alg()?,
+                &rng,
+                &self.input.lock(),
+                &mut out[..len],
+            )
+            .map_err(CryptoError::from)?;
+        Ok(len)
     }
 }
 
@@ -146,34 +222,42 @@ pub struct RSASignatureVerificationState {
 }
 
 impl RSASignatureVerificationState {
-    pub fn new(pk: RSASignaturePublicKey) -> Self {
-        RSASignatureVerificationState {
+    pub fn new(pk: RSASignaturePublicKey) -> Result<Self, Error> {
+        pk.validate()?;
+        Ok(RSASignatureVerificationState {
             pk,
             input: Mutex::new(vec![]),
-        }
+        })
     }
 
     pub fn update(&self, input: &[u8]) -> Result<(), Error> {
-        self.input.lock().extend_from_slice(input);
+        self.update_from_slices(&[input])
+    }
+
+    pub fn update_from_slices(&self, slices: &[&[u8]]) -> Result<(), Error> {
+        let mut input = self.input.lock();
+        slices
+            .iter()
+            .for_each(|slice| input.extend_from_slice(slice));
+        Ok(())
+    }
+
+    pub fn update_framed_from_slices(&self, fields: &[&[u8]]) -> Result<(), Error> {
+        let mut input = self.input.lock();
+        fields
+            .iter()
+            .for_each(|field| framing::append_framed(&mut input, field));
         Ok(())
     }
 
+    pub fn input_digest(&self) -> Vec<u8> {
+        ring::digest::digest(self.pk.alg.digest_alg(), &self.input.lock())
+            .as_ref()
+            .to_vec()
+    }
+
     pub fn verify(&self, signature: &RSASignature) -> Result<(), Error> {
-        let ring_alg = match self.pk.alg {
-            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256 => {
-                &ring::signature::RSA_PKCS1_2048_8192_SHA256
-            }
-            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA384 => {
-                &ring::signature::RSA_PKCS1_2048_8192_SHA384
-            }
-            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512 => {
-                &ring::signature::RSA_PKCS1_2048_8192_SHA512
-            }
-            SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384 => {
-                &ring::signature::RSA_PKCS1_3072_8192_SHA384
-            }
-            _ => bail!(CryptoError::NotAvailable),
-        };
+        let ring_alg = self.pk.ring_alg()?;
         let ring_pk = ring::signature::UnparsedPublicKey::new(ring_alg, self.pk.as_raw()?);
         ring_pk
             .verify(self.input.lock().as_ref(), signature.as_ref())
@@ -200,4 +284,91 @@ impl RSASignaturePublicKey {
     pub fn as_raw(&self) -> Result<&[u8], Error> {
         Ok(&self.raw)
     }
+
+    // ring only parses the key when verifying a signature, so we check the `RSAPublicKey`
+    // structure and the sizes that ring accepts for the algorithm up front.
+    pub fn validate(&self) -> Result<(), Error> {
+        let min_modulus_bits = match self.alg {
+            SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384 => 3072,
+            _ => 2048,
+        };
+        self.ring_alg()?;
+        let parse = || -> Result<(&[u8], &[u8]), Error> {
+            let mut decoder = der::Decoder::new(&self.raw);
+            let mut rsa_public_key = der::Decoder::new(decoder.read(der::TAG_SEQUENCE)?);
+            decoder.finish()?;
+            let modulus = rsa_public_key.read_unsigned_integer()?;
+            let exponent = rsa_public_key.read_unsigned_integer()?;
+            rsa_public_key.finish()?;
+            Ok((modulus, exponent))
+        };
+        let (modulus, exponent) = parse().map_err(|_| CryptoError::InvalidKey)?;
+        let bits = |be: &[u8]| be.len() * 8 - be[0].leading_zeros() as usize;
+        ensure!(
+            (min_modulus_bits..=8192).contains(&bits(modulus)),
+            CryptoError::InvalidKey
+        );
+        ensure!(
+            (2..=33).contains(&bits(exponent)) && exponent[exponent.len() - 1] & 1 == 1,
+            CryptoError::InvalidKey
+        );
+        Ok(())
+    }
+
+    fn ring_alg(&self) -> Result<&'static ring::signature::RsaParameters, Error> {
+        let ring_alg = match self.alg {
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256 => {
+                &ring::signature::RSA_PKCS1_2048_8192_SHA256
+            }
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA384 => {
+                &ring::signature::RSA_PKCS1_2048_8192_SHA384
+            }
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512 => {
+                &ring::signature::RSA_PKCS1_2048_8192_SHA512
+            }
+            SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384 => {
+                &ring::signature::RSA_PKCS1_3072_8192_SHA384
+            }
+            _ => bail!(CryptoError::NotAvailable),
+        };
+        Ok(ring_alg)
+    }
+}
+
+#[test]
+fn test_verification_state_rejects_malformed_key() {
+    let raw_pk = base64::decode(crate::self_test::RSA_PUBLIC_KEY).unwrap();
+    let new_state = |alg, raw_pk: &[u8]| {
+        let pk = RSASignaturePublicKey::from_raw(alg, raw_pk).unwrap();
+        RSASignatureVerificationState::new(pk)
+    };
+    for alg in &[
+        SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256,
+        SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384,
+    ] {
+        new_state(*alg, &raw_pk).unwrap();
+        assert!(new_state(*alg, &raw_pk[..raw_pk.len() - 1]).is_err());
+        assert!(new_state(*alg, &[raw_pk.as_slice(), &[0x00]].concat()).is_err());
+        assert!(new_state(*alg, &[0x42; 270]).is_err());
+    }
+
+    // A 2048-bit modulus is too short for RSA_PKCS1_3072_8192_SHA384
+    let modulus = [&[0x00, 0x80][..], &[0x01; 255]].concat();
+    let with_exponent = |exponent: &[u8]| {
+        der::sequence(&[
+            &der::encode(der::TAG_INTEGER, &modulus),
+            &der::encode(der::TAG_INTEGER, exponent),
+        ])
+    };
+    let alg = SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256;
+    new_state(alg, &with_exponent(&[0x01, 0x00, 0x01])).unwrap();
+    assert!(new_state(
+        SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384,
+        &with_exponent(&[0x01, 0x00, 0x01])
+    )
+    .is_err());
+    // Even, too small, and negative exponents
+    for exponent in &[&[0x01, 0x00, 0x00][..], &[0x01], &[0x81]] {
+        assert!(new_state(alg, &with_exponent(exponent)).is_err());
+    }
 }
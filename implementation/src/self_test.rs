@@ -0,0 +1,153 @@
+//! Power-on self-tests, for deployments that must check the primitives before using them.
+//! Deterministic schemes are checked against known answers; ECDSA signatures are randomized,
+//! so ECDSA is checked by signing with a fresh key and verifying the signature instead.
+
+use alloc::string::ToString;
+use ring::digest;
+use ring::hmac;
+
+use super::ecdsa::*;
+use super::eddsa::*;
+use super::error::*;
+use super::rsa::*;
+use super::signature::*;
+
+pub(crate) const MESSAGE: &[u8] = b"abc";
+
+// RFC 8032, section 7.1, test 1, and the signature of the BLAKE2b-512 digest of `MESSAGE`
+// with the same key
+const ED25519_SEED: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+const ED25519_SIGNATURE: &str = "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b";
+const ED25519_BLAKE2B512_SIGNATURE: &str = "bfca12678e0fcfe58a4289696a7bd159806a4ba27e66a7310890fc3d12e3235bc290a56701d00b9bc8befda34acc3c6aae577202320d9fd23e6c26ee0c8fdf07";
+
+// A 3072-bit RSA public key, and its PKCS#1 v1.5 signatures of `MESSAGE` with SHA-256,
+// SHA-384 and SHA-512, computed with OpenSSL
+pub(crate) const RSA_PUBLIC_KEY: &str = "MIIBigKCAYEA3yPzJal0Jd/w5sjjXe6JaDUPs6ggrO2ABrhdQBaEsH+HG8+FcC+YwsLHKUi3DOJQ0G+tObsrq7rSwopOSXvl1SpneqLBSgwNbRmfdTII4I+mBt3V9vP40vsKUD8cvuK5fcBvtd1ZeVpTLdsSmyKK5BdwV96NSehl5AnjA/bL9CHXKF16ITMTTRpDZ/C8PS2EmucqpqwihmL0P+sPs63rLM32kzFKQEKxlP4PpLyhMto/tNI2oGlXVl+3o/eMExkjL4A0cM7bJOuZqYIJSplJODtwBH7s05N0tPxF8NatMIc45vA6ljkvT2EmTCiGwoPiq8t6+tVjTSBH+2K+HhghR9RFsCvnLn5YB5HUAMJ9OQaCly3Mhj9SaKAruXeFMf0xyIRLgVMu1+dHafgf6YdB4jJ/3dwVmU8MkEmJ5I9J4qK5LYrXKGp0T7XSDCJoVzmFlEtrcOgAZa8PCu8hvOHhDxNFAjlsYfTn1uGCU9RQNneSZYM6KxpSzRVcTsgiiTpvAgMBAAE=";
+pub(crate) const RSA_SHA256_SIGNATURE: &str = "LxIrWl03Kqzi4vuXBnzeIlas1wsUYD4cEyTTkye/bWS0thvkIn5AW61731jRT6FOrDF2X74asPBWDXA2xTBdRLw97nc88CP7xUCWlAgz2ybsERyhFrqqVNpxBl0lB/6shmFeQFmPhqc5co2P2ehinN2DWJ/+J0A0Q8fcRvpM77fXjNIyC4Kt0mxFaGmfXrRg35eyjnpkbD+YuEEtKx6ftjyz5ZnAV3/fVGf27wX8IkCFLaso/jnMFFcIWIoxgr4hROsNnf8aee7LkG8RqoGqKKU9YhzCKXZFaSsuFibNnyE/eZivmuHY8pgIZP+8HC7L+g583UHl1GA7EyBwFCOcXiwLzDuvZFZSYq02f9RPPIIltdcmxabO2WLxzi/CI/Srmz4BLNaj1+TxX/fXSwTCM4r0SsWDhVl9zECSpShn8T7bzpQevqLIDXsIn4QxY7+GpD7oJn14doBX0cZhbdaqnNpb0sgjpo1OsO7H6e3E1drxZf0Am3rxR10u3C3do+A3";
+pub(crate) const RSA_SHA384_SIGNATURE: &str = "tqNPLeijBOmk20Iq8SNHnoi9s6CAyRhRvvddWySoVHf2Nt4w+2WsGkyxmk9aQGZjF+5iLTlB7aBUFr2au+0RFqdEBbwF669kKw0HeQ2ToT3xmBywahkdpWYV25K0l9m+eJ0U2NVxAku0weAe/opChhsPDNpMmexc7AYw6BQqHNZsOm1efjK+4Ot6sv1OgBUaY9509F/kHCzDBqtdumbNSTVBD/ZlhVHHQaGV+VifaklJCZokXv/SOJ8Gs/yWgbEBlLdoghRSgob1N3fWDZxScSMzfM9N4ig0XpDwjdwlycsIfv+akfx+xYD3b07C+C7pYJd2omBloN326R+8BUeZMed8LcattyfochrxzgfNcUFSwTvL6WjRPrbAKbPxult/QptMLBLd9dQWJkSYCE9kjaRDZtT7tjABs9hKPk1PkPoXX+4RZ2i/FkpxslQ+yxPgShPcx+7yB2t2l/vILNRe7hyJWMYBK/3uCtM4emORoa5Nk69sij+vMVWiLaj9ebFN";
+pub(crate) const RSA_SHA512_SIGNATURE: &str = "d7LoURl4OOl7EZp/z68srknxvEMJwgWGMLjZIDK84nncY32UVtRc1ldaI24dgZAx9UsZiLUACCPvP06xVWX/VikkvqKEbtJTuu43RjbSvAaM3EVMQmdVSYINYjYkdhrbKBHxSfUgP2IWZD2oWY5D4CCxmdDJMtz2SBOczsl/fLtVLhb1+PiaZH/wgZc28UpuIrH5rl/9QScMzGUYQBhUFJtDYGkk9qxLLuW4eSB+e/Q0TBGQM4/FU9gbtusdSRIWxV6DMdhJLGup+ZPCbdOyCuNiM8am0n1Iyq23Cunh6WeQ1xfx5fsuMZpBe3/kvT52FNznKqo7BITqljtL/bs/Ey+C55z5OijVdBBq0UeMjni43fozuTwsyxjAEVP/E0hA/Wpq3osYjqr1WXeIK7Vw6+kQq/vjFprzIa3oUp1xLrdRdpuYJSQwVHTH6tQvlUVoStnpfG44SokWlavyJftenOC6rDZiSx3t3nOblQgfbBcx7sdkG+p7JGBlIVDAF7SX";
+
+// FIPS 180-2 and RFC 7693, appendix A
+const DIGESTS: [(&str, &digest::Algorithm, &str); 3] = [
+    ("SHA-256", &digest::SHA256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+    ("SHA-384", &digest::SHA384, "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed8086072ba1e7cc2358baeca134c825a7"),
+    ("SHA-512", &digest::SHA512, "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"),
+];
+const BLAKE2B512_DIGEST: &str = "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923";
+
+// RFC 4231, test case 2
+const HMAC_KEY: &[u8] = b"Jefe";
+const HMAC_MESSAGE: &[u8] = b"what do ya want for nothing?";
+const HMACS: [(&str, hmac::Algorithm, &str); 3] = [
+    ("HMAC-SHA-256", hmac::HMAC_SHA256, "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"),
+    ("HMAC-SHA-384", hmac::HMAC_SHA384, "af45d2e376484031617f78d2b58a6b1b9c7ef464f5a01b47e42ec3736322445e8e2240ca5e69e2c78b3239ecfab21649"),
+    ("HMAC-SHA-512", hmac::HMAC_SHA512, "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea2505549758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737"),
+];
+
+fn check_equal(computed: &[u8], expected_hex: &str) -> Result<(), Error> {
+    ensure!(
+        computed == parse_hex(expected_hex)?.as_slice(),
+        CryptoError::AlgorithmFailure
+    );
+    Ok(())
+}
+
+fn ecdsa_self_test(alg: SignatureAlgorithm) -> Result<(), Error> {
+    let kp = ECDSASignatureKeyPair::generate(alg)?;
+    let signature = kp.sign_oneshot(MESSAGE)?;
+    let pk = ECDSASignaturePublicKey::from_raw(alg, kp.raw_public_key())?;
+    pk.verify(MESSAGE, signature.as_ref())?;
+    ensure!(
+        pk.verify(b"abd", signature.as_ref()).is_err(),
+        CryptoError::AlgorithmFailure
+    );
+    Ok(())
+}
+
+fn eddsa_self_test(alg: SignatureAlgorithm) -> Result<(), Error> {
+    let (message, expected) = match alg {
+        SignatureAlgorithm::Ed25519 => (&b""[..], ED25519_SIGNATURE),
+        _ => (MESSAGE, ED25519_BLAKE2B512_SIGNATURE),
+    };
+    let kp = EdDSASignatureKeyPair::from_seed(alg, &parse_hex(ED25519_SEED)?)?;
+    let signature = kp.sign_oneshot(message)?;
+    check_equal(signature.as_ref(), expected)?;
+    let pk = EdDSASignaturePublicKey::from_raw(alg, kp.raw_public_key())?;
+    pk.verify(message, signature.as_ref())?;
+    ensure!(
+        pk.verify(b"abd", signature.as_ref()).is_err(),
+        CryptoError::AlgorithmFailure
+    );
+    Ok(())
+}
+
+fn rsa_self_test(alg: SignatureAlgorithm) -> Result<(), Error> {
+    let encoded_signature = match alg.digest_alg().output_len {
+        32 => RSA_SHA256_SIGNATURE,
+        48 => RSA_SHA384_SIGNATURE,
+        _ => RSA_SHA512_SIGNATURE,
+    };
+    let decode = |encoded| base64::decode(encoded).map_err(|_| CryptoError::AlgorithmFailure);
+    let signature = RSASignature::new(decode(encoded_signature)?);
+    let pk = RSASignaturePublicKey::from_raw(alg, &decode(RSA_PUBLIC_KEY)?)?;
+    let verify = |message: &[u8]| {
+        let state = RSASignatureVerificationState::new(pk.clone())?;
+        state.update(message)?;
+        state.verify(&signature)
+    };
+    verify(MESSAGE)?;
+    ensure!(verify(b"abd").is_err(), CryptoError::AlgorithmFailure);
+    Ok(())
+}
+
+fn signature_self_test(alg: SignatureAlgorithm) -> Result<(), Error> {
+    match alg.category() {
+        AlgorithmCategory::ECDSA => ecdsa_self_test(alg),
+        AlgorithmCategory::EdDSA => eddsa_self_test(alg),
+        AlgorithmCategory::RSA => rsa_self_test(alg),
+    }
+}
+
+/// Run a self-test of every supported signature algorithm, hash function and HMAC, such as
+/// at startup. The error lists the primitives that failed, and carries an `AlgorithmFailure`.
+pub fn run_self_tests() -> Result<(), Error> {
+    let mut failed = vec![];
+    for &alg in SignatureAlgorithm::ALL.iter() {
+        if alg.is_supported() && signature_self_test(alg).is_err() {
+            failed.push(alg.name().to_string());
+        }
+    }
+    for (name, digest_alg, expected) in DIGESTS.iter() {
+        if check_equal(digest::digest(digest_alg, MESSAGE).as_ref(), expected).is_err() {
+            failed.push(name.to_string());
+        }
+    }
+    {
+        use blake2::Digest as _;
+
+        if check_equal(&blake2::Blake2b512::digest(MESSAGE), BLAKE2B512_DIGEST).is_err() {
+            failed.push("BLAKE2b-512".to_string());
+        }
+    }
+    for (name, hmac_alg, expected) in HMACS.iter() {
+        let key = hmac::Key::new(*hmac_alg, HMAC_KEY);
+        if check_equal(hmac::sign(&key, HMAC_MESSAGE).as_ref(), expected).is_err() {
+            failed.push(name.to_string());
+        }
+    }
+    if failed.is_empty() {
+        return Ok(());
+    }
+    Err(Error::new(CryptoError::AlgorithmFailure)
+        .context(format!("Self-tests failed: {}", failed.join(", "))))
+}
+
+#[test]
+fn test_self_tests() {
+    run_self_tests().unwrap();
+    for &alg in SignatureAlgorithm::ALL.iter() {
+        signature_self_test(alg).unwrap();
+    }
+    assert!(check_equal(b"abc", "616263").is_ok());
+    assert!(check_equal(b"abd", "616263").is_err());
+}
@@ -1,13 +1,19 @@
-use std::sync::Arc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 
+use super::capabilities;
 use super::ecdsa::*;
 use super::eddsa::*;
 use super::error::*;
+use super::framing;
 use super::handles::*;
+use super::remote::*;
 use super::rsa::*;
 use super::signature_keypair::*;
+use super::signature_op::*;
 use super::signature_publickey::*;
-use super::WASI_CRYPTO_CTX;
+use super::sync::Mutex;
+use super::{CryptoContext, WASI_CRYPTO_CTX};
 
 #[allow(non_camel_case_types)]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -16,12 +22,112 @@ pub enum SignatureAlgorithm {
     ECDSA_P256_SHA256,
     ECDSA_P384_SHA384,
     Ed25519,
+    Ed25519_BLAKE2b512,
     RSA_PKCS1_2048_8192_SHA256,
     RSA_PKCS1_2048_8192_SHA384,
     RSA_PKCS1_2048_8192_SHA512,
     RSA_PKCS1_3072_8192_SHA384,
 }
 
+/// The family of signature schemes that an algorithm belongs to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AlgorithmCategory {
+    ECDSA,
+    EdDSA,
+    RSA,
+}
+
+impl SignatureAlgorithm {
+    pub const ALL: [SignatureAlgorithm; 8] = [
+        SignatureAlgorithm::ECDSA_P256_SHA256,
+        SignatureAlgorithm::ECDSA_P384_SHA384,
+        SignatureAlgorithm::Ed25519,
+        SignatureAlgorithm::Ed25519_BLAKE2b512,
+        SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256,
+        SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA384,
+        SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512,
+        SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384,
+    ];
+
+    /// Whether the algorithm is usable with the ring build linked in, as determined by
+    /// probing it once. `signature_op_open()` returns `NotAvailable` for other algorithms.
+    pub fn is_supported(self) -> bool {
+        capabilities::is_supported(self)
+    }
+
+    pub fn category(self) -> AlgorithmCategory {
+        match self {
+            SignatureAlgorithm::ECDSA_P256_SHA256 | SignatureAlgorithm::ECDSA_P384_SHA384 => {
+                AlgorithmCategory::ECDSA
+            }
+            SignatureAlgorithm::Ed25519 | SignatureAlgorithm::Ed25519_BLAKE2b512 => {
+                AlgorithmCategory::EdDSA
+            }
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256
+            | SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA384
+            | SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512
+            | SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384 => AlgorithmCategory::RSA,
+        }
+    }
+
+    /// The approximate security level of the algorithm in bits, for its smallest allowed key
+    /// size. RSA levels are the NIST SP 800-57 estimates for 2048 and 3072-bit moduli.
+    pub fn security_level_bits(self) -> u32 {
+        match self {
+            SignatureAlgorithm::ECDSA_P256_SHA256 => 128,
+            SignatureAlgorithm::ECDSA_P384_SHA384 => 192,
+            SignatureAlgorithm::Ed25519 | SignatureAlgorithm::Ed25519_BLAKE2b512 => 128,
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256
+            | SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA384
+            | SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512 => 112,
+            SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384 => 128,
+        }
+    }
+
+    /// The name of the algorithm, as accepted by `signature_op_open()`.
+    pub fn name(self) -> &'static str {
+        match self {
+            SignatureAlgorithm::ECDSA_P256_SHA256 => "ECDSA_P256_SHA256",
+            SignatureAlgorithm::ECDSA_P384_SHA384 => "ECDSA_P384_SHA384",
+            SignatureAlgorithm::Ed25519 => "Ed25519",
+            SignatureAlgorithm::Ed25519_BLAKE2b512 => "Ed25519_BLAKE2b512",
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256 => "RSA_PKCS1_2048_8192_SHA256",
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA384 => "RSA_PKCS1_2048_8192_SHA384",
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512 => "RSA_PKCS1_2048_8192_SHA512",
+            SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384 => "RSA_PKCS1_3072_8192_SHA384",
+        }
+    }
+
+    /// The length of the PKCS#8 export of a key pair generated for the algorithm, to size
+    /// buffers in advance. Imported key pairs are exported as they were imported, and can
+    /// be shorter. `None` for RSA, whose length depends on the modulus.
+    pub fn pkcs8_len_hint(self) -> Option<usize> {
+        match self {
+            SignatureAlgorithm::ECDSA_P256_SHA256 => Some(138),
+            SignatureAlgorithm::ECDSA_P384_SHA384 => Some(185),
+            SignatureAlgorithm::Ed25519 | SignatureAlgorithm::Ed25519_BLAKE2b512 => Some(85),
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256
+            | SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA384
+            | SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512
+            | SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384 => None,
+        }
+    }
+
+    pub fn digest_alg(self) -> &'static ring::digest::Algorithm {
+        match self {
+            SignatureAlgorithm::ECDSA_P256_SHA256 => &ring::digest::SHA256,
+            SignatureAlgorithm::ECDSA_P384_SHA384 => &ring::digest::SHA384,
+            SignatureAlgorithm::Ed25519 => &ring::digest::SHA512,
+            // The input is prehashed with BLAKE2b-512, and ring hashes the digest with SHA-512
+            SignatureAlgorithm::Ed25519_BLAKE2b512 => &ring::digest::SHA512,
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256 => &ring::digest::SHA256,
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA384 => &ring::digest::SHA384,
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512 => &ring::digest::SHA512,
+            SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384 => &ring::digest::SHA384,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Signature {
     ECDSA(ECDSASignature),
@@ -39,6 +145,23 @@ impl AsRef<[u8]> for Signature {
     }
 }
 
+// Lowercase hex, for the `Display` implementations of the signature types
+pub(crate) fn fmt_hex(encoded: &[u8], f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    encoded.iter().try_for_each(|x| write!(f, "{:02x}", x))
+}
+
+// The inverse of `fmt_hex()`, accepting either case
+pub(crate) fn parse_hex(hex: &str) -> Result<Vec<u8>, Error> {
+    ensure!(
+        hex.len().is_multiple_of(2) && hex.bytes().all(|x| x.is_ascii_hexdigit()),
+        CryptoError::InvalidSignature
+    );
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| Ok(u8::from_str_radix(&hex[i..i + 2], 16)?))
+        .collect()
+}
+
 impl PartialEq for Signature {
     fn eq(&self, other: &Self) -> bool {
         ring::constant_time::verify_slices_are_equal(self.as_ref(), other.as_ref()).is_ok()
@@ -48,7 +171,7 @@ impl PartialEq for Signature {
 impl Eq for Signature {}
 
 impl Signature {
-    fn from_raw(alg: SignatureAlgorithm, encoded: &[u8]) -> Result<Self, Error> {
+    pub fn from_raw(alg: SignatureAlgorithm, encoded: &[u8]) -> Result<Self, Error> {
         let signature = match alg {
             SignatureAlgorithm::ECDSA_P256_SHA256 => {
                 ensure!(encoded.len() == 64, "Unexpected signature length");
@@ -64,7 +187,7 @@ impl Signature {
                     encoded.to_vec(),
                 ))
             }
-            SignatureAlgorithm::Ed25519 => {
+            SignatureAlgorithm::Ed25519 | SignatureAlgorithm::Ed25519_BLAKE2b512 => {
                 ensure!(encoded.len() == 64, "Unexpected signature length");
                 Signature::EdDSA(EdDSASignature::new(encoded.to_vec()))
             }
@@ -111,54 +234,264 @@ pub enum SignatureState {
     ECDSA(ECDSASignatureState),
     EdDSA(EdDSASignatureState),
     RSA(RSASignatureState),
+    Remote(RemoteSignatureState),
+}
+
+impl SignatureState {
+    fn from_keypair(kp: SignatureKeyPair) -> Self {
+        match kp {
+            SignatureKeyPair::ECDSA(kp) => SignatureState::ECDSA(ECDSASignatureState::new(kp)),
+            SignatureKeyPair::EdDSA(kp) => SignatureState::EdDSA(EdDSASignatureState::new(kp)),
+            SignatureKeyPair::RSA(kp) => SignatureState::RSA(RSASignatureState::new(kp)),
+            SignatureKeyPair::Remote(kp) => SignatureState::Remote(RemoteSignatureState::new(kp)),
+        }
+    }
+
+    fn keypair(&self) -> SignatureKeyPair {
+        match self {
+            SignatureState::ECDSA(state) => SignatureKeyPair::ECDSA(state.kp.clone()),
+            SignatureState::EdDSA(state) => SignatureKeyPair::EdDSA(state.kp.clone()),
+            SignatureState::RSA(state) => SignatureKeyPair::RSA(state.kp.clone()),
+            SignatureState::Remote(state) => SignatureKeyPair::Remote(state.kp.clone()),
+        }
+    }
+
+    fn prehashed_only(&self) -> bool {
+        match self {
+            SignatureState::ECDSA(state) => state.kp.prehashed_only,
+            SignatureState::EdDSA(state) => state.kp.prehashed_only,
+            SignatureState::RSA(_) | SignatureState::Remote(_) => false,
+        }
+    }
+
+    fn input(&self) -> Vec<u8> {
+        match self {
+            SignatureState::ECDSA(state) => state.input.lock().clone(),
+            SignatureState::EdDSA(state) => state.input.lock().clone(),
+            SignatureState::RSA(state) => state.input.lock().clone(),
+            SignatureState::Remote(state) => state.input.lock().clone(),
+        }
+    }
+
+    fn sign_digest(&self, digest: &[u8]) -> Result<Signature, Error> {
+        let signature = match self {
+            SignatureState::ECDSA(state) => Signature::ECDSA(state.kp.sign_digest(digest)?),
+            SignatureState::EdDSA(state) => Signature::EdDSA(state.kp.sign_digest(digest)?),
+            SignatureState::RSA(_) | SignatureState::Remote(_) => bail!(CryptoError::NotAvailable),
+        };
+        Ok(signature)
+    }
+
+    fn update(&self, input: &[u8]) -> Result<(), Error> {
+        match self {
+            SignatureState::ECDSA(state) => state.update(input),
+            SignatureState::EdDSA(state) => state.update(input),
+            SignatureState::RSA(state) => state.update(input),
+            SignatureState::Remote(state) => state.update(input),
+        }
+    }
+
+    fn update_from_slices(&self, slices: &[&[u8]]) -> Result<(), Error> {
+        match self {
+            SignatureState::ECDSA(state) => state.update_from_slices(slices),
+            SignatureState::EdDSA(state) => state.update_from_slices(slices),
+            SignatureState::RSA(state) => state.update_from_slices(slices),
+            SignatureState::Remote(state) => state.update_from_slices(slices),
+        }
+    }
+
+    fn update_framed_from_slices(&self, fields: &[&[u8]]) -> Result<(), Error> {
+        match self {
+            SignatureState::ECDSA(state) => state.update_framed_from_slices(fields),
+            SignatureState::EdDSA(state) => state.update_framed_from_slices(fields),
+            SignatureState::RSA(state) => state.update_framed_from_slices(fields),
+            SignatureState::Remote(state) => state.update_framed_from_slices(fields),
+        }
+    }
+
+    fn sign(&self) -> Result<Signature, Error> {
+        let signature = match self {
+            SignatureState::ECDSA(state) => Signature::ECDSA(state.sign()?),
+            SignatureState::EdDSA(state) => Signature::EdDSA(state.sign()?),
+            SignatureState::RSA(state) => Signature::RSA(state.sign()?),
+            SignatureState::Remote(state) => state.sign()?,
+        };
+        Ok(signature)
+    }
+
+    fn sign_into(&self, out: &mut [u8]) -> Result<usize, Error> {
+        match self {
+            SignatureState::ECDSA(state) => state.sign_into(out),
+            SignatureState::EdDSA(state) => state.sign_into(out),
+            SignatureState::RSA(state) => state.sign_into(out),
+            SignatureState::Remote(state) => state.sign_into(out),
+        }
+    }
+}
+
+/// The input covered by a signature, as returned by `signature_state_sign_and_return_input()`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SignedInput {
+    /// The exact bytes that were signed.
+    Message(Vec<u8>),
+    /// The digest that was signed, for algorithms that prehash the message. The message
+    /// itself is the input that was absorbed by the state.
+    Digest(Vec<u8>),
+}
+
+/// Options applying to a signature or verification state.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SignatureStateOptions {
+    /// Sign and verify `len(input) || input`, with the length of the whole input encoded as
+    /// a 64-bit big-endian integer, so that a signature doesn't verify for a truncated or
+    /// extended input that happens to share a prefix with the original one.
+    pub bind_length: bool,
+}
+
+// With length binding, the input is buffered until it is complete, and the length is then
+// absorbed before it, by a fresh state for the same key.
+fn length_bound_input(input: &[u8]) -> Vec<u8> {
+    let mut bound_input = framing::length_prefix(input).to_vec();
+    bound_input.extend_from_slice(input);
+    bound_input
 }
 
 #[derive(Debug, Clone)]
 pub struct ExclusiveSignatureState {
     state: Arc<SignatureState>,
+    bound_input: Option<Arc<Mutex<Vec<u8>>>>,
 }
 
 impl ExclusiveSignatureState {
-    fn new(signature_state: SignatureState) -> Self {
+    fn new(signature_state: SignatureState, options: &SignatureStateOptions) -> Self {
         ExclusiveSignatureState {
             state: Arc::new(signature_state),
+            bound_input: if options.bind_length {
+                Some(Arc::new(Mutex::new(vec![])))
+            } else {
+                None
+            },
         }
     }
 
-    fn open(kp_handle: Handle) -> Result<Handle, Error> {
-        let kp = WASI_CRYPTO_CTX.signature_keypair_manager.get(kp_handle)?;
-        let signature_state = match kp {
-            SignatureKeyPair::ECDSA(kp) => {
-                ExclusiveSignatureState::new(SignatureState::ECDSA(ECDSASignatureState::new(kp)))
-            }
-            SignatureKeyPair::EdDSA(kp) => {
-                ExclusiveSignatureState::new(SignatureState::EdDSA(EdDSASignatureState::new(kp)))
+    pub fn from_keypair(kp: SignatureKeyPair) -> Self {
+        Self::with_options(kp, &SignatureStateOptions::default())
+    }
+
+    pub fn with_options(kp: SignatureKeyPair, options: &SignatureStateOptions) -> Self {
+        ExclusiveSignatureState::new(SignatureState::from_keypair(kp), options)
+    }
+
+    pub(crate) fn open(
+        ctx: &CryptoContext,
+        kp_handle: Handle,
+        options: &SignatureStateOptions,
+    ) -> Result<Handle, Error> {
+        let kp = ctx.signature_keypair_manager.get(kp_handle)?;
+        let signature_state = Self::with_options(kp, options);
+        let handle = ctx.signature_state_manager.register(signature_state)?;
+        Ok(handle)
+    }
+
+    fn bound_state(&self) -> Result<Option<SignatureState>, Error> {
+        let bound_input = match &self.bound_input {
+            Some(bound_input) => bound_input,
+            None => return Ok(None),
+        };
+        let state = SignatureState::from_keypair(self.state.keypair());
+        state.update(&length_bound_input(&bound_input.lock()))?;
+        Ok(Some(state))
+    }
+
+    pub fn update(&mut self, input: &[u8]) -> Result<(), Error> {
+        self.update_from_slices(&[input])
+    }
+
+    fn update_framed(&mut self, field: &[u8]) -> Result<(), Error> {
+        self.update_framed_from_slices(&[field])
+    }
+
+    /// Absorb several buffers in order, as sequential calls to `update()` would.
+    pub fn update_from_slices(&mut self, slices: &[&[u8]]) -> Result<(), Error> {
+        ensure!(!self.state.prehashed_only(), CryptoError::NotAvailable);
+        match &self.bound_input {
+            Some(bound_input) => {
+                let mut bound_input = bound_input.lock();
+                slices
+                    .iter()
+                    .for_each(|slice| bound_input.extend_from_slice(slice));
+                Ok(())
             }
-            SignatureKeyPair::RSA(kp) => {
-                ExclusiveSignatureState::new(SignatureState::RSA(RSASignatureState::new(kp)))
+            None => self.state.update_from_slices(slices),
+        }
+    }
+
+    fn update_framed_from_slices(&mut self, fields: &[&[u8]]) -> Result<(), Error> {
+        ensure!(!self.state.prehashed_only(), CryptoError::NotAvailable);
+        match &self.bound_input {
+            Some(bound_input) => {
+                let mut bound_input = bound_input.lock();
+                fields
+                    .iter()
+                    .for_each(|field| framing::append_framed(&mut bound_input, field));
+                Ok(())
             }
-        };
-        let handle = WASI_CRYPTO_CTX
-            .signature_state_manager
-            .register(signature_state)?;
-        Ok(handle)
+            None => self.state.update_framed_from_slices(fields),
+        }
     }
 
-    fn update(&mut self, input: &[u8]) -> Result<(), Error> {
-        match self.state.as_ref() {
-            SignatureState::ECDSA(state) => state.update(input),
-            SignatureState::EdDSA(state) => state.update(input),
-            SignatureState::RSA(state) => state.update(input),
+    /// Sign a digest computed by the caller. Only available for prehashed-only key pairs,
+    /// which cannot sign anything else.
+    fn sign_digest(&mut self, digest: &[u8]) -> Result<Signature, Error> {
+        ensure!(
+            self.state.prehashed_only() && self.bound_input.is_none(),
+            CryptoError::NotAvailable
+        );
+        self.state.sign_digest(digest)
+    }
+
+    pub fn sign(&mut self) -> Result<Signature, Error> {
+        ensure!(!self.state.prehashed_only(), CryptoError::NotAvailable);
+        match self.bound_state()? {
+            Some(state) => state.sign(),
+            None => self.state.sign(),
         }
     }
 
-    fn sign(&mut self) -> Result<Signature, Error> {
-        let signature = match self.state.as_ref() {
-            SignatureState::ECDSA(state) => Signature::ECDSA(state.sign()?),
-            SignatureState::EdDSA(state) => Signature::EdDSA(state.sign()?),
-            SignatureState::RSA(state) => Signature::RSA(state.sign()?),
+    /// Sign the input, and return the signed input along with the signature. With length
+    /// binding, the signed input includes the length prefix.
+    fn sign_and_return_input(&mut self) -> Result<(Signature, SignedInput), Error> {
+        ensure!(!self.state.prehashed_only(), CryptoError::NotAvailable);
+        let input = match &self.bound_input {
+            Some(bound_input) => length_bound_input(&bound_input.lock()),
+            None => self.state.input(),
         };
-        Ok(signature)
+        let kp = self.state.keypair();
+        let signature = kp.sign_oneshot(&input)?;
+        let signed_input = match kp.alg() {
+            SignatureAlgorithm::Ed25519_BLAKE2b512 => {
+                use blake2::Digest as _;
+                SignedInput::Digest(blake2::Blake2b512::digest(&input).to_vec())
+            }
+            _ => SignedInput::Message(input),
+        };
+        Ok((signature, signed_input))
+    }
+
+    /// Sign the input, and return the raw signature along with the raw public key, so that
+    /// the recipient doesn't need to know the key beforehand.
+    fn sign_with_public_key(&mut self) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let signature = self.sign()?;
+        let raw_pk = self.state.keypair().raw_public_key().to_vec();
+        Ok((signature.as_ref().to_vec(), raw_pk))
+    }
+
+    fn sign_into(&mut self, out: &mut [u8]) -> Result<usize, Error> {
+        ensure!(!self.state.prehashed_only(), CryptoError::NotAvailable);
+        match self.bound_state()? {
+            Some(state) => state.sign_into(out),
+            None => self.state.sign_into(out),
+        }
     }
 }
 
@@ -181,51 +514,335 @@ pub enum SignatureVerificationState {
     RSA(RSASignatureVerificationState),
 }
 
+impl SignatureVerificationState {
+    fn from_publickey(pk: SignaturePublicKey) -> Result<Self, Error> {
+        let state = match pk {
+            SignaturePublicKey::ECDSA(pk) => {
+                SignatureVerificationState::ECDSA(ECDSASignatureVerificationState::new(pk)?)
+            }
+            SignaturePublicKey::EdDSA(pk) => {
+                SignatureVerificationState::EdDSA(EdDSASignatureVerificationState::new(pk)?)
+            }
+            SignaturePublicKey::RSA(pk) => {
+                SignatureVerificationState::RSA(RSASignatureVerificationState::new(pk)?)
+            }
+        };
+        Ok(state)
+    }
+
+    fn publickey(&self) -> SignaturePublicKey {
+        match self {
+            SignatureVerificationState::ECDSA(state) => SignaturePublicKey::ECDSA(state.pk.clone()),
+            SignatureVerificationState::EdDSA(state) => SignaturePublicKey::EdDSA(state.pk.clone()),
+            SignatureVerificationState::RSA(state) => SignaturePublicKey::RSA(state.pk.clone()),
+        }
+    }
+
+    fn update(&self, input: &[u8]) -> Result<(), Error> {
+        match self {
+            SignatureVerificationState::ECDSA(state) => state.update(input),
+            SignatureVerificationState::EdDSA(state) => state.update(input),
+            SignatureVerificationState::RSA(state) => state.update(input),
+        }
+    }
+
+    fn update_from_slices(&self, slices: &[&[u8]]) -> Result<(), Error> {
+        match self {
+            SignatureVerificationState::ECDSA(state) => state.update_from_slices(slices),
+            SignatureVerificationState::EdDSA(state) => state.update_from_slices(slices),
+            SignatureVerificationState::RSA(state) => state.update_from_slices(slices),
+        }
+    }
+
+    fn update_framed_from_slices(&self, fields: &[&[u8]]) -> Result<(), Error> {
+        match self {
+            SignatureVerificationState::ECDSA(state) => state.update_framed_from_slices(fields),
+            SignatureVerificationState::EdDSA(state) => state.update_framed_from_slices(fields),
+            SignatureVerificationState::RSA(state) => state.update_framed_from_slices(fields),
+        }
+    }
+
+    fn input_digest(&self) -> Vec<u8> {
+        match self {
+            SignatureVerificationState::ECDSA(state) => state.input_digest(),
+            SignatureVerificationState::EdDSA(state) => state.input_digest(),
+            SignatureVerificationState::RSA(state) => state.input_digest(),
+        }
+    }
+
+    fn verify(&self, signature: &Signature) -> Result<(), Error> {
+        match self {
+            SignatureVerificationState::ECDSA(state) => state.verify(signature.as_ecdsa()?),
+            SignatureVerificationState::EdDSA(state) => state.verify(signature.as_eddsa()?),
+            SignatureVerificationState::RSA(state) => state.verify(signature.as_rsa()?),
+        }
+    }
+
+    fn verify_with_encoding(
+        &self,
+        encoded: &[u8],
+        encoding: SignatureEncoding,
+    ) -> Result<(), Error> {
+        match self {
+            SignatureVerificationState::ECDSA(state) => {
+                state.verify_with_encoding(encoded, encoding)
+            }
+            SignatureVerificationState::EdDSA(state) => {
+                ensure!(
+                    encoding == SignatureEncoding::Raw,
+                    CryptoError::NotAvailable
+                );
+                state.verify(Signature::from_raw(state.pk.alg, encoded)?.as_eddsa()?)
+            }
+            SignatureVerificationState::RSA(state) => {
+                ensure!(
+                    encoding == SignatureEncoding::Raw,
+                    CryptoError::NotAvailable
+                );
+                state.verify(Signature::from_raw(state.pk.alg, encoded)?.as_rsa()?)
+            }
+        }
+    }
+
+    fn verify_any_encoding(&self, encoded: &[u8]) -> Result<(), Error> {
+        match self {
+            SignatureVerificationState::ECDSA(state) => state.verify_any_encoding(encoded),
+            // EdDSA and RSA signatures only have a raw encoding
+            _ => self.verify_with_encoding(encoded, SignatureEncoding::Raw),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ExclusiveSignatureVerificationState {
     state: Arc<SignatureVerificationState>,
+    bound_input: Option<Arc<Mutex<Vec<u8>>>>,
 }
 
 impl ExclusiveSignatureVerificationState {
-    fn new(signature_verification_state: SignatureVerificationState) -> Self {
-        ExclusiveSignatureVerificationState {
-            state: Arc::new(signature_verification_state),
-        }
-    }
-
-    fn open(pk_handle: Handle) -> Result<Handle, Error> {
-        let pk = WASI_CRYPTO_CTX.signature_publickey_manager.get(pk_handle)?;
-        let signature_verification_state = match pk {
-            SignaturePublicKey::ECDSA(pk) => ExclusiveSignatureVerificationState::new(
-                SignatureVerificationState::ECDSA(ECDSASignatureVerificationState::new(pk)),
-            ),
-            SignaturePublicKey::EdDSA(pk) => ExclusiveSignatureVerificationState::new(
-                SignatureVerificationState::EdDSA(EdDSASignatureVerificationState::new(pk)),
-            ),
-            SignaturePublicKey::RSA(pk) => ExclusiveSignatureVerificationState::new(
-                SignatureVerificationState::RSA(RSASignatureVerificationState::new(pk)),
-            ),
+    pub fn from_publickey(pk: SignaturePublicKey) -> Result<Self, Error> {
+        Self::with_options(pk, &SignatureStateOptions::default())
+    }
+
+    pub fn with_options(
+        pk: SignaturePublicKey,
+        options: &SignatureStateOptions,
+    ) -> Result<Self, Error> {
+        let signature_verification_state = ExclusiveSignatureVerificationState {
+            state: Arc::new(SignatureVerificationState::from_publickey(pk)?),
+            bound_input: if options.bind_length {
+                Some(Arc::new(Mutex::new(vec![])))
+            } else {
+                None
+            },
         };
-        let handle = WASI_CRYPTO_CTX
+        Ok(signature_verification_state)
+    }
+
+    pub(crate) fn open(
+        ctx: &CryptoContext,
+        pk_handle: Handle,
+        options: &SignatureStateOptions,
+    ) -> Result<Handle, Error> {
+        let pk = ctx.signature_publickey_manager.get(pk_handle)?;
+        let signature_verification_state = Self::with_options(pk, options)?;
+        let handle = ctx
             .signature_verification_state_manager
             .register(signature_verification_state)?;
         Ok(handle)
     }
 
-    fn update(&mut self, input: &[u8]) -> Result<(), Error> {
-        match self.state.as_ref() {
-            SignatureVerificationState::ECDSA(state) => state.update(input),
-            SignatureVerificationState::EdDSA(state) => state.update(input),
-            SignatureVerificationState::RSA(state) => state.update(input),
+    fn bound_state(&self) -> Result<Option<SignatureVerificationState>, Error> {
+        let bound_input = match &self.bound_input {
+            Some(bound_input) => bound_input,
+            None => return Ok(None),
+        };
+        let state = SignatureVerificationState::from_publickey(self.state.publickey())?;
+        state.update(&length_bound_input(&bound_input.lock()))?;
+        Ok(Some(state))
+    }
+
+    // Run `f` with the state that has absorbed the complete input
+    fn with_input<T>(
+        &self,
+        f: impl FnOnce(&SignatureVerificationState) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        match self.bound_state()? {
+            Some(state) => f(&state),
+            None => f(&self.state),
         }
     }
 
-    fn verify(&self, signature_handle: Handle) -> Result<(), Error> {
-        let signature = WASI_CRYPTO_CTX.signature_manager.get(signature_handle)?;
-        match self.state.as_ref() {
-            SignatureVerificationState::ECDSA(state) => state.verify(signature.as_ecdsa()?),
-            SignatureVerificationState::EdDSA(state) => state.verify(signature.as_eddsa()?),
-            SignatureVerificationState::RSA(state) => state.verify(signature.as_rsa()?),
+    pub fn update(&mut self, input: &[u8]) -> Result<(), Error> {
+        self.update_from_slices(&[input])
+    }
+
+    fn update_framed(&mut self, field: &[u8]) -> Result<(), Error> {
+        self.update_framed_from_slices(&[field])
+    }
+
+    /// Absorb several buffers in order, as sequential calls to `update()` would.
+    pub fn update_from_slices(&mut self, slices: &[&[u8]]) -> Result<(), Error> {
+        match &self.bound_input {
+            Some(bound_input) => {
+                let mut bound_input = bound_input.lock();
+                slices
+                    .iter()
+                    .for_each(|slice| bound_input.extend_from_slice(slice));
+                Ok(())
+            }
+            None => self.state.update_from_slices(slices),
+        }
+    }
+
+    fn update_framed_from_slices(&mut self, fields: &[&[u8]]) -> Result<(), Error> {
+        match &self.bound_input {
+            Some(bound_input) => {
+                let mut bound_input = bound_input.lock();
+                fields
+                    .iter()
+                    .for_each(|field| framing::append_framed(&mut bound_input, field));
+                Ok(())
+            }
+            None => self.state.update_framed_from_slices(fields),
+        }
+    }
+
+    pub fn verify(&self, ctx: &CryptoContext, signature_handle: Handle) -> Result<(), Error> {
+        let signature = ctx.signature_manager.get(signature_handle)?;
+        self.verify_signature(&signature)
+    }
+
+    pub fn verify_signature(&self, signature: &Signature) -> Result<(), Error> {
+        self.with_input(|state| state.verify(signature))
+    }
+
+    /// Check that the input digest matches a digest committed to beforehand, and only then
+    /// verify the signature. The digest is computed with the hash function of the algorithm.
+    pub(crate) fn verify_with_expected_digest(
+        &self,
+        ctx: &CryptoContext,
+        expected_digest: &[u8],
+        signature_handle: Handle,
+    ) -> Result<(), Error> {
+        let signature = ctx.signature_manager.get(signature_handle)?;
+        self.with_input(|state| {
+            ring::constant_time::verify_slices_are_equal(&state.input_digest(), expected_digest)
+                .map_err(|_| CryptoError::DigestMismatch)?;
+            state.verify(&signature)
+        })
+    }
+
+    pub fn verify_with_encoding(
+        &self,
+        encoded: &[u8],
+        encoding: SignatureEncoding,
+    ) -> Result<(), Error> {
+        self.with_input(|state| state.verify_with_encoding(encoded, encoding))
+    }
+
+    pub fn verify_any_encoding(&self, encoded: &[u8]) -> Result<(), Error> {
+        self.with_input(|state| state.verify_any_encoding(encoded))
+    }
+}
+
+impl Signature {
+    pub(crate) fn export(
+        ctx: &CryptoContext,
+        signature_handle: Handle,
+        encoding: SignatureEncoding,
+    ) -> Result<Vec<u8>, Error> {
+        match encoding {
+            SignatureEncoding::Raw => {}
+            _ => bail!(CryptoError::NotAvailable),
+        }
+        let signature = ctx.signature_manager.get(signature_handle)?;
+        Ok(signature.as_ref().to_vec())
+    }
+
+    pub(crate) fn import(
+        ctx: &CryptoContext,
+        op_handle: Handle,
+        encoding: SignatureEncoding,
+        encoded: &[u8],
+    ) -> Result<Handle, Error> {
+        let signature_op = ctx.signature_op_manager.get(op_handle)?;
+        let signature = match encoding {
+            SignatureEncoding::Raw => Signature::from_raw(signature_op.alg(), encoded)?,
+            _ => bail!(CryptoError::NotAvailable),
+        };
+        let handle = ctx.signature_manager.register(signature)?;
+        Ok(handle)
+    }
+
+    pub(crate) fn export_components(
+        ctx: &CryptoContext,
+        op_handle: Handle,
+        signature_handle: Handle,
+    ) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let signature_op = ctx.signature_op_manager.get(op_handle)?;
+        let signature = ctx.signature_manager.get(signature_handle)?;
+        signature.as_ecdsa()?.components(signature_op.alg())
+    }
+
+    pub(crate) fn import_components(
+        ctx: &CryptoContext,
+        op_handle: Handle,
+        r: &[u8],
+        s: &[u8],
+    ) -> Result<Handle, Error> {
+        let signature_op = ctx.signature_op_manager.get(op_handle)?;
+        let signature = match signature_op {
+            SignatureOp::ECDSA(_) => {
+                Signature::ECDSA(ECDSASignature::from_components(signature_op.alg(), r, s)?)
+            }
+            _ => bail!(CryptoError::NotAvailable),
+        };
+        let handle = ctx.signature_manager.register(signature)?;
+        Ok(handle)
+    }
+
+    // Sign a complete input, without registering a signature state
+    pub(crate) fn sign_input(
+        ctx: &CryptoContext,
+        kp_handle: Handle,
+        input: &[u8],
+    ) -> Result<Handle, Error> {
+        let kp = ctx.signature_keypair_manager.get(kp_handle)?;
+        let signature = kp.sign_oneshot(input)?;
+        let handle = ctx.signature_manager.register(signature)?;
+        Ok(handle)
+    }
+
+    // Verify a signature over a complete input, without registering a verification state
+    pub(crate) fn verify_input(
+        ctx: &CryptoContext,
+        pk_handle: Handle,
+        input: &[u8],
+        signature_handle: Handle,
+    ) -> Result<(), Error> {
+        let pk = ctx.signature_publickey_manager.get(pk_handle)?;
+        let mut state = ExclusiveSignatureVerificationState::from_publickey(pk)?;
+        state.update(input)?;
+        state.verify(ctx, signature_handle)
+    }
+
+    pub(crate) fn verify_root(
+        ctx: &CryptoContext,
+        pk_handle: Handle,
+        root: &[u8],
+        signature_handle: Handle,
+    ) -> Result<(), Error> {
+        let pk = ctx.signature_publickey_manager.get(pk_handle)?;
+        let signature = ctx.signature_manager.get(signature_handle)?;
+        ensure!(
+            root.len() == pk.alg().digest_alg().output_len,
+            CryptoError::InvalidLength
+        );
+        match pk {
+            SignaturePublicKey::ECDSA(pk) => pk.verify_digest(root, signature.as_ecdsa()?),
+            SignaturePublicKey::EdDSA(pk) => pk.verify_digest(root, signature.as_eddsa()?),
+            SignaturePublicKey::RSA(_) => bail!(CryptoError::NotAvailable),
         }
     }
 }
@@ -234,12 +851,7 @@ pub fn signature_export(
     signature_handle: Handle,
     encoding: SignatureEncoding,
 ) -> Result<Vec<u8>, Error> {
-    match encoding {
-        SignatureEncoding::Raw => {}
-        _ => bail!(CryptoError::NotAvailable),
-    }
-    let signature = WASI_CRYPTO_CTX.signature_manager.get(signature_handle)?;
-    Ok(signature.as_ref().to_vec())
+    Signature::export(&WASI_CRYPTO_CTX, signature_handle, encoding)
 }
 
 pub fn signature_import(
@@ -247,65 +859,691 @@ pub fn signature_import(
     encoding: SignatureEncoding,
     encoded: &[u8],
 ) -> Result<Handle, Error> {
-    let signature_op = WASI_CRYPTO_CTX.signature_op_manager.get(op_handle)?;
-    let signature = match encoding {
-        SignatureEncoding::Raw => Signature::from_raw(signature_op.alg(), encoded)?,
-        _ => bail!(CryptoError::NotAvailable),
-    };
-    let handle = WASI_CRYPTO_CTX.signature_manager.register(signature)?;
-    Ok(handle)
+    Signature::import(&WASI_CRYPTO_CTX, op_handle, encoding, encoded)
+}
+
+/// Export the `r` and `s` components of an ECDSA signature, as fixed-size big-endian integers.
+pub fn signature_export_components(
+    op_handle: Handle,
+    signature_handle: Handle,
+) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    WASI_CRYPTO_CTX.signature_export_components(op_handle, signature_handle)
+}
+
+pub fn signature_import_components(op_handle: Handle, r: &[u8], s: &[u8]) -> Result<Handle, Error> {
+    WASI_CRYPTO_CTX.signature_import_components(op_handle, r, s)
+}
+
+// Sign a complete input, without registering a signature state
+pub fn sign_input(kp_handle: Handle, input: &[u8]) -> Result<Handle, Error> {
+    WASI_CRYPTO_CTX.sign_input(kp_handle, input)
+}
+
+// Verify a signature over a complete input, without registering a verification state
+pub fn verify_input(
+    pk_handle: Handle,
+    input: &[u8],
+    signature_handle: Handle,
+) -> Result<(), Error> {
+    WASI_CRYPTO_CTX.verify_input(pk_handle, input, signature_handle)
+}
+
+/// Verify a signature over the root of a hash tree, such as a Merkle tree, computed by the
+/// caller with the hash function of the algorithm. The root is verified the way prehashed-only
+/// key pairs sign digests, without being hashed again, and must be as long as the output of
+/// the hash function. Only available for algorithms that support prehashed signatures.
+pub fn signature_verify_root(
+    pk_handle: Handle,
+    root: &[u8],
+    signature_handle: Handle,
+) -> Result<(), Error> {
+    WASI_CRYPTO_CTX.signature_verify_root(pk_handle, root, signature_handle)
 }
 
 pub fn signature_state_open(kp_handle: Handle) -> Result<Handle, Error> {
-    ExclusiveSignatureState::open(kp_handle)
+    ExclusiveSignatureState::open(
+        &WASI_CRYPTO_CTX,
+        kp_handle,
+        &SignatureStateOptions::default(),
+    )
+}
+
+pub fn signature_state_open_with_options(
+    kp_handle: Handle,
+    options: &SignatureStateOptions,
+) -> Result<Handle, Error> {
+    ExclusiveSignatureState::open(&WASI_CRYPTO_CTX, kp_handle, options)
 }
 
 pub fn signature_state_update(state_handle: Handle, input: &[u8]) -> Result<(), Error> {
+    WASI_CRYPTO_CTX.signature_state_update(state_handle, input)
+}
+
+/// Write the signature into a caller-provided buffer, and return its length.
+pub fn signature_state_sign_into(state_handle: Handle, out: &mut [u8]) -> Result<usize, Error> {
     let mut state = WASI_CRYPTO_CTX.signature_state_manager.get(state_handle)?;
-    state.update(input)
+    state.sign_into(out)
+}
+
+pub fn signature_state_update_framed(state_handle: Handle, field: &[u8]) -> Result<(), Error> {
+    let mut state = WASI_CRYPTO_CTX.signature_state_manager.get(state_handle)?;
+    state.update_framed(field)
+}
+
+/// Absorb several buffers at once, with the same result as calling `signature_state_update()`
+/// on each of them in order.
+pub fn signature_state_update_from_slices(
+    state_handle: Handle,
+    slices: &[&[u8]],
+) -> Result<(), Error> {
+    let mut state = WASI_CRYPTO_CTX.signature_state_manager.get(state_handle)?;
+    state.update_from_slices(slices)
+}
+
+/// Absorb several framed fields at once, with the same result as calling
+/// `signature_state_update_framed()` on each of them in order.
+pub fn signature_state_update_framed_from_slices(
+    state_handle: Handle,
+    fields: &[&[u8]],
+) -> Result<(), Error> {
+    let mut state = WASI_CRYPTO_CTX.signature_state_manager.get(state_handle)?;
+    state.update_framed_from_slices(fields)
 }
 
 pub fn signature_state_sign(state_handle: Handle) -> Result<Handle, Error> {
+    WASI_CRYPTO_CTX.signature_state_sign(state_handle)
+}
+
+pub fn signature_state_sign_digest(state_handle: Handle, digest: &[u8]) -> Result<Handle, Error> {
     let mut state = WASI_CRYPTO_CTX.signature_state_manager.get(state_handle)?;
-    let signature = state.sign()?;
+    let signature = state.sign_digest(digest)?;
     let handle = WASI_CRYPTO_CTX.signature_manager.register(signature)?;
     Ok(handle)
 }
 
+/// Return the raw signature and the raw public key of the signer.
+pub fn signature_state_sign_with_public_key(
+    state_handle: Handle,
+) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let mut state = WASI_CRYPTO_CTX.signature_state_manager.get(state_handle)?;
+    state.sign_with_public_key()
+}
+
+pub fn signature_state_sign_and_return_input(
+    state_handle: Handle,
+) -> Result<(Handle, SignedInput), Error> {
+    let mut state = WASI_CRYPTO_CTX.signature_state_manager.get(state_handle)?;
+    let (signature, signed_input) = state.sign_and_return_input()?;
+    let handle = WASI_CRYPTO_CTX.signature_manager.register(signature)?;
+    Ok((handle, signed_input))
+}
+
 pub fn signature_state_close(handle: Handle) -> Result<(), Error> {
-    WASI_CRYPTO_CTX.signature_state_manager.close(handle)
+    WASI_CRYPTO_CTX.signature_state_close(handle)
 }
 
 pub fn signature_verification_state_open(pk_handle: Handle) -> Result<Handle, Error> {
-    ExclusiveSignatureVerificationState::open(pk_handle)
+    ExclusiveSignatureVerificationState::open(
+        &WASI_CRYPTO_CTX,
+        pk_handle,
+        &SignatureStateOptions::default(),
+    )
+}
+
+pub fn signature_verification_state_open_with_options(
+    pk_handle: Handle,
+    options: &SignatureStateOptions,
+) -> Result<Handle, Error> {
+    ExclusiveSignatureVerificationState::open(&WASI_CRYPTO_CTX, pk_handle, options)
 }
 
 pub fn signature_verification_state_update(
     verification_state_handle: Handle,
     input: &[u8],
+) -> Result<(), Error> {
+    WASI_CRYPTO_CTX.signature_verification_state_update(verification_state_handle, input)
+}
+
+pub fn signature_verification_state_update_framed(
+    verification_state_handle: Handle,
+    field: &[u8],
+) -> Result<(), Error> {
+    let mut state = WASI_CRYPTO_CTX
+        .signature_verification_state_manager
+        .get(verification_state_handle)?;
+    state.update_framed(field)
+}
+
+pub fn signature_verification_state_update_from_slices(
+    verification_state_handle: Handle,
+    slices: &[&[u8]],
+) -> Result<(), Error> {
+    let mut state = WASI_CRYPTO_CTX
+        .signature_verification_state_manager
+        .get(verification_state_handle)?;
+    state.update_from_slices(slices)
+}
+
+pub fn signature_verification_state_update_framed_from_slices(
+    verification_state_handle: Handle,
+    fields: &[&[u8]],
 ) -> Result<(), Error> {
     let mut state = WASI_CRYPTO_CTX
         .signature_verification_state_manager
         .get(verification_state_handle)?;
-    state.update(input)
+    state.update_framed_from_slices(fields)
 }
 
 pub fn signature_verification_state_verify(
     verification_state_handle: Handle,
     signature_handle: Handle,
+) -> Result<(), Error> {
+    WASI_CRYPTO_CTX.signature_verification_state_verify(verification_state_handle, signature_handle)
+}
+
+pub fn signature_verification_state_verify_with_encoding(
+    verification_state_handle: Handle,
+    encoded: &[u8],
+    encoding: SignatureEncoding,
 ) -> Result<(), Error> {
     let state = WASI_CRYPTO_CTX
         .signature_verification_state_manager
         .get(verification_state_handle)?;
-    state.verify(signature_handle)
+    state.verify_with_encoding(encoded, encoding)
 }
 
-pub fn signature_verification_state_close(handle: Handle) -> Result<(), Error> {
-    WASI_CRYPTO_CTX
+/// Verify an ECDSA signature without knowing in advance whether it is encoded as `DER` or
+/// `Raw`. Other signatures must be raw.
+pub fn signature_verification_state_verify_any_encoding(
+    verification_state_handle: Handle,
+    encoded: &[u8],
+) -> Result<(), Error> {
+    let state = WASI_CRYPTO_CTX
         .signature_verification_state_manager
-        .close(handle)
+        .get(verification_state_handle)?;
+    state.verify_any_encoding(encoded)
+}
+
+pub fn signature_verification_state_verify_with_expected_digest(
+    verification_state_handle: Handle,
+    expected_digest: &[u8],
+    signature_handle: Handle,
+) -> Result<(), Error> {
+    WASI_CRYPTO_CTX.signature_verification_state_verify_with_expected_digest(
+        verification_state_handle,
+        expected_digest,
+        signature_handle,
+    )
+}
+
+pub fn signature_verification_state_close(handle: Handle) -> Result<(), Error> {
+    WASI_CRYPTO_CTX.signature_verification_state_close(handle)
 }
 
 pub fn signature_close(handle: Handle) -> Result<(), Error> {
-    WASI_CRYPTO_CTX.signature_manager.close(handle)
+    WASI_CRYPTO_CTX.signature_close(handle)
+}
+
+#[test]
+fn test_category_and_security_level() {
+    let expected = [
+        (
+            SignatureAlgorithm::ECDSA_P256_SHA256,
+            AlgorithmCategory::ECDSA,
+            128,
+        ),
+        (
+            SignatureAlgorithm::ECDSA_P384_SHA384,
+            AlgorithmCategory::ECDSA,
+            192,
+        ),
+        (SignatureAlgorithm::Ed25519, AlgorithmCategory::EdDSA, 128),
+        (
+            SignatureAlgorithm::Ed25519_BLAKE2b512,
+            AlgorithmCategory::EdDSA,
+            128,
+        ),
+        (
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256,
+            AlgorithmCategory::RSA,
+            112,
+        ),
+        (
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA384,
+            AlgorithmCategory::RSA,
+            112,
+        ),
+        (
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512,
+            AlgorithmCategory::RSA,
+            112,
+        ),
+        (
+            SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384,
+            AlgorithmCategory::RSA,
+            128,
+        ),
+    ];
+    assert_eq!(expected.len(), SignatureAlgorithm::ALL.len());
+    for (alg, category, bits) in expected.iter() {
+        assert_eq!(alg.category(), *category);
+        assert_eq!(alg.security_level_bits(), *bits);
+    }
+}
+
+#[test]
+fn test_verify_with_expected_digest() {
+    let op_handle = crate::signature_op_open("ECDSA_P256_SHA256").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    let signature_handle = sign_input(kp_handle, b"test").unwrap();
+    let other_signature_handle = sign_input(kp_handle, b"other").unwrap();
+    let expected_digest = ring::digest::digest(&ring::digest::SHA256, b"test");
+
+    let verify = |input: &[u8], signature_handle| {
+        let state_handle = signature_verification_state_open(pk_handle).unwrap();
+        signature_verification_state_update(state_handle, input).unwrap();
+        signature_verification_state_verify_with_expected_digest(
+            state_handle,
+            expected_digest.as_ref(),
+            signature_handle,
+        )
+    };
+    verify(b"test", signature_handle).unwrap();
+    let err = verify(b"other", other_signature_handle).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::DigestMismatch)
+    ));
+    let err = verify(b"test", other_signature_handle).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::VerificationFailed)
+    ));
+}
+
+#[test]
+fn test_sign_into() {
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let state_handle = signature_state_open(kp_handle).unwrap();
+    signature_state_update(state_handle, b"test").unwrap();
+
+    let mut out = [0u8; 64];
+    assert_eq!(
+        signature_state_sign_into(state_handle, &mut out).unwrap(),
+        64
+    );
+    let signature_handle = signature_state_sign(state_handle).unwrap();
+    assert_eq!(
+        signature_export(signature_handle, SignatureEncoding::Raw).unwrap(),
+        out.to_vec()
+    );
+
+    let mut out = [0u8; 63];
+    let err = signature_state_sign_into(state_handle, &mut out).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::InvalidLength)
+    ));
+
+    signature_close(signature_handle).unwrap();
+    signature_state_close(state_handle).unwrap();
+    crate::signature_keypair_close(kp_handle).unwrap();
+    crate::signature_keypair_builder_close(kp_builder_handle).unwrap();
+    crate::signature_op_close(op_handle).unwrap();
+}
+
+#[test]
+fn test_mismatched_publickey_algorithm() {
+    let pks = vec![
+        SignaturePublicKey::EdDSA(EdDSASignaturePublicKey {
+            alg: SignatureAlgorithm::ECDSA_P256_SHA256,
+            raw: vec![0x42; 32],
+            strict_verification: false,
+        }),
+        SignaturePublicKey::ECDSA(ECDSASignaturePublicKey {
+            alg: SignatureAlgorithm::Ed25519,
+            raw: vec![0x42; 65],
+            validated: false,
+        }),
+        SignaturePublicKey::RSA(RSASignaturePublicKey {
+            alg: SignatureAlgorithm::Ed25519,
+            raw: vec![0x42; 256],
+        }),
+    ];
+    for pk in pks {
+        let err = ExclusiveSignatureVerificationState::from_publickey(pk).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::NotAvailable)
+        ));
+    }
+}
+
+#[test]
+fn test_typed_publickey_verify() {
+    let kp = EdDSASignatureKeyPair::generate(SignatureAlgorithm::Ed25519).unwrap();
+    let pk = EdDSASignaturePublicKey::from_raw(kp.alg, kp.raw_public_key()).unwrap();
+    let signature = kp.sign_oneshot(b"test").unwrap();
+    pk.verify(b"test", signature.as_ref()).unwrap();
+    let err = pk.verify(b"test", &signature.as_ref()[..63]).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::InvalidSignature)
+    ));
+
+    let kp = ECDSASignatureKeyPair::generate(SignatureAlgorithm::ECDSA_P384_SHA384).unwrap();
+    let pk = ECDSASignaturePublicKey::from_raw(kp.alg, kp.raw_public_key()).unwrap();
+    let signature = kp.sign_oneshot(b"test").unwrap();
+    pk.verify(b"test", signature.as_ref()).unwrap();
+    let err = pk.verify(b"test", &[0x42; 64]).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::InvalidSignature)
+    ));
+}
+
+#[test]
+fn test_length_bound_states() {
+    let op_handle = crate::signature_op_open("ECDSA_P256_SHA256").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    let bound = SignatureStateOptions { bind_length: true };
+    let plain = SignatureStateOptions::default();
+
+    let sign = |options: &SignatureStateOptions, chunks: &[&[u8]]| {
+        let state_handle = signature_state_open_with_options(kp_handle, options).unwrap();
+        for chunk in chunks {
+            signature_state_update(state_handle, chunk).unwrap();
+        }
+        signature_state_sign(state_handle).unwrap()
+    };
+    let verify = |options: &SignatureStateOptions, input: &[u8], signature_handle| {
+        let verification_state_handle =
+            signature_verification_state_open_with_options(pk_handle, options).unwrap();
+        signature_verification_state_update(verification_state_handle, input).unwrap();
+        signature_verification_state_verify(verification_state_handle, signature_handle)
+    };
+
+    let signature_handle = sign(&bound, &[b"test", b" message"]);
+    verify(&bound, b"test message", signature_handle).unwrap();
+    assert!(verify(&bound, b"test messag", signature_handle).is_err());
+    assert!(verify(&bound, b"test message\0", signature_handle).is_err());
+    assert!(verify(&plain, b"test message", signature_handle).is_err());
+
+    let signature_handle = sign(&plain, &[b"test message"]);
+    assert!(verify(&bound, b"test message", signature_handle).is_err());
+}
+
+#[test]
+fn test_prehashed_only_states() {
+    use blake2::Digest as _;
+
+    let options = crate::SignatureOptions {
+        prehashed_only: true,
+        ..Default::default()
+    };
+    assert!(crate::signature_op_open_with_options("Ed25519", &options).is_err());
+    assert!(crate::signature_op_open_with_options("RSA_PKCS1_2048_8192_SHA256", &options).is_err());
+
+    let mut algs = vec![(
+        "Ed25519_BLAKE2b512",
+        blake2::Blake2b512::digest(b"test").to_vec(),
+    )];
+    if cfg!(feature = "prehash") {
+        for (alg_str, digest_alg) in &[
+            ("ECDSA_P256_SHA256", &ring::digest::SHA256),
+            ("ECDSA_P384_SHA384", &ring::digest::SHA384),
+        ] {
+            algs.push((
+                alg_str,
+                ring::digest::digest(digest_alg, b"test").as_ref().to_vec(),
+            ));
+        }
+    } else {
+        assert!(crate::signature_op_open_with_options("ECDSA_P256_SHA256", &options).is_err());
+    }
+    for (alg_str, digest) in algs {
+        let op_handle = crate::signature_op_open_with_options(alg_str, &options).unwrap();
+        let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+        let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+        let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+
+        let state_handle = signature_state_open(kp_handle).unwrap();
+        let err = signature_state_update(state_handle, b"test").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::NotAvailable)
+        ));
+        assert!(signature_state_sign(state_handle).is_err());
+        assert!(signature_state_sign_digest(state_handle, &digest[1..]).is_err());
+        let signature_handle = signature_state_sign_digest(state_handle, &digest).unwrap();
+
+        let verification_state_handle = signature_verification_state_open(pk_handle).unwrap();
+        signature_verification_state_update(verification_state_handle, b"test").unwrap();
+        signature_verification_state_verify(verification_state_handle, signature_handle).unwrap();
+
+        let kp_handle = crate::signature_keypair_generate(
+            crate::signature_keypair_builder_open(crate::signature_op_open(alg_str).unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+        let state_handle = signature_state_open(kp_handle).unwrap();
+        assert!(signature_state_sign_digest(state_handle, &digest).is_err());
+    }
+}
+
+#[test]
+fn test_sign_with_public_key() {
+    for alg_str in &["Ed25519", "ECDSA_P256_SHA256"] {
+        let op_handle = crate::signature_op_open(alg_str).unwrap();
+        let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+        let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+        let state_handle = signature_state_open(kp_handle).unwrap();
+        signature_state_update(state_handle, b"test").unwrap();
+        let (raw_signature, raw_pk) = signature_state_sign_with_public_key(state_handle).unwrap();
+
+        let pk_handle =
+            crate::signature_publickey_import(op_handle, &raw_pk, crate::PublicKeyEncoding::Raw)
+                .unwrap();
+        let pk = WASI_CRYPTO_CTX
+            .signature_publickey_manager
+            .get(pk_handle)
+            .unwrap();
+        let signature = Signature::from_raw(pk.alg(), &raw_signature).unwrap();
+        let mut state = ExclusiveSignatureVerificationState::from_publickey(pk).unwrap();
+        state.update(b"test").unwrap();
+        state.verify_signature(&signature).unwrap();
+        let mut state = ExclusiveSignatureVerificationState::from_publickey(
+            WASI_CRYPTO_CTX
+                .signature_publickey_manager
+                .get(pk_handle)
+                .unwrap(),
+        )
+        .unwrap();
+        state.update(b"other").unwrap();
+        assert!(state.verify_signature(&signature).is_err());
+    }
+}
+
+#[test]
+fn test_sign_and_return_input() {
+    use blake2::Digest as _;
+
+    for alg_str in &["Ed25519", "ECDSA_P256_SHA256", "Ed25519_BLAKE2b512"] {
+        let op_handle = crate::signature_op_open(alg_str).unwrap();
+        let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+        let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+        let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+        let options = SignatureStateOptions { bind_length: true };
+        let state_handle = signature_state_open_with_options(kp_handle, &options).unwrap();
+        signature_state_update(state_handle, b"test").unwrap();
+        let (signature_handle, signed_input) =
+            signature_state_sign_and_return_input(state_handle).unwrap();
+
+        let mut bound_input = framing::length_prefix(b"test").to_vec();
+        bound_input.extend_from_slice(b"test");
+        let message = match signed_input {
+            SignedInput::Message(message) => message,
+            SignedInput::Digest(digest) => {
+                assert_eq!(alg_str, &"Ed25519_BLAKE2b512");
+                assert_eq!(digest, blake2::Blake2b512::digest(&bound_input).to_vec());
+                bound_input.clone()
+            }
+        };
+        assert_eq!(message, bound_input);
+        let verification_state_handle = signature_verification_state_open(pk_handle).unwrap();
+        signature_verification_state_update(verification_state_handle, &message).unwrap();
+        signature_verification_state_verify(verification_state_handle, signature_handle).unwrap();
+    }
+}
+
+#[test]
+fn test_verify_root() {
+    use blake2::Digest as _;
+
+    // RFC 6962 tree hashes, with domain-separated leaves and nodes
+    fn merkle_root(hash: &dyn Fn(&[&[u8]]) -> Vec<u8>, leaves: &[&[u8]]) -> Vec<u8> {
+        let mut level: Vec<_> = leaves.iter().map(|leaf| hash(&[&[0x00], leaf])).collect();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => hash(&[&[0x01], left, right]),
+                    [single] => single.clone(),
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+        level.remove(0)
+    }
+    let sha256 = |parts: &[&[u8]]| {
+        let mut ctx = ring::digest::Context::new(&ring::digest::SHA256);
+        parts.iter().for_each(|part| ctx.update(part));
+        ctx.finish().as_ref().to_vec()
+    };
+    let blake2b512 = |parts: &[&[u8]]| {
+        let mut hasher = blake2::Blake2b512::new();
+        parts.iter().for_each(|part| hasher.update(part));
+        hasher.finalize().to_vec()
+    };
+    let leaves: [&[u8]; 3] = [b"leaf 0", b"leaf 1", b"leaf 2"];
+
+    let options = crate::SignatureOptions {
+        prehashed_only: true,
+        ..Default::default()
+    };
+    let mut algs = vec![("Ed25519_BLAKE2b512", merkle_root(&blake2b512, &leaves))];
+    if cfg!(feature = "prehash") {
+        algs.push(("ECDSA_P256_SHA256", merkle_root(&sha256, &leaves)));
+    }
+    for (alg_str, root) in algs {
+        let op_handle = crate::signature_op_open_with_options(alg_str, &options).unwrap();
+        let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+        let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+        let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+        let state_handle = signature_state_open(kp_handle).unwrap();
+        let signature_handle = signature_state_sign_digest(state_handle, &root).unwrap();
+
+        signature_verify_root(pk_handle, &root, signature_handle).unwrap();
+        let mut other_root = root.clone();
+        other_root[0] ^= 1;
+        let err = signature_verify_root(pk_handle, &other_root, signature_handle).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::VerificationFailed)
+        ));
+        let err = signature_verify_root(pk_handle, &root[1..], signature_handle).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::InvalidLength)
+        ));
+    }
+
+    // Ed25519 signs messages, not digests
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    let root = merkle_root(&blake2b512, &leaves);
+    let signature_handle = sign_input(kp_handle, &root).unwrap();
+    let err = signature_verify_root(pk_handle, &root, signature_handle).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::NotAvailable)
+    ));
+}
+
+#[test]
+fn test_update_from_slices() {
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    let slices: [&[u8]; 3] = [b"header", b"", b"body"];
+    let sign = |update: &dyn Fn(Handle)| {
+        let state_handle = signature_state_open(kp_handle).unwrap();
+        update(state_handle);
+        let signature_handle = signature_state_sign(state_handle).unwrap();
+        signature_export(signature_handle, SignatureEncoding::Raw).unwrap()
+    };
+
+    // Ed25519 is deterministic: the same input gives the same signature
+    let sequential = sign(&|state_handle| {
+        for slice in slices.iter() {
+            signature_state_update(state_handle, slice).unwrap();
+        }
+    });
+    let batched = sign(&|state_handle| {
+        signature_state_update_from_slices(state_handle, &slices).unwrap();
+    });
+    assert_eq!(sequential, batched);
+    let framed_sequential = sign(&|state_handle| {
+        for slice in slices.iter() {
+            signature_state_update_framed(state_handle, slice).unwrap();
+        }
+    });
+    let framed_batched = sign(&|state_handle| {
+        signature_state_update_framed_from_slices(state_handle, &slices).unwrap();
+    });
+    assert_eq!(framed_sequential, framed_batched);
+    assert_ne!(framed_batched, batched);
+
+    let verify = |signature: &[u8], update: &dyn Fn(Handle)| {
+        let signature_handle = signature_import(op_handle, SignatureEncoding::Raw, signature)?;
+        let verification_state_handle = signature_verification_state_open(pk_handle)?;
+        update(verification_state_handle);
+        signature_verification_state_verify(verification_state_handle, signature_handle)
+    };
+    verify(&batched, &|state_handle| {
+        signature_verification_state_update_from_slices(state_handle, &slices).unwrap();
+    })
+    .unwrap();
+    verify(&framed_batched, &|state_handle| {
+        signature_verification_state_update_framed_from_slices(state_handle, &slices).unwrap();
+    })
+    .unwrap();
+    assert!(verify(&framed_batched, &|state_handle| {
+        signature_verification_state_update_from_slices(state_handle, &slices).unwrap();
+    })
+    .is_err());
+
+    // ECDSA verification states hash their input as it is absorbed with the `prehash` feature
+    let op_handle = crate::signature_op_open("ECDSA_P256_SHA256").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    let state_handle = signature_state_open(kp_handle).unwrap();
+    for slice in slices.iter() {
+        signature_state_update_framed(state_handle, slice).unwrap();
+    }
+    let signature_handle = signature_state_sign(state_handle).unwrap();
+    let verification_state_handle = signature_verification_state_open(pk_handle).unwrap();
+    signature_verification_state_update_framed_from_slices(verification_state_handle, &slices)
+        .unwrap();
+    signature_verification_state_verify(verification_state_handle, signature_handle).unwrap();
 }
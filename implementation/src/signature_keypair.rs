@@ -1,11 +1,22 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use zeroize::Zeroize;
+
+use super::der;
+use super::drbg::*;
 use super::ecdsa::*;
 use super::eddsa::*;
 use super::error::*;
+use super::framing;
 use super::handles::*;
+use super::remote::*;
 use super::rsa::*;
+use super::signature::*;
 use super::signature_op::*;
 use super::signature_publickey::*;
-use super::WASI_CRYPTO_CTX;
+use super::signature_publickey_encoding;
+use super::sync::Mutex;
+use super::{CryptoContext, WASI_CRYPTO_CTX};
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[repr(u16)]
@@ -16,55 +27,179 @@ pub enum KeyPairEncoding {
     PEM = 4,
 }
 
+/// Persistent storage for the signature counter of an ECDSA key pair, so that the count, and
+/// the `max_signatures` limit, survive restarts of the process.
+pub trait CounterStore: core::fmt::Debug + Send {
+    /// The number of signatures produced so far, or 0 if nothing was stored yet.
+    fn load(&mut self) -> Result<u64, Error>;
+
+    /// Persist the new count. This is called before every signature is computed, and the
+    /// signature is not produced if it fails.
+    fn store(&mut self, count: u64) -> Result<(), Error>;
+}
+
 #[derive(Clone, Debug)]
 pub enum SignatureKeyPair {
     ECDSA(ECDSASignatureKeyPair),
     EdDSA(EdDSASignatureKeyPair),
     RSA(RSASignatureKeyPair),
+    Remote(RemoteSignatureKeyPair),
 }
 
 impl SignatureKeyPair {
-    fn export(&self, encoding: KeyPairEncoding) -> Result<Vec<u8>, Error> {
+    pub fn alg(&self) -> SignatureAlgorithm {
+        match self {
+            SignatureKeyPair::ECDSA(kp) => kp.alg,
+            SignatureKeyPair::EdDSA(kp) => kp.alg,
+            SignatureKeyPair::RSA(kp) => kp.alg,
+            SignatureKeyPair::Remote(kp) => kp.alg,
+        }
+    }
+
+    // The PKCS#8 document, regardless of whether the key pair is exportable
+    pub fn pkcs8(&self) -> &[u8] {
+        match self {
+            SignatureKeyPair::ECDSA(kp) => &kp.pkcs8,
+            SignatureKeyPair::EdDSA(kp) => &kp.pkcs8,
+            SignatureKeyPair::RSA(kp) => &kp.pkcs8,
+            SignatureKeyPair::Remote(_) => &[],
+        }
+    }
+
+    // A builder that creates key pairs with the same algorithm and options as this one
+    pub fn builder(&self) -> SignatureKeyPairBuilder {
+        match self {
+            SignatureKeyPair::ECDSA(kp) => {
+                SignatureKeyPairBuilder::ECDSA(ECDSASignatureKeyPairBuilder {
+                    alg: kp.alg,
+                    rng_fallback: kp.rng_fallback,
+                    prehashed_only: kp.prehashed_only,
+                    non_exportable: kp.non_exportable,
+                    max_signatures: kp.max_signatures,
+                })
+            }
+            SignatureKeyPair::EdDSA(kp) => {
+                SignatureKeyPairBuilder::EdDSA(EdDSASignatureKeyPairBuilder {
+                    alg: kp.alg,
+                    strict_verification: kp.strict_verification,
+                    prehashed_only: kp.prehashed_only,
+                    non_exportable: kp.non_exportable,
+                })
+            }
+            SignatureKeyPair::RSA(kp) => SignatureKeyPairBuilder::RSA(RSASignatureKeyPairBuilder {
+                alg: kp.alg,
+                non_exportable: kp.non_exportable,
+            }),
+            SignatureKeyPair::Remote(kp) => SignatureKeyPairBuilder::non_exportable_for(kp.alg),
+        }
+    }
+
+    pub fn raw_public_key(&self) -> &[u8] {
+        match self {
+            SignatureKeyPair::ECDSA(kp) => kp.raw_public_key(),
+            SignatureKeyPair::EdDSA(kp) => kp.raw_public_key(),
+            SignatureKeyPair::RSA(kp) => kp.raw_public_key(),
+            SignatureKeyPair::Remote(kp) => kp.raw_public_key(),
+        }
+    }
+
+    pub fn sign_oneshot(&self, input: &[u8]) -> Result<Signature, Error> {
+        let signature = match self {
+            SignatureKeyPair::ECDSA(kp) => Signature::ECDSA(kp.sign_oneshot(input)?),
+            SignatureKeyPair::EdDSA(kp) => Signature::EdDSA(kp.sign_oneshot(input)?),
+            SignatureKeyPair::RSA(kp) => Signature::RSA(kp.sign_oneshot(input)?),
+            SignatureKeyPair::Remote(kp) => kp.sign_oneshot(input)?,
+        };
+        Ok(signature)
+    }
+
+    pub(crate) fn export(&self, encoding: KeyPairEncoding) -> Result<Vec<u8>, Error> {
         let encoded = match encoding {
             KeyPairEncoding::PKCS8 => match self {
                 SignatureKeyPair::ECDSA(kp) => kp.as_pkcs8()?.to_vec(),
                 SignatureKeyPair::EdDSA(kp) => kp.as_pkcs8()?.to_vec(),
                 SignatureKeyPair::RSA(kp) => kp.as_pkcs8()?.to_vec(),
+                SignatureKeyPair::Remote(_) => bail!(CryptoError::NotAvailable),
             },
             _ => bail!(CryptoError::NotAvailable),
         };
         Ok(encoded)
     }
 
-    fn generate(kp_builder_handle: Handle) -> Result<Handle, Error> {
-        let kp_builder = WASI_CRYPTO_CTX
+    // The algorithms are compared first, and then the raw public keys, in constant time
+    pub(crate) fn matches_publickey(
+        ctx: &CryptoContext,
+        kp_handle: Handle,
+        pk_handle: Handle,
+    ) -> Result<bool, Error> {
+        let kp = ctx.signature_keypair_manager.get(kp_handle)?;
+        let pk = ctx.signature_publickey_manager.get(pk_handle)?;
+        let raw_pk = match &pk {
+            SignaturePublicKey::ECDSA(pk) => pk.as_raw()?,
+            SignaturePublicKey::EdDSA(pk) => pk.as_raw()?,
+            SignaturePublicKey::RSA(pk) => pk.as_raw()?,
+        };
+        Ok(kp.alg() == pk.alg() && kp.matches_raw_public_key(raw_pk))
+    }
+
+    /// Compare the raw public key of the key pair with `raw_pk`, in constant time.
+    pub fn matches_raw_public_key(&self, raw_pk: &[u8]) -> bool {
+        ring::constant_time::verify_slices_are_equal(self.raw_public_key(), raw_pk).is_ok()
+    }
+
+    pub(crate) fn generate(
+        ctx: &CryptoContext,
+        kp_builder_handle: Handle,
+    ) -> Result<Handle, Error> {
+        let kp_builder = ctx
             .signature_keypair_builder_manager
             .get(kp_builder_handle)?;
-        let handle = match kp_builder {
-            SignatureKeyPairBuilder::ECDSA(kp_builder) => kp_builder.generate()?,
-            SignatureKeyPairBuilder::EdDSA(kp_builder) => kp_builder.generate()?,
-            SignatureKeyPairBuilder::RSA(kp_builder) => kp_builder.generate()?,
-        };
-        Ok(handle)
+        ctx.signature_algorithm_policy
+            .read()
+            .check(kp_builder.alg())?;
+        let kp = ctx.signature_keypair_manager.generate(&kp_builder)?;
+        ctx.signature_keypair_manager.register(kp)
     }
 
-    fn import(
+    pub(crate) fn import(
+        ctx: &CryptoContext,
         kp_builder_handle: Handle,
         encoded: &[u8],
         encoding: KeyPairEncoding,
     ) -> Result<Handle, Error> {
-        let kp_builder = WASI_CRYPTO_CTX
+        let kp_builder = ctx
             .signature_keypair_builder_manager
             .get(kp_builder_handle)?;
-        let handle = match kp_builder {
-            SignatureKeyPairBuilder::ECDSA(kp_builder) => kp_builder.import(encoded, encoding)?,
-            SignatureKeyPairBuilder::EdDSA(kp_builder) => kp_builder.import(encoded, encoding)?,
-            SignatureKeyPairBuilder::RSA(kp_builder) => kp_builder.import(encoded, encoding)?,
+        ctx.signature_algorithm_policy
+            .read()
+            .check(kp_builder.alg())?;
+        match encoding {
+            KeyPairEncoding::PKCS8 => {}
+            _ => bail!(CryptoError::NotAvailable),
         };
-        Ok(handle)
+        let kp = kp_builder.import_owned(encoded)?;
+        ctx.signature_keypair_manager.register(kp)
+    }
+
+    /// Import a PKCS#8 key pair without a builder, and return its handle along with the
+    /// algorithm detected from its algorithm identifier.
+    pub(crate) fn import_detect(
+        ctx: &CryptoContext,
+        encoded: &[u8],
+        encoding: KeyPairEncoding,
+    ) -> Result<(Handle, SignatureAlgorithm), Error> {
+        match encoding {
+            KeyPairEncoding::PKCS8 => {}
+            _ => bail!(CryptoError::NotAvailable),
+        };
+        let alg = pkcs8_algorithm(encoded)?;
+        ctx.signature_algorithm_policy.read().check(alg)?;
+        let kp = SignatureKeyPairBuilder::default_for(alg).import_owned(encoded)?;
+        let handle = ctx.signature_keypair_manager.register(kp)?;
+        Ok((handle, alg))
     }
 
-    fn public_key(&self) -> Result<Handle, Error> {
+    pub(crate) fn public_key(&self, ctx: &CryptoContext) -> Result<Handle, Error> {
         let pk = match self {
             SignatureKeyPair::ECDSA(kp) => {
                 let raw_pk = kp.raw_public_key();
@@ -72,14 +207,17 @@ impl SignatureKeyPair {
             }
             SignatureKeyPair::EdDSA(kp) => {
                 let raw_pk = kp.raw_public_key();
-                SignaturePublicKey::EdDSA(EdDSASignaturePublicKey::from_raw(kp.alg, raw_pk)?)
+                let mut pk = EdDSASignaturePublicKey::from_raw(kp.alg, raw_pk)?;
+                pk.strict_verification = kp.strict_verification;
+                SignaturePublicKey::EdDSA(pk)
             }
             SignatureKeyPair::RSA(kp) => {
                 let raw_pk = kp.raw_public_key();
                 SignaturePublicKey::RSA(RSASignaturePublicKey::from_raw(kp.alg, raw_pk)?)
             }
+            SignatureKeyPair::Remote(kp) => kp.pk.clone(),
         };
-        let handle = WASI_CRYPTO_CTX.signature_publickey_manager.register(pk)?;
+        let handle = ctx.signature_publickey_manager.register(pk)?;
         Ok(handle)
     }
 }
@@ -92,28 +230,160 @@ pub enum SignatureKeyPairBuilder {
 }
 
 impl SignatureKeyPairBuilder {
-    fn open(op_handle: Handle) -> Result<Handle, Error> {
-        let signature_op = WASI_CRYPTO_CTX.signature_op_manager.get(op_handle)?;
+    pub(crate) fn default_for(alg: SignatureAlgorithm) -> Self {
+        match alg.category() {
+            AlgorithmCategory::ECDSA => {
+                SignatureKeyPairBuilder::ECDSA(ECDSASignatureKeyPairBuilder::new(alg))
+            }
+            AlgorithmCategory::EdDSA => {
+                SignatureKeyPairBuilder::EdDSA(EdDSASignatureKeyPairBuilder::new(alg))
+            }
+            AlgorithmCategory::RSA => {
+                SignatureKeyPairBuilder::RSA(RSASignatureKeyPairBuilder::new(alg))
+            }
+        }
+    }
+
+    // The default options for `alg`, except that key pairs cannot be exported
+    fn non_exportable_for(alg: SignatureAlgorithm) -> Self {
+        match alg.category() {
+            AlgorithmCategory::ECDSA => {
+                SignatureKeyPairBuilder::ECDSA(ECDSASignatureKeyPairBuilder {
+                    non_exportable: true,
+                    ..ECDSASignatureKeyPairBuilder::new(alg)
+                })
+            }
+            AlgorithmCategory::EdDSA => {
+                SignatureKeyPairBuilder::EdDSA(EdDSASignatureKeyPairBuilder {
+                    non_exportable: true,
+                    ..EdDSASignatureKeyPairBuilder::new(alg)
+                })
+            }
+            AlgorithmCategory::RSA => SignatureKeyPairBuilder::RSA(RSASignatureKeyPairBuilder {
+                alg,
+                non_exportable: true,
+            }),
+        }
+    }
+
+    pub fn alg(&self) -> SignatureAlgorithm {
+        match self {
+            SignatureKeyPairBuilder::ECDSA(kp_builder) => kp_builder.alg,
+            SignatureKeyPairBuilder::EdDSA(kp_builder) => kp_builder.alg,
+            SignatureKeyPairBuilder::RSA(kp_builder) => kp_builder.alg,
+        }
+    }
+
+    fn non_exportable(&self) -> bool {
+        match self {
+            SignatureKeyPairBuilder::ECDSA(kp_builder) => kp_builder.non_exportable,
+            SignatureKeyPairBuilder::EdDSA(kp_builder) => kp_builder.non_exportable,
+            SignatureKeyPairBuilder::RSA(kp_builder) => kp_builder.non_exportable,
+        }
+    }
+
+    pub fn generate_owned(&self) -> Result<SignatureKeyPair, Error> {
+        let kp = match self {
+            SignatureKeyPairBuilder::ECDSA(kp_builder) => {
+                SignatureKeyPair::ECDSA(kp_builder.generate_owned()?)
+            }
+            SignatureKeyPairBuilder::EdDSA(kp_builder) => {
+                SignatureKeyPair::EdDSA(kp_builder.generate_owned()?)
+            }
+            SignatureKeyPairBuilder::RSA(_) => bail!(CryptoError::NotAvailable),
+        };
+        Ok(kp)
+    }
+
+    /// Generate a key pair whose key material is drawn from `drbg`, instead of the system RNG.
+    pub fn generate_with_drbg(&self, drbg: &Drbg) -> Result<SignatureKeyPair, Error> {
+        let kp = match self {
+            SignatureKeyPairBuilder::ECDSA(kp_builder) => {
+                SignatureKeyPair::ECDSA(kp_builder.generate_with_drbg(drbg)?)
+            }
+            SignatureKeyPairBuilder::EdDSA(kp_builder) => {
+                SignatureKeyPair::EdDSA(kp_builder.generate_with_drbg(drbg)?)
+            }
+            SignatureKeyPairBuilder::RSA(_) => bail!(CryptoError::NotAvailable),
+        };
+        Ok(kp)
+    }
+
+    /// Generate and register a key pair in `ctx`, and return its handle along with its public
+    /// key in `encoding`, such as SPKI or JWK. The algorithm must be allowed by the policy of
+    /// `ctx`.
+    pub fn generate_with_public_key(
+        &self,
+        ctx: &CryptoContext,
+        encoding: PublicKeyEncoding,
+    ) -> Result<(Handle, Vec<u8>), Error> {
+        ctx.signature_algorithm_policy.read().check(self.alg())?;
+        let kp = ctx.signature_keypair_manager.generate(self)?;
+        let encoded_pk =
+            signature_publickey_encoding::encode(kp.alg(), kp.raw_public_key(), encoding)?;
+        let handle = ctx.signature_keypair_manager.register(kp)?;
+        Ok((handle, encoded_pk))
+    }
+
+    pub fn import_owned(&self, pkcs8: &[u8]) -> Result<SignatureKeyPair, Error> {
+        let kp = match self {
+            SignatureKeyPairBuilder::ECDSA(kp_builder) => {
+                SignatureKeyPair::ECDSA(kp_builder.import_owned(pkcs8)?)
+            }
+            SignatureKeyPairBuilder::EdDSA(kp_builder) => {
+                SignatureKeyPair::EdDSA(kp_builder.import_owned(pkcs8)?)
+            }
+            SignatureKeyPairBuilder::RSA(kp_builder) => {
+                SignatureKeyPair::RSA(kp_builder.import_owned(pkcs8)?)
+            }
+        };
+        Ok(kp)
+    }
+
+    pub(crate) fn open(ctx: &CryptoContext, op_handle: Handle) -> Result<Handle, Error> {
+        let signature_op = ctx.signature_op_manager.get(op_handle)?;
         let kp_builder = match signature_op {
-            SignatureOp::ECDSA(_) => SignatureKeyPairBuilder::ECDSA(
-                ECDSASignatureKeyPairBuilder::new(signature_op.alg()),
-            ),
-            SignatureOp::EdDSA(_) => SignatureKeyPairBuilder::EdDSA(
-                EdDSASignatureKeyPairBuilder::new(signature_op.alg()),
-            ),
-            SignatureOp::RSA(_) => {
-                SignatureKeyPairBuilder::RSA(RSASignatureKeyPairBuilder::new(signature_op.alg()))
+            SignatureOp::ECDSA(op) => {
+                SignatureKeyPairBuilder::ECDSA(ECDSASignatureKeyPairBuilder::from_op(op))
+            }
+            SignatureOp::EdDSA(op) => {
+                SignatureKeyPairBuilder::EdDSA(EdDSASignatureKeyPairBuilder::from_op(op))
+            }
+            SignatureOp::RSA(op) => {
+                SignatureKeyPairBuilder::RSA(RSASignatureKeyPairBuilder::from_op(op))
             }
         };
-        let handle = WASI_CRYPTO_CTX
-            .signature_keypair_builder_manager
-            .register(kp_builder)?;
+        let handle = ctx.signature_keypair_builder_manager.register(kp_builder)?;
         Ok(handle)
     }
 }
 
+/// Return the algorithm of a PKCS#8 key pair, from the OID of its algorithm identifier, and
+/// the curve OID for EC keys. Ed25519 keys are assumed to be used with `Ed25519`, and RSA keys
+/// with `RSA_PKCS1_2048_8192_SHA256`, since the key doesn't tell the digest.
+fn pkcs8_algorithm(pkcs8: &[u8]) -> Result<SignatureAlgorithm, Error> {
+    let pkcs8 = der::parse_pkcs8(pkcs8).map_err(|_| CryptoError::InvalidKey)?;
+    let mut alg_id = der::Decoder::new(pkcs8.alg_id);
+    let mut read_oid = || -> Result<&[u8], Error> {
+        Ok(alg_id
+            .read(der::TAG_OID)
+            .map_err(|_| CryptoError::InvalidKey)?)
+    };
+    let alg = match read_oid()? {
+        der::OID_ED25519 => SignatureAlgorithm::Ed25519,
+        der::OID_EC_PUBLIC_KEY => match read_oid()? {
+            der::OID_PRIME256V1 => SignatureAlgorithm::ECDSA_P256_SHA256,
+            der::OID_SECP384R1 => SignatureAlgorithm::ECDSA_P384_SHA384,
+            _ => bail!(CryptoError::UnsupportedAlgorithm),
+        },
+        der::OID_RSA_ENCRYPTION => SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256,
+        _ => bail!(CryptoError::UnsupportedAlgorithm),
+    };
+    Ok(alg)
+}
+
 pub fn signature_keypair_builder_open(op_handle: Handle) -> Result<Handle, Error> {
-    SignatureKeyPairBuilder::open(op_handle)
+    SignatureKeyPairBuilder::open(&WASI_CRYPTO_CTX, op_handle)
 }
 
 pub fn signature_keypair_builder_close(handle: Handle) -> Result<(), Error> {
@@ -123,7 +393,7 @@ pub fn signature_keypair_builder_close(handle: Handle) -> Result<(), Error> {
 }
 
 pub fn signature_keypair_generate(kp_builder_handle: Handle) -> Result<Handle, Error> {
-    SignatureKeyPair::generate(kp_builder_handle)
+    SignatureKeyPair::generate(&WASI_CRYPTO_CTX, kp_builder_handle)
 }
 
 pub fn signature_keypair_import(
@@ -131,7 +401,35 @@ pub fn signature_keypair_import(
     encoded: &[u8],
     encoding: KeyPairEncoding,
 ) -> Result<Handle, Error> {
-    SignatureKeyPair::import(kp_builder_handle, encoded, encoding)
+    SignatureKeyPair::import(&WASI_CRYPTO_CTX, kp_builder_handle, encoded, encoding)
+}
+
+/// Import a key pair, and check that its public key matches the raw public key the caller
+/// expects, for example one pinned when the key pair was first created. The key pair is not
+/// kept if it doesn't match.
+pub fn signature_keypair_import_expecting(
+    kp_builder_handle: Handle,
+    encoded: &[u8],
+    encoding: KeyPairEncoding,
+    expected_raw_pk: &[u8],
+) -> Result<Handle, Error> {
+    let handle = SignatureKeyPair::import(&WASI_CRYPTO_CTX, kp_builder_handle, encoded, encoding)?;
+    let kp = WASI_CRYPTO_CTX.signature_keypair_manager.get(handle)?;
+    if ring::constant_time::verify_slices_are_equal(kp.raw_public_key(), expected_raw_pk).is_err() {
+        WASI_CRYPTO_CTX.signature_keypair_manager.close(handle)?;
+        bail!(CryptoError::KeyMismatch);
+    }
+    Ok(handle)
+}
+
+/// Import a PKCS#8 key pair whose algorithm is not known in advance, and return its handle
+/// along with the detected algorithm. Unknown algorithm or curve OIDs are rejected with
+/// `UnsupportedAlgorithm`.
+pub fn signature_keypair_import_detect(
+    encoded: &[u8],
+    encoding: KeyPairEncoding,
+) -> Result<(Handle, SignatureAlgorithm), Error> {
+    SignatureKeyPair::import_detect(&WASI_CRYPTO_CTX, encoded, encoding)
 }
 
 pub fn signature_keypair_from_id(
@@ -155,12 +453,655 @@ pub fn signature_keypair_export(
     Ok(encoded)
 }
 
-pub fn signature_keypair_publickey(kp_handle: Handle) -> Result<Handle, Error> {
+/// Write the encoded key pair into a caller-provided buffer, and return its length. For
+/// PKCS#8, a buffer shorter than `SignatureAlgorithm::pkcs8_len_hint()` is rejected upfront.
+pub fn signature_keypair_export_into(
+    kp_handle: Handle,
+    encoding: KeyPairEncoding,
+    out: &mut [u8],
+) -> Result<usize, Error> {
     let kp = WASI_CRYPTO_CTX.signature_keypair_manager.get(kp_handle)?;
-    let handle = kp.public_key()?;
-    Ok(handle)
+    if let (KeyPairEncoding::PKCS8, Some(len_hint)) = (encoding, kp.alg().pkcs8_len_hint()) {
+        ensure!(out.len() >= len_hint, CryptoError::InvalidLength);
+    }
+    let mut encoded = kp.export(encoding)?;
+    let len = encoded.len();
+    let fits = out.len() >= len;
+    if fits {
+        out[..len].copy_from_slice(&encoded);
+    }
+    encoded.zeroize();
+    ensure!(fits, CryptoError::InvalidLength);
+    Ok(len)
+}
+
+const SERIALIZED_KEYPAIR_CONTEXT: &[u8] = b"wasi-crypto keypair";
+
+/// Serialize a key pair so that it can be imported in another process. The result is the
+/// algorithm name followed by the PKCS#8 document, both framed with a 64-bit big-endian
+/// length after a fixed context string. Fails for non-exportable key pairs.
+pub fn signature_keypair_serialize(kp_handle: Handle) -> Result<Vec<u8>, Error> {
+    let kp = WASI_CRYPTO_CTX.signature_keypair_manager.get(kp_handle)?;
+    let mut pkcs8 = kp.export(KeyPairEncoding::PKCS8)?;
+    let mut serialized = vec![];
+    framing::append_framed(&mut serialized, SERIALIZED_KEYPAIR_CONTEXT);
+    framing::append_framed(&mut serialized, kp.alg().name().as_bytes());
+    framing::append_framed(&mut serialized, &pkcs8);
+    pkcs8.zeroize();
+    Ok(serialized)
+}
+
+/// Import a key pair serialized with `signature_keypair_serialize()`.
+pub fn signature_keypair_deserialize(serialized: &[u8]) -> Result<Handle, Error> {
+    let mut serialized = serialized;
+    let context = framing::read_framed(&mut serialized).ok_or(CryptoError::InvalidKey)?;
+    ensure!(
+        context == SERIALIZED_KEYPAIR_CONTEXT,
+        CryptoError::InvalidKey
+    );
+    let alg_name = framing::read_framed(&mut serialized).ok_or(CryptoError::InvalidKey)?;
+    let pkcs8 = framing::read_framed(&mut serialized).ok_or(CryptoError::InvalidKey)?;
+    ensure!(serialized.is_empty(), CryptoError::InvalidKey);
+    let alg_name = core::str::from_utf8(alg_name).map_err(|_| CryptoError::InvalidKey)?;
+    let op_handle = signature_op_open(alg_name)?;
+    let kp_builder_handle = signature_keypair_builder_open(op_handle);
+    signature_op_close(op_handle)?;
+    let kp_builder_handle = kp_builder_handle?;
+    let handle = signature_keypair_import(kp_builder_handle, pkcs8, KeyPairEncoding::PKCS8);
+    signature_keypair_builder_close(kp_builder_handle)?;
+    handle
+}
+
+/// Generate a key pair, and return its handle along with its public key in `encoding`, without
+/// a separate public key handle to open and close.
+pub fn signature_keypair_generate_with_public_key(
+    kp_builder_handle: Handle,
+    encoding: PublicKeyEncoding,
+) -> Result<(Handle, Vec<u8>), Error> {
+    let kp_builder = WASI_CRYPTO_CTX
+        .signature_keypair_builder_manager
+        .get(kp_builder_handle)?;
+    kp_builder.generate_with_public_key(&WASI_CRYPTO_CTX, encoding)
+}
+
+/// Import an ECDSA key pair whose signature counter is persisted in `counter_store`, and
+/// resumes from the count it holds, for example after a restart.
+pub fn signature_keypair_import_with_counter_store(
+    kp_builder_handle: Handle,
+    encoded: &[u8],
+    encoding: KeyPairEncoding,
+    counter_store: impl CounterStore + 'static,
+) -> Result<Handle, Error> {
+    let kp_builder = WASI_CRYPTO_CTX
+        .signature_keypair_builder_manager
+        .get(kp_builder_handle)?;
+    WASI_CRYPTO_CTX
+        .signature_algorithm_policy
+        .read()
+        .check(kp_builder.alg())?;
+    let kp_builder = match (kp_builder, encoding) {
+        (SignatureKeyPairBuilder::ECDSA(kp_builder), KeyPairEncoding::PKCS8) => kp_builder,
+        _ => bail!(CryptoError::NotAvailable),
+    };
+    let kp = kp_builder
+        .import_owned(encoded)?
+        .with_counter_store(Arc::new(Mutex::new(counter_store)))?;
+    WASI_CRYPTO_CTX
+        .signature_keypair_manager
+        .register(SignatureKeyPair::ECDSA(kp))
+}
+
+/// Return `true` if the public key is the one of the key pair, for example to check that a
+/// key pair loaded later is the one whose public key was registered during enrollment.
+pub fn signature_publickey_matches_keypair(
+    pk_handle: Handle,
+    kp_handle: Handle,
+) -> Result<bool, Error> {
+    SignatureKeyPair::matches_publickey(&WASI_CRYPTO_CTX, kp_handle, pk_handle)
+}
+
+/// Return `true` if `raw_pk` is the raw public key of the key pair, for example to check that
+/// a provisioned key pair matches the public key of its certificate.
+pub fn signature_keypair_matches_public_key(
+    kp_handle: Handle,
+    raw_pk: &[u8],
+) -> Result<bool, Error> {
+    let kp = WASI_CRYPTO_CTX.signature_keypair_manager.get(kp_handle)?;
+    Ok(kp.matches_raw_public_key(raw_pk))
+}
+
+/// The number of signatures an ECDSA key pair has produced, including those of all the
+/// signature states opened with it.
+pub fn signature_keypair_sign_count(kp_handle: Handle) -> Result<u64, Error> {
+    match WASI_CRYPTO_CTX.signature_keypair_manager.get(kp_handle)? {
+        SignatureKeyPair::ECDSA(kp) => Ok(kp.sign_count()),
+        _ => bail!(CryptoError::NotAvailable),
+    }
+}
+
+pub fn signature_keypair_publickey(kp_handle: Handle) -> Result<Handle, Error> {
+    WASI_CRYPTO_CTX.signature_keypair_publickey(kp_handle)
+}
+
+/// Non-secret properties of a stored key pair.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct KeyPairInfo {
+    pub alg: SignatureAlgorithm,
+    pub exportable: bool,
+    pub encrypted_in_memory: bool,
+}
+
+impl KeyPairInfo {
+    pub fn new(kp_builder: &SignatureKeyPairBuilder, encrypted_in_memory: bool) -> Self {
+        KeyPairInfo {
+            alg: kp_builder.alg(),
+            exportable: !kp_builder.non_exportable(),
+            encrypted_in_memory,
+        }
+    }
+}
+
+/// Return the handles of all the key pairs currently registered.
+pub fn signature_keypair_handles() -> Vec<Handle> {
+    WASI_CRYPTO_CTX.signature_keypair_manager.handles()
+}
+
+/// Call `f` for every key pair currently registered, along with its properties. Key pairs
+/// are enumerated from a snapshot, so `f` can create or close key pairs.
+pub fn signature_keypair_for_each(f: impl FnMut(Handle, &KeyPairInfo)) {
+    WASI_CRYPTO_CTX.signature_keypair_manager.for_each(f)
+}
+
+/// Encrypt the key pairs created or imported from now on while they are stored in memory,
+/// and only decrypt them when they are used. `false` stops encrypting new key pairs.
+///
+/// A signature state keeps the decrypted key pair until it is closed, so states should be
+/// closed as soon as their signature has been computed.
+pub fn signature_keypair_encrypt_in_memory(enabled: bool) -> Result<(), Error> {
+    WASI_CRYPTO_CTX
+        .signature_keypair_manager
+        .set_encryption(enabled)
+}
+
+/// Install a DRBG seeded from the system RNG, that key pairs are generated from from now on,
+/// and that provides the nonces of ECDSA signatures. It reseeds itself after
+/// `reseed_interval` requests. Requires the `drbg` feature, without which ECDSA couldn't use
+/// it; returns `NotAvailable` otherwise, and key pairs keep using the system RNG.
+pub fn signature_install_drbg(reseed_interval: u64) -> Result<(), Error> {
+    WASI_CRYPTO_CTX.signature_install_drbg(reseed_interval)
 }
 
 pub fn signature_keypair_close(handle: Handle) -> Result<(), Error> {
     WASI_CRYPTO_CTX.signature_keypair_manager.close(handle)
 }
+
+#[test]
+fn test_keypair_serialization() {
+    use crate::SignatureOptions;
+
+    for alg_str in &["Ed25519", "ECDSA_P384_SHA384"] {
+        let op_handle = signature_op_open(alg_str).unwrap();
+        let kp_builder_handle = signature_keypair_builder_open(op_handle).unwrap();
+        let kp_handle = signature_keypair_generate(kp_builder_handle).unwrap();
+        let serialized = signature_keypair_serialize(kp_handle).unwrap();
+        let kp_handle2 = signature_keypair_deserialize(&serialized).unwrap();
+        assert_ne!(kp_handle, kp_handle2);
+        let kp2 = WASI_CRYPTO_CTX
+            .signature_keypair_manager
+            .get(kp_handle2)
+            .unwrap();
+        assert_eq!(kp2.alg().name(), *alg_str);
+        assert_eq!(
+            signature_keypair_export(kp_handle, KeyPairEncoding::PKCS8).unwrap(),
+            signature_keypair_export(kp_handle2, KeyPairEncoding::PKCS8).unwrap()
+        );
+        assert!(signature_keypair_deserialize(&serialized[..serialized.len() - 1]).is_err());
+    }
+
+    let options = SignatureOptions {
+        non_exportable: true,
+        ..Default::default()
+    };
+    let op_handle = signature_op_open_with_options("Ed25519", &options).unwrap();
+    let kp_builder_handle = signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = signature_keypair_generate(kp_builder_handle).unwrap();
+    assert!(signature_keypair_serialize(kp_handle).is_err());
+    assert!(signature_keypair_export(kp_handle, KeyPairEncoding::PKCS8).is_err());
+    assert!(crate::signature_keypair_export_wrapped(kp_handle, &[0u8; 32]).is_err());
+}
+
+#[test]
+fn test_import_expecting() {
+    let op_handle = signature_op_open("ECDSA_P256_SHA256").unwrap();
+    let kp_builder_handle = signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = signature_keypair_generate(kp_builder_handle).unwrap();
+    let kp_handle2 = signature_keypair_generate(kp_builder_handle).unwrap();
+    let pkcs8 = signature_keypair_export(kp_handle, KeyPairEncoding::PKCS8).unwrap();
+    let get_raw_pk = |kp_handle| {
+        WASI_CRYPTO_CTX
+            .signature_keypair_manager
+            .get(kp_handle)
+            .unwrap()
+            .raw_public_key()
+            .to_vec()
+    };
+
+    let handle = signature_keypair_import_expecting(
+        kp_builder_handle,
+        &pkcs8,
+        KeyPairEncoding::PKCS8,
+        &get_raw_pk(kp_handle),
+    )
+    .unwrap();
+    assert_eq!(get_raw_pk(handle), get_raw_pk(kp_handle));
+
+    let err = signature_keypair_import_expecting(
+        kp_builder_handle,
+        &pkcs8,
+        KeyPairEncoding::PKCS8,
+        &get_raw_pk(kp_handle2),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::KeyMismatch)
+    ));
+}
+
+#[test]
+fn test_export_into() {
+    for alg in SignatureAlgorithm::ALL.iter() {
+        let len_hint = match alg.pkcs8_len_hint() {
+            Some(len_hint) => len_hint,
+            None => continue,
+        };
+        let op_handle = crate::signature_op_open(alg.name()).unwrap();
+        let kp_builder_handle = signature_keypair_builder_open(op_handle).unwrap();
+        let kp_handle = signature_keypair_generate(kp_builder_handle).unwrap();
+        let pkcs8 = signature_keypair_export(kp_handle, KeyPairEncoding::PKCS8).unwrap();
+        assert!(len_hint >= pkcs8.len());
+
+        let mut out = vec![0u8; len_hint];
+        let len =
+            signature_keypair_export_into(kp_handle, KeyPairEncoding::PKCS8, &mut out).unwrap();
+        assert_eq!(&out[..len], &pkcs8[..]);
+        let err = signature_keypair_export_into(kp_handle, KeyPairEncoding::PKCS8, &mut out[1..])
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::InvalidLength)
+        ));
+    }
+    assert!(SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256
+        .pkcs8_len_hint()
+        .is_none());
+}
+
+#[test]
+fn test_double_close() {
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = signature_keypair_generate(kp_builder_handle).unwrap();
+    signature_keypair_close(kp_handle).unwrap();
+
+    let is_invalid_handle = |err: Error| {
+        matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::InvalidHandle)
+        )
+    };
+    assert!(is_invalid_handle(
+        signature_keypair_close(kp_handle).unwrap_err()
+    ));
+    assert!(is_invalid_handle(
+        crate::signature_state_open(kp_handle).unwrap_err()
+    ));
+    assert!(is_invalid_handle(
+        crate::signature_sign_reader(kp_handle, &b"test"[..]).unwrap_err()
+    ));
+}
+
+#[test]
+fn test_counter_store() {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    // Stands for a file or a database row that outlives the process
+    #[derive(Clone, Debug, Default)]
+    struct MemoryCounterStore(Arc<AtomicU64>);
+    impl CounterStore for MemoryCounterStore {
+        fn load(&mut self) -> Result<u64, Error> {
+            Ok(self.0.load(Ordering::Relaxed))
+        }
+        fn store(&mut self, count: u64) -> Result<(), Error> {
+            self.0.store(count, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    let options = SignatureOptions {
+        max_signatures: Some(3),
+        ..Default::default()
+    };
+    let op_handle = crate::signature_op_open_with_options("ECDSA_P256_SHA256", &options).unwrap();
+    let kp_builder_handle = signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = signature_keypair_generate(kp_builder_handle).unwrap();
+    let pkcs8 = signature_keypair_export(kp_handle, KeyPairEncoding::PKCS8).unwrap();
+    let sign = |kp_handle| {
+        let state_handle = crate::signature_state_open(kp_handle).unwrap();
+        crate::signature_state_update(state_handle, b"test").unwrap();
+        crate::signature_state_sign(state_handle)
+    };
+
+    let import_with_store = |store| {
+        signature_keypair_import_with_counter_store(
+            kp_builder_handle,
+            &pkcs8,
+            KeyPairEncoding::PKCS8,
+            store,
+        )
+        .unwrap()
+    };
+    let store = MemoryCounterStore::default();
+    let import = || import_with_store(store.clone());
+    let kp_handle = import();
+    sign(kp_handle).unwrap();
+    sign(kp_handle).unwrap();
+    assert_eq!(store.0.load(Ordering::Relaxed), 2);
+
+    // A restart imports the key pair again, and resumes from the stored count
+    signature_keypair_close(kp_handle).unwrap();
+    let kp_handle = import();
+    assert_eq!(signature_keypair_sign_count(kp_handle).unwrap(), 2);
+    sign(kp_handle).unwrap();
+    let kp_handle = import();
+    let err = sign(kp_handle).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::NotAvailable)
+    ));
+    assert_eq!(store.0.load(Ordering::Relaxed), 3);
+
+    // Two imports sharing a store share the limit, even while both are open
+    let store = MemoryCounterStore::default();
+    let kp_handle = import_with_store(store.clone());
+    let kp_handle2 = import_with_store(store.clone());
+    sign(kp_handle).unwrap();
+    sign(kp_handle2).unwrap();
+    sign(kp_handle).unwrap();
+    assert_eq!(signature_keypair_sign_count(kp_handle).unwrap(), 3);
+    let err = sign(kp_handle2).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::NotAvailable)
+    ));
+    assert_eq!(store.0.load(Ordering::Relaxed), 3);
+
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = signature_keypair_generate(kp_builder_handle).unwrap();
+    let pkcs8 = signature_keypair_export(kp_handle, KeyPairEncoding::PKCS8).unwrap();
+    assert!(signature_keypair_import_with_counter_store(
+        kp_builder_handle,
+        &pkcs8,
+        KeyPairEncoding::PKCS8,
+        store
+    )
+    .is_err());
+}
+
+#[test]
+fn test_publickey_matches_keypair() {
+    let ctx = CryptoContext::new().unwrap();
+    let op_handle = ctx.signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = ctx.signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = ctx.signature_keypair_generate(kp_builder_handle).unwrap();
+    let other_kp_handle = ctx.signature_keypair_generate(kp_builder_handle).unwrap();
+    let kp = ctx.signature_keypair_manager.get(kp_handle).unwrap();
+    let pk = EdDSASignaturePublicKey::from_raw(kp.alg(), kp.raw_public_key()).unwrap();
+    let pk_handle = ctx
+        .signature_publickey_manager
+        .register(SignaturePublicKey::EdDSA(pk))
+        .unwrap();
+    assert!(ctx
+        .signature_publickey_matches_keypair(pk_handle, kp_handle)
+        .unwrap());
+    assert!(!ctx
+        .signature_publickey_matches_keypair(pk_handle, other_kp_handle)
+        .unwrap());
+
+    let kp_handle = signature_keypair_generate(
+        signature_keypair_builder_open(crate::signature_op_open("ECDSA_P256_SHA256").unwrap())
+            .unwrap(),
+    )
+    .unwrap();
+    let pk_handle = signature_keypair_publickey(kp_handle).unwrap();
+    assert!(signature_publickey_matches_keypair(pk_handle, kp_handle).unwrap());
+    let op_handle = crate::signature_op_open("ECDSA_P384_SHA384").unwrap();
+    let other_kp_handle =
+        signature_keypair_generate(signature_keypair_builder_open(op_handle).unwrap()).unwrap();
+    assert!(!signature_publickey_matches_keypair(pk_handle, other_kp_handle).unwrap());
+    assert!(signature_publickey_matches_keypair(pk_handle, 0xffff_ffff).is_err());
+}
+
+#[test]
+fn test_generate_with_public_key() {
+    use p256::pkcs8::DecodePublicKey as _;
+
+    let op_handle = crate::signature_op_open("ECDSA_P256_SHA256").unwrap();
+    let kp_builder_handle = signature_keypair_builder_open(op_handle).unwrap();
+    let (kp_handle, spki) =
+        signature_keypair_generate_with_public_key(kp_builder_handle, PublicKeyEncoding::SPKI)
+            .unwrap();
+    let pk = p256::PublicKey::from_public_key_der(&spki).unwrap();
+    let kp = WASI_CRYPTO_CTX
+        .signature_keypair_manager
+        .get(kp_handle)
+        .unwrap();
+    assert_eq!(&pk.to_sec1_bytes()[..], kp.raw_public_key());
+
+    let pk_handle = signature_keypair_publickey(kp_handle).unwrap();
+    for &encoding in &[
+        PublicKeyEncoding::Raw,
+        PublicKeyEncoding::PEM,
+        PublicKeyEncoding::JWK,
+    ] {
+        let (_, encoded_pk) =
+            signature_keypair_generate_with_public_key(kp_builder_handle, encoding).unwrap();
+        assert_ne!(
+            encoded_pk,
+            signature_publickey_export(pk_handle, encoding).unwrap()
+        );
+    }
+    assert_eq!(
+        signature_publickey_export(pk_handle, PublicKeyEncoding::SPKI).unwrap(),
+        spki
+    );
+}
+
+#[test]
+fn test_import_detect() {
+    let ctx = CryptoContext::new().unwrap();
+    for alg_str in ["Ed25519", "ECDSA_P256_SHA256", "ECDSA_P384_SHA384"] {
+        let op_handle = ctx.signature_op_open(alg_str).unwrap();
+        let kp_builder_handle = ctx.signature_keypair_builder_open(op_handle).unwrap();
+        let kp_handle = ctx.signature_keypair_generate(kp_builder_handle).unwrap();
+        let pkcs8 = ctx
+            .signature_keypair_export(kp_handle, KeyPairEncoding::PKCS8)
+            .unwrap();
+        let (handle, alg) = ctx.import_detect(&pkcs8, KeyPairEncoding::PKCS8).unwrap();
+        assert_eq!(alg.name(), alg_str);
+        let kp = ctx.signature_keypair_manager.get(handle).unwrap();
+        assert_eq!(
+            kp.raw_public_key(),
+            ctx.signature_keypair_manager
+                .get(kp_handle)
+                .unwrap()
+                .raw_public_key()
+        );
+    }
+
+    let unsupported = [
+        // X25519
+        der::oid(&[0x2b, 0x65, 0x6e]),
+        // An EC key on secp256k1
+        [
+            der::oid(der::OID_EC_PUBLIC_KEY),
+            der::oid(&[0x2b, 0x81, 0x04, 0x00, 0x0a]),
+        ]
+        .concat(),
+    ];
+    for alg_id in unsupported.iter() {
+        let pkcs8 = der::pkcs8_v1(alg_id, &[0x42; 32]);
+        let err = ctx
+            .import_detect(&pkcs8, KeyPairEncoding::PKCS8)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::UnsupportedAlgorithm)
+        ));
+    }
+    let err = ctx
+        .import_detect(b"not a key", KeyPairEncoding::PKCS8)
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::InvalidKey)
+    ));
+}
+
+#[test]
+fn test_import_detect_rsa() {
+    // openssl genpkey -algorithm RSA -pkeyopt rsa_keygen_bits:2048 |
+    //   openssl pkcs8 -topk8 -nocrypt -outform DER
+    let pkcs8 = crate::signature::parse_hex(
+        "308204bd020100300d06092a864886f70d0101010500048204a7308204a30201000282010100abad5bc28e\
+         de369d71e364847210e6a11f1ba6ea4b1cdbb4f31c635caea83f3c5a3f4081eee8eb0e9d35fe263a50e1e8\
+         4271ab7dc37ed18062cec514f3997c3f603a65da92e686ada72d24cd531cf8f45323f19ba4b21af596b096\
+         024f2c1315e0e1c3f1857cfe7dec4209e71507d3a15e323a62be40e1165cf03481284203a72787a6653e49\
+         cfb31a64bedf71436c31ebbd389a6125eb263e66727e6e1fc86ba5453509eceeaece73ceed6de2d4c13f6d\
+         ae7258d567d63813d0ff794d993618f95b353aefac7e2ec68fe105e543e848e3db75a63bd2137e5d6c477d\
+         50e291000cd98388d3ff894d3ef24ad41e2396914dffea05c2f3b6c0f18afb86cc2c131b02030100010282\
+         0100212ad97701ae19c41a4c31cfcacfcc16e5d12c52c897373542e0c1ab96ccc786cbf2bb6e3eef7df631\
+         564c972b1e143f1093306c1838689652c09adfdcde52dc55a582a71349794c94674adc6649cb293ce4cd59\
+         621ccccb94c4e1fe69385d29956c58b45133606188a30b91bb04eb949a03bf36f385ca56cd36d515fce6a0\
+         18baa82679f3b648e287228784e16ad91b0dd2503992777920d08492e3fdb7cdd5e9074f60761c6797e6ea\
+         9f31eef67e04dbd0f155b4ea072c637da283bb1347796ae6af1ebf219b9d60e8e0ea4d95d7acc0b4b023a5\
+         9f14f08a904adaf5a4c4a8bd42dfc1282cadb47be27f5bc6bb5bcec9612ec990269bb854a1b1d4b0105031\
+         02818100d757819c24947d9947ace064743f6dd30ebbfa3aaf59e160a42fb8b794f1abd8f7ff8aad133bb3\
+         84bf283bd00f9d851014cf6876704d2e1954a9ffa35947d54a1f65da964359a845deb006bc7f90f77d81d9\
+         9aa707264bc145aa162dca24183b438c11d5f0431be6b5cb9d92bd8655b7567dd6bc3183330ee582ad2979\
+         62ca5102818100cc175522fd41747f2def1fb54001819a3cb9247effd9c6607c40767f5a42cb598d2b9d91\
+         9f235fa5cfe53d03831edf311445e0db8162ac99a9d4c79469d446039ffaa17894bdc71eba46a0d4d6057d\
+         149b87e6217379dbdd2b79a8cf0177a63d95c337da2d153da16173af673474286a02fe34830fbb8c85b0a4\
+         a0d972d53fab0281810082724bdd5e7b652a39350c294e122e613007c7294d97d0e34e126a7f2b06cae700\
+         3418b987711eea47320429a18bf6532b8808ebd653aa9d45a0456f9a61dd5ead6e71df9ca4ef3e840b75c9\
+         322d8afec86e1d35192ba5b04a97222052d752a673e0a1dd4b521bd0c247ba086e2eb635fd7af55dc3508a\
+         d9774695c70df53e41028180708780eabce6e53be670cd73db69b932f8be409041b3aabcb265bcb0756d14\
+         c038a54ec8e70cd30512bedbc85ef89ba96cfb268301310de7e00b314bf49a154c945dd5c02d8b020a403d\
+         071ab332ed877a46112e6a844b25047e9d041e5401763d68191893f8fd35490d472e496eae85dd469a5f95\
+         421cb5edf2a7b99da654e70281806c6f768bff65ffdd9da81b6f6c8e0109b9cf56e1c203172984d8a4e3f3\
+         520df6b4d3a367a6fffed9fd66f9f1bc00367c5e7317c016b138cd51532e44fd945569a453ee1bb1c9d41c\
+         6eac3d34cc1ff36443f165f87119c24ecaf2effc4df26b094038781d322a90e8611a55d8a02c9179a3822a\
+         05dd0f47db04db60e54f420cc3",
+    )
+    .unwrap();
+    let ctx = CryptoContext::new().unwrap();
+    let (handle, alg) = ctx.import_detect(&pkcs8, KeyPairEncoding::PKCS8).unwrap();
+    assert_eq!(alg, SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256);
+
+    // The detected key pair is the one the RSA builder imports
+    let op_handle = ctx.signature_op_open("RSA_PKCS1_2048_8192_SHA256").unwrap();
+    let kp_builder_handle = ctx.signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = ctx
+        .signature_keypair_import(kp_builder_handle, &pkcs8, KeyPairEncoding::PKCS8)
+        .unwrap();
+    assert_eq!(
+        ctx.signature_keypair_manager
+            .get(handle)
+            .unwrap()
+            .raw_public_key(),
+        ctx.signature_keypair_manager
+            .get(kp_handle)
+            .unwrap()
+            .raw_public_key()
+    );
+}
+
+#[test]
+fn test_keypair_generate_export_public() {
+    let ctx = CryptoContext::new().unwrap();
+    let encodings = [
+        PublicKeyEncoding::Raw,
+        PublicKeyEncoding::Hex,
+        PublicKeyEncoding::Base64Original,
+        PublicKeyEncoding::Base64OriginalNoPadding,
+        PublicKeyEncoding::Base64URLSafe,
+        PublicKeyEncoding::Base64URLSafeNoPadding,
+        PublicKeyEncoding::CompressedSEC1,
+        PublicKeyEncoding::SPKI,
+        PublicKeyEncoding::PEM,
+        PublicKeyEncoding::JWK,
+    ];
+    for alg in [
+        SignatureAlgorithm::Ed25519,
+        SignatureAlgorithm::ECDSA_P256_SHA256,
+        SignatureAlgorithm::ECDSA_P384_SHA384,
+    ] {
+        for encoding in encodings {
+            let res = ctx.keypair_generate_export_public(alg, encoding);
+            if alg == SignatureAlgorithm::Ed25519 && encoding == PublicKeyEncoding::CompressedSEC1 {
+                assert!(res.is_err());
+                continue;
+            }
+            let (kp_handle, encoded_pk) = res.unwrap();
+            let kp = ctx.signature_keypair_manager.get(kp_handle).unwrap();
+            assert_eq!(kp.alg(), alg);
+            // Decompressing points requires optional dependencies
+            if encoding == PublicKeyEncoding::CompressedSEC1 {
+                assert_eq!(encoded_pk.len(), 1 + (kp.raw_public_key().len() - 1) / 2);
+                continue;
+            }
+            assert_eq!(
+                signature_publickey_encoding::decode(alg, &encoded_pk, encoding).unwrap(),
+                kp.raw_public_key()
+            );
+        }
+    }
+    // Key pairs whose public key can't be encoded are not kept
+    assert_eq!(
+        ctx.signature_keypair_manager.handles().len(),
+        3 * encodings.len() - 1
+    );
+}
+
+#[test]
+fn test_keypair_matches_public_key() {
+    let ctx = CryptoContext::new().unwrap();
+    let (kp_handle, raw_pk) = ctx
+        .keypair_generate_export_public(SignatureAlgorithm::Ed25519, PublicKeyEncoding::Raw)
+        .unwrap();
+    let (other_kp_handle, other_raw_pk) = ctx
+        .keypair_generate_export_public(SignatureAlgorithm::Ed25519, PublicKeyEncoding::Raw)
+        .unwrap();
+    assert!(ctx.keypair_matches_public_key(kp_handle, &raw_pk).unwrap());
+    assert!(!ctx
+        .keypair_matches_public_key(kp_handle, &other_raw_pk)
+        .unwrap());
+    assert!(!ctx
+        .keypair_matches_public_key(kp_handle, &raw_pk[1..])
+        .unwrap());
+    assert!(ctx
+        .keypair_matches_public_key(other_kp_handle, &other_raw_pk)
+        .unwrap());
+    assert!(ctx
+        .keypair_matches_public_key(0xffff_ffff, &raw_pk)
+        .is_err());
+
+    let op_handle = crate::signature_op_open("ECDSA_P256_SHA256").unwrap();
+    let kp_handle =
+        signature_keypair_generate(signature_keypair_builder_open(op_handle).unwrap()).unwrap();
+    let pk_handle = signature_keypair_publickey(kp_handle).unwrap();
+    let raw_pk = crate::signature_publickey_export(pk_handle, PublicKeyEncoding::Raw).unwrap();
+    assert!(signature_keypair_matches_public_key(kp_handle, &raw_pk).unwrap());
+    assert!(!signature_keypair_matches_public_key(kp_handle, &other_raw_pk).unwrap());
+}
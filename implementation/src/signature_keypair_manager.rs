@@ -0,0 +1,323 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::SecureRandom;
+use zeroize::Zeroize;
+
+use super::drbg::*;
+use super::error::*;
+use super::handles::*;
+use super::signature_keypair::*;
+use super::sync::Mutex;
+
+// A key pair whose PKCS#8 document is encrypted under the manager's ephemeral key. Only the
+// builder and the ECDSA signature counter and its store are kept in the clear; they hold the
+// algorithm and options, but no key material.
+#[derive(Debug)]
+pub struct SealedKeyPair {
+    builder: SignatureKeyPairBuilder,
+    sign_count: Option<Arc<AtomicU64>>,
+    counter_store: Option<Arc<Mutex<dyn CounterStore>>>,
+    nonce: [u8; NONCE_LEN],
+    ciphertext: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub enum StoredKeyPair {
+    Plain(SignatureKeyPair),
+    Sealed(Arc<SealedKeyPair>),
+}
+
+/// Stores key pairs, optionally encrypting them in memory with AES-256-GCM under a key
+/// generated when the mode is first enabled, and that never leaves the process.
+///
+/// Sealed key pairs are decrypted every time they are retrieved, and the decrypted PKCS#8
+/// document is wiped as soon as the key pair has been reconstructed. This doesn't protect
+/// against a compromised process, but reduces the amount of plaintext key material found in
+/// a heap dump.
+///
+/// The reconstructed key pair is owned by whoever retrieved it. In particular, a signature
+/// state keeps it, in the clear, until the state is closed, even if the key pair handle is
+/// closed first; only idle key pairs stay encrypted.
+pub struct KeyPairManager {
+    handles: HandlesManager<StoredKeyPair>,
+    encrypt: AtomicBool,
+    sealing_key: Mutex<Option<Arc<LessSafeKey>>>,
+    drbg: Mutex<Option<Arc<Drbg>>>,
+}
+
+impl KeyPairManager {
+    pub fn new(handle_type: u8) -> Self {
+        Self::with_context(handle_type, 0)
+    }
+
+    pub fn with_context(handle_type: u8, context_id: u8) -> Self {
+        KeyPairManager {
+            handles: HandlesManager::with_context(handle_type, context_id),
+            encrypt: AtomicBool::new(false),
+            sealing_key: Mutex::new(None),
+            drbg: Mutex::new(None),
+        }
+    }
+
+    /// Encrypt the key pairs registered from now on, or stop doing so. Key pairs that are
+    /// already stored remain as they are.
+    pub fn set_encryption(&self, enabled: bool) -> Result<(), Error> {
+        let mut sealing_key = self.sealing_key.lock();
+        if enabled && sealing_key.is_none() {
+            let mut key_bytes = [0u8; 32];
+            ring::rand::SystemRandom::new()
+                .fill(&mut key_bytes)
+                .map_err(|_| CryptoError::RNGError)?;
+            let key = UnboundKey::new(&AES_256_GCM, &key_bytes);
+            key_bytes.zeroize();
+            let key = key.map_err(CryptoError::from)?;
+            *sealing_key = Some(Arc::new(LessSafeKey::new(key)));
+        }
+        self.encrypt.store(enabled, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Draw the key material of generated key pairs and the ECDSA nonces of all the stored key
+    /// pairs from `drbg`, or from the system RNG again if `None`. ECDSA with a DRBG requires
+    /// the `drbg` feature.
+    pub fn set_drbg(&self, drbg: Option<Arc<Drbg>>) {
+        *self.drbg.lock() = drbg;
+    }
+
+    /// Generate a key pair owned by the caller, from the DRBG if one is installed.
+    pub fn generate(
+        &self,
+        kp_builder: &SignatureKeyPairBuilder,
+    ) -> Result<SignatureKeyPair, Error> {
+        match self.drbg.lock().clone() {
+            Some(drbg) => kp_builder.generate_with_drbg(&drbg),
+            None => kp_builder.generate_owned(),
+        }
+    }
+
+    fn seal(key: &LessSafeKey, kp: &SignatureKeyPair) -> Result<SealedKeyPair, Error> {
+        let mut nonce = [0u8; NONCE_LEN];
+        ring::rand::SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| CryptoError::RNGError)?;
+        let mut ciphertext = kp.pkcs8().to_vec();
+        if key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut ciphertext,
+            )
+            .is_err()
+        {
+            ciphertext.zeroize();
+            bail!(CryptoError::AlgorithmFailure);
+        }
+        let (sign_count, counter_store) = match kp {
+            SignatureKeyPair::ECDSA(kp) => (Some(kp.sign_count.clone()), kp.counter_store.clone()),
+            _ => (None, None),
+        };
+        Ok(SealedKeyPair {
+            builder: kp.builder(),
+            sign_count,
+            counter_store,
+            nonce,
+            ciphertext,
+        })
+    }
+
+    fn open(key: &LessSafeKey, sealed: &SealedKeyPair) -> Result<SignatureKeyPair, Error> {
+        let mut buffer = sealed.ciphertext.clone();
+        let kp = match key.open_in_place(
+            Nonce::assume_unique_for_key(sealed.nonce),
+            Aad::empty(),
+            &mut buffer,
+        ) {
+            Ok(pkcs8) => sealed.builder.import_owned(pkcs8),
+            Err(_) => Err(CryptoError::AlgorithmFailure.into()),
+        };
+        buffer.zeroize();
+        let mut kp = kp?;
+        if let (SignatureKeyPair::ECDSA(kp), Some(sign_count)) = (&mut kp, &sealed.sign_count) {
+            kp.sign_count = sign_count.clone();
+            kp.counter_store = sealed.counter_store.clone();
+        }
+        Ok(kp)
+    }
+
+    pub fn register(&self, kp: SignatureKeyPair) -> Result<Handle, Error> {
+        let sealing_key = self.sealing_key.lock().clone();
+        let stored = match sealing_key {
+            // Remote key pairs hold no secret
+            Some(key)
+                if self.encrypt.load(Ordering::Relaxed)
+                    && !matches!(kp, SignatureKeyPair::Remote(_)) =>
+            {
+                StoredKeyPair::Sealed(Arc::new(Self::seal(&key, &kp)?))
+            }
+            _ => StoredKeyPair::Plain(kp),
+        };
+        self.handles.register(stored)
+    }
+
+    pub fn get(&self, handle: Handle) -> Result<SignatureKeyPair, Error> {
+        let mut kp = match self.handles.get(handle)? {
+            StoredKeyPair::Plain(kp) => kp,
+            StoredKeyPair::Sealed(sealed) => {
+                let sealing_key = self.sealing_key.lock().clone();
+                let key = sealing_key.ok_or(CryptoError::AlgorithmFailure)?;
+                Self::open(&key, &sealed)?
+            }
+        };
+        if let SignatureKeyPair::ECDSA(kp) = &mut kp {
+            kp.drbg = self.drbg.lock().clone();
+        }
+        Ok(kp)
+    }
+
+    pub fn close(&self, handle: Handle) -> Result<(), Error> {
+        self.handles.close(handle)
+    }
+
+    pub fn handles(&self) -> Vec<Handle> {
+        self.handles
+            .snapshot()
+            .into_iter()
+            .map(|(handle, _)| handle)
+            .collect()
+    }
+
+    // Sealed key pairs are not decrypted; their information comes from the stored builder
+    pub fn for_each(&self, mut f: impl FnMut(Handle, &KeyPairInfo)) {
+        for (handle, stored) in self.handles.snapshot() {
+            let info = match stored {
+                StoredKeyPair::Plain(kp) => KeyPairInfo::new(&kp.builder(), false),
+                StoredKeyPair::Sealed(sealed) => KeyPairInfo::new(&sealed.builder, true),
+            };
+            f(handle, &info);
+        }
+    }
+}
+
+#[test]
+fn test_keys_encrypted_in_memory() {
+    let manager = KeyPairManager::new(0x02);
+    let kp = SignatureKeyPair::EdDSA(
+        crate::EdDSASignatureKeyPairBuilder::new(crate::SignatureAlgorithm::Ed25519)
+            .generate_owned()
+            .unwrap(),
+    );
+    let pkcs8 = kp.pkcs8().to_vec();
+    let plain_handle = manager.register(kp.clone()).unwrap();
+
+    manager.set_encryption(true).unwrap();
+    let handle = manager.register(kp.clone()).unwrap();
+    manager.set_encryption(false).unwrap();
+    let plain_handle2 = manager.register(kp).unwrap();
+
+    for &handle in &[plain_handle, plain_handle2] {
+        assert!(matches!(
+            manager.handles.get(handle).unwrap(),
+            StoredKeyPair::Plain(_)
+        ));
+    }
+    let sealed = match manager.handles.get(handle).unwrap() {
+        StoredKeyPair::Sealed(sealed) => sealed,
+        _ => panic!("key pair stored in the clear"),
+    };
+    assert_eq!(sealed.ciphertext.len(), pkcs8.len() + AES_256_GCM.tag_len());
+    assert_ne!(&sealed.ciphertext[..pkcs8.len()], &pkcs8[..]);
+    // The 32-byte seed shouldn't appear anywhere in the stored entry
+    let seed = &pkcs8[16..48];
+    assert!(!sealed
+        .ciphertext
+        .windows(seed.len())
+        .any(|window| window == seed));
+
+    // Sealed key pairs remain usable after new key pairs stop being encrypted
+    let kp = manager.get(handle).unwrap();
+    assert_eq!(kp.pkcs8(), &pkcs8[..]);
+    // Ed25519 signatures are deterministic
+    assert_eq!(
+        kp.sign_oneshot(b"test").unwrap().as_ref(),
+        manager
+            .get(plain_handle)
+            .unwrap()
+            .sign_oneshot(b"test")
+            .unwrap()
+            .as_ref()
+    );
+}
+
+#[test]
+fn test_decrypted_keypair_lifetime() {
+    use crate::{ExclusiveSignatureState, SignatureAlgorithm};
+
+    let manager = KeyPairManager::new(0x02);
+    manager.set_encryption(true).unwrap();
+    for kp in [
+        SignatureKeyPair::ECDSA(
+            crate::ECDSASignatureKeyPairBuilder::new(SignatureAlgorithm::ECDSA_P256_SHA256)
+                .generate_owned()
+                .unwrap(),
+        ),
+        SignatureKeyPair::EdDSA(
+            crate::EdDSASignatureKeyPairBuilder::new(SignatureAlgorithm::Ed25519)
+                .generate_owned()
+                .unwrap(),
+        ),
+    ] {
+        let handle = manager.register(kp).unwrap();
+        let kp = manager.get(handle).unwrap();
+        let pkcs8 = match &kp {
+            SignatureKeyPair::ECDSA(kp) => Arc::downgrade(&kp.pkcs8),
+            SignatureKeyPair::EdDSA(kp) => Arc::downgrade(&kp.pkcs8),
+            _ => unreachable!(),
+        };
+        let mut state = ExclusiveSignatureState::from_keypair(kp);
+
+        // The state holds the only decrypted copy, and still signs once the handle is closed
+        manager.close(handle).unwrap();
+        assert_eq!(pkcs8.strong_count(), 1);
+        state.update(b"test").unwrap();
+        state.sign().unwrap();
+        assert_eq!(pkcs8.strong_count(), 1);
+
+        // Closing the state releases and zeroizes it
+        drop(state);
+        assert!(pkcs8.upgrade().is_none());
+    }
+}
+
+#[test]
+fn test_keypair_enumeration() {
+    let manager = KeyPairManager::new(0x02);
+    let kp_builder = crate::EdDSASignatureKeyPairBuilder::new(crate::SignatureAlgorithm::Ed25519);
+    let mut handles = vec![];
+    for i in 0..5 {
+        manager.set_encryption(i % 2 == 1).unwrap();
+        let kp = SignatureKeyPair::EdDSA(kp_builder.generate_owned().unwrap());
+        handles.push(manager.register(kp).unwrap());
+    }
+    manager.close(handles.remove(0)).unwrap();
+    assert_eq!(manager.handles(), handles);
+
+    let mut encrypted = 0;
+    manager.for_each(|handle, info| {
+        assert!(handles.contains(&handle));
+        assert_eq!(info.alg, crate::SignatureAlgorithm::Ed25519);
+        assert!(info.exportable);
+        encrypted += info.encrypted_in_memory as usize;
+        // Registering while enumerating doesn't deadlock, and isn't visible to `for_each()`
+        let kp = SignatureKeyPair::EdDSA(kp_builder.generate_owned().unwrap());
+        manager.register(kp).unwrap();
+    });
+    assert_eq!(encrypted, 2);
+    assert_eq!(manager.handles().len(), 8);
+
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    assert!(crate::signature_keypair_handles().contains(&kp_handle));
+}
@@ -4,7 +4,30 @@ use super::error::*;
 use super::handles::*;
 use super::rsa::*;
 use super::signature::*;
-use super::WASI_CRYPTO_CTX;
+use super::{CryptoContext, WASI_CRYPTO_CTX};
+
+/// Options applying to the key pairs created from a signature operation.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SignatureOptions {
+    /// ECDSA: if the system RNG fails, use deterministic (RFC 6979) nonces instead of
+    /// returning `RNGError`. Requires the `deterministic` feature.
+    pub rng_fallback: bool,
+    /// EdDSA: verify signatures with the strict RFC 8032 rules, rejecting small-order `R`
+    /// components in addition to non-canonical encodings, so that a message only has one
+    /// valid signature per key. Requires the `strict` feature.
+    pub strict_verification: bool,
+    /// ECDSA and Ed25519_BLAKE2b512: only sign digests computed by the caller with
+    /// `signature_state_sign_digest()`, and refuse to absorb input. ECDSA requires the
+    /// `prehash` feature.
+    pub prehashed_only: bool,
+    /// Refuse to export key pairs created from this operation, in any encoding.
+    pub non_exportable: bool,
+    /// ECDSA: refuse to sign with `NotAvailable` once a key pair has produced this many
+    /// signatures, as a reminder to rotate it. Counters are not persisted, and a key pair that
+    /// is imported again starts from zero, unless it is imported with
+    /// `signature_keypair_import_with_counter_store()`.
+    pub max_signatures: Option<u64>,
+}
 
 #[derive(Clone, Copy, Debug)]
 pub enum SignatureOp {
@@ -22,38 +45,64 @@ impl SignatureOp {
         }
     }
 
-    fn open(alg_str: &str) -> Result<Handle, Error> {
+    pub(crate) fn open(
+        ctx: &CryptoContext,
+        alg_str: &str,
+        options: &SignatureOptions,
+    ) -> Result<Handle, Error> {
         let signature_op = match alg_str {
-            "ECDSA_P256_SHA256" => {
-                SignatureOp::ECDSA(ECDSASignatureOp::new(SignatureAlgorithm::ECDSA_P256_SHA256))
-            }
-            "ECDSA_P384_SHA384" => {
-                SignatureOp::ECDSA(ECDSASignatureOp::new(SignatureAlgorithm::ECDSA_P384_SHA384))
-            }
-            "Ed25519" => SignatureOp::EdDSA(EdDSASignatureOp::new(SignatureAlgorithm::Ed25519)),
-            "RSA_PKCS1_2048_8192_SHA256" => SignatureOp::RSA(RSASignatureOp::new(
+            "ECDSA_P256_SHA256" => SignatureOp::ECDSA(ECDSASignatureOp::with_options(
+                SignatureAlgorithm::ECDSA_P256_SHA256,
+                options,
+            )?),
+            "ECDSA_P384_SHA384" => SignatureOp::ECDSA(ECDSASignatureOp::with_options(
+                SignatureAlgorithm::ECDSA_P384_SHA384,
+                options,
+            )?),
+            "Ed25519" => SignatureOp::EdDSA(EdDSASignatureOp::with_options(
+                SignatureAlgorithm::Ed25519,
+                options,
+            )?),
+            "Ed25519_BLAKE2b512" => SignatureOp::EdDSA(EdDSASignatureOp::with_options(
+                SignatureAlgorithm::Ed25519_BLAKE2b512,
+                options,
+            )?),
+            "RSA_PKCS1_2048_8192_SHA256" => SignatureOp::RSA(RSASignatureOp::with_options(
                 SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256,
+                options,
             )),
-            "RSA_PKCS1_2048_8192_SHA384" => SignatureOp::RSA(RSASignatureOp::new(
+            "RSA_PKCS1_2048_8192_SHA384" => SignatureOp::RSA(RSASignatureOp::with_options(
                 SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA384,
+                options,
             )),
-            "RSA_PKCS1_2048_8192_SHA512" => SignatureOp::RSA(RSASignatureOp::new(
+            "RSA_PKCS1_2048_8192_SHA512" => SignatureOp::RSA(RSASignatureOp::with_options(
                 SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512,
+                options,
             )),
-            "RSA_PKCS1_3072_8192_SHA384" => SignatureOp::RSA(RSASignatureOp::new(
+            "RSA_PKCS1_3072_8192_SHA384" => SignatureOp::RSA(RSASignatureOp::with_options(
                 SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384,
+                options,
             )),
             _ => bail!(CryptoError::NotAvailable),
         };
-        let handle = WASI_CRYPTO_CTX
-            .signature_op_manager
-            .register(signature_op)?;
+        ensure!(signature_op.alg().is_supported(), CryptoError::NotAvailable);
+        if let SignatureOp::RSA(_) = signature_op {
+            ensure!(!options.prehashed_only, CryptoError::NotAvailable);
+        }
+        let handle = ctx.signature_op_manager.register(signature_op)?;
         Ok(handle)
     }
 }
 
 pub fn signature_op_open(alg_str: &str) -> Result<Handle, Error> {
-    SignatureOp::open(alg_str)
+    SignatureOp::open(&WASI_CRYPTO_CTX, alg_str, &SignatureOptions::default())
+}
+
+pub fn signature_op_open_with_options(
+    alg_str: &str,
+    options: &SignatureOptions,
+) -> Result<Handle, Error> {
+    SignatureOp::open(&WASI_CRYPTO_CTX, alg_str, options)
 }
 
 pub fn signature_op_close(handle: Handle) -> Result<(), Error> {
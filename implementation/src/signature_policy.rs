@@ -0,0 +1,108 @@
+use alloc::vec::Vec;
+
+use super::error::*;
+use super::signature::*;
+use super::WASI_CRYPTO_CTX;
+
+/// The algorithms that key pairs and public keys can be generated or imported for.
+/// The default policy allows every algorithm.
+#[derive(Clone, Debug, Default)]
+pub struct AlgorithmPolicy {
+    allowed: Option<Vec<SignatureAlgorithm>>,
+}
+
+impl AlgorithmPolicy {
+    /// A policy that only allows the given algorithms.
+    pub fn allow_only(algs: &[SignatureAlgorithm]) -> Self {
+        AlgorithmPolicy {
+            allowed: Some(algs.to_vec()),
+        }
+    }
+
+    pub fn allows(&self, alg: SignatureAlgorithm) -> bool {
+        match &self.allowed {
+            None => true,
+            Some(allowed) => allowed.contains(&alg),
+        }
+    }
+
+    pub fn check(&self, alg: SignatureAlgorithm) -> Result<(), Error> {
+        ensure!(self.allows(alg), CryptoError::PolicyViolation);
+        Ok(())
+    }
+}
+
+/// Replace the policy consulted by every key generation and import. Handles that already
+/// exist are not affected.
+pub fn signature_set_algorithm_policy(policy: AlgorithmPolicy) {
+    *WASI_CRYPTO_CTX.signature_algorithm_policy.write() = policy;
+}
+
+pub fn check_algorithm_policy(alg: SignatureAlgorithm) -> Result<(), Error> {
+    WASI_CRYPTO_CTX.signature_algorithm_policy.read().check(alg)
+}
+
+#[test]
+fn test_algorithm_policy() {
+    let policy = AlgorithmPolicy::default();
+    policy
+        .check(SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512)
+        .unwrap();
+
+    let policy = AlgorithmPolicy::allow_only(&[SignatureAlgorithm::Ed25519]);
+    policy.check(SignatureAlgorithm::Ed25519).unwrap();
+    let err = policy
+        .check(SignatureAlgorithm::ECDSA_P256_SHA256)
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::PolicyViolation)
+    ));
+}
+
+#[test]
+fn test_import_rejected_by_policy() {
+    // A private context, since other tests use the default one concurrently
+    let ctx = crate::CryptoContext::new().unwrap();
+    *ctx.signature_algorithm_policy.write() =
+        AlgorithmPolicy::allow_only(&[SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256]);
+    let assert_policy_violation = |err: Error| {
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::PolicyViolation)
+        ));
+    };
+
+    let raw_pk = [0x42; 270];
+    let disallowed_op = ctx.signature_op_open("RSA_PKCS1_2048_8192_SHA512").unwrap();
+    assert_policy_violation(
+        ctx.signature_publickey_import(disallowed_op, &raw_pk, crate::PublicKeyEncoding::Raw)
+            .unwrap_err(),
+    );
+    let kp_builder_handle = ctx.signature_keypair_builder_open(disallowed_op).unwrap();
+    assert_policy_violation(
+        ctx.signature_keypair_generate(kp_builder_handle)
+            .unwrap_err(),
+    );
+
+    let ed25519_op = ctx.signature_op_open("Ed25519").unwrap();
+    let kp_builder = ctx
+        .signature_keypair_builder_manager
+        .get(ctx.signature_keypair_builder_open(ed25519_op).unwrap())
+        .unwrap();
+    assert_policy_violation(
+        kp_builder
+            .generate_with_public_key(&ctx, crate::PublicKeyEncoding::Raw)
+            .unwrap_err(),
+    );
+    assert!(ctx.signature_keypair_manager.handles().is_empty());
+
+    let allowed_op = ctx.signature_op_open("RSA_PKCS1_2048_8192_SHA256").unwrap();
+    ctx.signature_publickey_import(allowed_op, &raw_pk, crate::PublicKeyEncoding::Raw)
+        .unwrap();
+
+    // The default context keeps its own policy
+    kp_builder
+        .generate_with_public_key(&WASI_CRYPTO_CTX, crate::PublicKeyEncoding::Raw)
+        .unwrap();
+}
@@ -1,10 +1,21 @@
+use alloc::vec::Vec;
+
 use super::ecdsa::*;
 use super::eddsa::*;
 use super::error::*;
+use super::framing;
 use super::handles::*;
 use super::rsa::*;
+use super::signature::*;
 use super::signature_op::*;
-use super::WASI_CRYPTO_CTX;
+use super::signature_policy::*;
+use super::signature_publickey_encoding;
+use super::{CryptoContext, WASI_CRYPTO_CTX};
+
+#[cfg(test)]
+std::thread_local! {
+    static PARSES: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+}
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[repr(u16)]
@@ -15,6 +26,23 @@ pub enum PublicKeyEncoding {
     Base64OriginalNoPadding = 4,
     Base64URLSafe = 5,
     Base64URLSafeNoPadding = 6,
+    /// A SEC1 point with only the `x` coordinate, for ECDSA keys.
+    CompressedSEC1 = 7,
+    /// A DER-encoded X.509 `SubjectPublicKeyInfo`.
+    SPKI = 8,
+    /// A `SubjectPublicKeyInfo` in a `PUBLIC KEY` PEM block.
+    PEM = 9,
+    /// A JSON Web Key.
+    JWK = 10,
+}
+
+/// Formats for a list of raw public keys, such as a trust store.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PublicKeyListFormat {
+    /// One standard base64-encoded key per line. Empty lines are ignored.
+    Base64Lines,
+    /// Keys prefixed with their length as a 64-bit big-endian integer.
+    LengthPrefixed,
 }
 
 #[derive(Clone, Debug)]
@@ -25,45 +53,87 @@ pub enum SignaturePublicKey {
 }
 
 impl SignaturePublicKey {
-    fn import(
+    pub fn alg(&self) -> SignatureAlgorithm {
+        match self {
+            SignaturePublicKey::ECDSA(pk) => pk.alg,
+            SignaturePublicKey::EdDSA(pk) => pk.alg,
+            SignaturePublicKey::RSA(pk) => pk.alg,
+        }
+    }
+
+    // Counts the keys parsed by the current thread, so that tests can observe cache hits
+    #[cfg(test)]
+    fn parses() -> usize {
+        PARSES.with(core::cell::Cell::get)
+    }
+
+    pub(crate) fn from_raw(signature_op: &SignatureOp, encoded: &[u8]) -> Result<Self, Error> {
+        let alg = signature_op.alg();
+        let pk = match signature_op {
+            SignatureOp::ECDSA(_) => {
+                SignaturePublicKey::ECDSA(ECDSASignaturePublicKey::from_raw(alg, encoded)?)
+            }
+            SignatureOp::EdDSA(op) => {
+                let mut pk = EdDSASignaturePublicKey::from_raw(alg, encoded)?;
+                pk.strict_verification = op.strict_verification;
+                SignaturePublicKey::EdDSA(pk)
+            }
+            SignatureOp::RSA(_) => {
+                SignaturePublicKey::RSA(RSASignaturePublicKey::from_raw(alg, encoded)?)
+            }
+        };
+        Ok(pk)
+    }
+
+    // Parse a raw public key, or reuse the result of a previous import of the same key
+    fn from_raw_cached(
+        ctx: &CryptoContext,
+        signature_op: &SignatureOp,
+        encoded: &[u8],
+    ) -> Result<Self, Error> {
+        let cache = &ctx.signature_publickey_cache;
+        let alg = signature_op.alg();
+        let strict_verification = match signature_op {
+            SignatureOp::EdDSA(op) => op.strict_verification,
+            SignatureOp::ECDSA(_) | SignatureOp::RSA(_) => false,
+        };
+        if let Some(pk) = cache.get(alg, strict_verification, encoded) {
+            return Ok(pk);
+        }
+        #[cfg(test)]
+        PARSES.with(|parses| parses.set(parses.get() + 1));
+        let pk = Self::from_raw(signature_op, encoded)?;
+        cache.insert(alg, strict_verification, encoded, &pk);
+        Ok(pk)
+    }
+
+    pub(crate) fn import(
+        ctx: &CryptoContext,
         signature_op: Handle,
         encoded: &[u8],
         encoding: PublicKeyEncoding,
     ) -> Result<Handle, Error> {
-        match encoding {
-            PublicKeyEncoding::Raw => {}
-            _ => bail!(CryptoError::NotAvailable),
-        }
-        let signature_op = WASI_CRYPTO_CTX.signature_op_manager.get(signature_op)?;
-        let pk =
-            match signature_op {
-                SignatureOp::ECDSA(_) => SignaturePublicKey::ECDSA(
-                    ECDSASignaturePublicKey::from_raw(signature_op.alg(), encoded)?,
-                ),
-                SignatureOp::EdDSA(_) => SignaturePublicKey::EdDSA(
-                    EdDSASignaturePublicKey::from_raw(signature_op.alg(), encoded)?,
-                ),
-                SignatureOp::RSA(_) => SignaturePublicKey::RSA(RSASignaturePublicKey::from_raw(
-                    signature_op.alg(),
-                    encoded,
-                )?),
-            };
-        let handle = WASI_CRYPTO_CTX.signature_publickey_manager.register(pk)?;
-        Ok(handle)
+        let signature_op = ctx.signature_op_manager.get(signature_op)?;
+        ctx.signature_algorithm_policy
+            .read()
+            .check(signature_op.alg())?;
+        let raw = signature_publickey_encoding::decode(signature_op.alg(), encoded, encoding)?;
+        let pk = Self::from_raw_cached(ctx, &signature_op, &raw)?;
+        ctx.signature_publickey_manager.register(pk)
     }
 
-    fn export(pk: Handle, encoding: PublicKeyEncoding) -> Result<Vec<u8>, Error> {
-        match encoding {
-            PublicKeyEncoding::Raw => {}
-            _ => bail!(CryptoError::NotAvailable),
-        }
-        let pk = WASI_CRYPTO_CTX.signature_publickey_manager.get(pk)?;
-        let raw_pk = match pk {
-            SignaturePublicKey::ECDSA(pk) => pk.as_raw()?.to_vec(),
-            SignaturePublicKey::EdDSA(pk) => pk.as_raw()?.to_vec(),
-            SignaturePublicKey::RSA(pk) => pk.as_raw()?.to_vec(),
+    pub(crate) fn export(
+        ctx: &CryptoContext,
+        pk: Handle,
+        encoding: PublicKeyEncoding,
+    ) -> Result<Vec<u8>, Error> {
+        let pk = ctx.signature_publickey_manager.get(pk)?;
+        let raw_pk = match &pk {
+            SignaturePublicKey::ECDSA(pk) => pk.as_raw()?,
+            SignaturePublicKey::EdDSA(pk) => pk.as_raw()?,
+            SignaturePublicKey::RSA(pk) => pk.as_raw()?,
         };
-        Ok(raw_pk)
+        signature_publickey_encoding::encode(pk.alg(), raw_pk, encoding)
     }
 }
 
@@ -72,16 +142,347 @@ pub fn signature_publickey_import(
     encoded: &[u8],
     encoding: PublicKeyEncoding,
 ) -> Result<Handle, Error> {
-    SignaturePublicKey::import(signature_op, encoded, encoding)
+    SignaturePublicKey::import(&WASI_CRYPTO_CTX, signature_op, encoded, encoding)
 }
 
 pub fn signature_publickey_export(
     pk: Handle,
     encoding: PublicKeyEncoding,
 ) -> Result<Vec<u8>, Error> {
-    SignaturePublicKey::export(pk, encoding)
+    SignaturePublicKey::export(&WASI_CRYPTO_CTX, pk, encoding)
 }
 
 pub fn signature_publickey_close(handle: Handle) -> Result<(), Error> {
-    WASI_CRYPTO_CTX.signature_publickey_manager.close(handle)
+    WASI_CRYPTO_CTX.signature_publickey_close(handle)
+}
+
+/// Import a list of raw public keys for the same algorithm, and return their handles in
+/// order. Either all keys are imported, or none of them are; the error for a malformed entry
+/// includes its index.
+pub fn signature_publickey_import_list(
+    signature_op: Handle,
+    data: &[u8],
+    format: PublicKeyListFormat,
+) -> Result<Vec<Handle>, Error> {
+    let signature_op = WASI_CRYPTO_CTX.signature_op_manager.get(signature_op)?;
+    check_algorithm_policy(signature_op.alg())?;
+    let mut entries = vec![];
+    match format {
+        PublicKeyListFormat::Base64Lines => {
+            let lines = data
+                .split(|&x| x == b'\n')
+                .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+                .filter(|line| !line.is_empty());
+            for (index, line) in lines.enumerate() {
+                let encoded = base64::decode(line)
+                    .map_err(|_| CryptoError::InvalidKey)
+                    .with_context(|| format!("Malformed public key at index {}", index))?;
+                entries.push(encoded);
+            }
+        }
+        PublicKeyListFormat::LengthPrefixed => {
+            let mut data = data;
+            while !data.is_empty() {
+                let encoded = framing::read_framed(&mut data)
+                    .ok_or(CryptoError::InvalidKey)
+                    .with_context(|| format!("Malformed public key at index {}", entries.len()))?;
+                entries.push(encoded.to_vec());
+            }
+        }
+    }
+    let mut pks = vec![];
+    for (index, encoded) in entries.iter().enumerate() {
+        let pk = SignaturePublicKey::from_raw_cached(&WASI_CRYPTO_CTX, &signature_op, encoded)
+            .with_context(|| format!("Malformed public key at index {}", index))?;
+        pks.push(pk);
+    }
+    pks.into_iter()
+        .map(|pk| WASI_CRYPTO_CTX.signature_publickey_manager.register(pk))
+        .collect()
+}
+
+/// Set the number of imported public keys to remember, so that importing the same key again
+/// doesn't parse it again. Every import still returns a new handle, that can be closed
+/// independently of the others. `0` disables the cache.
+pub fn signature_publickey_cache_set_capacity(capacity: usize) {
+    WASI_CRYPTO_CTX
+        .signature_publickey_cache
+        .set_capacity(capacity)
+}
+
+#[test]
+fn test_publickey_cache() {
+    // A private context, since the capacity would otherwise apply to concurrent tests
+    let ctx = CryptoContext::new().unwrap();
+    let op_handle = ctx.signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = ctx.signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = ctx.signature_keypair_generate(kp_builder_handle).unwrap();
+    let pk_handle = ctx.signature_keypair_publickey(kp_handle).unwrap();
+    let raw_pk = ctx
+        .signature_publickey_export(pk_handle, PublicKeyEncoding::Raw)
+        .unwrap();
+
+    let import = || {
+        ctx.signature_publickey_import(op_handle, &raw_pk, PublicKeyEncoding::Raw)
+            .unwrap()
+    };
+    ctx.signature_publickey_cache.set_capacity(16);
+    let parses = SignaturePublicKey::parses();
+    let pk_handle = import();
+    assert_eq!(SignaturePublicKey::parses(), parses + 1);
+    let pk_handle2 = import();
+    assert_eq!(SignaturePublicKey::parses(), parses + 1);
+    assert_ne!(pk_handle, pk_handle2);
+
+    // Closing a handle doesn't affect the other importers
+    ctx.signature_publickey_close(pk_handle).unwrap();
+    assert!(ctx.signature_publickey_manager.get(pk_handle).is_err());
+    assert_eq!(
+        ctx.signature_publickey_export(pk_handle2, PublicKeyEncoding::Raw)
+            .unwrap(),
+        raw_pk
+    );
+    let pk_handle3 = import();
+    assert_ne!(pk_handle3, pk_handle2);
+    ctx.signature_publickey_manager.get(pk_handle3).unwrap();
+    assert_eq!(SignaturePublicKey::parses(), parses + 1);
+
+    // Cached keys are returned until they are evicted
+    let cache = &ctx.signature_publickey_cache;
+    assert!(cache
+        .get(SignatureAlgorithm::Ed25519, false, &raw_pk)
+        .is_some());
+    cache.set_capacity(0);
+    assert!(cache
+        .get(SignatureAlgorithm::Ed25519, false, &raw_pk)
+        .is_none());
+    import();
+    assert_eq!(SignaturePublicKey::parses(), parses + 2);
+    import();
+    assert_eq!(SignaturePublicKey::parses(), parses + 3);
+    assert!(cache
+        .get(SignatureAlgorithm::Ed25519, false, &raw_pk)
+        .is_none());
+}
+
+#[test]
+fn test_publickey_cache_options() {
+    if !cfg!(feature = "strict") {
+        return;
+    }
+    let ctx = CryptoContext::new().unwrap();
+    ctx.signature_publickey_cache.set_capacity(16);
+    let lenient_op_handle = ctx.signature_op_open("Ed25519").unwrap();
+    let strict_options = SignatureOptions {
+        strict_verification: true,
+        ..Default::default()
+    };
+    let strict_op_handle = ctx
+        .signature_op_open_with_options("Ed25519", &strict_options)
+        .unwrap();
+    let kp_builder_handle = ctx
+        .signature_keypair_builder_open(lenient_op_handle)
+        .unwrap();
+    let kp_handle = ctx.signature_keypair_generate(kp_builder_handle).unwrap();
+    let pk_handle = ctx.signature_keypair_publickey(kp_handle).unwrap();
+    let raw_pk = ctx
+        .signature_publickey_export(pk_handle, PublicKeyEncoding::Raw)
+        .unwrap();
+
+    let import = |op_handle| {
+        ctx.signature_publickey_import(op_handle, &raw_pk, PublicKeyEncoding::Raw)
+            .unwrap()
+    };
+    let lenient_pk_handle = import(lenient_op_handle);
+    let strict_pk_handle = import(strict_op_handle);
+    for (pk_handle, strict_verification) in [
+        (lenient_pk_handle, false),
+        (strict_pk_handle, true),
+        (import(lenient_op_handle), false),
+        (import(strict_op_handle), true),
+    ] {
+        match ctx.signature_publickey_manager.get(pk_handle).unwrap() {
+            SignaturePublicKey::EdDSA(pk) => {
+                assert_eq!(pk.strict_verification, strict_verification)
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[test]
+fn test_publickey_import_list() {
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let mut raw_pks = vec![];
+    for _ in 0..3 {
+        let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+        let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+        raw_pks.push(signature_publickey_export(pk_handle, PublicKeyEncoding::Raw).unwrap());
+    }
+
+    let lines: Vec<String> = raw_pks.iter().map(base64::encode).collect();
+    let base64_lines = format!("{}\n{}\r\n\n{}\n", lines[0], lines[1], lines[2]);
+    let mut length_prefixed = vec![];
+    for raw_pk in &raw_pks {
+        framing::append_framed(&mut length_prefixed, raw_pk);
+    }
+    for (data, format) in &[
+        (base64_lines.as_bytes(), PublicKeyListFormat::Base64Lines),
+        (&length_prefixed[..], PublicKeyListFormat::LengthPrefixed),
+    ] {
+        let pk_handles = signature_publickey_import_list(op_handle, data, *format).unwrap();
+        assert_eq!(pk_handles.len(), 3);
+        for (pk_handle, raw_pk) in pk_handles.iter().zip(&raw_pks) {
+            assert_eq!(
+                &signature_publickey_export(*pk_handle, PublicKeyEncoding::Raw).unwrap(),
+                raw_pk
+            );
+        }
+    }
+
+    let malformed = format!("{}\n{}\n!!!\n", lines[0], lines[1]);
+    let err = signature_publickey_import_list(
+        op_handle,
+        malformed.as_bytes(),
+        PublicKeyListFormat::Base64Lines,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("index 2"));
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::InvalidKey)
+    ));
+
+    let mut malformed = vec![];
+    framing::append_framed(&mut malformed, &raw_pks[0]);
+    framing::append_framed(&mut malformed, &raw_pks[1][..31]);
+    let err =
+        signature_publickey_import_list(op_handle, &malformed, PublicKeyListFormat::LengthPrefixed)
+            .unwrap_err();
+    assert!(err.to_string().contains("index 1"));
+    let err = signature_publickey_import_list(
+        op_handle,
+        &length_prefixed[..length_prefixed.len() - 1],
+        PublicKeyListFormat::LengthPrefixed,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("index 2"));
+}
+
+#[test]
+fn test_publickey_encodings() {
+    let encodings = [
+        PublicKeyEncoding::Raw,
+        PublicKeyEncoding::Hex,
+        PublicKeyEncoding::Base64Original,
+        PublicKeyEncoding::Base64OriginalNoPadding,
+        PublicKeyEncoding::Base64URLSafe,
+        PublicKeyEncoding::Base64URLSafeNoPadding,
+        PublicKeyEncoding::CompressedSEC1,
+        PublicKeyEncoding::SPKI,
+        PublicKeyEncoding::PEM,
+        PublicKeyEncoding::JWK,
+    ];
+    for alg_str in &["Ed25519", "ECDSA_P256_SHA256", "ECDSA_P384_SHA384"] {
+        let op_handle = crate::signature_op_open(alg_str).unwrap();
+        let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+        let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+        let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+        let raw_pk = signature_publickey_export(pk_handle, PublicKeyEncoding::Raw).unwrap();
+        let is_ecdsa = alg_str.starts_with("ECDSA");
+        for &encoding in &encodings {
+            let exported = signature_publickey_export(pk_handle, encoding);
+            if encoding == PublicKeyEncoding::CompressedSEC1 && !is_ecdsa {
+                assert!(exported.is_err());
+                continue;
+            }
+            let exported = exported.unwrap();
+            let imported = signature_publickey_import(op_handle, &exported, encoding);
+            if encoding == PublicKeyEncoding::CompressedSEC1 && !cfg!(feature = "p256") {
+                assert!(imported.is_err());
+                continue;
+            }
+            assert_eq!(
+                signature_publickey_export(imported.unwrap(), PublicKeyEncoding::Raw).unwrap(),
+                raw_pk
+            );
+        }
+
+        let spki = signature_publickey_export(pk_handle, PublicKeyEncoding::SPKI).unwrap();
+        let pk = WASI_CRYPTO_CTX
+            .signature_publickey_manager
+            .get(pk_handle)
+            .unwrap();
+        let expected_spki = match pk {
+            SignaturePublicKey::ECDSA(pk) => pk.as_spki().unwrap(),
+            SignaturePublicKey::EdDSA(pk) => pk.as_spki().unwrap(),
+            SignaturePublicKey::RSA(_) => unreachable!(),
+        };
+        assert_eq!(spki, expected_spki);
+        let other_op_handle = crate::signature_op_open(if is_ecdsa {
+            "Ed25519"
+        } else {
+            "ECDSA_P256_SHA256"
+        })
+        .unwrap();
+        assert!(
+            signature_publickey_import(other_op_handle, &spki, PublicKeyEncoding::SPKI).is_err()
+        );
+    }
+
+    // RFC 7515, appendix A.3
+    let op_handle = crate::signature_op_open("ECDSA_P256_SHA256").unwrap();
+    let jwk = br#"{"kty": "EC", "crv": "P-256",
+        "x": "f83OJ3D2xF1Bg8vub9tLe1gHMzV76e8Tus9uPHvRVEU",
+        "y": "x_FEzRu9m36HLN_tue659LNpXW6pCyStikYjKIWI5a0"}"#;
+    let pk_handle = signature_publickey_import(op_handle, jwk, PublicKeyEncoding::JWK).unwrap();
+    let raw_pk = signature_publickey_export(pk_handle, PublicKeyEncoding::Raw).unwrap();
+    assert_eq!(
+        &raw_pk[..5],
+        &[0x04, 0x7f, 0xcd, 0xce, 0x27][..],
+        "{:02x?}",
+        raw_pk
+    );
+    let pem = signature_publickey_export(pk_handle, PublicKeyEncoding::PEM).unwrap();
+    assert!(pem.starts_with(b"-----BEGIN PUBLIC KEY-----\nMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE"));
+
+    // RSA keys, in every encoding but compressed SEC1
+    let op_handle = crate::signature_op_open("RSA_PKCS1_2048_8192_SHA256").unwrap();
+    let raw_pk = base64::decode(crate::self_test::RSA_PUBLIC_KEY).unwrap();
+    let pk_handle = signature_publickey_import(op_handle, &raw_pk, PublicKeyEncoding::Raw).unwrap();
+    for &encoding in &encodings {
+        let exported = signature_publickey_export(pk_handle, encoding);
+        if encoding == PublicKeyEncoding::CompressedSEC1 {
+            assert!(exported.is_err());
+            continue;
+        }
+        let imported = signature_publickey_import(op_handle, &exported.unwrap(), encoding);
+        assert_eq!(
+            signature_publickey_export(imported.unwrap(), PublicKeyEncoding::Raw).unwrap(),
+            raw_pk
+        );
+    }
+    let spki = signature_publickey_export(pk_handle, PublicKeyEncoding::SPKI).unwrap();
+    assert_eq!(
+        &spki[..24],
+        &[
+            0x30, 0x82, 0x01, 0xa2, 0x30, 0x0d, 0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d,
+            0x01, 0x01, 0x01, 0x05, 0x00, 0x03, 0x82, 0x01, 0x8f, 0x00
+        ][..]
+    );
+    assert_eq!(&spki[24..], &raw_pk[..]);
+    let other_op_handle = crate::signature_op_open("ECDSA_P256_SHA256").unwrap();
+    assert!(signature_publickey_import(other_op_handle, &spki, PublicKeyEncoding::SPKI).is_err());
+
+    // RFC 7515, appendix A.2
+    let jwk = br#"{"kty": "RSA", "e": "AQAB",
+        "n": "ofgWCuLjybRlzo0tZWJjNiuSfb4p4fAkd_wWJcyQoTbji9k0l8W26mPddxHmfHQp-Vaw-4qPCJrcS2mJPMEzP1Pt0Bm4d4QlL-yRT-SFd2lZS-pCgNMsD1W_YpRPEwOWvG6b32690r2jZ47soMZo9wGzjb_7OMg0LOL-bSf63kpaSHSXndS5z5rexMdbBYUsLA9e-KXBdQOS-UTo7WTBEMa2R2CapHg665xsmtdVMTBQY4uDZlxvb3qCo5ZwKh9kG4LT6_I5IhlJH7aGhyxXFvUK-DWNmoudF8NAco9_h9iaGNj8q2ethFkMLs91kzk2PAcDTW9gb54h4FRWyuXpoQ"}"#;
+    let pk_handle = signature_publickey_import(op_handle, jwk, PublicKeyEncoding::JWK).unwrap();
+    let pem = signature_publickey_export(pk_handle, PublicKeyEncoding::PEM).unwrap();
+    assert!(pem.starts_with(
+        b"-----BEGIN PUBLIC KEY-----\nMIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEAofgWCuLjybRl"
+    ));
+    let ec_jwk = br#"{"kty": "EC", "crv": "P-256", "n": "AQAB", "e": "AQAB"}"#;
+    assert!(signature_publickey_import(op_handle, ec_jwk, PublicKeyEncoding::JWK).is_err());
 }
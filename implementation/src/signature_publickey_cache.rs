@@ -0,0 +1,84 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use super::signature::*;
+use super::signature_publickey::SignaturePublicKey;
+use super::sync::Mutex;
+
+struct PublicKeyCacheEntry {
+    alg: SignatureAlgorithm,
+    strict_verification: bool,
+    encoded: Vec<u8>,
+    pk: SignaturePublicKey,
+}
+
+struct PublicKeyCacheInner {
+    capacity: usize,
+    entries: VecDeque<PublicKeyCacheEntry>,
+}
+
+/// Maps encoded public keys to their parsed form, evicting the least recently used entries
+/// first. Keys imported with different verification options are cached separately.
+/// A capacity of `0`, the default, disables the cache.
+pub struct PublicKeyCache {
+    inner: Mutex<PublicKeyCacheInner>,
+}
+
+impl PublicKeyCache {
+    pub fn new(capacity: usize) -> Self {
+        PublicKeyCache {
+            inner: Mutex::new(PublicKeyCacheInner {
+                capacity,
+                entries: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut inner = self.inner.lock();
+        inner.capacity = capacity;
+        while inner.entries.len() > capacity {
+            inner.entries.pop_front();
+        }
+    }
+
+    pub fn get(
+        &self,
+        alg: SignatureAlgorithm,
+        strict_verification: bool,
+        encoded: &[u8],
+    ) -> Option<SignaturePublicKey> {
+        let mut inner = self.inner.lock();
+        let index = inner.entries.iter().position(|entry| {
+            entry.alg == alg
+                && entry.strict_verification == strict_verification
+                && entry.encoded == encoded
+        })?;
+        let entry = inner.entries.remove(index)?;
+        let pk = entry.pk.clone();
+        inner.entries.push_back(entry);
+        Some(pk)
+    }
+
+    pub fn insert(
+        &self,
+        alg: SignatureAlgorithm,
+        strict_verification: bool,
+        encoded: &[u8],
+        pk: &SignaturePublicKey,
+    ) {
+        let mut inner = self.inner.lock();
+        if inner.capacity == 0 {
+            return;
+        }
+        if inner.entries.len() >= inner.capacity {
+            inner.entries.pop_front();
+        }
+        inner.entries.push_back(PublicKeyCacheEntry {
+            alg,
+            strict_verification,
+            encoded: encoded.to_vec(),
+            pk: pk.clone(),
+        });
+    }
+}
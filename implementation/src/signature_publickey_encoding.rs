@@ -0,0 +1,315 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use serde_json::{json, Value};
+
+use super::der;
+use super::ecdsa::*;
+use super::error::*;
+use super::signature::*;
+
+use super::signature_publickey::PublicKeyEncoding;
+
+const PEM_LABEL: &str = "PUBLIC KEY";
+
+enum KeyType {
+    EC {
+        curve_oid: &'static [u8],
+        jwk_crv: &'static str,
+        coordinate_len: usize,
+    },
+    Ed25519,
+    RSA,
+}
+
+impl KeyType {
+    fn from_alg(alg: SignatureAlgorithm) -> Result<Self, Error> {
+        let key_type = match alg {
+            SignatureAlgorithm::ECDSA_P256_SHA256 => KeyType::EC {
+                curve_oid: der::OID_PRIME256V1,
+                jwk_crv: "P-256",
+                coordinate_len: ECDSASignatureOp::coordinate_len(alg)?,
+            },
+            SignatureAlgorithm::ECDSA_P384_SHA384 => KeyType::EC {
+                curve_oid: der::OID_SECP384R1,
+                jwk_crv: "P-384",
+                coordinate_len: ECDSASignatureOp::coordinate_len(alg)?,
+            },
+            SignatureAlgorithm::Ed25519 | SignatureAlgorithm::Ed25519_BLAKE2b512 => {
+                KeyType::Ed25519
+            }
+            SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA256
+            | SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA384
+            | SignatureAlgorithm::RSA_PKCS1_2048_8192_SHA512
+            | SignatureAlgorithm::RSA_PKCS1_3072_8192_SHA384 => KeyType::RSA,
+        };
+        Ok(key_type)
+    }
+
+    fn spki_alg_id(&self) -> Vec<u8> {
+        match self {
+            KeyType::EC { curve_oid, .. } => {
+                der::sequence(&[&der::oid(der::OID_EC_PUBLIC_KEY), &der::oid(curve_oid)])
+            }
+            KeyType::Ed25519 => der::sequence(&[&der::oid(der::OID_ED25519)]),
+            KeyType::RSA => der::sequence(&[
+                &der::oid(der::OID_RSA_ENCRYPTION),
+                &der::encode(der::TAG_NULL, &[]),
+            ]),
+        }
+    }
+}
+
+fn base64_config(encoding: PublicKeyEncoding) -> Option<base64::Config> {
+    let config = match encoding {
+        PublicKeyEncoding::Base64Original => base64::STANDARD,
+        PublicKeyEncoding::Base64OriginalNoPadding => base64::STANDARD_NO_PAD,
+        PublicKeyEncoding::Base64URLSafe => base64::URL_SAFE,
+        PublicKeyEncoding::Base64URLSafeNoPadding => base64::URL_SAFE_NO_PAD,
+        _ => return None,
+    };
+    Some(config)
+}
+
+fn decode_hex(encoded: &[u8]) -> Result<Vec<u8>, Error> {
+    ensure!(encoded.len().is_multiple_of(2), CryptoError::InvalidKey);
+    encoded
+        .chunks(2)
+        .map(|pair| {
+            let pair = core::str::from_utf8(pair).map_err(|_| CryptoError::InvalidKey)?;
+            Ok(u8::from_str_radix(pair, 16).map_err(|_| CryptoError::InvalidKey)?)
+        })
+        .collect()
+}
+
+fn decode_spki(key_type: &KeyType, spki: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut decoder = der::Decoder::new(spki);
+    let mut spki_content = der::Decoder::new(decoder.read(der::TAG_SEQUENCE)?);
+    decoder.finish()?;
+    let alg_id = spki_content.read(der::TAG_SEQUENCE)?;
+    let public_key = spki_content.read(der::TAG_BIT_STRING)?;
+    spki_content.finish()?;
+    ensure!(
+        der::encode(der::TAG_SEQUENCE, alg_id) == key_type.spki_alg_id(),
+        CryptoError::InvalidKey
+    );
+    ensure!(public_key.first() == Some(&0x00), CryptoError::InvalidKey);
+    Ok(public_key[1..].to_vec())
+}
+
+fn decode_pem(pem: &[u8]) -> Result<Vec<u8>, Error> {
+    let pem = core::str::from_utf8(pem).map_err(|_| CryptoError::InvalidKey)?;
+    let begin = format!("-----BEGIN {}-----", PEM_LABEL);
+    let end = format!("-----END {}-----", PEM_LABEL);
+    let mut lines = pem.lines().map(str::trim).filter(|line| !line.is_empty());
+    ensure!(
+        lines.next() == Some(begin.as_str()),
+        CryptoError::InvalidKey
+    );
+    let mut encoded = String::new();
+    for line in lines.by_ref() {
+        if line == end {
+            ensure!(lines.next().is_none(), CryptoError::InvalidKey);
+            return Ok(base64::decode(&encoded).map_err(|_| CryptoError::InvalidKey)?);
+        }
+        encoded.push_str(line);
+    }
+    bail!(CryptoError::InvalidKey)
+}
+
+// Extract the raw public key of a JSON Web Key, or return `None` if it is not a key of
+// `key_type`
+fn jwk_raw_public_key(key_type: &KeyType, jwk: &Value) -> Result<Option<Vec<u8>>, Error> {
+    let field = |name: &str| -> Result<Vec<u8>, Error> {
+        let encoded = jwk
+            .get(name)
+            .and_then(Value::as_str)
+            .ok_or(CryptoError::InvalidKey)?;
+        Ok(base64::decode_config(encoded, base64::URL_SAFE_NO_PAD)
+            .map_err(|_| CryptoError::InvalidKey)?)
+    };
+    let kty = jwk.get("kty").and_then(Value::as_str);
+    let crv = jwk.get("crv").and_then(Value::as_str);
+    let raw = match key_type {
+        KeyType::EC {
+            jwk_crv,
+            coordinate_len,
+            ..
+        } => {
+            if kty != Some("EC") || crv != Some(jwk_crv) {
+                return Ok(None);
+            }
+            let (x, y) = (field("x")?, field("y")?);
+            ensure!(
+                x.len() == *coordinate_len && y.len() == *coordinate_len,
+                CryptoError::InvalidKey
+            );
+            [&[0x04][..], &x, &y].concat()
+        }
+        KeyType::Ed25519 => {
+            if kty != Some("OKP") || crv != Some("Ed25519") {
+                return Ok(None);
+            }
+            field("x")?
+        }
+        KeyType::RSA => {
+            if kty != Some("RSA") {
+                return Ok(None);
+            }
+            der::sequence(&[
+                &der::unsigned_integer(&field("n")?),
+                &der::unsigned_integer(&field("e")?),
+            ])
+        }
+    };
+    Ok(Some(raw))
+}
+
+/// Decode a parsed JSON Web Key into a raw public key for `alg`. Return `None` if its key
+/// type or curve is not the one of `alg`, and `InvalidKey` if it is, but is malformed.
+pub(crate) fn decode_jwk_value(
+    alg: SignatureAlgorithm,
+    jwk: &Value,
+) -> Result<Option<Vec<u8>>, Error> {
+    jwk_raw_public_key(&KeyType::from_alg(alg)?, jwk)
+}
+
+fn decode_jwk(key_type: &KeyType, jwk: &[u8]) -> Result<Vec<u8>, Error> {
+    let jwk: Value = serde_json::from_slice(jwk).map_err(|_| CryptoError::InvalidKey)?;
+    Ok(jwk_raw_public_key(key_type, &jwk)?.ok_or(CryptoError::InvalidKey)?)
+}
+
+#[cfg(feature = "p256")]
+fn decompress_sec1(alg: SignatureAlgorithm, compressed: &[u8]) -> Result<Vec<u8>, Error> {
+    use p256::elliptic_curve::sec1::ToEncodedPoint as _;
+
+    ensure!(
+        matches!(compressed.first(), Some(0x02) | Some(0x03)),
+        CryptoError::InvalidKey
+    );
+    let uncompressed = match alg {
+        SignatureAlgorithm::ECDSA_P256_SHA256 => p256::PublicKey::from_sec1_bytes(compressed)
+            .map_err(|_| CryptoError::InvalidKey)?
+            .to_encoded_point(false)
+            .as_bytes()
+            .to_vec(),
+        SignatureAlgorithm::ECDSA_P384_SHA384 => p384::PublicKey::from_sec1_bytes(compressed)
+            .map_err(|_| CryptoError::InvalidKey)?
+            .to_encoded_point(false)
+            .as_bytes()
+            .to_vec(),
+        _ => bail!(CryptoError::NotAvailable),
+    };
+    Ok(uncompressed)
+}
+
+#[cfg(not(feature = "p256"))]
+fn decompress_sec1(_alg: SignatureAlgorithm, _compressed: &[u8]) -> Result<Vec<u8>, Error> {
+    bail!(CryptoError::NotAvailable)
+}
+
+/// Decode a public key into the raw form used internally: uncompressed SEC1 for ECDSA, the
+/// 32-byte key for Ed25519, and a DER `RSAPublicKey` for RSA.
+///
+/// Decompressing SEC1 points requires the `deterministic` or `prehash` features.
+pub fn decode(
+    alg: SignatureAlgorithm,
+    encoded: &[u8],
+    encoding: PublicKeyEncoding,
+) -> Result<Vec<u8>, Error> {
+    let key_type = KeyType::from_alg(alg)?;
+    let raw = match encoding {
+        PublicKeyEncoding::Raw => encoded.to_vec(),
+        PublicKeyEncoding::Hex => decode_hex(encoded)?,
+        PublicKeyEncoding::Base64Original
+        | PublicKeyEncoding::Base64OriginalNoPadding
+        | PublicKeyEncoding::Base64URLSafe
+        | PublicKeyEncoding::Base64URLSafeNoPadding => {
+            let config = base64_config(encoding).ok_or(CryptoError::NotAvailable)?;
+            base64::decode_config(encoded, config).map_err(|_| CryptoError::InvalidKey)?
+        }
+        PublicKeyEncoding::CompressedSEC1 => match key_type {
+            KeyType::EC { .. } => decompress_sec1(alg, encoded)?,
+            _ => bail!(CryptoError::NotAvailable),
+        },
+        PublicKeyEncoding::SPKI => decode_spki(&key_type, encoded)?,
+        PublicKeyEncoding::PEM => decode_spki(&key_type, &decode_pem(encoded)?)?,
+        PublicKeyEncoding::JWK => decode_jwk(&key_type, encoded)?,
+    };
+    Ok(raw)
+}
+
+/// Encode a raw public key, as returned by `decode()`.
+pub fn encode(
+    alg: SignatureAlgorithm,
+    raw: &[u8],
+    encoding: PublicKeyEncoding,
+) -> Result<Vec<u8>, Error> {
+    let key_type = KeyType::from_alg(alg)?;
+    let spki = || der::sequence(&[&key_type.spki_alg_id(), &der::bit_string(raw)]);
+    let base64url = |data: &[u8]| base64::encode_config(data, base64::URL_SAFE_NO_PAD);
+    let encoded = match encoding {
+        PublicKeyEncoding::Raw => raw.to_vec(),
+        PublicKeyEncoding::Hex => raw
+            .iter()
+            .map(|x| format!("{:02x}", x))
+            .collect::<String>()
+            .into_bytes(),
+        PublicKeyEncoding::Base64Original
+        | PublicKeyEncoding::Base64OriginalNoPadding
+        | PublicKeyEncoding::Base64URLSafe
+        | PublicKeyEncoding::Base64URLSafeNoPadding => {
+            let config = base64_config(encoding).ok_or(CryptoError::NotAvailable)?;
+            base64::encode_config(raw, config).into_bytes()
+        }
+        PublicKeyEncoding::CompressedSEC1 => match key_type {
+            KeyType::EC { coordinate_len, .. } => {
+                ensure!(
+                    raw.len() == 1 + coordinate_len * 2 && raw[0] == 0x04,
+                    CryptoError::InvalidKey
+                );
+                let (x, y) = raw[1..].split_at(coordinate_len);
+                [&[0x02 | (y[coordinate_len - 1] & 1)][..], x].concat()
+            }
+            _ => bail!(CryptoError::NotAvailable),
+        },
+        PublicKeyEncoding::SPKI => spki(),
+        PublicKeyEncoding::PEM => {
+            let encoded = base64::encode(spki());
+            let mut pem = format!("-----BEGIN {}-----\n", PEM_LABEL);
+            for line in encoded.as_bytes().chunks(64) {
+                pem.push_str(core::str::from_utf8(line)?);
+                pem.push('\n');
+            }
+            pem.push_str(&format!("-----END {}-----\n", PEM_LABEL));
+            pem.into_bytes()
+        }
+        PublicKeyEncoding::JWK => {
+            let jwk = match key_type {
+                KeyType::EC {
+                    jwk_crv,
+                    coordinate_len,
+                    ..
+                } => {
+                    ensure!(
+                        raw.len() == 1 + coordinate_len * 2 && raw[0] == 0x04,
+                        CryptoError::InvalidKey
+                    );
+                    let (x, y) = raw[1..].split_at(coordinate_len);
+                    json!({"kty": "EC", "crv": jwk_crv, "x": base64url(x), "y": base64url(y)})
+                }
+                KeyType::Ed25519 => json!({"kty": "OKP", "crv": "Ed25519", "x": base64url(raw)}),
+                KeyType::RSA => {
+                    let mut decoder = der::Decoder::new(raw);
+                    let mut rsa_pk = der::Decoder::new(decoder.read(der::TAG_SEQUENCE)?);
+                    decoder.finish()?;
+                    let n = rsa_pk.read_unsigned_integer()?;
+                    let e = rsa_pk.read_unsigned_integer()?;
+                    rsa_pk.finish()?;
+                    json!({"kty": "RSA", "n": base64url(n), "e": base64url(e)})
+                }
+            };
+            jwk.to_string().into_bytes()
+        }
+    };
+    Ok(encoded)
+}
@@ -0,0 +1,169 @@
+//! Verification of the artifact signature of a Sigstore bundle, with the P-256 key of its
+//! signing certificate. The certificate chain up to the Fulcio root and the transparency log
+//! entries are not checked; callers relying on them must verify them separately.
+
+use alloc::vec::Vec;
+use serde_json::Value;
+
+use super::der;
+use super::ecdsa::*;
+use super::error::*;
+use super::signature::*;
+use super::signature_policy::*;
+use super::signature_publickey::*;
+use super::signature_publickey_encoding;
+
+const ALG: SignatureAlgorithm = SignatureAlgorithm::ECDSA_P256_SHA256;
+
+/// The parts of a Sigstore bundle needed to verify its artifact signature.
+#[derive(Clone, Debug)]
+pub struct SigstoreBundle {
+    /// The DER-encoded signing certificate.
+    pub certificate: Vec<u8>,
+    /// The SHA-256 digest of the artifact, if the bundle includes it.
+    pub message_digest: Option<Vec<u8>>,
+    /// The DER-encoded ECDSA signature of the artifact.
+    pub signature: Vec<u8>,
+}
+
+fn invalid_bundle() -> Error {
+    anyhow::anyhow!("Invalid Sigstore bundle")
+}
+
+impl SigstoreBundle {
+    /// Parse a bundle in its JSON serialization. The certificate is taken from
+    /// `verificationMaterial.certificate` (v0.3), or is the first one of
+    /// `verificationMaterial.x509CertificateChain` (v0.1 and v0.2).
+    pub fn parse(json: &str) -> Result<Self, Error> {
+        let bundle: Value = serde_json::from_str(json)?;
+        let decode = |value: Option<&Value>| -> Result<Vec<u8>, Error> {
+            let encoded = value.and_then(Value::as_str).ok_or_else(invalid_bundle)?;
+            base64::decode(encoded).map_err(|_| invalid_bundle())
+        };
+        let material = &bundle["verificationMaterial"];
+        let certificate = match material.get("certificate") {
+            Some(certificate) => &certificate["rawBytes"],
+            None => &material["x509CertificateChain"]["certificates"][0]["rawBytes"],
+        };
+        let message_signature = &bundle["messageSignature"];
+        let message_digest = match message_signature.get("messageDigest") {
+            None => None,
+            Some(message_digest) => {
+                ensure!(
+                    message_digest["algorithm"] == "SHA2_256",
+                    CryptoError::NotAvailable
+                );
+                Some(decode(message_digest.get("digest"))?)
+            }
+        };
+        Ok(SigstoreBundle {
+            certificate: decode(Some(certificate))?,
+            message_digest,
+            signature: decode(message_signature.get("signature"))?,
+        })
+    }
+}
+
+// Return the `SubjectPublicKeyInfo` of an X.509 certificate
+fn certificate_spki(certificate: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut decoder = der::Decoder::new(certificate);
+    let mut certificate = der::Decoder::new(decoder.read(der::TAG_SEQUENCE)?);
+    decoder.finish()?;
+    let mut tbs_certificate = der::Decoder::new(certificate.read(der::TAG_SEQUENCE)?);
+    let (mut tag, _) = tbs_certificate.read_any()?;
+    // The version is optional, and followed by the serial number
+    if tag == der::TAG_CONTEXT_0 {
+        tag = tbs_certificate.read_any()?.0;
+    }
+    ensure!(tag == der::TAG_INTEGER, "Invalid certificate");
+    // Signature algorithm, issuer, validity and subject
+    for _ in 0..4 {
+        tbs_certificate.read(der::TAG_SEQUENCE)?;
+    }
+    let spki = tbs_certificate.read(der::TAG_SEQUENCE)?;
+    Ok(der::encode(der::TAG_SEQUENCE, spki))
+}
+
+/// Return the public key of the signing certificate of a bundle, which must be a P-256 key.
+pub fn signature_sigstore_bundle_publickey(
+    bundle: &SigstoreBundle,
+) -> Result<ECDSASignaturePublicKey, Error> {
+    let spki = certificate_spki(&bundle.certificate).map_err(|_| CryptoError::InvalidKey)?;
+    let raw_pk = signature_publickey_encoding::decode(ALG, &spki, PublicKeyEncoding::SPKI)?;
+    ECDSASignaturePublicKey::from_raw(ALG, &raw_pk)
+}
+
+/// Verify the signature of `artifact` in a bundle, with the key of its signing certificate.
+/// If the bundle includes the digest of the artifact, it must match, or `DigestMismatch` is
+/// returned.
+pub fn signature_verify_sigstore_bundle(
+    artifact: &[u8],
+    bundle: &SigstoreBundle,
+) -> Result<(), Error> {
+    check_algorithm_policy(ALG)?;
+    if let Some(message_digest) = &bundle.message_digest {
+        let digest = ring::digest::digest(ALG.digest_alg(), artifact);
+        ensure!(
+            digest.as_ref() == message_digest.as_slice(),
+            CryptoError::DigestMismatch
+        );
+    }
+    let pk = signature_sigstore_bundle_publickey(bundle)?;
+    let coordinate_len = ECDSASignatureOp::coordinate_len(ALG)?;
+    let signature = der::ecdsa_signature_to_fixed(&bundle.signature, coordinate_len)
+        .map_err(|_| CryptoError::InvalidSignature)?;
+    pk.verify(artifact, &signature)
+}
+
+#[test]
+fn test_verify_sigstore_bundle() {
+    // A v0.3 bundle for a self-signed code signing certificate, recorded with OpenSSL
+    let bundle = SigstoreBundle::parse(
+        r#"{
+  "mediaType": "application/vnd.dev.sigstore.bundle.v0.3+json",
+  "verificationMaterial": {
+    "certificate": {
+      "rawBytes": "MIIB5TCCAYugAwIBAgIUAjvAQxdhEHBnjwgUpZYGQfgHmMEwCgYIKoZIzj0EAwIwNzEVMBMGA1UECgwMc2lnc3RvcmUuZGV2MR4wHAYDVQQDDBVzaWdzdG9yZS1pbnRlcm1lZGlhdGUwHhcNMjYxMDE0MTQxMDI3WhcNMjYxMDE1MTQxMDI3WjA3MRUwEwYDVQQKDAxzaWdzdG9yZS5kZXYxHjAcBgNVBAMMFXNpZ3N0b3JlLWludGVybWVkaWF0ZTBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IABKcnkvDRtBgPupdzprxSpGS3Yo+ChoV5T8U8jeSf0C0pHeOczcBJHQCLZq2tBG4bpEw4mFZLjthtbk4TZ3EjRAujdTBzMB0GA1UdDgQWBBRVIRIemSK/ieCD8jI8tRXbIU/dYTAfBgNVHSMEGDAWgBRVIRIemSK/ieCD8jI8tRXbIU/dYTAPBgNVHRMBAf8EBTADAQH/MAsGA1UdDwQEAwIHgDATBgNVHSUEDDAKBggrBgEFBQcDAzAKBggqhkjOPQQDAgNIADBFAiEAkqFXKeepViC+v0AiNvEwDOuRCelWVwDq8qSqn523ptgCIFZlNLDIIbuE2+f+L10MBUflZ5JPr0heW+babWGD0CpB"
+    },
+    "tlogEntries": []
+  },
+  "messageSignature": {
+    "messageDigest": {
+      "algorithm": "SHA2_256",
+      "digest": "n9ywkbB/PjqZEjGCcQMRHUqyyIuJyM9h0+S7AcCkX9Q="
+    },
+    "signature": "MEYCIQCH6ITB+ORxvN2wKj/LwnUENe2LEEsjTnOD6gxllPvnIQIhAIM1xDf+AftPj/xJQMxLVTrjE1loF/UTZ/6cl7E6ZFV8"
+  }
+}"#,
+    )
+    .unwrap();
+    let artifact = b"hello, sigstore\n";
+    signature_verify_sigstore_bundle(artifact, &bundle).unwrap();
+    let err = signature_verify_sigstore_bundle(b"hello, world\n", &bundle).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::DigestMismatch)
+    ));
+
+    let detached = SigstoreBundle {
+        message_digest: None,
+        ..bundle.clone()
+    };
+    signature_verify_sigstore_bundle(artifact, &detached).unwrap();
+    let err = signature_verify_sigstore_bundle(b"hello, world\n", &detached).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::VerificationFailed)
+    ));
+
+    // v0.1 and v0.2 bundles carry a certificate chain instead
+    let chain = format!(
+        r#"{{"verificationMaterial": {{"x509CertificateChain": {{"certificates":
+            [{{"rawBytes": "{}"}}]}}}}, "messageSignature": {{"signature": "{}"}}}}"#,
+        base64::encode(&bundle.certificate),
+        base64::encode(&bundle.signature)
+    );
+    let v02_bundle = SigstoreBundle::parse(&chain).unwrap();
+    signature_verify_sigstore_bundle(artifact, &v02_bundle).unwrap();
+    assert!(SigstoreBundle::parse(r#"{"messageSignature": {}}"#).is_err());
+}
@@ -0,0 +1,155 @@
+use alloc::vec::Vec;
+use zeroize::Zeroizing;
+
+use super::der;
+use super::error::*;
+use super::handles::*;
+use super::signature::*;
+use super::signature_keypair::*;
+use super::WASI_CRYPTO_CTX;
+
+const HARDENED_OFFSET: u32 = 0x8000_0000;
+
+/// Parse a derivation path such as `m/44'/501'/0'`, returning the child indices with the
+/// hardened bit set. Ed25519 only supports hardened derivation, marked with `'` or `h`.
+fn parse_path(path: &str) -> Result<Vec<u32>, Error> {
+    let mut components = path.split('/');
+    ensure!(components.next() == Some("m"), "Invalid derivation path");
+    components
+        .map(|component| {
+            let index = match component
+                .strip_suffix('\'')
+                .or_else(|| component.strip_suffix('h'))
+            {
+                Some(index) => index,
+                None => bail!("SLIP-0010 only supports hardened derivation for Ed25519"),
+            };
+            ensure!(
+                !index.is_empty() && index.bytes().all(|x| x.is_ascii_digit()),
+                "Invalid derivation path"
+            );
+            let index: u32 = index
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid derivation path"))?;
+            ensure!(index < HARDENED_OFFSET, "Invalid derivation path");
+            Ok(index | HARDENED_OFFSET)
+        })
+        .collect()
+}
+
+/// Derive the Ed25519 seed at `path` from a master seed, as specified in SLIP-0010.
+fn derive_seed(seed: &[u8], path: &str) -> Result<Zeroizing<[u8; 32]>, Error> {
+    ensure!((16..=64).contains(&seed.len()), CryptoError::InvalidLength);
+    let indices = parse_path(path)?;
+    let split = |tag: ring::hmac::Tag| {
+        let mut key = Zeroizing::new([0u8; 32]);
+        let mut chain_code = Zeroizing::new([0u8; 32]);
+        key.copy_from_slice(&tag.as_ref()[..32]);
+        chain_code.copy_from_slice(&tag.as_ref()[32..]);
+        (key, chain_code)
+    };
+    let master_key = ring::hmac::Key::new(ring::hmac::HMAC_SHA512, b"ed25519 seed");
+    let (mut key, mut chain_code) = split(ring::hmac::sign(&master_key, seed));
+    for index in indices {
+        let hmac_key = ring::hmac::Key::new(ring::hmac::HMAC_SHA512, &chain_code[..]);
+        let mut data = Zeroizing::new([0u8; 37]);
+        data[1..33].copy_from_slice(&key[..]);
+        data[33..].copy_from_slice(&index.to_be_bytes());
+        let (child_key, child_chain_code) = split(ring::hmac::sign(&hmac_key, &data[..]));
+        key = child_key;
+        chain_code = child_chain_code;
+    }
+    Ok(key)
+}
+
+/// Derive an Ed25519 key pair from a seed of 16 to 64 bytes and a SLIP-0010 derivation path
+/// such as `m/44'/501'/0'`, as used by Solana and Cardano wallets. The builder must be for
+/// `Ed25519`; its options apply to the key pair.
+pub fn signature_keypair_derive_slip10_ed25519(
+    kp_builder_handle: Handle,
+    seed: &[u8],
+    path: &str,
+) -> Result<Handle, Error> {
+    let kp_builder = WASI_CRYPTO_CTX
+        .signature_keypair_builder_manager
+        .get(kp_builder_handle)?;
+    ensure!(
+        kp_builder.alg() == SignatureAlgorithm::Ed25519,
+        CryptoError::NotAvailable
+    );
+    let derived_seed = derive_seed(seed, path)?;
+    let private_key = Zeroizing::new(der::encode(der::TAG_OCTET_STRING, &derived_seed[..]));
+    let pkcs8 = Zeroizing::new(der::pkcs8_v1(&der::oid(der::OID_ED25519), &private_key));
+    signature_keypair_import(kp_builder_handle, &pkcs8, KeyPairEncoding::PKCS8)
+}
+
+#[test]
+fn test_slip10_vectors() {
+    // Test vector 1 for ed25519 from SLIP-0010
+    let seed = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+    let vectors: [(&str, [u8; 32]); 4] = [
+        (
+            "m",
+            [
+                0xa4, 0xb2, 0x85, 0x6b, 0xfe, 0xc5, 0x10, 0xab, 0xab, 0x89, 0x75, 0x3f, 0xac, 0x1a,
+                0xc0, 0xe1, 0x11, 0x23, 0x64, 0xe7, 0xd2, 0x50, 0x54, 0x59, 0x63, 0xf1, 0x35, 0xf2,
+                0xa3, 0x31, 0x88, 0xed,
+            ],
+        ),
+        (
+            "m/0'",
+            [
+                0x8c, 0x8a, 0x13, 0xdf, 0x77, 0xa2, 0x8f, 0x34, 0x45, 0x21, 0x3a, 0x0f, 0x43, 0x2f,
+                0xde, 0x64, 0x4a, 0xca, 0xa2, 0x15, 0xfc, 0x72, 0xdc, 0xdf, 0x30, 0x0d, 0x5e, 0xfa,
+                0xa8, 0x5d, 0x35, 0x0c,
+            ],
+        ),
+        (
+            "m/0'/1'",
+            [
+                0x19, 0x32, 0xa5, 0x27, 0x0f, 0x33, 0x5b, 0xed, 0x61, 0x7d, 0x5b, 0x93, 0x5c, 0x80,
+                0xae, 0xdb, 0x1a, 0x35, 0xbd, 0x9f, 0xc1, 0xe3, 0x1a, 0xca, 0xfd, 0x53, 0x72, 0xc3,
+                0x0f, 0x5c, 0x11, 0x87,
+            ],
+        ),
+        (
+            "m/0h/1h/2h/2h/1000000000h",
+            [
+                0x3c, 0x24, 0xda, 0x04, 0x94, 0x51, 0x55, 0x5d, 0x51, 0xa7, 0x01, 0x4a, 0x37, 0x33,
+                0x7a, 0xa4, 0xe1, 0x2d, 0x41, 0xe4, 0x85, 0xab, 0xcc, 0xfa, 0x46, 0xb4, 0x7d, 0xfb,
+                0x2a, 0xf5, 0x4b, 0x7a,
+            ],
+        ),
+    ];
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    for (path, expected_pk) in &vectors {
+        let kp_handle =
+            signature_keypair_derive_slip10_ed25519(kp_builder_handle, &seed, path).unwrap();
+        let kp = WASI_CRYPTO_CTX
+            .signature_keypair_manager
+            .get(kp_handle)
+            .unwrap();
+        assert_eq!(kp.raw_public_key(), &expected_pk[..]);
+    }
+
+    for path in &[
+        "m/0",
+        "m/0'/1",
+        "0'",
+        "m/",
+        "m/x'",
+        "m/2147483648'",
+        "m/+1'",
+    ] {
+        assert!(signature_keypair_derive_slip10_ed25519(kp_builder_handle, &seed, path).is_err());
+    }
+    assert!(signature_keypair_derive_slip10_ed25519(kp_builder_handle, &seed[..15], "m").is_err());
+
+    let op_handle = crate::signature_op_open("ECDSA_P256_SHA256").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    assert!(signature_keypair_derive_slip10_ed25519(kp_builder_handle, &seed, "m").is_err());
+}
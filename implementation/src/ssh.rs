@@ -0,0 +1,217 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use ring::rand::SecureRandom;
+
+use super::eddsa::*;
+use super::error::*;
+use super::handles::*;
+use super::signature::*;
+use super::signature_publickey::*;
+use super::WASI_CRYPTO_CTX;
+
+const SSH_ED25519: &str = "ssh-ed25519";
+const SSH_ED25519_CERT: &str = "ssh-ed25519-cert-v01@openssh.com";
+const SSH_CERT_TYPE_USER: u32 = 1;
+
+fn append_string(out: &mut Vec<u8>, s: &[u8]) {
+    out.extend_from_slice(&(s.len() as u32).to_be_bytes());
+    out.extend_from_slice(s);
+}
+
+fn ed25519_raw_public_key(pk_handle: Handle) -> Result<Vec<u8>, Error> {
+    let pk = WASI_CRYPTO_CTX.signature_publickey_manager.get(pk_handle)?;
+    match pk {
+        SignaturePublicKey::EdDSA(pk) if pk.alg == SignatureAlgorithm::Ed25519 => Ok(pk.raw),
+        _ => bail!(CryptoError::NotAvailable),
+    }
+}
+
+/// Encode an Ed25519 public key as an SSH public key blob.
+pub(crate) fn ed25519_public_key_blob(raw_pk: &[u8]) -> Vec<u8> {
+    let mut blob = vec![];
+    append_string(&mut blob, SSH_ED25519.as_bytes());
+    append_string(&mut blob, raw_pk);
+    blob
+}
+
+fn read_string<'a>(input: &mut &'a [u8]) -> Option<&'a [u8]> {
+    if input.len() < 4 {
+        return None;
+    }
+    let (len, rest) = input.split_at(4);
+    let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+    if rest.len() < len {
+        return None;
+    }
+    let (s, rest) = rest.split_at(len);
+    *input = rest;
+    Some(s)
+}
+
+impl EdDSASignaturePublicKey {
+    /// Encode an Ed25519 public key as an `authorized_keys` line, the way `ssh-keygen`
+    /// writes `.pub` files: `ssh-ed25519`, the base64-encoded key blob, and the comment if
+    /// there is one. There is no trailing newline.
+    pub fn to_ssh_authorized_key(&self, comment: &str) -> Result<String, Error> {
+        ensure!(
+            self.alg == SignatureAlgorithm::Ed25519,
+            CryptoError::NotAvailable
+        );
+        let blob = base64::encode(ed25519_public_key_blob(&self.raw));
+        match comment {
+            "" => Ok(format!("{} {}", SSH_ED25519, blob)),
+            comment => Ok(format!("{} {} {}", SSH_ED25519, blob, comment)),
+        }
+    }
+
+    /// Parse an `ssh-ed25519` line of an `authorized_keys` file, and return the public key
+    /// along with its comment, which may be empty. Lines starting with options are not
+    /// supported.
+    pub fn from_ssh_authorized_key(line: &str) -> Result<(Self, String), Error> {
+        let mut fields = line.trim().splitn(3, char::is_whitespace);
+        ensure!(fields.next() == Some(SSH_ED25519), CryptoError::InvalidKey);
+        let blob = fields.next().ok_or(CryptoError::InvalidKey)?;
+        let comment = fields.next().unwrap_or("").trim().to_string();
+        let blob = base64::decode(blob).map_err(|_| CryptoError::InvalidKey)?;
+        let mut input = &blob[..];
+        ensure!(
+            read_string(&mut input) == Some(SSH_ED25519.as_bytes()),
+            CryptoError::InvalidKey
+        );
+        let raw_pk = read_string(&mut input).ok_or(CryptoError::InvalidKey)?;
+        ensure!(
+            input.is_empty() && raw_pk.len() == 32,
+            CryptoError::InvalidKey
+        );
+        let pk = EdDSASignaturePublicKey::from_raw(SignatureAlgorithm::Ed25519, raw_pk)?;
+        Ok((pk, comment))
+    }
+}
+
+/// Serialize the body of an OpenSSH user certificate for an Ed25519 public key, up to and
+/// including the CA public key. The CA signs this body, and the certificate is the body
+/// followed by the encoded CA signature.
+///
+/// The certificate has a random nonce, a serial number of `0`, an empty key identifier, and
+/// no critical options nor extensions. Validity is in seconds since the UNIX epoch.
+pub fn signature_publickey_ssh_cert_body(
+    pk_handle: Handle,
+    ca_pk_handle: Handle,
+    principals: &[&str],
+    valid_after: u64,
+    valid_before: u64,
+) -> Result<Vec<u8>, Error> {
+    ensure!(valid_after < valid_before, "Invalid validity period");
+    let raw_pk = ed25519_raw_public_key(pk_handle)?;
+    let raw_ca_pk = ed25519_raw_public_key(ca_pk_handle)?;
+    let mut nonce = [0u8; 32];
+    ring::rand::SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| CryptoError::RNGError)?;
+    let mut packed_principals = vec![];
+    for principal in principals {
+        append_string(&mut packed_principals, principal.as_bytes());
+    }
+
+    let mut body = vec![];
+    append_string(&mut body, SSH_ED25519_CERT.as_bytes());
+    append_string(&mut body, &nonce);
+    append_string(&mut body, &raw_pk);
+    body.extend_from_slice(&0u64.to_be_bytes());
+    body.extend_from_slice(&SSH_CERT_TYPE_USER.to_be_bytes());
+    append_string(&mut body, b"");
+    append_string(&mut body, &packed_principals);
+    body.extend_from_slice(&valid_after.to_be_bytes());
+    body.extend_from_slice(&valid_before.to_be_bytes());
+    append_string(&mut body, b"");
+    append_string(&mut body, b"");
+    append_string(&mut body, b"");
+    append_string(&mut body, &ed25519_public_key_blob(&raw_ca_pk));
+    Ok(body)
+}
+
+#[test]
+#[ignore = "requires ssh-keygen; run with `cargo test -- --ignored`"]
+fn test_ssh_cert_body_parses_with_ssh_keygen() {
+    use std::process::Command;
+
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let ca_kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    let ca_pk_handle = crate::signature_keypair_publickey(ca_kp_handle).unwrap();
+
+    let mut cert = signature_publickey_ssh_cert_body(
+        pk_handle,
+        ca_pk_handle,
+        &["alice", "bob"],
+        1_600_000_000,
+        1_900_000_000,
+    )
+    .unwrap();
+    let signature_handle = sign_input(ca_kp_handle, &cert).unwrap();
+    let raw_signature = crate::signature_export(signature_handle, SignatureEncoding::Raw).unwrap();
+    let mut signature = vec![];
+    append_string(&mut signature, SSH_ED25519.as_bytes());
+    append_string(&mut signature, &raw_signature);
+    append_string(&mut cert, &signature);
+
+    let path = std::env::temp_dir().join(format!("wasi-crypto-ssh-cert-{}", std::process::id()));
+    let line = format!("{} {}\n", SSH_ED25519_CERT, base64::encode(&cert));
+    std::fs::write(&path, line).unwrap();
+    let output = Command::new("ssh-keygen")
+        .arg("-L")
+        .arg("-f")
+        .arg(&path)
+        .output();
+    std::fs::remove_file(&path).unwrap();
+    let output = output.expect("ssh-keygen not found");
+    assert!(output.status.success(), "{:?}", output);
+    let listing = String::from_utf8_lossy(&output.stdout);
+    assert!(listing.contains("user certificate"), "{}", listing);
+    assert!(listing.contains("alice"), "{}", listing);
+    assert!(listing.contains("bob"), "{}", listing);
+
+    crate::signature_close(signature_handle).unwrap();
+    crate::signature_publickey_close(pk_handle).unwrap();
+    crate::signature_publickey_close(ca_pk_handle).unwrap();
+    crate::signature_keypair_close(kp_handle).unwrap();
+    crate::signature_keypair_close(ca_kp_handle).unwrap();
+    crate::signature_keypair_builder_close(kp_builder_handle).unwrap();
+    crate::signature_op_close(op_handle).unwrap();
+}
+
+#[test]
+fn test_ssh_authorized_key() {
+    // Written by `ssh-keygen -t ed25519 -C test@example.com`
+    const LINE: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOzctWMCgCPxUP+d1JZIFcyOW2bD2m702MaqsLiGHTQw test@example.com";
+    const RAW_PK: [u8; 32] = [
+        0xec, 0xdc, 0xb5, 0x63, 0x02, 0x80, 0x23, 0xf1, 0x50, 0xff, 0x9d, 0xd4, 0x96, 0x48, 0x15,
+        0xcc, 0x8e, 0x5b, 0x66, 0xc3, 0xda, 0x6e, 0xf4, 0xd8, 0xc6, 0xaa, 0xb0, 0xb8, 0x86, 0x1d,
+        0x34, 0x30,
+    ];
+    let pk = EdDSASignaturePublicKey::from_raw(SignatureAlgorithm::Ed25519, &RAW_PK).unwrap();
+    assert_eq!(pk.to_ssh_authorized_key("test@example.com").unwrap(), LINE);
+
+    let (parsed, comment) = EdDSASignaturePublicKey::from_ssh_authorized_key(LINE).unwrap();
+    assert_eq!(parsed.raw, RAW_PK);
+    assert_eq!(comment, "test@example.com");
+    let (parsed, comment) =
+        EdDSASignaturePublicKey::from_ssh_authorized_key(&pk.to_ssh_authorized_key("").unwrap())
+            .unwrap();
+    assert_eq!(parsed.raw, RAW_PK);
+    assert_eq!(comment, "");
+
+    for line in &[
+        "ssh-rsa AAAAC3NzaC1lZDI1NTE5AAAAIOzctWMCgCPxUP+d1JZIFcyOW2bD2m702MaqsLiGHTQw",
+        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAH+zctWMCgCPxUP+d1JZIFcyOW2bD2m702MaqsLiGHTQ=",
+        "ssh-ed25519 not-base64",
+        "ssh-ed25519",
+    ] {
+        assert!(EdDSASignaturePublicKey::from_ssh_authorized_key(line).is_err());
+    }
+    let pk =
+        EdDSASignaturePublicKey::from_raw(SignatureAlgorithm::Ed25519_BLAKE2b512, &RAW_PK).unwrap();
+    assert!(pk.to_ssh_authorized_key("").is_err());
+}
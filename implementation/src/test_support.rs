@@ -0,0 +1,42 @@
+//! Helpers shared by the unit tests. This module installs the global allocator of the test
+//! binary: it flags freed heap blocks that still contain the secret of the current thread, if
+//! one is set, and otherwise behaves like the system allocator. This is best effort: it can
+//! only see heap allocations, not copies left on the stack.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    static SECRET: Cell<Option<[u8; 32]>> = const { Cell::new(None) };
+    static LEAKED: Cell<bool> = const { Cell::new(false) };
+}
+
+struct LeakCheckAllocator;
+
+unsafe impl GlobalAlloc for LeakCheckAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if let Ok(Some(secret)) = SECRET.try_with(Cell::get) {
+            let block = core::slice::from_raw_parts(ptr, layout.size());
+            if block.windows(secret.len()).any(|window| window == secret) {
+                LEAKED.with(|leaked| leaked.set(true));
+            }
+        }
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: LeakCheckAllocator = LeakCheckAllocator;
+
+/// Run `f`, and return whether it freed a heap block that still contained `secret`.
+pub fn frees_secret(secret: [u8; 32], f: impl FnOnce()) -> bool {
+    LEAKED.with(|leaked| leaked.set(false));
+    SECRET.with(|s| s.set(Some(secret)));
+    f();
+    SECRET.with(|s| s.set(None));
+    LEAKED.with(Cell::get)
+}
@@ -0,0 +1,389 @@
+//! RFC 3161 trusted timestamping: building a `TimeStampReq` for a digest, and checking the
+//! signature of the `TimeStampResp` returned by a timestamping authority (TSA). Validating the
+//! TSA certificate is up to the caller, who provides its public key.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::der;
+use super::ecdsa::*;
+use super::error::*;
+use super::handles::*;
+use super::rsa::*;
+use super::signature::*;
+use super::signature_publickey::*;
+use super::WASI_CRYPTO_CTX;
+
+/// The hash function of the digest that is timestamped.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimestampHashAlgorithm {
+    SHA256,
+    SHA384,
+    SHA512,
+}
+
+impl TimestampHashAlgorithm {
+    fn oid(self) -> &'static [u8] {
+        match self {
+            TimestampHashAlgorithm::SHA256 => der::OID_SHA256,
+            TimestampHashAlgorithm::SHA384 => der::OID_SHA384,
+            TimestampHashAlgorithm::SHA512 => der::OID_SHA512,
+        }
+    }
+
+    fn digest_len(self) -> usize {
+        match self {
+            TimestampHashAlgorithm::SHA256 => 32,
+            TimestampHashAlgorithm::SHA384 => 48,
+            TimestampHashAlgorithm::SHA512 => 64,
+        }
+    }
+}
+
+// Remove the leading zeros of a big-endian integer, as a DER decoder does
+fn strip_leading_zeros(be: &[u8]) -> &[u8] {
+    let start = be
+        .iter()
+        .position(|&x| x != 0)
+        .unwrap_or_else(|| be.len().saturating_sub(1));
+    &be[start..]
+}
+
+// The `MessageImprint` of a digest. The algorithm has NULL parameters, as OpenSSL and most
+// TSAs write them.
+fn message_imprint(hash_alg: TimestampHashAlgorithm, digest: &[u8]) -> Vec<u8> {
+    let alg_id = der::sequence(&[&der::oid(hash_alg.oid()), &der::encode(der::TAG_NULL, &[])]);
+    der::sequence(&[&alg_id, &der::encode(der::TAG_OCTET_STRING, digest)])
+}
+
+/// Build a DER-encoded `TimeStampReq` for the digest of a message. The nonce is a big-endian
+/// integer, which the response must repeat. If `cert_req` is set, the TSA is asked to
+/// include its certificate in the response.
+pub fn signature_timestamp_request(
+    hash_alg: TimestampHashAlgorithm,
+    digest: &[u8],
+    nonce: Option<&[u8]>,
+    cert_req: bool,
+) -> Result<Vec<u8>, Error> {
+    ensure!(
+        digest.len() == hash_alg.digest_len(),
+        CryptoError::InvalidLength
+    );
+    let mut fields = vec![
+        der::unsigned_integer(&[1]),
+        message_imprint(hash_alg, digest),
+    ];
+    if let Some(nonce) = nonce {
+        ensure!(!nonce.is_empty(), CryptoError::InvalidLength);
+        fields.push(der::unsigned_integer(strip_leading_zeros(nonce)));
+    }
+    // `certReq` defaults to false, and DER omits default values
+    if cert_req {
+        fields.push(der::encode(der::TAG_BOOLEAN, &[0xff]));
+    }
+    let fields: Vec<&[u8]> = fields.iter().map(|field| field.as_slice()).collect();
+    Ok(der::sequence(&fields))
+}
+
+// The parts of a `SignedData` structure with a single signer that are needed to verify it
+struct SignedToken<'a> {
+    content: &'a [u8],
+    digest_alg_oid: &'a [u8],
+    signed_attrs: &'a [u8],
+    signature: &'a [u8],
+}
+
+fn read_alg_oid<'a>(decoder: &mut der::Decoder<'a>) -> Result<&'a [u8], Error> {
+    let mut alg_id = der::Decoder::new(decoder.read(der::TAG_SEQUENCE)?);
+    let oid = alg_id.read(der::TAG_OID)?;
+    if !alg_id.is_empty() {
+        alg_id.read(der::TAG_NULL)?;
+    }
+    alg_id.finish()?;
+    Ok(oid)
+}
+
+fn parse_token(response: &[u8]) -> Result<SignedToken<'_>, Error> {
+    let mut decoder = der::Decoder::new(response);
+    let mut resp = der::Decoder::new(decoder.read(der::TAG_SEQUENCE)?);
+    decoder.finish()?;
+
+    // `granted` or `grantedWithMods`; a rejected request has no token
+    let mut status_info = der::Decoder::new(resp.read(der::TAG_SEQUENCE)?);
+    let status = status_info.read_unsigned_integer()?;
+    ensure!(status == [0] || status == [1], "Timestamp request rejected");
+    let mut content_info = der::Decoder::new(resp.read(der::TAG_SEQUENCE)?);
+    resp.finish()?;
+    ensure!(
+        content_info.read(der::TAG_OID)? == der::OID_SIGNED_DATA,
+        "Unexpected timestamp token content type"
+    );
+    let mut explicit = der::Decoder::new(content_info.read(der::TAG_CONTEXT_0)?);
+    content_info.finish()?;
+    let mut signed_data = der::Decoder::new(explicit.read(der::TAG_SEQUENCE)?);
+    explicit.finish()?;
+
+    signed_data.read_unsigned_integer()?;
+    signed_data.read(der::TAG_SET)?;
+    let mut encap_content_info = der::Decoder::new(signed_data.read(der::TAG_SEQUENCE)?);
+    ensure!(
+        encap_content_info.read(der::TAG_OID)? == der::OID_TST_INFO,
+        "Unexpected timestamp token content type"
+    );
+    let mut explicit = der::Decoder::new(encap_content_info.read(der::TAG_CONTEXT_0)?);
+    encap_content_info.finish()?;
+    let content = explicit.read(der::TAG_OCTET_STRING)?;
+    explicit.finish()?;
+
+    // Skip the optional certificates and CRLs
+    let signer_infos = loop {
+        match signed_data.read_any()? {
+            (der::TAG_CONTEXT_0, _) | (der::TAG_CONTEXT_1, _) => continue,
+            (der::TAG_SET, signer_infos) => break signer_infos,
+            _ => bail!("Unexpected DER tag"),
+        }
+    };
+    signed_data.finish()?;
+    let mut signer_infos = der::Decoder::new(signer_infos);
+    let mut signer_info = der::Decoder::new(signer_infos.read(der::TAG_SEQUENCE)?);
+    ensure!(
+        signer_infos.is_empty(),
+        "Timestamp tokens must have a single signer"
+    );
+    signer_info.read_unsigned_integer()?;
+    signer_info.read_any()?;
+    let digest_alg_oid = read_alg_oid(&mut signer_info)?;
+    let signed_attrs = signer_info.read(der::TAG_CONTEXT_0)?;
+    read_alg_oid(&mut signer_info)?;
+    let signature = signer_info.read(der::TAG_OCTET_STRING)?;
+    Ok(SignedToken {
+        content,
+        digest_alg_oid,
+        signed_attrs,
+        signature,
+    })
+}
+
+// Check the `contentType` and `messageDigest` signed attributes against the content
+fn check_signed_attrs(token: &SignedToken<'_>, digest: &[u8]) -> Result<(), Error> {
+    let (mut content_type_ok, mut message_digest_ok) = (false, false);
+    let mut attrs = der::Decoder::new(token.signed_attrs);
+    while !attrs.is_empty() {
+        let mut attr = der::Decoder::new(attrs.read(der::TAG_SEQUENCE)?);
+        let attr_type = attr.read(der::TAG_OID)?;
+        let mut values = der::Decoder::new(attr.read(der::TAG_SET)?);
+        attr.finish()?;
+        match attr_type {
+            der::OID_CONTENT_TYPE => {
+                ensure!(!content_type_ok, "Duplicate signed attribute");
+                content_type_ok = values.read(der::TAG_OID)? == der::OID_TST_INFO;
+                values.finish()?;
+            }
+            der::OID_MESSAGE_DIGEST => {
+                ensure!(!message_digest_ok, "Duplicate signed attribute");
+                message_digest_ok = values.read(der::TAG_OCTET_STRING)? == digest;
+                values.finish()?;
+            }
+            _ => {}
+        }
+    }
+    ensure!(
+        content_type_ok && message_digest_ok,
+        CryptoError::VerificationFailed
+    );
+    Ok(())
+}
+
+// Check that the `TSTInfo` is for the digest and the nonce, and return its `genTime`
+fn check_tst_info(
+    tst_info: &[u8],
+    hash_alg: TimestampHashAlgorithm,
+    digest: &[u8],
+    nonce: Option<&[u8]>,
+) -> Result<String, Error> {
+    let mut decoder = der::Decoder::new(tst_info);
+    let mut tst_info = der::Decoder::new(decoder.read(der::TAG_SEQUENCE)?);
+    decoder.finish()?;
+    ensure!(
+        tst_info.read_unsigned_integer()? == [1],
+        "Unsupported TSTInfo version"
+    );
+    tst_info.read(der::TAG_OID)?;
+    let mut imprint = der::Decoder::new(tst_info.read(der::TAG_SEQUENCE)?);
+    let imprint_alg_oid = read_alg_oid(&mut imprint)?;
+    let imprint_digest = imprint.read(der::TAG_OCTET_STRING)?;
+    imprint.finish()?;
+    ensure!(
+        imprint_alg_oid == hash_alg.oid() && imprint_digest == digest,
+        CryptoError::VerificationFailed
+    );
+    tst_info.read_unsigned_integer()?;
+    let gen_time = tst_info.read(der::TAG_GENERALIZED_TIME)?;
+    let gen_time = core::str::from_utf8(gen_time)
+        .map_err(|_| anyhow::anyhow!("Invalid timestamp genTime"))?
+        .to_string();
+
+    // `accuracy` and `ordering` come before the nonce, `tsa` and `extensions` after it
+    let mut response_nonce = None;
+    while !tst_info.is_empty() {
+        if let (der::TAG_INTEGER, content) = tst_info.read_any()? {
+            response_nonce = Some(strip_leading_zeros(content));
+        }
+    }
+    if let Some(nonce) = nonce {
+        ensure!(
+            response_nonce == Some(strip_leading_zeros(nonce)),
+            CryptoError::VerificationFailed
+        );
+    }
+    Ok(gen_time)
+}
+
+/// Verify a DER-encoded `TimeStampResp` for the digest of a message with the public key of
+/// the TSA, and return the time at which it was timestamped as a `GeneralizedTime` string,
+/// such as `20261014134552Z`. The nonce must be the one of the request, if it had one.
+pub fn signature_verify_timestamp_response(
+    pk_handle: Handle,
+    response: &[u8],
+    hash_alg: TimestampHashAlgorithm,
+    digest: &[u8],
+    nonce: Option<&[u8]>,
+) -> Result<String, Error> {
+    let pk = WASI_CRYPTO_CTX.signature_publickey_manager.get(pk_handle)?;
+    let token = parse_token(response)?;
+    let signer_digest_alg = match pk.alg().digest_alg() {
+        alg if alg == &ring::digest::SHA256 => TimestampHashAlgorithm::SHA256,
+        alg if alg == &ring::digest::SHA384 => TimestampHashAlgorithm::SHA384,
+        _ => TimestampHashAlgorithm::SHA512,
+    };
+    ensure!(
+        token.digest_alg_oid == signer_digest_alg.oid(),
+        CryptoError::VerificationFailed
+    );
+    let content_digest = ring::digest::digest(pk.alg().digest_alg(), token.content);
+    check_signed_attrs(&token, content_digest.as_ref())?;
+
+    // The signature covers the signed attributes with the tag of a SET
+    let signed_attrs = der::encode(der::TAG_SET, token.signed_attrs);
+    match pk {
+        SignaturePublicKey::ECDSA(pk) => {
+            let state = ECDSASignatureVerificationState::new(pk)?;
+            state.update(&signed_attrs)?;
+            state.verify(&ECDSASignature::new(
+                SignatureEncoding::DER,
+                token.signature.to_vec(),
+            ))?;
+        }
+        SignaturePublicKey::RSA(pk) => {
+            let state = RSASignatureVerificationState::new(pk)?;
+            state.update(&signed_attrs)?;
+            state.verify(&RSASignature::new(token.signature.to_vec()))?;
+        }
+        SignaturePublicKey::EdDSA(_) => bail!(CryptoError::NotAvailable),
+    }
+    check_tst_info(token.content, hash_alg, digest, nonce)
+}
+
+#[test]
+fn test_timestamp() {
+    // Request and response for the SHA-256 digest of `MESSAGE`, recorded with `openssl ts`
+    // and a self-signed P-256 TSA certificate
+    const MESSAGE: &[u8] = b"timestamp me";
+    const NONCE: [u8; 8] = [0x86, 0x9b, 0xc1, 0x6b, 0xab, 0xdc, 0xd2, 0x43];
+    const REQUEST: [u8; 67] = [
+        0x30, 0x41, 0x02, 0x01, 0x01, 0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01,
+        0x65, 0x03, 0x04, 0x02, 0x01, 0x05, 0x00, 0x04, 0x20, 0x06, 0xc6, 0x62, 0xb0, 0x84, 0x3d,
+        0xb4, 0x7a, 0xdd, 0x7e, 0xb4, 0x44, 0xc6, 0xeb, 0x25, 0x17, 0x31, 0xe3, 0x5a, 0x24, 0x0d,
+        0x13, 0xbb, 0x05, 0xe8, 0x9a, 0x84, 0xec, 0x2a, 0xb2, 0x8c, 0x4a, 0x02, 0x09, 0x00, 0x86,
+        0x9b, 0xc1, 0x6b, 0xab, 0xdc, 0xd2, 0x43,
+    ];
+    const RESPONSE: &str = concat!(
+        "MIIB6DADAgEAMIIB3wYJKoZIhvcNAQcCoIIB0DCCAcwCAQMxDzANBglghkgBZQMEAgEFADBzBgsqhkiG9w0BCRAB",
+        "BKBkBGIwYAIBAQYEKgMEATAxMA0GCWCGSAFlAwQCAQUABCAGxmKwhD20et1+tETG6yUXMeNaJA0TuwXomoTsKrKM",
+        "SgIBAhgPMjAyNjEwMTQxMzQ1NTJaMAMCAQECCQCGm8Frq9zSQzGCAT8wggE7AgEBMCswEzERMA8GA1UEAwwIVGVz",
+        "dCBUU0ECFG56vk0iXZBwPrvc8bY+w/MZ9oIZMA0GCWCGSAFlAwQCAQUAoIGkMBoGCSqGSIb3DQEJAzENBgsqhkiG",
+        "9w0BCRABBDAcBgkqhkiG9w0BCQUxDxcNMjYxMDE0MTM0NTUyWjAvBgkqhkiG9w0BCQQxIgQgxscaDKJ0NKpp0GTu",
+        "En0EMrCSZ/duZ0M6OCDrAEnigfAwNwYLKoZIhvcNAQkQAi8xKDAmMCQwIgQg8/TH1pdvJHHzEOAJq1SZZzxHR2kA",
+        "VAmFVPGilyVcZA4wCgYIKoZIzj0EAwIERzBFAiBoJpMFNYOnHKpIQ4Dd02bQjPdIp2LvVL8uqTXo6RKdGAIhAPIJ",
+        "M3JdiAs557XGo4ljwdpTW49Y+sooZEqyqbv3FHQ3",
+    );
+    const TSA_PK: [u8; 65] = [
+        0x04, 0x7c, 0xd4, 0xee, 0xae, 0x09, 0xcf, 0xc1, 0xe2, 0x46, 0x64, 0x14, 0x11, 0x95, 0x0b,
+        0xd0, 0xa4, 0xdd, 0x83, 0x1d, 0x26, 0xb1, 0xaf, 0x29, 0x22, 0x80, 0x86, 0x22, 0xf9, 0xdf,
+        0x38, 0xde, 0xa0, 0xce, 0x05, 0xea, 0x9a, 0x4b, 0xaa, 0xe8, 0x69, 0x17, 0x49, 0xb6, 0x64,
+        0xfd, 0xbf, 0x30, 0x00, 0xb9, 0xc9, 0x64, 0x8e, 0x2d, 0x2d, 0xdc, 0x66, 0x3d, 0x0d, 0x9d,
+        0x54, 0x87, 0x92, 0x98, 0xa3,
+    ];
+
+    let hash_alg = TimestampHashAlgorithm::SHA256;
+    let digest = ring::digest::digest(&ring::digest::SHA256, MESSAGE);
+    let digest = digest.as_ref();
+    let request = signature_timestamp_request(hash_alg, digest, Some(&NONCE), false).unwrap();
+    assert_eq!(request, REQUEST);
+    let with_cert_req = signature_timestamp_request(hash_alg, digest, None, true).unwrap();
+    assert_eq!(
+        &with_cert_req[with_cert_req.len() - 3..],
+        &[0x01, 0x01, 0xff]
+    );
+    assert!(signature_timestamp_request(hash_alg, &digest[1..], None, false).is_err());
+
+    let op_handle = crate::signature_op_open("ECDSA_P256_SHA256").unwrap();
+    let pk_handle =
+        crate::signature_publickey_import(op_handle, &TSA_PK, PublicKeyEncoding::Raw).unwrap();
+    let response = base64::decode(RESPONSE).unwrap();
+    let gen_time =
+        signature_verify_timestamp_response(pk_handle, &response, hash_alg, digest, Some(&NONCE))
+            .unwrap();
+    assert_eq!(gen_time, "20261014134552Z");
+    signature_verify_timestamp_response(pk_handle, &response, hash_alg, digest, None).unwrap();
+
+    let is_verification_failure = |err: Error| {
+        matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::VerificationFailed)
+        )
+    };
+    let other_digest = ring::digest::digest(&ring::digest::SHA256, b"other");
+    let err = signature_verify_timestamp_response(
+        pk_handle,
+        &response,
+        hash_alg,
+        other_digest.as_ref(),
+        Some(&NONCE),
+    )
+    .unwrap_err();
+    assert!(is_verification_failure(err));
+    let err =
+        signature_verify_timestamp_response(pk_handle, &response, hash_alg, digest, Some(&[1]))
+            .unwrap_err();
+    assert!(is_verification_failure(err));
+
+    // Flip a bit of the serial number, covered by the `messageDigest` attribute, then of the
+    // signing time, which is a signed attribute
+    for &offset in &[135, 290] {
+        let mut tampered = response.clone();
+        tampered[offset] ^= 0x01;
+        let err = signature_verify_timestamp_response(
+            pk_handle,
+            &tampered,
+            hash_alg,
+            digest,
+            Some(&NONCE),
+        )
+        .unwrap_err();
+        assert!(is_verification_failure(err));
+    }
+
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let other_pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    let err = signature_verify_timestamp_response(
+        other_pk_handle,
+        &response,
+        hash_alg,
+        digest,
+        Some(&NONCE),
+    )
+    .unwrap_err();
+    assert!(is_verification_failure(err));
+}
@@ -0,0 +1,183 @@
+use alloc::vec::Vec;
+
+use super::der;
+use super::error::*;
+use super::handles::*;
+use super::signature::*;
+use super::WASI_CRYPTO_CTX;
+
+const SERVER_CONTEXT: &str = "TLS 1.3, server CertificateVerify";
+const CLIENT_CONTEXT: &str = "TLS 1.3, client CertificateVerify";
+
+/// The content covered by a `CertificateVerify` signature, as defined in RFC 8446 §4.4.3:
+/// 64 spaces, the context string, a zero byte, and the transcript hash.
+fn certificate_verify_content(transcript_hash: &[u8], is_server: bool) -> Vec<u8> {
+    let context = if is_server {
+        SERVER_CONTEXT
+    } else {
+        CLIENT_CONTEXT
+    };
+    let mut content = vec![0x20; 64];
+    content.extend_from_slice(context.as_bytes());
+    content.push(0x00);
+    content.extend_from_slice(transcript_hash);
+    content
+}
+
+// TLS 1.3 only defines signature schemes for these algorithms, among the ones we support.
+// ECDSA signatures are DER-encoded on the wire, Ed25519 signatures are raw.
+fn certificate_verify_encoding(alg: SignatureAlgorithm) -> Result<SignatureEncoding, Error> {
+    let encoding = match alg {
+        SignatureAlgorithm::ECDSA_P256_SHA256 | SignatureAlgorithm::ECDSA_P384_SHA384 => {
+            SignatureEncoding::DER
+        }
+        SignatureAlgorithm::Ed25519 => SignatureEncoding::Raw,
+        _ => bail!(CryptoError::NotAvailable),
+    };
+    Ok(encoding)
+}
+
+/// Compute the signature of a TLS 1.3 `CertificateVerify` message for a transcript hash,
+/// encoded as it is sent in the handshake.
+pub fn signature_sign_tls13_certificate_verify(
+    kp_handle: Handle,
+    transcript_hash: &[u8],
+    is_server: bool,
+) -> Result<Vec<u8>, Error> {
+    let kp = WASI_CRYPTO_CTX.signature_keypair_manager.get(kp_handle)?;
+    let encoding = certificate_verify_encoding(kp.alg())?;
+    let signature = kp.sign_oneshot(&certificate_verify_content(transcript_hash, is_server))?;
+    match encoding {
+        SignatureEncoding::DER => der::ecdsa_signature_from_fixed(signature.as_ref()),
+        _ => Ok(signature.as_ref().to_vec()),
+    }
+}
+
+/// Verify the signature of a TLS 1.3 `CertificateVerify` message, as received from a peer.
+pub fn signature_verify_tls13_certificate_verify(
+    pk_handle: Handle,
+    transcript_hash: &[u8],
+    is_server: bool,
+    signature: &[u8],
+) -> Result<(), Error> {
+    let pk = WASI_CRYPTO_CTX.signature_publickey_manager.get(pk_handle)?;
+    let encoding = certificate_verify_encoding(pk.alg())?;
+    let mut state = ExclusiveSignatureVerificationState::from_publickey(pk)?;
+    state.update(&certificate_verify_content(transcript_hash, is_server))?;
+    state.verify_with_encoding(signature, encoding)
+}
+
+#[test]
+fn test_tls13_certificate_verify() {
+    use crate::{KeyPairEncoding, PublicKeyEncoding};
+
+    // SHA-256 of "ClientHello...Certificate", standing in for a handshake transcript hash
+    const TRANSCRIPT_HASH: [u8; 32] = [
+        0x76, 0x91, 0x34, 0xec, 0xa9, 0x22, 0x71, 0xad, 0x5a, 0x3f, 0xb3, 0x3e, 0x9b, 0x14, 0x21,
+        0x69, 0xda, 0x42, 0x41, 0xe4, 0xbe, 0xcb, 0xed, 0xf6, 0xf4, 0x37, 0xac, 0x50, 0x7c, 0x9d,
+        0xa4, 0x44,
+    ];
+    // Server signature with the Ed25519 key derived from the seed 0x00..0x1f, computed with
+    // OpenSSL over the RFC 8446 content
+    const ED25519_SIGNATURE: [u8; 64] = [
+        0xf5, 0xfd, 0xf5, 0x21, 0x95, 0x87, 0x1a, 0x41, 0x98, 0xb1, 0x4e, 0x7a, 0x80, 0x60, 0x59,
+        0x6d, 0x5e, 0x61, 0x5f, 0x81, 0x9d, 0xf1, 0xbd, 0xcc, 0xdd, 0x86, 0x95, 0x8e, 0x23, 0xac,
+        0x15, 0xc4, 0x99, 0x4c, 0x40, 0x77, 0x2e, 0xac, 0x8d, 0x5a, 0x50, 0xf3, 0x16, 0x79, 0xed,
+        0x3a, 0xd2, 0x60, 0x50, 0x7f, 0x36, 0x3e, 0xfb, 0x4d, 0x94, 0xae, 0xad, 0xd6, 0xcf, 0x19,
+        0x5b, 0x35, 0x30, 0x09,
+    ];
+    // Client signature with an OpenSSL-generated P-256 key, DER-encoded
+    const P256_PUBLIC_KEY: [u8; 65] = [
+        0x04, 0x35, 0x61, 0x7b, 0x2b, 0x52, 0xad, 0x4e, 0xa9, 0xd0, 0x02, 0xa6, 0x57, 0xed, 0x9f,
+        0xf7, 0x8a, 0x87, 0xb4, 0xf1, 0x35, 0xa4, 0x55, 0x98, 0x59, 0xd9, 0xb1, 0x87, 0x4d, 0x83,
+        0x3a, 0xb6, 0x7d, 0x32, 0xab, 0xc7, 0x11, 0xe6, 0x61, 0x4a, 0x57, 0x3c, 0x05, 0x59, 0x94,
+        0x8f, 0x9c, 0x61, 0xe1, 0x86, 0x90, 0x4c, 0xb0, 0xb5, 0x5b, 0x48, 0xeb, 0xe9, 0xe8, 0xa8,
+        0x94, 0x59, 0x60, 0xfa, 0x79,
+    ];
+    const P256_SIGNATURE: [u8; 72] = [
+        0x30, 0x46, 0x02, 0x21, 0x00, 0x97, 0x3e, 0x53, 0xaa, 0x74, 0x0a, 0xfd, 0x8d, 0x7f, 0xe3,
+        0x22, 0x7c, 0xf4, 0xc5, 0xb5, 0x2a, 0x4b, 0x6e, 0x24, 0x1c, 0x77, 0x1e, 0x5e, 0x3a, 0x97,
+        0x92, 0x41, 0x68, 0x9f, 0x2f, 0xe4, 0x36, 0x02, 0x21, 0x00, 0xbc, 0xe5, 0x0e, 0x64, 0x9c,
+        0xf8, 0x87, 0x07, 0xbc, 0x79, 0xe7, 0x5d, 0xd4, 0x05, 0x7d, 0xa1, 0xa7, 0xf1, 0xb3, 0x06,
+        0x2c, 0xf5, 0x02, 0xbd, 0x8c, 0xe8, 0x94, 0xe2, 0x7d, 0x62, 0x8f, 0xfc,
+    ];
+
+    const ED25519_PUBLIC_KEY: [u8; 32] = [
+        0x03, 0xa1, 0x07, 0xbf, 0xf3, 0xce, 0x10, 0xbe, 0x1d, 0x70, 0xdd, 0x18, 0xe7, 0x4b, 0xc0,
+        0x99, 0x67, 0xe4, 0xd6, 0x30, 0x9b, 0xa5, 0x0d, 0x5f, 0x1d, 0xdc, 0x86, 0x64, 0x12, 0x55,
+        0x31, 0xb8,
+    ];
+
+    let seed: Vec<u8> = (0..32).collect();
+    let pkcs8 = [
+        &[
+            0x30, 0x53, 0x02, 0x01, 0x01, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22,
+            0x04, 0x20,
+        ][..],
+        &seed,
+        &[0xa1, 0x23, 0x03, 0x21, 0x00],
+        &ED25519_PUBLIC_KEY,
+    ]
+    .concat();
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle =
+        crate::signature_keypair_import(kp_builder_handle, &pkcs8, KeyPairEncoding::PKCS8).unwrap();
+    let signature =
+        signature_sign_tls13_certificate_verify(kp_handle, &TRANSCRIPT_HASH, true).unwrap();
+    assert_eq!(signature, &ED25519_SIGNATURE[..]);
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    signature_verify_tls13_certificate_verify(pk_handle, &TRANSCRIPT_HASH, true, &signature)
+        .unwrap();
+    assert!(signature_verify_tls13_certificate_verify(
+        pk_handle,
+        &TRANSCRIPT_HASH,
+        false,
+        &signature
+    )
+    .is_err());
+
+    let op_handle = crate::signature_op_open("ECDSA_P256_SHA256").unwrap();
+    let pk_handle =
+        crate::signature_publickey_import(op_handle, &P256_PUBLIC_KEY, PublicKeyEncoding::Raw)
+            .unwrap();
+    signature_verify_tls13_certificate_verify(pk_handle, &TRANSCRIPT_HASH, false, &P256_SIGNATURE)
+        .unwrap();
+    assert!(signature_verify_tls13_certificate_verify(
+        pk_handle,
+        &TRANSCRIPT_HASH,
+        true,
+        &P256_SIGNATURE
+    )
+    .is_err());
+
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    let signature =
+        signature_sign_tls13_certificate_verify(kp_handle, &TRANSCRIPT_HASH, false).unwrap();
+    assert_eq!(signature[0], 0x30);
+    signature_verify_tls13_certificate_verify(pk_handle, &TRANSCRIPT_HASH, false, &signature)
+        .unwrap();
+}
+
+#[test]
+fn test_tls13_prehashed_only() {
+    // Prehashed-only ECDSA key pairs require the `prehash` feature, and TLS 1.3 has no
+    // scheme for Ed25519 with BLAKE2b-512
+    if !cfg!(feature = "prehash") {
+        return;
+    }
+    let options = crate::SignatureOptions {
+        prehashed_only: true,
+        ..Default::default()
+    };
+    let op_handle = crate::signature_op_open_with_options("ECDSA_P256_SHA256", &options).unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+    let kp_handle = crate::signature_keypair_generate(kp_builder_handle).unwrap();
+    let err = signature_sign_tls13_certificate_verify(kp_handle, &[0u8; 32], true).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::NotAvailable)
+    ));
+}
@@ -0,0 +1,95 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use super::error::*;
+use super::handles::*;
+use super::signature_keypair::*;
+use super::signature_policy::*;
+use super::sync::Mutex;
+use super::WASI_CRYPTO_CTX;
+
+/// Lowercase hex encoding of the SHA-256 digest of a raw public key.
+fn fingerprint(raw_pk: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, raw_pk)
+        .as_ref()
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect()
+}
+
+/// Generate key pairs until the hex-encoded SHA-256 fingerprint of the raw public key starts
+/// with `prefix`, and register the first match. Candidates are generated on all available
+/// threads. Returns `None` if none of the `max_attempts` key pairs matched.
+///
+/// Each hex digit of the prefix multiplies the expected number of attempts by 16.
+pub fn signature_keypair_generate_vanity(
+    kp_builder_handle: Handle,
+    prefix: &str,
+    max_attempts: usize,
+) -> Result<Option<Handle>, Error> {
+    let prefix = prefix.to_ascii_lowercase();
+    ensure!(
+        prefix.len() <= 64 && prefix.bytes().all(|x| x.is_ascii_hexdigit()),
+        "Invalid fingerprint prefix"
+    );
+    let kp_builder = WASI_CRYPTO_CTX
+        .signature_keypair_builder_manager
+        .get(kp_builder_handle)?;
+    check_algorithm_policy(kp_builder.alg())?;
+    let threads = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(max_attempts);
+    let attempts = AtomicUsize::new(0);
+    let found: Mutex<Option<Result<SignatureKeyPair, Error>>> = Mutex::new(None);
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                while found.lock().is_none()
+                    && attempts.fetch_add(1, Ordering::Relaxed) < max_attempts
+                {
+                    let candidate = match WASI_CRYPTO_CTX
+                        .signature_keypair_manager
+                        .generate(&kp_builder)
+                    {
+                        Ok(kp) if !fingerprint(kp.raw_public_key()).starts_with(&prefix) => {
+                            continue
+                        }
+                        candidate => candidate,
+                    };
+                    found.lock().get_or_insert(candidate);
+                }
+            });
+        }
+    });
+    match found.into_inner() {
+        None => Ok(None),
+        Some(kp) => {
+            let handle = WASI_CRYPTO_CTX.signature_keypair_manager.register(kp?)?;
+            Ok(Some(handle))
+        }
+    }
+}
+
+#[test]
+fn test_generate_vanity() {
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    let kp_builder_handle = crate::signature_keypair_builder_open(op_handle).unwrap();
+
+    // A given hex digit is missing from 1000 fingerprints with a probability of about 1e-28
+    let kp_handle = signature_keypair_generate_vanity(kp_builder_handle, "A", 1000)
+        .unwrap()
+        .unwrap();
+    let pk_handle = crate::signature_keypair_publickey(kp_handle).unwrap();
+    let raw_pk =
+        crate::signature_publickey_export(pk_handle, crate::PublicKeyEncoding::Raw).unwrap();
+    assert!(fingerprint(&raw_pk).starts_with('a'));
+
+    let fingerprint_prefix = "0123456789abcdef".repeat(4);
+    assert!(
+        signature_keypair_generate_vanity(kp_builder_handle, &fingerprint_prefix, 8)
+            .unwrap()
+            .is_none()
+    );
+    assert!(signature_keypair_generate_vanity(kp_builder_handle, "xyz", 8).is_err());
+}
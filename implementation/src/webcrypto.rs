@@ -0,0 +1,66 @@
+use super::ecdsa::*;
+use super::error::*;
+use super::signature::*;
+
+/// Verify an ECDSA signature in the formats produced by WebCrypto: a raw `r || s` signature,
+/// and an uncompressed `0x04 || X || Y` public key.
+pub fn signature_verify_webcrypto_ecdsa(
+    raw_pk: &[u8],
+    message: &[u8],
+    raw_signature: &[u8],
+    alg: SignatureAlgorithm,
+) -> Result<(), Error> {
+    let coordinate_len = ECDSASignatureOp::coordinate_len(alg)?;
+    ensure!(
+        raw_pk.len() == 1 + coordinate_len * 2,
+        CryptoError::InvalidKey
+    );
+    ECDSASignaturePublicKey::from_raw(alg, raw_pk)?.verify(message, raw_signature)
+}
+
+#[test]
+fn test_verify_webcrypto_ecdsa() {
+    // P-256 key and signature of `MESSAGE`, recorded with OpenSSL and converted to the raw
+    // formats returned by `crypto.subtle.exportKey("raw", ...)` and `crypto.subtle.sign()`
+    const MESSAGE: &[u8] = b"hello from the browser";
+    const PK: [u8; 65] = [
+        0x04, 0xf2, 0xc6, 0x00, 0x4e, 0xa0, 0x07, 0x20, 0x44, 0xb5, 0x01, 0xbc, 0x85, 0xe1, 0x45,
+        0x8b, 0xad, 0x9a, 0x22, 0x99, 0x28, 0xac, 0x36, 0xc3, 0xe3, 0xa2, 0x84, 0x97, 0x1f, 0xe1,
+        0xd0, 0x12, 0x48, 0xfb, 0x56, 0x1b, 0x80, 0xe2, 0x08, 0x1f, 0xc4, 0xc8, 0x25, 0xab, 0x43,
+        0xee, 0x4f, 0xe3, 0x7c, 0xcd, 0x7d, 0x08, 0x1b, 0x92, 0x4c, 0xaf, 0xfb, 0xf4, 0x3e, 0x38,
+        0x6b, 0xe4, 0x30, 0x93, 0xea,
+    ];
+    const SIG: [u8; 64] = [
+        0xdc, 0x3d, 0x47, 0x1a, 0xae, 0x24, 0xb6, 0xc1, 0x5e, 0xfc, 0x35, 0xcf, 0x2b, 0xbf, 0xda,
+        0xdf, 0x88, 0x4f, 0x60, 0x40, 0x94, 0x25, 0xd4, 0x2a, 0x91, 0xf8, 0xbb, 0x24, 0x35, 0x15,
+        0x33, 0xc1, 0x5a, 0x34, 0x39, 0x4e, 0xde, 0x30, 0xea, 0xf5, 0x4a, 0xb6, 0xf5, 0x0f, 0x5a,
+        0xca, 0x78, 0xa3, 0x13, 0xeb, 0xea, 0xaa, 0xf3, 0xf2, 0x27, 0x36, 0x26, 0x16, 0xfa, 0xdf,
+        0x86, 0xd5, 0x24, 0x19,
+    ];
+
+    let alg = SignatureAlgorithm::ECDSA_P256_SHA256;
+    signature_verify_webcrypto_ecdsa(&PK, MESSAGE, &SIG, alg).unwrap();
+
+    let err = signature_verify_webcrypto_ecdsa(&PK, b"other", &SIG, alg).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::VerificationFailed)
+    ));
+    let err = signature_verify_webcrypto_ecdsa(&PK[..64], MESSAGE, &SIG, alg).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::InvalidKey)
+    ));
+    let err = signature_verify_webcrypto_ecdsa(&PK, MESSAGE, &SIG[..63], alg).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::InvalidSignature)
+    ));
+    let err =
+        signature_verify_webcrypto_ecdsa(&PK, MESSAGE, &SIG, SignatureAlgorithm::ECDSA_P384_SHA384)
+            .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<CryptoError>(),
+        Some(CryptoError::InvalidKey)
+    ));
+}
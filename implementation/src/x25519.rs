@@ -0,0 +1,254 @@
+use alloc::vec::Vec;
+
+use super::eddsa::*;
+use super::error::*;
+use super::handles::*;
+use super::hmac::*;
+use super::signature::*;
+use super::signature_publickey::*;
+use super::WASI_CRYPTO_CTX;
+use zeroize::{Zeroize, Zeroizing};
+
+const SHARED_SECRET_LEN: usize = 32;
+
+/// Convert a raw Ed25519 public key into the little-endian Montgomery u-coordinate used by
+/// X25519, computed as `(1 + y) / (1 - y)`. The sign of `x` is lost in the conversion.
+///
+/// Like libsodium's `crypto_sign_ed25519_pk_to_curve25519()`, non-canonical encodings and
+/// points that are not in the prime-order subgroup, including the identity and all
+/// low-order points, are rejected.
+#[cfg(feature = "x25519")]
+pub fn ed25519_pub_to_x25519(raw_pk: &[u8]) -> Result<[u8; 32], Error> {
+    use curve25519_dalek::edwards::CompressedEdwardsY;
+
+    let compressed = CompressedEdwardsY::from_slice(raw_pk).map_err(|_| CryptoError::InvalidKey)?;
+    let point = compressed.decompress().ok_or(CryptoError::InvalidKey)?;
+    ensure!(point.compress() == compressed, CryptoError::InvalidKey);
+    ensure!(
+        !point.is_small_order() && point.is_torsion_free(),
+        CryptoError::InvalidKey
+    );
+    Ok(point.to_montgomery().to_bytes())
+}
+
+#[cfg(not(feature = "x25519"))]
+pub fn ed25519_pub_to_x25519(_raw_pk: &[u8]) -> Result<[u8; 32], Error> {
+    bail!(CryptoError::NotAvailable)
+}
+
+impl EdDSASignaturePublicKey {
+    /// Compute the public key of a raw 32-byte secret scalar, as found in the first half of
+    /// an expanded Ed25519 secret key or in an X25519 private key, and not of a seed. The
+    /// scalar is clamped like an X25519 private key, with the 3 lowest bits cleared, the
+    /// highest bit cleared and the second highest set, so scalars that only differ in these
+    /// bits have the same public key. Signing also requires the nonce key derived from the
+    /// seed along with the scalar, so there is no key pair counterpart; holders of a seed want
+    /// `EdDSASignatureKeyPair::from_seed()` instead.
+    #[cfg(feature = "x25519")]
+    pub fn from_raw_scalar(alg: SignatureAlgorithm, scalar: &[u8]) -> Result<Self, Error> {
+        use curve25519_dalek::edwards::EdwardsPoint;
+        use zeroize::Zeroizing;
+
+        let mut clamped = Zeroizing::new([0u8; 32]);
+        ensure!(scalar.len() == clamped.len(), CryptoError::InvalidKey);
+        clamped.copy_from_slice(scalar);
+        let point = EdwardsPoint::mul_base_clamped(*clamped);
+        EdDSASignaturePublicKey::from_raw(alg, point.compress().as_bytes())
+    }
+
+    #[cfg(not(feature = "x25519"))]
+    pub fn from_raw_scalar(_alg: SignatureAlgorithm, _scalar: &[u8]) -> Result<Self, Error> {
+        bail!(CryptoError::NotAvailable)
+    }
+}
+
+/// A 32-byte shared secret, such as the output of an X25519 key exchange, zeroized when
+/// dropped. It cannot be cloned: it can only be read in place, or expanded into keys.
+#[repr(transparent)]
+pub struct SharedSecret(Zeroizing<[u8; SHARED_SECRET_LEN]>);
+
+impl core::fmt::Debug for SharedSecret {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("SharedSecret").finish_non_exhaustive()
+    }
+}
+
+impl SharedSecret {
+    /// Move a shared secret into a `SharedSecret`, and zeroize `secret`.
+    pub fn from_bytes(secret: &mut [u8; SHARED_SECRET_LEN]) -> Self {
+        let shared_secret = SharedSecret(Zeroizing::new(*secret));
+        secret.zeroize();
+        shared_secret
+    }
+
+    pub fn as_bytes(&self) -> &[u8; SHARED_SECRET_LEN] {
+        &self.0
+    }
+
+    /// Derive `out.len()` bytes from the shared secret with HKDF-SHA256 (RFC 5869), which can
+    /// be at most 255 * 32 bytes. The pseudorandom key and the intermediate blocks are
+    /// zeroized once the output is expanded.
+    pub fn hkdf(&self, salt: &[u8], info: &[u8], out: &mut [u8]) -> Result<(), Error> {
+        const HASH_LEN: usize = 32;
+        ensure!(out.len() <= 255 * HASH_LEN, CryptoError::InvalidLength);
+        let hmac = |key: &[u8], parts: &[&[u8]]| -> Result<Zeroizing<Vec<u8>>, Error> {
+            let mut state = HmacState::new(&HmacKey::new(HashAlgorithm::SHA256, key)?);
+            for part in parts {
+                state.update(part);
+            }
+            Ok(Zeroizing::new(state.sign()))
+        };
+        let salt = if salt.is_empty() {
+            &[0u8; HASH_LEN][..]
+        } else {
+            salt
+        };
+        let prk = hmac(salt, &[&self.0[..]])?;
+        let mut block = Zeroizing::new(vec![]);
+        for (i, chunk) in out.chunks_mut(HASH_LEN).enumerate() {
+            block = hmac(&prk, &[&block, info, &[i as u8 + 1]])?;
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+        Ok(())
+    }
+}
+
+/// Return the X25519 public key corresponding to an Ed25519 public key.
+pub fn signature_publickey_to_x25519(pk_handle: Handle) -> Result<[u8; 32], Error> {
+    let pk = WASI_CRYPTO_CTX.signature_publickey_manager.get(pk_handle)?;
+    match pk {
+        SignaturePublicKey::EdDSA(pk) => ed25519_pub_to_x25519(pk.as_raw()?),
+        _ => bail!(CryptoError::InvalidKey),
+    }
+}
+
+#[test]
+fn test_ed25519_pub_to_x25519() {
+    // Public keys generated from the seeds `00..1f`, `42` * 32 and `ff` * 32, converted with
+    // libsodium's `crypto_sign_ed25519_pk_to_curve25519()`
+    const VECTORS: [(&str, &str); 3] = [
+        (
+            "03a107bff3ce10be1d70dd18e74bc09967e4d6309ba50d5f1ddc8664125531b8",
+            "4701d08488451f545a409fb58ae3e58581ca40ac3f7f114698cd71deac73ca01",
+        ),
+        (
+            "2152f8d19b791d24453242e15f2eab6cb7cffa7b6a5ed30097960e069881db12",
+            "cc4f2cdb695dd766f34118eb67b98652fed1d8bc49c330b119bbfa8a64989378",
+        ),
+        (
+            "76a1592044a6e4f511265bca73a604d90b0529d1df602be30a19a9257660d1f5",
+            "d1fa3f01826bd8b78e057c086c7b22c7ad4358ca918099cd7b7e5d3acd7e285b",
+        ),
+    ];
+    // The identity, a point of order 8 and a mixed-order point, all rejected by libsodium
+    const REJECTED: [&str; 3] = [
+        "0100000000000000000000000000000000000000000000000000000000000000",
+        "c7176a703d4dd84fba3c0b760d10670f2a2053fa2c39ccc64ec7fd7792ac037a",
+        "e6b937386b28616b48902a14df24fe94050e78aeac3e9e20ae8dbf1737026a13",
+    ];
+    let from_hex = |hex: &str| -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    };
+
+    if !cfg!(feature = "x25519") {
+        let err = ed25519_pub_to_x25519(&from_hex(VECTORS[0].0)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::NotAvailable)
+        ));
+        return;
+    }
+    let op_handle = crate::signature_op_open("Ed25519").unwrap();
+    for (ed25519_pk, x25519_pk) in &VECTORS {
+        let pk_handle = crate::signature_publickey_import(
+            op_handle,
+            &from_hex(ed25519_pk),
+            PublicKeyEncoding::Raw,
+        )
+        .unwrap();
+        assert_eq!(
+            &signature_publickey_to_x25519(pk_handle).unwrap()[..],
+            &from_hex(x25519_pk)[..]
+        );
+    }
+    for rejected in &REJECTED {
+        assert!(ed25519_pub_to_x25519(&from_hex(rejected)).is_err());
+    }
+    assert!(ed25519_pub_to_x25519(&[0u8; 31]).is_err());
+}
+
+#[test]
+fn test_raw_scalar_and_seed() {
+    // Test 1 of RFC 8032, section 7.1
+    let seed =
+        parse_hex("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60").unwrap();
+    let raw_pk =
+        parse_hex("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a").unwrap();
+    let alg = SignatureAlgorithm::Ed25519;
+    let kp = EdDSASignatureKeyPair::from_seed(alg, &seed).unwrap();
+    assert_eq!(kp.raw_public_key(), &raw_pk[..]);
+    assert!(EdDSASignatureKeyPair::from_seed(alg, &seed[1..]).is_err());
+
+    if !cfg!(feature = "x25519") {
+        let err = EdDSASignaturePublicKey::from_raw_scalar(alg, &seed).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::NotAvailable)
+        ));
+        return;
+    }
+    // The seed is not the scalar: the scalar is the clamped first half of its SHA-512 digest
+    let pk = EdDSASignaturePublicKey::from_raw_scalar(alg, &seed).unwrap();
+    assert_ne!(pk.as_raw().unwrap(), &raw_pk[..]);
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(&ring::digest::digest(&ring::digest::SHA512, &seed).as_ref()[..32]);
+    let pk = EdDSASignaturePublicKey::from_raw_scalar(alg, &scalar).unwrap();
+    assert_eq!(pk.as_raw().unwrap(), &raw_pk[..]);
+
+    // Bits that are clamped don't change the public key
+    scalar[0] ^= 0x07;
+    scalar[31] ^= 0xc0;
+    let pk = EdDSASignaturePublicKey::from_raw_scalar(alg, &scalar).unwrap();
+    assert_eq!(pk.as_raw().unwrap(), &raw_pk[..]);
+    scalar[0] ^= 0x08;
+    let pk = EdDSASignaturePublicKey::from_raw_scalar(alg, &scalar).unwrap();
+    assert_ne!(pk.as_raw().unwrap(), &raw_pk[..]);
+    assert!(EdDSASignaturePublicKey::from_raw_scalar(alg, &scalar[1..]).is_err());
+}
+
+#[test]
+fn test_shared_secret() {
+    // The salt and info of test case 1 of RFC 5869, whose IKM is shorter than a shared
+    // secret. The output was computed with Python's `hmac` module.
+    let mut secret = [0x0b; SHARED_SECRET_LEN];
+    let shared_secret = SharedSecret::from_bytes(&mut secret);
+    assert_eq!(secret, [0u8; SHARED_SECRET_LEN]);
+    assert_eq!(shared_secret.as_bytes(), &[0x0b; SHARED_SECRET_LEN]);
+    let salt = parse_hex("000102030405060708090a0b0c").unwrap();
+    let info = parse_hex("f0f1f2f3f4f5f6f7f8f9").unwrap();
+    let mut okm = [0u8; 42];
+    shared_secret.hkdf(&salt, &info, &mut okm).unwrap();
+    assert_eq!(
+        okm.to_vec(),
+        parse_hex(
+            "d4100799f26a09615a72af3e58fa3841a2ff20d5ace3fb392e562e207fe6b718\
+             581eea4341652d405fe5"
+        )
+        .unwrap()
+    );
+    assert!(shared_secret
+        .hkdf(&salt, &info, &mut vec![0u8; 255 * 32 + 1])
+        .is_err());
+
+    // The secret is cleared in place when dropped
+    // SAFETY: the value is dropped once, and its storage stays valid for the slot's lifetime.
+    // `SharedSecret` and `Zeroizing` are transparent, so the secret is at the start of the slot.
+    let mut slot = core::mem::MaybeUninit::new(shared_secret);
+    let secret_ptr = slot.as_ptr() as *const u8;
+    unsafe { core::ptr::drop_in_place(slot.as_mut_ptr()) };
+    let cleared = unsafe { core::slice::from_raw_parts(secret_ptr, SHARED_SECRET_LEN) };
+    assert_eq!(cleared, &[0u8; SHARED_SECRET_LEN]);
+}